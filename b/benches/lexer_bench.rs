@@ -1,11 +1,10 @@
 use criterion::{criterion_group, criterion_main, Criterion, black_box};
-use b::lexer::scanner::Scanner;
 
-/// Helper: lex a source string, collecting all tokens.
+/// Helper: lex a source string, collecting all tokens (EOF included).
 fn lex_all(source: &str) -> Vec<b::lexer::token::SpannedToken<'_>> {
-    Scanner::new(source)
-        .collect::<Result<Vec<_>, _>>()
-        .expect("lex error in benchmark input")
+    let lexed = b::lexer::lex_all(source);
+    assert!(lexed.errors.is_empty(), "lex error in benchmark input");
+    lexed.tokens
 }
 
 // ---------------------------------------------------------------------------
@@ -83,7 +82,7 @@ fn many_numbers() -> String {
             s.push_str(&format!("x = 0{};\n", i)); // octal
         }
     }
-    s.push_str("}");
+    s.push('}');
     s
 }
 
@@ -11,7 +11,8 @@
 static ALLOC: dhat::Alloc = dhat::Alloc;
 
 use b::lexer::scanner::Scanner;
-use b::lexer::token::{SpannedToken, Token};
+use b::lexer::lex_all;
+use b::lexer::token::SpannedToken;
 use b::parser::Parser;
 
 // ---------------------------------------------------------------------------
@@ -19,14 +20,9 @@ use b::parser::Parser;
 // ---------------------------------------------------------------------------
 
 fn lex(source: &str) -> Vec<SpannedToken<'_>> {
-    let mut tokens: Vec<_> = Scanner::new(source)
-        .collect::<Result<Vec<_>, _>>()
-        .expect("lex error");
-    tokens.push(SpannedToken {
-        token: Token::EOF,
-        span: b::common::span::Span::default(),
-    });
-    tokens
+    let lexed = lex_all(source);
+    assert!(lexed.errors.is_empty(), "lex error");
+    lexed.tokens
 }
 
 // ---------------------------------------------------------------------------
@@ -125,7 +121,7 @@ fn main() {
             let src = many_globals_source();
             profile_parse_large(&src);
         }
-        "all" | _ => {
+        _ => {
             eprintln!("=== dhat: end-to-end large (1000 fns) ===");
             let src = large_source();
             profile_e2e(&src);
@@ -1,5 +1,5 @@
 use criterion::{criterion_group, criterion_main, Criterion, black_box};
-use b::lexer::scanner::Scanner;
+use b::lexer::lex_all;
 use b::lexer::token::SpannedToken;
 use b::parser::Parser;
 
@@ -8,15 +8,10 @@ use b::parser::Parser;
 // ---------------------------------------------------------------------------
 
 fn lex(source: &str) -> Vec<SpannedToken<'_>> {
-    let mut tokens: Vec<_> = Scanner::new(source)
-        .collect::<Result<Vec<_>, _>>()
-        .expect("lex error in benchmark input");
-    // Parser expects an EOF sentinel at the end.
-    tokens.push(SpannedToken {
-        token: b::lexer::token::Token::EOF,
-        span: b::common::span::Span::default(),
-    });
-    tokens
+    // lex_all ends the stream with the EOF sentinel the parser expects.
+    let lexed = lex_all(source);
+    assert!(lexed.errors.is_empty(), "lex error in benchmark input");
+    lexed.tokens
 }
 
 fn parse_program(source: &str) {
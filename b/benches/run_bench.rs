@@ -0,0 +1,98 @@
+use criterion::{criterion_group, criterion_main, Criterion, black_box};
+use b::ast::Program;
+use b::interp::Interpreter;
+use b::ir::lower::lower;
+use b::ir::ssa::to_ssa;
+use b::lexer::lex_all;
+use b::lexer::token::SpannedToken;
+use b::parser::Parser;
+use b::sema::{analyze, SemaOutput};
+use b::vm::compile::compile;
+use b::vm::machine::Vm;
+
+// ---------------------------------------------------------------------------
+//  Helpers
+// ---------------------------------------------------------------------------
+
+fn lex(source: &str) -> Vec<SpannedToken<'_>> {
+    let lexed = lex_all(source);
+    assert!(lexed.errors.is_empty(), "lex error in benchmark input");
+    lexed.tokens
+}
+
+fn check<'t>(tokens: &'t [SpannedToken<'t>]) -> (Program<'t>, SemaOutput) {
+    let mut parser = Parser::new(tokens);
+    let program = parser.parse_program().expect("parse error in benchmark input");
+    let sema = analyze(&program);
+    assert!(sema.errors.is_empty(), "sema error in benchmark input");
+    (program, sema)
+}
+
+// ---------------------------------------------------------------------------
+//  Programs
+// ---------------------------------------------------------------------------
+
+/// Calls and arithmetic: fib(20) makes about 22 000 calls.
+const FIB: &str = "fib(n) return(n < 2 ? n : fib(n - 1) + fib(n - 2)); main() return(fib(20));";
+
+/// Loops, vector indexing and memory traffic.
+const SIEVE: &str = "
+main() {
+  auto v[10000], i, j, n;
+  i = 2;
+  while (i < 10000) v[i++] = 1;
+  n = 0;
+  i = 2;
+  while (i < 10000) {
+    if (v[i]) {
+      n++;
+      j = i + i;
+      while (j < 10000) { v[j] = 0; j =+ i; }
+    }
+    i++;
+  }
+  return(n);
+}";
+
+// ---------------------------------------------------------------------------
+//  Benchmarks: the same program on the interpreter and the vm
+// ---------------------------------------------------------------------------
+
+fn bench_program(c: &mut Criterion, name: &str, source: &str, expected: i64) {
+    let tokens = lex(source);
+    let (program, sema) = check(&tokens);
+    let mut ir = lower(&program, &sema);
+    let module = compile(&ir);
+    ir.functions.iter_mut().for_each(|f| { to_ssa(f); });
+    let ssa_module = compile(&ir);
+
+    c.bench_function(&format!("run/{}/interp", name), |b| {
+        b.iter(|| {
+            let mut interpreter = Interpreter::new(black_box(&program), &sema).unwrap();
+            assert_eq!(interpreter.run(), Ok(expected));
+        })
+    });
+    c.bench_function(&format!("run/{}/vm", name), |b| {
+        b.iter(|| {
+            let mut vm = Vm::new(black_box(&module)).unwrap();
+            assert_eq!(vm.run(), Ok(expected));
+        })
+    });
+    c.bench_function(&format!("run/{}/vm (ssa)", name), |b| {
+        b.iter(|| {
+            let mut vm = Vm::new(black_box(&ssa_module)).unwrap();
+            assert_eq!(vm.run(), Ok(expected));
+        })
+    });
+}
+
+fn bench_fib(c: &mut Criterion) {
+    bench_program(c, "fib(20)", FIB, 6765);
+}
+
+fn bench_sieve(c: &mut Criterion) {
+    bench_program(c, "sieve(10000)", SIEVE, 1229);
+}
+
+criterion_group!(benches, bench_fib, bench_sieve);
+criterion_main!(benches);
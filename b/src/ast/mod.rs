@@ -1,4 +1,5 @@
-use crate::lexer::token::SpannedToken;
+use crate::lexer::token::{SpannedToken, Token, Operator};
+use crate::common::span::Span;
 pub mod visitor;
 pub mod pretty_printer;
 
@@ -16,10 +17,83 @@ pub enum Expr<'a> {
   Variable { name: &'a SpannedToken<'a> }, //variable use (symbol table)
   Get { target: Box<Expr<'a>>, index: Box<Expr<'a>>}, //array index a[10]
   Ternary { condition: Box<Expr<'a>>, then_branch: Box<Expr<'a>>, else_branch: Box<Expr<'a>>},
-  Postfix { left: Box<Expr<'a>>, operator: &'a SpannedToken<'a>} //post inc/dec
+  Postfix { left: Box<Expr<'a>>, operator: &'a SpannedToken<'a>}, //post inc/dec
+  Error { span: Span }, //placeholder left by error recovery; span covers the skipped tokens
 
 }
 
+impl<'a> Expr<'a> {
+
+  ///Source range covered by the expression's tokens. Closing delimiters are not kept in the AST, so
+  ///for calls, groupings and indexing this stops at the last operand rather than the `)` or `]`.
+  pub fn span(&self) -> Span {
+    let join = |a: Span, b: Span| Span { start: a.start, end: b.end };
+    match self {
+      Expr::Literal { value: t } | Expr::Variable { name: t } => t.span,
+      Expr::Assign { lvalue: left, value: right, .. } | Expr::Binary { left, right, .. } |
+      Expr::Bitwise { left, right, .. } | Expr::Get { target: left, index: right } => join(left.span(), right.span()),
+      Expr::Call { callee, arguments } => match arguments.last() {
+        Some(last) => join(callee.span(), last.span()),
+        None => callee.span(),
+      },
+      Expr::Grouping { expression } => expression.span(),
+      Expr::Unary { operator, right } => join(operator.span, right.span()),
+      Expr::Ternary { condition, else_branch, .. } => join(condition.span(), else_branch.span()),
+      Expr::Postfix { left, operator } => join(left.span(), operator.span),
+      Expr::Error { span } => *span,
+    }
+  }
+
+  ///Value of a constant expression: integer and char literals combined with unary and binary
+  ///operators, e.g. `-1` or `'a' + 1`. None if anything in it is not a compile-time constant
+  ///(names, strings, calls, assignments) or if evaluating it would divide by zero.
+  pub fn const_value(&self) -> Option<i64> {
+    match self {
+      Expr::Literal { value } => match value.token {
+        Token::Integer(n) | Token::CharLiteral(n) => Some(n),
+        _ => None,
+      },
+      Expr::Grouping { expression } => expression.const_value(),
+      Expr::Unary { operator, right } => {
+        let n = right.const_value()?;
+        match operator.token {
+          Token::Operator(Operator::Minus) => Some(n.wrapping_neg()),
+          Token::Operator(Operator::Plus) => Some(n),
+          Token::Operator(Operator::Bang) => Some((n == 0) as i64),
+          Token::Operator(Operator::Tilde) => Some(!n),
+          _ => None,
+        }
+      }
+      Expr::Binary { left, operator, right } | Expr::Bitwise { left, operator, right } => {
+        let (l, r) = (left.const_value()?, right.const_value()?);
+        match operator.token {
+          Token::Operator(Operator::Plus) => Some(l.wrapping_add(r)),
+          Token::Operator(Operator::Minus) => Some(l.wrapping_sub(r)),
+          Token::Operator(Operator::Star) => Some(l.wrapping_mul(r)),
+          Token::Operator(Operator::Slash) => l.checked_div(r),
+          Token::Operator(Operator::Percent) => l.checked_rem(r),
+          Token::Operator(Operator::Amp) => Some(l & r),
+          Token::Operator(Operator::Bar) => Some(l | r),
+          Token::Operator(Operator::Caret) => Some(l ^ r),
+          Token::Operator(Operator::LShift) => Some(l.wrapping_shl(r as u32)),
+          Token::Operator(Operator::RShift) => Some(l.wrapping_shr(r as u32)),
+          Token::Operator(Operator::Equal) => Some((l == r) as i64),
+          Token::Operator(Operator::NotEqual) => Some((l != r) as i64),
+          Token::Operator(Operator::Less) => Some((l < r) as i64),
+          Token::Operator(Operator::LessEq) => Some((l <= r) as i64),
+          Token::Operator(Operator::Greater) => Some((l > r) as i64),
+          Token::Operator(Operator::GreaterEq) => Some((l >= r) as i64),
+          _ => None,
+        }
+      }
+      Expr::Ternary { condition, then_branch, else_branch } => {
+        if condition.const_value()? != 0 { then_branch.const_value() } else { else_branch.const_value() }
+      }
+      _ => None,
+    }
+  }
+}
+
 
 //variables must be allocated before usage; auto a = 5 is invalid.
 //auto and extrn must be first statement in a block
@@ -31,14 +105,79 @@ pub enum Stmt<'a> {
   Expression { expression: Box<Expr<'a>> },
   If { condition: Box<Expr<'a>>, then_branch: Box<Stmt<'a>>, else_branch: Option<Box<Stmt<'a>>> }, //else branch can fall through
   While { condition: Box<Expr<'a>>, body: Box<Stmt<'a>> },
+  //the switch body is an ordinary statement list; case and default label the statement after them,
+  //so control falls through from one case into the next
   Switch { condition: Box<Expr<'a>>, cases: Vec<Stmt<'a>> },
-  Case { value: &'a SpannedToken<'a>, body: Box<Stmt<'a>> },
-  Default {body: Box<Stmt<'a>>},
+  Case { value: Box<Expr<'a>>, body: Box<Stmt<'a>> }, //constant: a literal, optionally negated (`case -1:`)
+  Default { keyword: &'a SpannedToken<'a>, body: Box<Stmt<'a>> }, //keyword kept for diagnostics
   Label { name: &'a SpannedToken<'a>, body: Box<Stmt<'a>> },
   Goto { expression: Box<Expr<'a>> },
   Return { value: Option<Box<Expr<'a>>> }, //return und if Option<T> = None
   Null,
-  
+  Error { span: Span }, //placeholder left by error recovery; span covers the skipped tokens
+}
+
+impl<'a> Stmt<'a> {
+
+  ///Source range covered by the statement, for diagnostics. Keywords other than `default` and all
+  ///delimiters are not kept in the AST, so this starts at the first operand (the condition of an `if`,
+  ///the value of a `return`). None for statements with no tokens of their own: `;`, `{}`, `return;`.
+  pub fn span(&self) -> Option<Span> {
+    let join = |a: Span, b: Option<Span>| Span { start: a.start, end: b.map_or(a.end, |b| b.end) };
+    match self {
+      Stmt::Block { statements } => {
+        let first = statements.iter().find_map(Stmt::span)?;
+        Some(join(first, statements.iter().rev().find_map(Stmt::span)))
+      }
+      Stmt::Auto { declarations } => {
+        let (first, last) = (declarations.first()?, declarations.last()?);
+        Some(join(first.name.span, Some(last.size.unwrap_or(last.name).span)))
+      }
+      Stmt::Extrn { names } => Some(join(names.first()?.span, names.last().map(|t| t.span))),
+      Stmt::Expression { expression } | Stmt::Goto { expression } => Some(expression.span()),
+      Stmt::If { condition, then_branch, else_branch } => {
+        let last = else_branch.as_deref().unwrap_or(then_branch);
+        Some(join(condition.span(), last.span()))
+      }
+      Stmt::While { condition, body } => Some(join(condition.span(), body.span())),
+      Stmt::Switch { condition, cases } => Some(join(condition.span(), cases.iter().rev().find_map(Stmt::span))),
+      Stmt::Case { value, body } => Some(join(value.span(), body.span())),
+      Stmt::Default { keyword: name, body } | Stmt::Label { name, body } => Some(join(name.span, body.span())),
+      Stmt::Return { value } => value.as_ref().map(|v| v.span()),
+      Stmt::Null => None,
+      Stmt::Error { span } => Some(*span),
+    }
+  }
+}
+
+///Every case and default of one switch body, in order: (span of the case value or the `default`
+///keyword, the case value or None for default). Labels of nested switches belong to those and are
+///not included.
+pub fn switch_labels(body: &[Stmt]) -> Vec<(Span, Option<i64>)> {
+  fn collect(statement: &Stmt, labels: &mut Vec<(Span, Option<i64>)>) {
+    match statement {
+      Stmt::Case { value, body } => {
+        labels.push((value.span(), Some(value.const_value().unwrap_or(0))));
+        collect(body, labels);
+      }
+      Stmt::Default { keyword, body } => {
+        labels.push((keyword.span, None));
+        collect(body, labels);
+      }
+      Stmt::Block { statements } => statements.iter().for_each(|s| collect(s, labels)),
+      Stmt::If { then_branch, else_branch, .. } => {
+        collect(then_branch, labels);
+        if let Some(else_branch) = else_branch {
+          collect(else_branch, labels);
+        }
+      }
+      Stmt::While { body, .. } | Stmt::Label { body, .. } => collect(body, labels),
+      _ => {}
+    }
+  }
+  let mut labels = Vec::new();
+  body.iter().for_each(|s| collect(s, &mut labels));
+  labels
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -69,7 +208,7 @@ pub struct Function<'a>{
 pub struct GlobalDecl<'a> {
   pub name: &'a SpannedToken<'a>,
   pub size: Option<&'a SpannedToken<'a>>,
-  pub initializer: Option<Vec<&'a SpannedToken<'a>>>,
+  pub initializer: Option<Vec<Expr<'a>>>, //constants, see Parser::parse_constant
 }
 
 
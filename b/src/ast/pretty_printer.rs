@@ -1,11 +1,18 @@
 use crate::ast::visitor::{ExprVisitor, StmtVisitor, ItemVisitor, walk_expr, walk_stmt, walk_item};
 use crate::ast::{Expr, Stmt, AutoDecl, Program, Item, GlobalDecl};
 use crate::lexer::token::SpannedToken;
+use crate::common::span::Span;
 
 pub struct AstPrinter {
   indent: usize,
 }
 
+impl Default for AstPrinter {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl AstPrinter {
   pub fn new() -> Self {
     Self { indent: 0 }
@@ -78,8 +85,8 @@ impl StmtVisitor<String> for AstPrinter {
     result
   }
 
-  fn visit_case(&mut self, value: &SpannedToken, body: &Stmt) -> String {
-    let mut result = format!("{}(case {}\n", self.indent_str(), value.token);
+  fn visit_case(&mut self, value: &Expr, body: &Stmt) -> String {
+    let mut result = format!("{}(case {}\n", self.indent_str(), self.print_expr(value));
     self.indent += 1;
     result.push_str(&self.print_stmt(body));
     result.push('\n');
@@ -89,7 +96,7 @@ impl StmtVisitor<String> for AstPrinter {
     result
   }
 
-  fn visit_default(&mut self, body: &Stmt) -> String {
+  fn visit_default(&mut self, _keyword: &SpannedToken, body: &Stmt) -> String {
     let mut result = format!("{}(default\n", self.indent_str());
     self.indent += 1;
     result.push_str(&self.print_stmt(body));
@@ -149,6 +156,10 @@ impl StmtVisitor<String> for AstPrinter {
     format!("{}(null)", self.indent_str())
   }
 
+  fn visit_error_stmt(&mut self, _span: Span) -> String {
+    format!("{}(error)", self.indent_str())
+  }
+
   fn visit_return(&mut self, value: Option<&Expr>) -> String {
     match value {
       Some(expr) => format!("{}(return {})", self.indent_str(), self.print_expr(expr)),
@@ -201,7 +212,7 @@ impl ItemVisitor<String> for AstPrinter {
         s.push_str(&format!("[{}]", size.token));
       }
       if let Some(ref inits) = decl.initializer {
-        let init_strs: Vec<String> = inits.iter().map(|i| format!("{}", i.token)).collect();
+        let init_strs: Vec<String> = inits.iter().map(|i| self.print_expr(i)).collect();
         s.push_str(&format!(" {}", init_strs.join(" ")));
       }
       s
@@ -262,4 +273,8 @@ impl ExprVisitor<String> for AstPrinter {
   fn visit_variable(&mut self, name: &SpannedToken) -> String {
       format!("{}", name.token)
   }
+
+  fn visit_error_expr(&mut self, _span: Span) -> String {
+      "(error)".to_string()
+  }
 }
\ No newline at end of file
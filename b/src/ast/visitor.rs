@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 use super::*;
 use crate::lexer::token::SpannedToken;
+use crate::common::span::Span;
 
 
 
@@ -17,6 +18,7 @@ pub trait ExprVisitor<T> {
   fn visit_get(&mut self, target: &Expr, index: &Expr) -> T;
   fn visit_ternary(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) -> T;
   fn visit_postfix(&mut self, left: &Expr, operator: &SpannedToken) -> T;
+  fn visit_error_expr(&mut self, span: Span) -> T;
 }
 
 pub trait StmtVisitor<T> {
@@ -27,12 +29,13 @@ pub trait StmtVisitor<T> {
   fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> T;
   fn visit_while(&mut self, condition: &Expr, body: &Stmt) -> T;
   fn visit_switch(&mut self, condition: &Expr, cases: &[Stmt]) -> T;
-  fn visit_case(&mut self, value: &SpannedToken, body: &Stmt) -> T;
-  fn visit_default(&mut self, body: &Stmt) -> T;
+  fn visit_case(&mut self, value: &Expr, body: &Stmt) -> T;
+  fn visit_default(&mut self, keyword: &SpannedToken, body: &Stmt) -> T;
   fn visit_label(&mut self, name: &SpannedToken, body: &Stmt) -> T;
   fn visit_goto(&mut self, expression: &Expr) -> T;
   fn visit_return(&mut self, value: Option<&Expr>) -> T;
   fn visit_null(&mut self) -> T;
+  fn visit_error_stmt(&mut self, span: Span) -> T;
 }
 
 //top level constructs; walk program iterates over items
@@ -91,6 +94,10 @@ pub fn walk_expr<T, V: ExprVisitor<T>>(visitor: &mut V, expr: &Expr) -> T {
     Expr::Postfix { left, operator } => {
       visitor.visit_postfix(left, operator)
     }
+
+    Expr::Error { span } => {
+      visitor.visit_error_expr(*span)
+    }
   }
 }
 
@@ -134,8 +141,8 @@ pub fn walk_stmt<T, V: StmtVisitor<T>>(visitor: &mut V, stmt: &Stmt) -> T {
       visitor.visit_case(value, body)
     }
 
-    Stmt::Default {body} => {
-      visitor.visit_default(body)
+    Stmt::Default { keyword, body } => {
+      visitor.visit_default(keyword, body)
     }
 
     Stmt::Label { name, body } => {
@@ -153,6 +160,10 @@ pub fn walk_stmt<T, V: StmtVisitor<T>>(visitor: &mut V, stmt: &Stmt) -> T {
     Stmt::Null => {
       visitor.visit_null()
     }
+
+    Stmt::Error { span } => {
+      visitor.visit_error_stmt(*span)
+    }
   }
 }
 
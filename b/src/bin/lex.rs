@@ -2,6 +2,8 @@ use std::env;
 use std::fs;
 use std::process;
 use b::lexer::scanner::Scanner;
+use b::common::source_map::SourceMap;
+use b::diagnostics::render::{ColorChoice, Emitter};
 
 fn main() {
     // 1. Collect command line arguments
@@ -25,21 +27,32 @@ fn main() {
     // 4. Initialize the Scanner with the file content
     // Note: 'content' lives for the rest of main(), 
     // satisfying the Scanner's lifetime requirement.
-    let scanner = Scanner::new(&content);
+    let mut scanner = Scanner::new(&content);
+    let results: Vec<_> = scanner.by_ref().collect();
 
-    // 5. Iterate and print
-    for result in scanner {
+    // 5. Register the file with the line table the scanner built while lexing
+    let mut sources = SourceMap::new();
+    let file = sources.add_file(filename.as_str(), &content, scanner.line_index());
+
+    // 6. Iterate and print
+    let emitter = Emitter::new(&sources, ColorChoice::Auto);
+    for result in results {
         match result {
             Ok(spanned_token) => {
+                let pos = sources.line_col(file, spanned_token.span.start);
                 println!(
-                    "[{:?}] {:<15}", 
-                    spanned_token.span.start,
+                    "[{}:{}] {:<15}", 
+                    pos.line,
+                    pos.col,
                     format!("{:?}", spanned_token.token),
                 );
             },
             Err(e) => {
-                eprintln!("Lexical Error: {:?}", e);
+                emitter.emit(&e.to_diagnostic(file));
             }
         }
     }
+    for w in scanner.take_warnings() {
+        emitter.emit(&w.to_diagnostic(file));
+    }
 }
\ No newline at end of file
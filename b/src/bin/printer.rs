@@ -1,7 +1,9 @@
 use std::env;
 use std::fs;
 
-use b::lexer::scanner::Scanner;
+use b::lexer::lex_all;
+use b::common::source_map::SourceMap;
+use b::diagnostics::render::{ColorChoice, Emitter};
 use b::parser::Parser;
 use b::ast::pretty_printer::AstPrinter;
 
@@ -12,39 +14,37 @@ fn main() {
         // If no file provided, use a test expression
         let test_expr = "a + b * c";
         println!("No file provided. Testing with: {}", test_expr);
-        run(test_expr);
+        run_file("<test>", test_expr);
         return;
     }
 
     let filename = &args[1];
     let source = fs::read_to_string(filename).expect("Failed to read file");
-    run(&source);
+    run_file(filename, &source);
 }
 
-fn run(source: &str) {
-    let scanner = Scanner::new(source);
-    let mut toks = Vec::new();
-    for token in scanner {
-      match token {
-        Ok(token) => toks.push(token),
-        Err(e) => {
-          eprintln!("Lexer error: {:?}", e);
-          return;
-        }
-      }
+fn run_file(name: &str, source: &str) {
+    let lexed = lex_all(source);
+    let mut sources = SourceMap::new();
+    let file = sources.add_file(name, source, lexed.lines);
+    let emitter = Emitter::new(&sources, ColorChoice::Auto);
+
+    //bad tokens are skipped; report them and parse whatever is left
+    for e in &lexed.errors {
+        emitter.emit(&e.to_diagnostic(file));
+    }
+    for w in &lexed.warnings {
+        emitter.emit(&w.to_diagnostic(file));
     }
 
-    let mut parser = Parser::new(&toks);
-
-    match parser.parse_program() {
-        Ok(program) => {
-            println!("AST:\n{:?}\n", program);
-            let mut printer = AstPrinter::new();
-            let output = printer.print_program(&program);
-            println!("Formatted:\n{}", output);
-        }
-        Err(e) => {
-            eprintln!("Parse error: {:?}", e);
-        }
+    let mut parser = Parser::new(&lexed.tokens);
+
+    //print whatever parsed, with (error) where recovery kicked in
+    let output = parser.parse();
+    for e in &output.errors {
+        emitter.emit(&e.to_diagnostic(file));
     }
-}
\ No newline at end of file
+    println!("AST:\n{:?}\n", output.program);
+    let mut printer = AstPrinter::new();
+    println!("Formatted:\n{}", printer.print_program(&output.program));
+}
@@ -0,0 +1,468 @@
+use rustc_hash::FxHashSet;
+
+use crate::ir::{self, BinaryOp, BlockId, Callee, Function, Init, Instr, Operand, Terminator, UnaryOp, Word, WORD_BYTES};
+use crate::ir::ssa::without_phis;
+use crate::libb;
+use super::x86_64::symbol;
+
+//AArch64 code generator: GNU as for AArch64 Linux under AAPCS64, one assembly file per IR module,
+//linked with runtime(), libb and the C library by link::link_with.
+//
+//As on x86-64, B values are word addresses: a pointer is a byte address shifted right by 3, every
+//load and store shifts it back, and frames, data, functions and computed-goto targets are 8-byte
+//aligned. Every virtual register gets a stack slot; instructions load operands into x0/x1,
+//compute, and store the result.
+//
+//Frame of a function, upwards from sp after the prologue: the outgoing stack arguments of its
+//widest call, one slot per register, then the IR frame words in increasing address order (so
+//params are contiguous and `&x1 + 1` is x2), then the saved x29 and x30 that x29 points at. Calls
+//follow AAPCS64: the first eight arguments in x0..x7, the rest in words at the caller's sp, the
+//result in x0. x9 carries the argument count, so a callee sets params the caller left out to 0 and
+//a B function can walk its params through a pointer like a C variadic one.
+//
+//sdiv gives 0 on division by zero rather than trapping, and `%` by zero leaves the dividend.
+//Symbols are the same `b_` names as the x86-64 backend's, and the same per-module routine in
+//.init_array stores the word addresses that cannot be relocations.
+
+///Argument registers in order.
+const ARG_REGS: [&str; 8] = ["x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7"];
+
+///Largest offset `ldr`/`str` of a doubleword can encode.
+const MAX_OFFSET: u64 = 4095 * WORD_BYTES as u64;
+
+///Trampolines to libb::c, as in x86_64::runtime: each b_NAME stores the arguments its libb function
+///reads, from registers and the caller's stack, in a word array at sp, and calls libb_NAME with x9's
+///count and the array. Assemble it once per executable; link::link_with adds libb itself.
+pub fn runtime() -> String {
+  let mut out = String::from("  .text\n");
+  for (name, params) in libb::FUNCTIONS {
+    let array = (WORD_BYTES as usize * params).next_multiple_of(16);
+    out.push_str(&format!("  .p2align 3\n  .weak {0}\n  .type {0}, %function\n{0}:\n", symbol(name)));
+    out.push_str("  stp x29, x30, [sp, #-16]!\n  mov x29, sp\n");
+    if params > 0 {
+      out.push_str(&format!("  sub sp, sp, #{}\n", array));
+    }
+    for i in 0..params {
+      match ARG_REGS.get(i) {
+        Some(reg) => out.push_str(&format!("  str {}, [sp, #{}]\n", reg, 8 * i)),
+        //stack arguments start above the saved x29 and x30
+        None => out.push_str(&format!("  ldr x16, [x29, #{}]\n  str x16, [sp, #{}]\n", 16 + 8 * (i - ARG_REGS.len()), 8 * i)),
+      }
+    }
+    out.push_str(&format!("  mov x1, sp\n  mov x0, x9\n  bl libb_{}\n  mov sp, x29\n  ldp x29, x30, [sp], #16\n  ret\n\n", name));
+  }
+  out.push_str("  .section .note.GNU-stack,\"\",%progbits\n");
+  out
+}
+
+///Assembly for a module. It defines the C entry point `main` if the module has a B main, whose
+///result becomes the exit status.
+pub fn emit(module: &ir::Module) -> String {
+  let mut asm = Asm::default();
+  asm.ins(".text");
+  for function in &module.functions {
+    FunctionEmitter::new(&without_phis(function), &mut asm).emit();
+  }
+  if module.function("main").is_some() {
+    asm.ins(".p2align 3");
+    asm.ins(".globl main");
+    asm.ins(".type main, %function");
+    asm.label("main");
+    asm.ins("stp x29, x30, [sp, #-16]!");
+    asm.ins("mov x29, sp");
+    asm.ins("mov x9, #0");
+    asm.ins(format!("bl {}", symbol("main")));
+    asm.ins("ldp x29, x30, [sp], #16");
+    asm.ins("ret");
+  }
+  data(module, &mut asm);
+  asm.ins(".section .note.GNU-stack,\"\",%progbits");
+  asm.out
+}
+
+#[derive(Default)]
+struct Asm {
+  out: String,
+}
+
+impl Asm {
+  fn ins(&mut self, text: impl AsRef<str>) {
+    self.out.push_str("  ");
+    self.out.push_str(text.as_ref());
+    self.out.push('\n');
+  }
+
+  fn label(&mut self, name: impl AsRef<str>) {
+    self.out.push_str(name.as_ref());
+    self.out.push_str(":\n");
+  }
+
+  //reg = the address of `symbol`
+  fn address_of(&mut self, reg: &str, symbol: &str) {
+    self.ins(format!("adrp {}, {}", reg, symbol));
+    self.ins(format!("add {}, {}, :lo12:{}", reg, reg, symbol));
+  }
+
+  //reg = value, whatever it is
+  fn constant(&mut self, reg: &str, value: Word) {
+    if (-65536..65536).contains(&value) {
+      return self.ins(format!("mov {}, #{}", reg, value));
+    }
+    let mut first = true;
+    for shift in (0..64).step_by(16) {
+      let chunk = (value as u64 >> shift) & 0xffff;
+      if chunk != 0 {
+        let op = if first { "movz" } else { "movk" };
+        self.ins(format!("{} {}, #{}, lsl #{}", op, reg, chunk, shift));
+        first = false;
+      }
+    }
+  }
+}
+
+//strings and globals, and the routine that fills in their word addresses
+fn data(module: &ir::Module, asm: &mut Asm) {
+  let mut fixups: Vec<(String, String)> = Vec::new(); //(word to set, what its address is of)
+  asm.ins(".data");
+  asm.ins(".p2align 3");
+  for (i, s) in module.strings.iter().enumerate() {
+    asm.label(format!(".Lstr{}", i));
+    asm.ins(format!(".quad {}", join(&ir::pack_string(s))));
+  }
+  for global in &module.globals {
+    let name = symbol(&global.name);
+    asm.ins(format!(".globl {}", name));
+    asm.label(&name);
+    //a vector's storage follows its pointer word
+    let first = global.vector.is_some() as usize;
+    let mut words = vec![0; first];
+    if first == 1 {
+      fixups.push((name.clone(), format!("{}+{}", name, WORD_BYTES)));
+    }
+    for (i, init) in global.init.iter().enumerate() {
+      match init {
+        Init::Word(w) => words.push(*w),
+        Init::String(index) => {
+          words.push(0);
+          fixups.push((format!("{}+{}", name, (first + i) as u32 * WORD_BYTES), format!(".Lstr{}", index)));
+        }
+      }
+    }
+    let size = first + global.vector.map_or(1, |storage| storage as usize);
+    words.resize(words.len().max(size), 0);
+    asm.ins(format!(".quad {}", join(&words)));
+  }
+  if fixups.is_empty() {
+    return;
+  }
+  asm.ins(".text");
+  asm.ins(".p2align 3");
+  asm.label(".Linit");
+  for (word, target) in &fixups {
+    asm.address_of("x0", target);
+    asm.ins("lsr x0, x0, #3");
+    asm.address_of("x1", word);
+    asm.ins("str x0, [x1]");
+  }
+  asm.ins("ret");
+  asm.ins(".section .init_array,\"aw\"");
+  asm.ins(".p2align 3");
+  asm.ins(".quad .Linit");
+}
+
+fn join(words: &[Word]) -> String {
+  words.iter().map(|w| w.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+struct FunctionEmitter<'a> {
+  function: &'a Function,
+  name: String,
+  asm: &'a mut Asm,
+  label_targets: FxHashSet<BlockId>, //blocks whose address is taken; they must be aligned
+  outgoing: u32, //words of stack arguments of the widest call
+}
+
+impl<'a> FunctionEmitter<'a> {
+
+  fn new(function: &'a Function, asm: &'a mut Asm) -> Self {
+    let instrs = || function.blocks.iter().flat_map(|b| &b.instrs);
+    let label_targets = instrs().filter_map(|instr| match instr {
+      Instr::LabelAddr { block, .. } => Some(*block),
+      _ => None,
+    }).collect();
+    let outgoing = instrs().filter_map(|instr| match instr {
+      Instr::Call { args, .. } => Some(args.len().saturating_sub(ARG_REGS.len()) as u32),
+      _ => None,
+    }).max().unwrap_or(0);
+    FunctionEmitter { function, name: symbol(&function.name), asm, label_targets, outgoing }
+  }
+
+  fn block_label(&self, block: BlockId) -> String {
+    format!(".L{}.{}", self.name, block.0)
+  }
+
+  //offset from sp of register r's slot
+  fn slot(&self, r: ir::VReg) -> u64 {
+    8 * (self.outgoing as u64 + r.0 as u64)
+  }
+
+  //offset from sp of frame word k
+  fn word(&self, k: u32) -> u64 {
+    8 * (self.outgoing as u64 + self.function.reg_count as u64 + k as u64)
+  }
+
+  //bytes from sp to the saved x29
+  fn frame_bytes(&self) -> u64 {
+    self.word(self.function.frame_size).next_multiple_of(16)
+  }
+
+  //`op reg, [sp, #offset]`, going through x16 for offsets ldr and str cannot encode
+  fn access(&mut self, op: &str, reg: &str, offset: u64) {
+    if offset <= MAX_OFFSET {
+      self.asm.ins(format!("{} {}, [sp, #{}]", op, reg, offset));
+    } else {
+      self.asm.constant("x16", offset as Word);
+      self.asm.ins(format!("{} {}, [sp, x16]", op, reg));
+    }
+  }
+
+  //reg = sp + offset
+  fn sp_plus(&mut self, reg: &str, offset: u64) {
+    if offset < 4096 {
+      self.asm.ins(format!("add {}, sp, #{}", reg, offset));
+    } else {
+      self.asm.constant(reg, offset as Word);
+      self.asm.ins(format!("add {}, sp, {}", reg, reg));
+    }
+  }
+
+  fn load(&mut self, operand: &Operand, reg: &str) {
+    match operand {
+      Operand::Const(c) => self.asm.constant(reg, *c),
+      Operand::Reg(r) => {
+        let slot = self.slot(*r);
+        self.access("ldr", reg, slot);
+      }
+    }
+  }
+
+  fn store(&mut self, dest: ir::VReg) {
+    let slot = self.slot(dest);
+    self.access("str", "x0", slot);
+  }
+
+  fn emit(mut self) {
+    self.asm.ins(".p2align 3");
+    self.asm.ins(format!(".globl {}", self.name));
+    self.asm.ins(format!(".type {}, %function", self.name));
+    self.asm.label(self.name.clone());
+    self.asm.ins("stp x29, x30, [sp, #-16]!");
+    self.asm.ins("mov x29, sp");
+    let bytes = self.frame_bytes();
+    if bytes > 0 {
+      if bytes < 4096 {
+        self.asm.ins(format!("sub sp, sp, #{}", bytes));
+      } else {
+        self.asm.constant("x16", bytes as Word);
+        self.asm.ins("sub sp, sp, x16");
+      }
+    }
+    self.params();
+
+    let f = self.function;
+    for (i, block) in f.blocks.iter().enumerate() {
+      let id = BlockId(i as u32);
+      if self.label_targets.contains(&id) {
+        self.asm.ins(".p2align 3");
+      }
+      self.asm.label(self.block_label(id));
+      for instr in &block.instrs {
+        self.instr(instr);
+      }
+      self.terminator(&block.term, BlockId(id.0 + 1));
+    }
+    self.asm.ins(format!(".size {}, .-{}", self.name, self.name));
+  }
+
+  //copy the arguments into the frame, then zero the params the caller did not pass
+  fn params(&mut self) {
+    let params = self.function.params;
+    if params == 0 {
+      return;
+    }
+    for i in 0..params {
+      let word = self.word(i);
+      match ARG_REGS.get(i as usize) {
+        Some(reg) => self.access("str", reg, word),
+        None => {
+          //stack arguments start above the saved x29 and x30
+          self.asm.ins(format!("ldr x17, [x29, #{}]", 16 + 8 * (i as usize - ARG_REGS.len())));
+          self.access("str", "x17", word);
+        }
+      }
+    }
+    let (zero, done) = (format!(".L{}.zero", self.name), format!(".L{}.args", self.name));
+    let base = self.word(0);
+    self.sp_plus("x17", base);
+    self.asm.label(&zero);
+    self.asm.ins(format!("cmp x9, #{}", params));
+    self.asm.ins(format!("b.ge {}", done));
+    self.asm.ins("str xzr, [x17, x9, lsl #3]");
+    self.asm.ins("add x9, x9, #1");
+    self.asm.ins(format!("b {}", zero));
+    self.asm.label(&done);
+  }
+
+  fn instr(&mut self, instr: &Instr) {
+    match instr {
+      Instr::Copy { dest, src } => {
+        self.load(src, "x0");
+        self.store(*dest);
+      }
+      Instr::Unary { dest, op, src } => {
+        self.load(src, "x0");
+        match op {
+          UnaryOp::Neg => self.asm.ins("neg x0, x0"),
+          UnaryOp::BitNot => self.asm.ins("mvn x0, x0"),
+          UnaryOp::Not => {
+            self.asm.ins("cmp x0, #0");
+            self.asm.ins("cset x0, eq");
+          }
+        }
+        self.store(*dest);
+      }
+      Instr::Binary { dest, op, lhs, rhs } => {
+        self.load(lhs, "x0");
+        self.load(rhs, "x1");
+        self.binary(*op);
+        self.store(*dest);
+      }
+      Instr::Load { dest, addr } => {
+        self.load(addr, "x0");
+        self.asm.ins("lsl x0, x0, #3");
+        self.asm.ins("ldr x0, [x0]");
+        self.store(*dest);
+      }
+      Instr::Store { addr, value } => {
+        self.load(addr, "x0");
+        self.load(value, "x1");
+        self.asm.ins("lsl x0, x0, #3");
+        self.asm.ins("str x1, [x0]");
+      }
+      Instr::LocalAddr { dest, offset } => {
+        let word = self.word(*offset);
+        self.sp_plus("x0", word);
+        self.asm.ins("lsr x0, x0, #3");
+        self.store(*dest);
+      }
+      Instr::GlobalAddr { dest, name } => self.address(*dest, &symbol(name)),
+      Instr::StringAddr { dest, index } => self.address(*dest, &format!(".Lstr{}", index)),
+      Instr::LabelAddr { dest, block } => {
+        let label = self.block_label(*block);
+        self.address(*dest, &label);
+      }
+      Instr::Call { dest, callee, args } => self.call(*dest, callee, args),
+      Instr::Phi { .. } => unreachable!("phis are removed before codegen"),
+    }
+  }
+
+  //the word address of a symbol
+  fn address(&mut self, dest: ir::VReg, symbol: &str) {
+    self.asm.address_of("x0", symbol);
+    self.asm.ins("lsr x0, x0, #3");
+    self.store(dest);
+  }
+
+  //x0 = x0 op x1
+  fn binary(&mut self, op: BinaryOp) {
+    let condition = match op {
+      BinaryOp::Add => return self.asm.ins("add x0, x0, x1"),
+      BinaryOp::Sub => return self.asm.ins("sub x0, x0, x1"),
+      BinaryOp::Mul => return self.asm.ins("mul x0, x0, x1"),
+      BinaryOp::Div => return self.asm.ins("sdiv x0, x0, x1"),
+      BinaryOp::Rem => {
+        self.asm.ins("sdiv x2, x0, x1");
+        self.asm.ins("msub x0, x2, x1, x0");
+        return;
+      }
+      BinaryOp::And => return self.asm.ins("and x0, x0, x1"),
+      BinaryOp::Or => return self.asm.ins("orr x0, x0, x1"),
+      BinaryOp::Xor => return self.asm.ins("eor x0, x0, x1"),
+      BinaryOp::Shl => return self.asm.ins("lsl x0, x0, x1"),
+      BinaryOp::Shr => return self.asm.ins("asr x0, x0, x1"),
+      BinaryOp::Eq => "eq",
+      BinaryOp::Ne => "ne",
+      BinaryOp::Lt => "lt",
+      BinaryOp::Le => "le",
+      BinaryOp::Gt => "gt",
+      BinaryOp::Ge => "ge",
+    };
+    self.asm.ins("cmp x0, x1");
+    self.asm.ins(format!("cset x0, {}", condition));
+  }
+
+  fn call(&mut self, dest: ir::VReg, callee: &Callee, args: &[Operand]) {
+    //stack arguments go in the outgoing area at the bottom of the frame
+    for (i, arg) in args.iter().enumerate().skip(ARG_REGS.len()) {
+      self.load(arg, "x0");
+      self.access("str", "x0", 8 * (i - ARG_REGS.len()) as u64);
+    }
+    for (arg, reg) in args.iter().zip(ARG_REGS) {
+      self.load(arg, reg);
+    }
+    self.asm.constant("x9", args.len() as Word);
+    match callee {
+      Callee::Direct(name) => self.asm.ins(format!("bl {}", symbol(name))),
+      Callee::Indirect(target) => {
+        self.load(target, "x16");
+        self.asm.ins("lsl x16, x16, #3");
+        self.asm.ins("blr x16");
+      }
+    }
+    self.store(dest);
+  }
+
+  fn jump(&mut self, target: BlockId, next: BlockId) {
+    if target != next {
+      let label = self.block_label(target);
+      self.asm.ins(format!("b {}", label));
+    }
+  }
+
+  fn terminator(&mut self, term: &Terminator, next: BlockId) {
+    match term {
+      Terminator::Jump(target) => self.jump(*target, next),
+      Terminator::Branch { cond, then_block, else_block } => {
+        self.load(cond, "x0");
+        let label = self.block_label(*then_block);
+        self.asm.ins(format!("cbnz x0, {}", label));
+        self.jump(*else_block, next);
+      }
+      Terminator::Switch { value, cases, default } => {
+        self.load(value, "x0");
+        for (case, target) in cases {
+          if (0..4096).contains(case) {
+            self.asm.ins(format!("cmp x0, #{}", case));
+          } else {
+            self.asm.constant("x1", *case);
+            self.asm.ins("cmp x0, x1");
+          }
+          let label = self.block_label(*target);
+          self.asm.ins(format!("b.eq {}", label));
+        }
+        self.jump(*default, next);
+      }
+      Terminator::Return(value) => {
+        self.load(value, "x0");
+        self.asm.ins("mov sp, x29");
+        self.asm.ins("ldp x29, x30, [sp], #16");
+        self.asm.ins("ret");
+      }
+      Terminator::IndirectJump { addr, .. } => {
+        self.load(addr, "x0");
+        self.asm.ins("lsl x0, x0, #3");
+        self.asm.ins("br x0");
+      }
+    }
+  }
+}
@@ -0,0 +1,340 @@
+use rustc_hash::FxHashSet;
+
+use crate::ir::{self, BinaryOp, BlockId, Callee, Function, Init, Instr, Operand, Terminator, UnaryOp, Word};
+use crate::ir::ssa::without_phis;
+use crate::libb;
+
+//C code generator: one C99 translation unit per IR module, for anywhere a C compiler exists.
+//
+//A word is an intptr_t, which must be 64 bits. B values are word addresses, so a pointer is the
+//byte address divided by the word size and B_MEM multiplies it back; every object is an intptr_t
+//array, so its address divides exactly. Arithmetic goes through uintptr_t so that it wraps as in
+//the IR instead of overflowing.
+//
+//Every function has the type b_fn, (argc, args) -> word, and copies its arguments into a frame
+//array laid out as in the IR, setting params the caller left out to 0, so `&` works on params and
+//autos. IR registers are locals r0, r1, ...; blocks are labels and jumps are gotos. Function
+//values are function pointers cast to words (functions need not be word aligned), and a label
+//value is its block's number, dispatched by a switch at a computed goto.
+//
+//Symbols get a `b_` prefix, like the assembly backends, so B names never clash with C keywords or
+//the C library. A global vector's pointer word and string initializers are not constants in C, so
+//b_init stores them before main runs.
+
+///The C name of a B global or function.
+pub fn symbol(name: &str) -> String {
+  format!("b_{}", name)
+}
+
+const PRELUDE: &str = "#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef char b_word_is_64_bits[sizeof(intptr_t) == 8 ? 1 : -1];
+typedef intptr_t b_fn(int argc, intptr_t *args);
+
+#define B_ADDR(p) ((intptr_t)(p) / (intptr_t)sizeof(intptr_t))
+#define B_MEM(a) (*(intptr_t *)((uintptr_t)(a) * sizeof(intptr_t)))
+
+static void b_fault(const char *what) {
+  fprintf(stderr, \"b: %s\\n\", what);
+  abort();
+}
+static inline intptr_t b_add(intptr_t a, intptr_t b) { return (intptr_t)((uintptr_t)a + (uintptr_t)b); }
+static inline intptr_t b_sub(intptr_t a, intptr_t b) { return (intptr_t)((uintptr_t)a - (uintptr_t)b); }
+static inline intptr_t b_mul(intptr_t a, intptr_t b) { return (intptr_t)((uintptr_t)a * (uintptr_t)b); }
+static inline intptr_t b_div(intptr_t a, intptr_t b) {
+  if (b == 0) b_fault(\"division by zero\");
+  return b == -1 ? b_sub(0, a) : a / b;
+}
+static inline intptr_t b_rem(intptr_t a, intptr_t b) {
+  if (b == 0) b_fault(\"division by zero\");
+  return b == -1 ? 0 : a % b;
+}
+static inline intptr_t b_shl(intptr_t a, intptr_t b) { return (intptr_t)((uintptr_t)a << (b & 63)); }
+static inline intptr_t b_shr(intptr_t a, intptr_t b) { return a >> (b & 63); }
+";
+
+///A complete translation unit for a module: the prelude, libb if it imports any of it, and its data
+///and functions. It defines the C `main` if the module has a B main, whose result becomes the
+///exit status; other imports are declared and must be linked in.
+pub fn emit(module: &ir::Module) -> String {
+  //libb comes first, as it needs POSIX before any header is included
+  let mut out = String::new();
+  if module.imports.iter().any(|name| libb::provides(name)) {
+    out.push_str(&libb::c::source());
+    out.push('\n');
+  }
+  out.push_str(PRELUDE);
+  for name in &module.imports {
+    if libb::provides(name) {
+      out.push_str(&format!("static intptr_t {}(int argc, intptr_t *args) {{ return libb_{}(argc, args); }}\n", symbol(name), name));
+    } else {
+      out.push_str(&format!("extern intptr_t {}(int argc, intptr_t *args);\n", symbol(name)));
+    }
+  }
+  out.push('\n');
+  for function in &module.functions {
+    out.push_str(&format!("intptr_t {}(int argc, intptr_t *args);\n", symbol(&function.name)));
+  }
+
+  //strings, then globals; the address words are filled in by b_init
+  let mut fixups: Vec<String> = Vec::new();
+  for (i, s) in module.strings.iter().enumerate() {
+    out.push_str(&format!("static intptr_t b_str{}[] = {{ {} }};\n", i, join(&ir::pack_string(s))));
+  }
+  for global in &module.globals {
+    let name = symbol(&global.name);
+    //a vector's storage follows its pointer word
+    let first = global.vector.is_some() as usize;
+    let mut words = vec![0; first];
+    if first == 1 {
+      fixups.push(format!("{}[0] = B_ADDR(&{}[1]);", name, name));
+    }
+    for (i, init) in global.init.iter().enumerate() {
+      match init {
+        Init::Word(w) => words.push(*w),
+        Init::String(index) => {
+          words.push(0);
+          fixups.push(format!("{}[{}] = B_ADDR(b_str{});", name, first + i, index));
+        }
+      }
+    }
+    let size = first + global.vector.map_or(1, |storage| storage as usize);
+    words.resize(words.len().max(size), 0);
+    out.push_str(&format!("intptr_t {}[{}] = {{ {} }};\n", name, words.len(), join(&words)));
+  }
+  if !fixups.is_empty() {
+    out.push_str("\nstatic void b_init(void) {\n");
+    for fixup in &fixups {
+      out.push_str(&format!("  {}\n", fixup));
+    }
+    out.push_str("}\n");
+  }
+
+  let functions: FxHashSet<&str> = module.functions.iter().map(|f| f.name.as_str()).chain(module.imports.iter().map(String::as_str)).collect();
+  for function in &module.functions {
+    out.push('\n');
+    let function = without_phis(function);
+    let mut read = vec![false; function.reg_count as usize];
+    let operands = function.blocks.iter().flat_map(|b| b.instrs.iter().flat_map(|i| i.operands()).chain(b.term.operands()));
+    for operand in operands {
+      if let Operand::Reg(r) = operand {
+        read[r.0 as usize] = true;
+      }
+    }
+    FunctionEmitter { function: &function, functions: &functions, read, out: &mut out }.emit();
+  }
+  if module.function("main").is_some() {
+    out.push_str("\nint main(void) {\n");
+    if !fixups.is_empty() {
+      out.push_str("  b_init();\n");
+    }
+    out.push_str(&format!("  return (int)({}(0, 0) & 0xff);\n}}\n", symbol("main")));
+  }
+  out
+}
+
+fn join(words: &[Word]) -> String {
+  words.iter().map(|&w| literal(w)).collect::<Vec<_>>().join(", ")
+}
+
+//a C integer constant for a word; the most negative one has no literal
+fn literal(w: Word) -> String {
+  if w == Word::MIN {
+    format!("({} - 1)", Word::MIN + 1)
+  } else {
+    w.to_string()
+  }
+}
+
+struct FunctionEmitter<'a> {
+  function: &'a Function,
+  functions: &'a FxHashSet<&'a str>, //names whose address is a function pointer
+  read: Vec<bool>, //by register
+  out: &'a mut String,
+}
+
+impl FunctionEmitter<'_> {
+
+  fn emit(mut self) {
+    let f = self.function;
+    self.out.push_str(&format!("intptr_t {}(int argc, intptr_t *args) {{\n", symbol(&f.name)));
+    //in SSA form a function may not need its frame beyond the params
+    let addressed = f.blocks.iter().flat_map(|b| &b.instrs).any(|i| matches!(i, Instr::LocalAddr { .. }));
+    if f.params > 0 || addressed {
+      self.out.push_str(&format!("  intptr_t frame[{}] = {{ 0 }};\n", f.frame_size));
+    }
+    let regs: Vec<String> = (0..f.reg_count).filter(|&r| self.read[r as usize]).map(|r| format!("r{}", r)).collect();
+    if !regs.is_empty() {
+      self.out.push_str(&format!("  intptr_t {};\n", regs.join(", ")));
+    }
+    for i in 0..f.params {
+      self.out.push_str(&format!("  frame[{}] = argc > {} ? args[{}] : 0;\n", i, i, i));
+    }
+    if f.params == 0 {
+      self.out.push_str("  (void)argc; (void)args;\n");
+    }
+
+    //only blocks a goto names need a label; falling into the next block needs none
+    let mut targets: FxHashSet<BlockId> = FxHashSet::default();
+    for (i, block) in f.blocks.iter().enumerate() {
+      //with whether the last of them is left out when it is the next block
+      let (mut gotos, falls) = match &block.term {
+        Terminator::Jump(target) => (vec![*target], true),
+        Terminator::Branch { then_block, else_block, .. } => (vec![*then_block, *else_block], true),
+        Terminator::Switch { cases, default, .. } => (cases.iter().map(|&(_, b)| b).chain([*default]).collect(), true),
+        Terminator::Return(_) => (Vec::new(), false),
+        Terminator::IndirectJump { targets, .. } => (targets.clone(), false),
+      };
+      if falls && gotos.last() == Some(&BlockId(i as u32 + 1)) {
+        gotos.pop();
+      }
+      targets.extend(gotos);
+    }
+    for (i, block) in f.blocks.iter().enumerate() {
+      let id = BlockId(i as u32);
+      if targets.contains(&id) {
+        self.out.push_str(&format!("bb{}:\n", i));
+      }
+      for instr in &block.instrs {
+        self.instr(instr);
+      }
+      self.terminator(&block.term, BlockId(id.0 + 1));
+    }
+    self.out.push_str("}\n");
+  }
+
+  //registers nothing reads are not declared, so their values are discarded
+  fn assign(&mut self, dest: ir::VReg, value: String) {
+    match self.read[dest.0 as usize] {
+      true => self.line(format!("r{} = {};", dest.0, value)),
+      false => self.line(format!("(void){};", value)),
+    }
+  }
+
+  fn line(&mut self, text: String) {
+    self.out.push_str("  ");
+    self.out.push_str(&text);
+    self.out.push('\n');
+  }
+
+  fn operand(&self, operand: &Operand) -> String {
+    match operand {
+      Operand::Reg(r) => format!("r{}", r.0),
+      Operand::Const(c) => literal(*c),
+    }
+  }
+
+  fn instr(&mut self, instr: &Instr) {
+    match instr {
+      Instr::Copy { dest, src } => self.assign(*dest, self.operand(src)),
+      Instr::Unary { dest, op, src } => {
+        let src = self.operand(src);
+        let value = match op {
+          UnaryOp::Neg => format!("b_sub(0, {})", src),
+          UnaryOp::Not => format!("!{}", src),
+          UnaryOp::BitNot => format!("~{}", src),
+        };
+        self.assign(*dest, value);
+      }
+      Instr::Binary { dest, op, lhs, rhs } => {
+        let (l, r) = (self.operand(lhs), self.operand(rhs));
+        let value = match binary(*op) {
+          Ok(function) => format!("{}({}, {})", function, l, r),
+          Err(operator) => format!("(intptr_t)({} {} {})", l, operator, r),
+        };
+        self.assign(*dest, value);
+      }
+      Instr::Load { dest, addr } => self.assign(*dest, format!("B_MEM({})", self.operand(addr))),
+      Instr::Store { addr, value } => self.line(format!("B_MEM({}) = {};", self.operand(addr), self.operand(value))),
+      Instr::LocalAddr { dest, offset } => self.assign(*dest, format!("B_ADDR(&frame[{}])", offset)),
+      Instr::GlobalAddr { dest, name } => {
+        let value = if self.functions.contains(name.as_str()) {
+          format!("(intptr_t){}", symbol(name))
+        } else {
+          format!("B_ADDR({})", symbol(name))
+        };
+        self.assign(*dest, value);
+      }
+      Instr::StringAddr { dest, index } => self.assign(*dest, format!("B_ADDR(b_str{})", index)),
+      Instr::LabelAddr { dest, block } => self.assign(*dest, block.0.to_string()),
+      Instr::Call { dest, callee, args } => {
+        let args = if args.is_empty() {
+          "0, 0".to_string()
+        } else {
+          let args: Vec<String> = args.iter().map(|a| self.operand(a)).collect();
+          format!("{}, (intptr_t[]){{ {} }}", args.len(), args.join(", "))
+        };
+        let function = match callee {
+          Callee::Direct(name) if self.functions.contains(name.as_str()) => symbol(name),
+          Callee::Direct(_) => "((b_fn *)0)".to_string(),
+          Callee::Indirect(target) => format!("((b_fn *){})", self.operand(target)),
+        };
+        //a call's result is often unused, and then it is a statement of its own
+        let call = format!("{}({})", function, args);
+        match self.read[dest.0 as usize] {
+          true => self.line(format!("r{} = {};", dest.0, call)),
+          false => self.line(format!("{};", call)),
+        }
+      }
+      Instr::Phi { .. } => unreachable!("phis are removed before codegen"),
+    }
+  }
+
+  fn jump(&mut self, target: BlockId, next: BlockId) {
+    if target != next {
+      self.line(format!("goto bb{};", target.0));
+    }
+  }
+
+  fn terminator(&mut self, term: &Terminator, next: BlockId) {
+    match term {
+      Terminator::Jump(target) => self.jump(*target, next),
+      Terminator::Branch { cond, then_block, else_block } => {
+        self.line(format!("if ({}) goto bb{};", self.operand(cond), then_block.0));
+        self.jump(*else_block, next);
+      }
+      Terminator::Switch { value, cases, default } => {
+        self.line(format!("switch ({}) {{", self.operand(value)));
+        for (case, target) in cases {
+          self.line(format!("case {}: goto bb{};", literal(*case), target.0));
+        }
+        self.line("}".to_string());
+        self.jump(*default, next);
+      }
+      Terminator::Return(value) => self.line(format!("return {};", self.operand(value))),
+      Terminator::IndirectJump { addr, targets } => {
+        self.line(format!("switch ({}) {{", self.operand(addr)));
+        for target in targets {
+          self.line(format!("case {}: goto bb{};", target.0, target.0));
+        }
+        self.line("}".to_string());
+        self.line("b_fault(\"goto to a value that is not a label\");".to_string());
+        self.line("return 0;".to_string());
+      }
+    }
+  }
+}
+
+//the prelude function for an operation that must wrap or check, or else the C operator
+fn binary(op: BinaryOp) -> Result<&'static str, &'static str> {
+  match op {
+    BinaryOp::Add => Ok("b_add"),
+    BinaryOp::Sub => Ok("b_sub"),
+    BinaryOp::Mul => Ok("b_mul"),
+    BinaryOp::Div => Ok("b_div"),
+    BinaryOp::Rem => Ok("b_rem"),
+    BinaryOp::Shl => Ok("b_shl"),
+    BinaryOp::Shr => Ok("b_shr"),
+    BinaryOp::And => Err("&"),
+    BinaryOp::Or => Err("|"),
+    BinaryOp::Xor => Err("^"),
+    BinaryOp::Eq => Err("=="),
+    BinaryOp::Ne => Err("!="),
+    BinaryOp::Lt => Err("<"),
+    BinaryOp::Le => Err("<="),
+    BinaryOp::Gt => Err(">"),
+    BinaryOp::Ge => Err(">="),
+  }
+}
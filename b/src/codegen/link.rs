@@ -0,0 +1,97 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::libb;
+
+//the driver step from assembly to an executable. A C compiler (by default `cc`, or $CC) assembles
+//the files, compiles libb::c beside them, and links them with the C runtime and library, which
+//libb calls into.
+
+#[derive(Debug)]
+pub enum LinkError {
+  Io(io::Error), //writing the temporary files, or starting the tool
+  Failed { command: String, stderr: String }, //the tool ran and reported an error
+}
+
+impl fmt::Display for LinkError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LinkError::Io(err) => write!(f, "cannot run the linker: {}", err),
+      LinkError::Failed { command, stderr } => write!(f, "`{}` failed:\n{}", command, stderr.trim_end()),
+    }
+  }
+}
+
+impl std::error::Error for LinkError {}
+
+impl From<io::Error> for LinkError {
+  fn from(err: io::Error) -> Self {
+    LinkError::Io(err)
+  }
+}
+
+///Assemble `sources`, each the text of one assembly file, and link them with libb into the executable
+///`output`.
+pub fn link(sources: &[&str], output: &Path) -> Result<(), LinkError> {
+  let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+  link_with(&cc, sources, output)
+}
+
+///Link with the C compiler `cc`, such as a cross compiler for another target.
+pub fn link_with(cc: &str, sources: &[&str], output: &Path) -> Result<(), LinkError> {
+  let dir = scratch_dir()?;
+  let result = link_in(cc, &dir, sources, output);
+  let _ = fs::remove_dir_all(&dir);
+  result
+}
+
+///Assemble one assembly file into the object file `output` with `cc`, without linking libb or
+///anything else.
+pub fn assemble_with(cc: &str, source: &str, output: &Path) -> Result<(), LinkError> {
+  let dir = scratch_dir()?;
+  let result = write_sources(&dir, &[source]).and_then(|files| run(cc, Command::new(cc).arg("-c").arg("-o").arg(output).args(&files)));
+  let _ = fs::remove_dir_all(&dir);
+  result
+}
+
+fn link_in(cc: &str, dir: &Path, sources: &[&str], output: &Path) -> Result<(), LinkError> {
+  let mut files = write_sources(dir, sources)?;
+  let lib = dir.join("libb.c");
+  fs::write(&lib, libb::c::source())?;
+  files.push(lib);
+  run(cc, Command::new(cc).arg("-o").arg(output).args(&files))
+}
+
+//each source as a numbered .s file in `dir`
+fn write_sources(dir: &Path, sources: &[&str]) -> Result<Vec<PathBuf>, LinkError> {
+  let mut files = Vec::new();
+  for (i, source) in sources.iter().enumerate() {
+    let file = dir.join(format!("{}.s", i));
+    fs::write(&file, source)?;
+    files.push(file);
+  }
+  Ok(files)
+}
+
+fn run(cc: &str, command: &mut Command) -> Result<(), LinkError> {
+  let out = command.output()?;
+  if out.status.success() {
+    Ok(())
+  } else {
+    let args: Vec<String> = command.get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+    Err(LinkError::Failed { command: format!("{} {}", cc, args.join(" ")), stderr: String::from_utf8_lossy(&out.stderr).into_owned() })
+  }
+}
+
+//a fresh directory for one link, unique across threads and processes
+fn scratch_dir() -> io::Result<PathBuf> {
+  static NEXT: AtomicUsize = AtomicUsize::new(0);
+  let dir = env::temp_dir().join(format!("b-link-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+  fs::create_dir_all(&dir)?;
+  Ok(dir)
+}
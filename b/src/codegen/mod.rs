@@ -0,0 +1,10 @@
+pub mod x86_64;
+pub mod aarch64;
+pub mod wasm;
+pub mod c;
+pub mod link;
+
+//code generation from the IR. x86_64 and aarch64 turn an ir::Module into the text of one assembly
+//file, and link::link assembles and links those with the target's runtime into an executable. wasm
+//turns it into a complete WebAssembly module, run by a host that provides the runtime, and c into a
+//C99 translation unit carrying its own runtime.
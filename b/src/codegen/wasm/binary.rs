@@ -0,0 +1,237 @@
+use super::{ExportKind, Instr, Module, ValType};
+
+//the WebAssembly binary format, version 1. Sections are written in the order the format requires,
+//each as its id and its byte length followed by the content; integers are LEB128, unsigned for
+//counts and indexes and signed for constants.
+
+///The first bytes of every module: `\0asm` and the version.
+pub const HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+const FUNC_TYPE: u8 = 0x60;
+const FUNCREF: u8 = 0x70;
+const EMPTY_BLOCK: u8 = 0x40;
+const LOG2_WORD: u32 = 3; //alignment of every load and store
+
+pub fn encode(module: &Module) -> Vec<u8> {
+  let mut out = HEADER.to_vec();
+
+  let mut w = Writer::default();
+  w.len(module.types.len());
+  for ty in &module.types {
+    w.byte(FUNC_TYPE);
+    w.len(ty.params.len());
+    ty.params.iter().for_each(|&t| w.val_type(t));
+    w.len(ty.results.len());
+    ty.results.iter().for_each(|&t| w.val_type(t));
+  }
+  section(&mut out, 1, w);
+
+  let mut w = Writer::default();
+  w.len(module.imports.len());
+  for import in &module.imports {
+    w.name(&import.module);
+    w.name(&import.name);
+    w.byte(0x00); //a function
+    w.u(import.type_index);
+  }
+  section(&mut out, 2, w);
+
+  let mut w = Writer::default();
+  w.len(module.functions.len());
+  module.functions.iter().for_each(|f| w.u(f.type_index));
+  section(&mut out, 3, w);
+
+  let mut w = Writer::default();
+  w.len(1);
+  w.byte(FUNCREF);
+  w.byte(0x00); //a minimum and no maximum
+  w.len(module.table.len() + 1);
+  section(&mut out, 4, w);
+
+  let mut w = Writer::default();
+  w.len(1);
+  w.byte(0x00);
+  w.u(module.memory_pages);
+  section(&mut out, 5, w);
+
+  let mut w = Writer::default();
+  w.len(module.globals.len());
+  for global in &module.globals {
+    w.val_type(global.ty);
+    w.byte(global.mutable as u8);
+    w.instr(&global.init);
+    w.instr(&Instr::End);
+  }
+  section(&mut out, 6, w);
+
+  let mut w = Writer::default();
+  w.len(module.exports.len());
+  for export in &module.exports {
+    w.name(&export.name);
+    w.byte(match export.kind {
+      ExportKind::Func => 0x00,
+      ExportKind::Memory => 0x02,
+    });
+    w.u(export.index);
+  }
+  section(&mut out, 7, w);
+
+  if !module.table.is_empty() {
+    let mut w = Writer::default();
+    w.len(1);
+    w.byte(0x00); //active, in table 0, function indexes
+    w.instr(&Instr::I32Const(1));
+    w.instr(&Instr::End);
+    w.len(module.table.len());
+    module.table.iter().for_each(|&f| w.u(f));
+    section(&mut out, 9, w);
+  }
+
+  let mut w = Writer::default();
+  w.len(module.functions.len());
+  for f in &module.functions {
+    let mut body = Writer::default();
+    body.len(f.locals.len());
+    for &(count, ty) in &f.locals {
+      body.u(count);
+      body.val_type(ty);
+    }
+    f.body.iter().for_each(|instr| body.instr(instr));
+    body.instr(&Instr::End);
+    w.len(body.out.len());
+    w.out.extend(body.out);
+  }
+  section(&mut out, 10, w);
+
+  if !module.data.is_empty() {
+    let mut w = Writer::default();
+    w.len(1);
+    w.byte(0x00); //active, in memory 0
+    w.instr(&Instr::I32Const(module.data_offset as i32));
+    w.instr(&Instr::End);
+    w.len(module.data.len());
+    w.out.extend(&module.data);
+    section(&mut out, 11, w);
+  }
+  out
+}
+
+fn section(out: &mut Vec<u8>, id: u8, content: Writer) {
+  let mut w = Writer { out: vec![id] };
+  w.len(content.out.len());
+  out.extend(w.out);
+  out.extend(content.out);
+}
+
+#[derive(Default)]
+struct Writer {
+  out: Vec<u8>,
+}
+
+impl Writer {
+  fn byte(&mut self, b: u8) {
+    self.out.push(b);
+  }
+
+  fn u(&mut self, mut value: u32) {
+    loop {
+      let byte = (value & 0x7f) as u8;
+      value >>= 7;
+      if value == 0 {
+        self.out.push(byte);
+        return;
+      }
+      self.out.push(byte | 0x80);
+    }
+  }
+
+  fn s(&mut self, mut value: i64) {
+    loop {
+      let byte = (value & 0x7f) as u8;
+      value >>= 7;
+      //done once the rest is all sign bits and the sign bit of this byte agrees
+      if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+        self.out.push(byte);
+        return;
+      }
+      self.out.push(byte | 0x80);
+    }
+  }
+
+  fn len(&mut self, len: usize) {
+    self.u(len as u32);
+  }
+
+  fn name(&mut self, name: &str) {
+    self.len(name.len());
+    self.out.extend(name.as_bytes());
+  }
+
+  fn val_type(&mut self, ty: ValType) {
+    self.byte(match ty {
+      ValType::I32 => 0x7f,
+      ValType::I64 => 0x7e,
+    });
+  }
+
+  fn instr(&mut self, instr: &Instr) {
+    match instr {
+      Instr::Unreachable => self.byte(0x00),
+      Instr::Block => self.out.extend([0x02, EMPTY_BLOCK]),
+      Instr::Loop => self.out.extend([0x03, EMPTY_BLOCK]),
+      Instr::If => self.out.extend([0x04, EMPTY_BLOCK]),
+      Instr::Else => self.byte(0x05),
+      Instr::End => self.byte(0x0b),
+      Instr::Br(depth) => {
+        self.byte(0x0c);
+        self.u(*depth);
+      }
+      Instr::BrTable { targets, default } => {
+        self.byte(0x0e);
+        self.len(targets.len());
+        targets.iter().for_each(|&t| self.u(t));
+        self.u(*default);
+      }
+      Instr::Return => self.byte(0x0f),
+      Instr::Call(f) => {
+        self.byte(0x10);
+        self.u(*f);
+      }
+      Instr::CallIndirect(ty) => {
+        self.byte(0x11);
+        self.u(*ty);
+        self.byte(0x00); //table 0
+      }
+      Instr::Select => self.byte(0x1b),
+      Instr::LocalGet(i) => self.index(0x20, *i),
+      Instr::LocalSet(i) => self.index(0x21, *i),
+      Instr::LocalTee(i) => self.index(0x22, *i),
+      Instr::GlobalGet(i) => self.index(0x23, *i),
+      Instr::GlobalSet(i) => self.index(0x24, *i),
+      Instr::I64Load(offset) => {
+        self.byte(0x29);
+        self.u(LOG2_WORD);
+        self.u(*offset);
+      }
+      Instr::I64Store(offset) => {
+        self.byte(0x37);
+        self.u(LOG2_WORD);
+        self.u(*offset);
+      }
+      Instr::I32Const(c) => {
+        self.byte(0x41);
+        self.s(*c as i64);
+      }
+      Instr::I64Const(c) => {
+        self.byte(0x42);
+        self.s(*c);
+      }
+      Instr::Numeric(op) => self.byte(op.opcode()),
+    }
+  }
+
+  fn index(&mut self, opcode: u8, index: u32) {
+    self.byte(opcode);
+    self.u(index);
+  }
+}
@@ -0,0 +1,466 @@
+use rustc_hash::FxHashMap;
+
+use crate::ir::{self, pack_string, BinaryOp, BlockId, Callee, Init, Operand, Terminator, UnaryOp, Word, WORD_BYTES};
+use crate::ir::ssa::without_phis;
+use crate::lexer::token::STRING_TERMINATOR;
+use crate::libb;
+use super::{Export, ExportKind, Func, FuncType, Global, Import, Instr, Module, Numeric, ValType, HOST, PAGE_BYTES, STACK_BYTES};
+
+//IR to a wasm module. Locals of a B function: the params args and argc, then frame (the frame's
+//byte address) and pc (the block to run next), then one i64 per IR register.
+
+const B_TYPE: u32 = 0; //(args: i32, argc: i32) -> i64
+const PUTCHAR_TYPE: u32 = 1; //(i32) -> ()
+const GETCHAR_TYPE: u32 = 2; //() -> i32
+const MAIN_TYPE: u32 = 3; //() -> i64
+
+const ARGS: u32 = 0;
+const ARGC: u32 = 1;
+const FRAME: u32 = 2;
+const PC: u32 = 3;
+const FIRST_REG: u32 = 4;
+
+const SP: u32 = 0; //the one global
+
+pub fn compile(module: &ir::Module) -> Module {
+  let module = &libb::b::link(module);
+  let mut data: Vec<Word> = Vec::new(); //data[i] is at word address i + 1
+  let strings: Vec<Word> = module.strings.iter().map(|s| {
+    let addr = data.len() as Word + 1;
+    data.extend(pack_string(s));
+    addr
+  }).collect();
+  let mut symbols: FxHashMap<&str, Word> = FxHashMap::default();
+  for global in &module.globals {
+    let addr = data.len() as Word + 1;
+    let mut init: Vec<Word> = global.init.iter().map(|init| match init {
+      Init::Word(w) => *w,
+      Init::String(index) => strings[*index as usize],
+    }).collect();
+    match global.vector {
+      Some(words) => {
+        data.push(addr + 1);
+        init.resize(init.len().max(words as usize), 0);
+      }
+      None => init.resize(init.len().max(1), 0),
+    }
+    data.extend(init);
+    symbols.insert(&global.name, addr);
+  }
+
+  //imports, then wrappers for the byte-sized host functions, then the B functions
+  let mut imports = Vec::new();
+  let mut wrapped = Vec::new();
+  for name in &module.imports {
+    let type_index = match name.as_str() {
+      "putchar" => PUTCHAR_TYPE,
+      "getchar" => GETCHAR_TYPE,
+      _ => B_TYPE,
+    };
+    if type_index != B_TYPE {
+      wrapped.push((name.as_str(), imports.len() as u32));
+    }
+    imports.push(Import { module: HOST.to_string(), name: name.clone(), type_index });
+  }
+  let first_function = imports.len() as u32 + wrapped.len() as u32;
+  let mut callable: Vec<(&str, u32)> = Vec::new(); //in function index order
+  for (i, import) in imports.iter().enumerate() {
+    if import.type_index == B_TYPE {
+      callable.push((&import.name, i as u32));
+    }
+  }
+  for (i, (name, _)) in wrapped.iter().enumerate() {
+    callable.push((name, imports.len() as u32 + i as u32));
+  }
+  for (i, f) in module.functions.iter().enumerate() {
+    callable.push((&f.name, first_function + i as u32));
+  }
+  let calls: FxHashMap<&str, u32> = callable.iter().copied().collect();
+  //every callable function has a table slot, numbered from 1, and that is its value
+  for (slot, (name, _)) in callable.iter().enumerate() {
+    symbols.insert(name, slot as Word + 1);
+  }
+  let table = callable.iter().map(|(_, function)| *function).collect();
+
+  let stack_limit = (data.len() as u32 + 1) * WORD_BYTES;
+  let memory_pages = (stack_limit + STACK_BYTES).div_ceil(PAGE_BYTES);
+  let mut functions: Vec<Func> = wrapped.iter().map(|(name, host)| match *name {
+    "putchar" => putchar(*host),
+    _ => getchar(*host),
+  }).collect();
+  for f in &module.functions {
+    let f = without_phis(f);
+    let mut compiler = FunctionCompiler { symbols: &symbols, calls: &calls, strings: &strings, function: &f, stack_limit, body: Vec::new() };
+    compiler.compile();
+    functions.push(Func {
+      name: f.name.clone(),
+      type_index: B_TYPE,
+      locals: vec![(2, ValType::I32), (f.reg_count, ValType::I64)],
+      body: compiler.body,
+    });
+  }
+
+  let mut exports = vec![Export { name: "memory".to_string(), kind: ExportKind::Memory, index: 0 }];
+  if let Some(&main) = calls.get("main")
+    && main >= first_function {
+    let index = imports.len() as u32 + functions.len() as u32;
+    functions.push(Func {
+      name: "export.main".to_string(),
+      type_index: MAIN_TYPE,
+      locals: Vec::new(),
+      body: vec![Instr::GlobalGet(SP), Instr::I32Const(0), Instr::Call(main)],
+    });
+    exports.push(Export { name: "main".to_string(), kind: ExportKind::Func, index });
+  }
+
+  let i32_i32 = vec![ValType::I32, ValType::I32];
+  Module {
+    types: vec![
+      FuncType { params: i32_i32, results: vec![ValType::I64] },
+      FuncType { params: vec![ValType::I32], results: Vec::new() },
+      FuncType { params: Vec::new(), results: vec![ValType::I32] },
+      FuncType { params: Vec::new(), results: vec![ValType::I64] },
+    ],
+    imports,
+    functions,
+    table,
+    memory_pages,
+    globals: vec![Global { name: "sp".to_string(), ty: ValType::I32, mutable: true, init: Instr::I32Const((memory_pages * PAGE_BYTES) as i32) }],
+    exports,
+    data_offset: WORD_BYTES,
+    data: data.iter().flat_map(|w| w.to_le_bytes()).collect(),
+  }
+}
+
+//putchar(c) writes the non-zero bytes of c, most significant first, and returns c
+fn putchar(host: u32) -> Func {
+  let (c, byte) = (2, 3);
+  let mut body = vec![
+    Instr::LocalGet(ARGC),
+    Instr::Numeric(Numeric::I32Eqz),
+    Instr::If,
+    Instr::I64Const(0),
+    Instr::Return,
+    Instr::End,
+    Instr::LocalGet(ARGS),
+    Instr::I64Load(0),
+    Instr::LocalSet(c),
+  ];
+  for shift in (0..8).rev() {
+    body.extend([
+      Instr::LocalGet(c),
+      Instr::I64Const(shift * 8),
+      Instr::Numeric(Numeric::I64ShrU),
+      Instr::I64Const(0xff),
+      Instr::Numeric(Numeric::I64And),
+      Instr::Numeric(Numeric::I32WrapI64),
+      Instr::LocalTee(byte),
+      Instr::If,
+      Instr::LocalGet(byte),
+      Instr::Call(host),
+      Instr::End,
+    ]);
+  }
+  body.push(Instr::LocalGet(c));
+  Func { name: "putchar".to_string(), type_index: B_TYPE, locals: vec![(1, ValType::I64), (1, ValType::I32)], body }
+}
+
+//getchar() returns the next byte of input, or `*e` after the last
+fn getchar(host: u32) -> Func {
+  let byte = 2;
+  let body = vec![
+    Instr::Call(host),
+    Instr::LocalTee(byte),
+    Instr::Numeric(Numeric::I64ExtendI32U),
+    Instr::I64Const(STRING_TERMINATOR as Word),
+    Instr::LocalGet(byte),
+    Instr::I32Const(0),
+    Instr::Numeric(Numeric::I32GeS),
+    Instr::Select,
+  ];
+  Func { name: "getchar".to_string(), type_index: B_TYPE, locals: vec![(1, ValType::I32)], body }
+}
+
+struct FunctionCompiler<'a> {
+  symbols: &'a FxHashMap<&'a str, Word>,
+  calls: &'a FxHashMap<&'a str, u32>,
+  strings: &'a [Word],
+  function: &'a ir::Function,
+  stack_limit: u32, //byte address of the end of static data
+  body: Vec<Instr>,
+}
+
+impl FunctionCompiler<'_> {
+
+  fn compile(&mut self) {
+    self.prologue();
+    //blocks are dispatched on pc: a loop holding one wasm block per IR block, innermost first,
+    //with the br_table in the innermost. Block i's code follows the end of its wasm block, so it
+    //falls through into block i + 1 and branching to the loop from it crosses n - 1 - i blocks.
+    let n = self.function.blocks.len() as u32;
+    self.body.push(Instr::Loop);
+    for _ in 0..n + 1 {
+      self.body.push(Instr::Block);
+    }
+    self.body.push(Instr::LocalGet(PC));
+    self.body.push(Instr::BrTable { targets: (1..=n).collect(), default: 0 });
+    self.body.push(Instr::End);
+    self.body.push(Instr::Unreachable); //pc is not a block of the function
+    for (i, block) in self.function.blocks.iter().enumerate() {
+      self.body.push(Instr::End);
+      for instr in &block.instrs {
+        self.instr(instr);
+      }
+      let dispatch = n - 1 - i as u32;
+      self.terminator(&block.term, BlockId(i as u32 + 1), dispatch);
+    }
+    self.body.push(Instr::End);
+    self.body.push(Instr::Unreachable);
+  }
+
+  //allocate the frame, trapping if it would reach static data, and copy the arguments into it
+  fn prologue(&mut self) {
+    let f = self.function;
+    self.body.extend([
+      Instr::GlobalGet(SP),
+      Instr::I32Const((f.frame_size * WORD_BYTES) as i32),
+      Instr::Numeric(Numeric::I32Sub),
+      Instr::LocalTee(FRAME),
+      Instr::GlobalSet(SP),
+      Instr::LocalGet(FRAME),
+      Instr::I32Const(self.stack_limit as i32),
+      Instr::Numeric(Numeric::I32LtS),
+      Instr::If,
+      Instr::Unreachable,
+      Instr::End,
+    ]);
+    for i in 0..f.params {
+      let offset = i * WORD_BYTES;
+      self.body.extend([
+        Instr::LocalGet(ARGC),
+        Instr::I32Const(i as i32),
+        Instr::Numeric(Numeric::I32GtU),
+        Instr::If,
+        Instr::LocalGet(FRAME),
+        Instr::LocalGet(ARGS),
+        Instr::I64Load(offset),
+        Instr::I64Store(offset),
+        Instr::Else,
+        Instr::LocalGet(FRAME),
+        Instr::I64Const(0),
+        Instr::I64Store(offset),
+        Instr::End,
+      ]);
+    }
+  }
+
+  fn operand(&mut self, operand: &Operand) {
+    self.body.push(match operand {
+      Operand::Reg(r) => Instr::LocalGet(FIRST_REG + r.0),
+      Operand::Const(c) => Instr::I64Const(*c),
+    });
+  }
+
+  fn set(&mut self, dest: ir::VReg) {
+    self.body.push(Instr::LocalSet(FIRST_REG + dest.0));
+  }
+
+  //the byte address of a word address
+  fn address(&mut self, addr: &Operand) {
+    self.operand(addr);
+    self.body.extend([Instr::Numeric(Numeric::I32WrapI64), Instr::I32Const(3), Instr::Numeric(Numeric::I32Shl)]);
+  }
+
+  fn instr(&mut self, instr: &ir::Instr) {
+    match instr {
+      ir::Instr::Copy { dest, src } => {
+        self.operand(src);
+        self.set(*dest);
+      }
+      ir::Instr::Unary { dest, op, src } => {
+        match op {
+          UnaryOp::Neg => {
+            self.body.push(Instr::I64Const(0));
+            self.operand(src);
+            self.body.push(Instr::Numeric(Numeric::I64Sub));
+          }
+          UnaryOp::Not => {
+            self.operand(src);
+            self.body.extend([Instr::Numeric(Numeric::I64Eqz), Instr::Numeric(Numeric::I64ExtendI32U)]);
+          }
+          UnaryOp::BitNot => {
+            self.operand(src);
+            self.body.extend([Instr::I64Const(-1), Instr::Numeric(Numeric::I64Xor)]);
+          }
+        }
+        self.set(*dest);
+      }
+      //i64.div_s traps on the one quotient that overflows, min / -1, so dividing by -1 negates and
+      //wraps like the IR. i64.rem_s already gives 0 there
+      ir::Instr::Binary { dest, op: BinaryOp::Div, lhs, rhs } if !matches!(rhs, Operand::Const(c) if *c != -1) => {
+        self.operand(rhs);
+        self.body.extend([Instr::I64Const(-1), Instr::Numeric(Numeric::I64Eq), Instr::If, Instr::I64Const(0)]);
+        self.operand(lhs);
+        self.body.push(Instr::Numeric(Numeric::I64Sub));
+        self.set(*dest);
+        self.body.push(Instr::Else);
+        self.operand(lhs);
+        self.operand(rhs);
+        self.body.push(Instr::Numeric(Numeric::I64DivS));
+        self.set(*dest);
+        self.body.push(Instr::End);
+      }
+      ir::Instr::Binary { dest, op, lhs, rhs } => {
+        self.operand(lhs);
+        self.operand(rhs);
+        let (numeric, compare) = binary(*op);
+        self.body.push(Instr::Numeric(numeric));
+        if compare {
+          self.body.push(Instr::Numeric(Numeric::I64ExtendI32U));
+        }
+        self.set(*dest);
+      }
+      ir::Instr::Load { dest, addr } => {
+        self.address(addr);
+        self.body.push(Instr::I64Load(0));
+        self.set(*dest);
+      }
+      ir::Instr::Store { addr, value } => {
+        self.address(addr);
+        self.operand(value);
+        self.body.push(Instr::I64Store(0));
+      }
+      ir::Instr::LocalAddr { dest, offset } => {
+        self.body.extend([
+          Instr::LocalGet(FRAME),
+          Instr::I32Const(3),
+          Instr::Numeric(Numeric::I32ShrU),
+          Instr::Numeric(Numeric::I64ExtendI32U),
+        ]);
+        if *offset > 0 {
+          self.body.extend([Instr::I64Const(*offset as i64), Instr::Numeric(Numeric::I64Add)]);
+        }
+        self.set(*dest);
+      }
+      ir::Instr::GlobalAddr { dest, name } => {
+        //lowering only names symbols it has seen, so 0 never runs
+        let addr = self.symbols.get(name.as_str()).copied().unwrap_or(0);
+        self.body.push(Instr::I64Const(addr));
+        self.set(*dest);
+      }
+      ir::Instr::StringAddr { dest, index } => {
+        self.body.push(Instr::I64Const(self.strings[*index as usize]));
+        self.set(*dest);
+      }
+      ir::Instr::LabelAddr { dest, block } => {
+        self.body.push(Instr::I64Const(block.0 as i64));
+        self.set(*dest);
+      }
+      ir::Instr::Call { dest, callee, args } => {
+        self.call(callee, args);
+        self.set(*dest);
+      }
+      ir::Instr::Phi { .. } => unreachable!("phis are removed before codegen"),
+    }
+  }
+
+  //arguments go in the words below the frame, which $sp points at for the call
+  fn call(&mut self, callee: &Callee, args: &[Operand]) {
+    let bytes = args.len() as u32 * WORD_BYTES;
+    if bytes > 0 {
+      self.body.extend([Instr::LocalGet(FRAME), Instr::I32Const(bytes as i32), Instr::Numeric(Numeric::I32Sub), Instr::GlobalSet(SP)]);
+      for (i, arg) in args.iter().enumerate() {
+        self.body.push(Instr::GlobalGet(SP));
+        self.operand(arg);
+        self.body.push(Instr::I64Store(i as u32 * WORD_BYTES));
+      }
+    }
+    self.body.extend([Instr::GlobalGet(SP), Instr::I32Const(args.len() as i32)]);
+    match callee {
+      Callee::Direct(name) => match self.calls.get(name.as_str()) {
+        Some(&function) => self.body.push(Instr::Call(function)),
+        None => self.body.extend([Instr::I32Const(0), Instr::CallIndirect(B_TYPE)]),
+      },
+      Callee::Indirect(target) => {
+        self.operand(target);
+        self.body.extend([Instr::Numeric(Numeric::I32WrapI64), Instr::CallIndirect(B_TYPE)]);
+      }
+    }
+    if bytes > 0 {
+      self.body.extend([Instr::LocalGet(FRAME), Instr::GlobalSet(SP)]);
+    }
+  }
+
+  //`dispatch` is the depth of the loop; None for `next` means there is no falling through
+  fn jump(&mut self, target: BlockId, next: Option<BlockId>, dispatch: u32) {
+    if Some(target) != next {
+      self.body.extend([Instr::I32Const(target.0 as i32), Instr::LocalSet(PC), Instr::Br(dispatch)]);
+    }
+  }
+
+  fn terminator(&mut self, term: &Terminator, next: BlockId, dispatch: u32) {
+    match term {
+      Terminator::Jump(target) => self.jump(*target, Some(next), dispatch),
+      Terminator::Branch { cond, then_block, else_block } => {
+        //branch to whichever target does not follow, then fall through or jump to the other
+        self.operand(cond);
+        self.body.push(Instr::Numeric(Numeric::I64Eqz));
+        let (taken, other) = if *then_block == next {
+          (*else_block, *then_block)
+        } else {
+          self.body.push(Instr::Numeric(Numeric::I32Eqz));
+          (*then_block, *else_block)
+        };
+        self.body.push(Instr::If);
+        self.jump(taken, None, dispatch + 1);
+        self.body.push(Instr::End);
+        self.jump(other, Some(next), dispatch);
+      }
+      Terminator::Switch { value, cases, default } => {
+        for (case, target) in cases {
+          self.operand(value);
+          self.body.extend([Instr::I64Const(*case), Instr::Numeric(Numeric::I64Eq), Instr::If]);
+          self.jump(*target, None, dispatch + 1);
+          self.body.push(Instr::End);
+        }
+        self.jump(*default, Some(next), dispatch);
+      }
+      Terminator::Return(value) => {
+        self.operand(value);
+        self.body.extend([
+          Instr::LocalGet(FRAME),
+          Instr::I32Const((self.function.frame_size * WORD_BYTES) as i32),
+          Instr::Numeric(Numeric::I32Add),
+          Instr::GlobalSet(SP),
+          Instr::Return,
+        ]);
+      }
+      Terminator::IndirectJump { addr, .. } => {
+        self.operand(addr);
+        self.body.extend([Instr::Numeric(Numeric::I32WrapI64), Instr::LocalSet(PC), Instr::Br(dispatch)]);
+      }
+    }
+  }
+}
+
+//the instruction for an operation, and whether it is a comparison whose i32 result needs widening
+fn binary(op: BinaryOp) -> (Numeric, bool) {
+  match op {
+    BinaryOp::Add => (Numeric::I64Add, false),
+    BinaryOp::Sub => (Numeric::I64Sub, false),
+    BinaryOp::Mul => (Numeric::I64Mul, false),
+    BinaryOp::Div => (Numeric::I64DivS, false),
+    BinaryOp::Rem => (Numeric::I64RemS, false),
+    BinaryOp::And => (Numeric::I64And, false),
+    BinaryOp::Or => (Numeric::I64Or, false),
+    BinaryOp::Xor => (Numeric::I64Xor, false),
+    BinaryOp::Shl => (Numeric::I64Shl, false),
+    BinaryOp::Shr => (Numeric::I64ShrS, false),
+    BinaryOp::Eq => (Numeric::I64Eq, true),
+    BinaryOp::Ne => (Numeric::I64Ne, true),
+    BinaryOp::Lt => (Numeric::I64LtS, true),
+    BinaryOp::Le => (Numeric::I64LeS, true),
+    BinaryOp::Gt => (Numeric::I64GtS, true),
+    BinaryOp::Ge => (Numeric::I64GeS, true),
+  }
+}
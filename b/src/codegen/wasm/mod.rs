@@ -0,0 +1,210 @@
+pub mod compile;
+pub mod text;
+pub mod binary;
+
+//WebAssembly code generation. compile::compile turns an IR module into a Module here, a single wasm
+//module that text::print writes as WAT and binary::encode as a .wasm file.
+//
+//Memory is one linear memory of 64-bit words. B values are word addresses, so every load and store
+//shifts the address left by 3; word 0 is never allocated. Static data (strings, then globals) is
+//laid out from word 1 at compile time, so global and string addresses are constants, and the rest
+//of memory is a stack growing down from the top. A function's frame lives on that stack, as in the
+//IR (params, then autos, then vector storage), so `&` works on params and autos; $sp is the byte
+//address of the lowest frame.
+//
+//Every B function has the wasm type (args, argc) -> i64. The caller stores the arguments in words
+//just below its frame and passes their byte address and count; the callee copies them into its own
+//frame, setting params the caller left out to 0. Function values are indexes into the table, which
+//holds every function from index 1, so calling 0 traps; a label value is its block's number, and a
+//function's blocks run from a loop over a br_table on that number.
+//
+//putchar and getchar are imported from the host module HOST with byte-sized signatures, (i32) -> ()
+//and () -> i32 returning -1 at end of input, and wrapped to behave like the interpreter's. Any
+//other extrn the program does not define is imported with the B function type, reading its
+//arguments from the exported memory. The export `main` takes no arguments and runs B's main.
+
+///The module the host's imports are looked up in.
+pub const HOST: &str = "b";
+
+///Bytes of memory reserved for the stack, on top of static data.
+pub const STACK_BYTES: u32 = 1 << 20;
+
+pub const PAGE_BYTES: u32 = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValType {
+  I32,
+  I64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncType {
+  pub params: Vec<ValType>,
+  pub results: Vec<ValType>,
+}
+
+///Numeric instructions, which take no immediates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numeric {
+  I32Eqz,
+  I32LtS,
+  I32GtU,
+  I32GeS,
+  I32Add,
+  I32Sub,
+  I32Shl,
+  I32ShrU,
+  I64Eqz,
+  I64Eq,
+  I64Ne,
+  I64LtS,
+  I64GtS,
+  I64LeS,
+  I64GeS,
+  I64Add,
+  I64Sub,
+  I64Mul,
+  I64DivS,
+  I64RemS,
+  I64And,
+  I64Or,
+  I64Xor,
+  I64Shl,
+  I64ShrS,
+  I64ShrU,
+  I32WrapI64,
+  I64ExtendI32U,
+}
+
+impl Numeric {
+  ///Name in the text format.
+  pub fn name(self) -> &'static str {
+    self.info().0
+  }
+
+  pub fn opcode(self) -> u8 {
+    self.info().1
+  }
+
+  fn info(self) -> (&'static str, u8) {
+    match self {
+      Numeric::I32Eqz => ("i32.eqz", 0x45),
+      Numeric::I32LtS => ("i32.lt_s", 0x48),
+      Numeric::I32GtU => ("i32.gt_u", 0x4b),
+      Numeric::I32GeS => ("i32.ge_s", 0x4e),
+      Numeric::I32Add => ("i32.add", 0x6a),
+      Numeric::I32Sub => ("i32.sub", 0x6b),
+      Numeric::I32Shl => ("i32.shl", 0x74),
+      Numeric::I32ShrU => ("i32.shr_u", 0x76),
+      Numeric::I64Eqz => ("i64.eqz", 0x50),
+      Numeric::I64Eq => ("i64.eq", 0x51),
+      Numeric::I64Ne => ("i64.ne", 0x52),
+      Numeric::I64LtS => ("i64.lt_s", 0x53),
+      Numeric::I64GtS => ("i64.gt_s", 0x55),
+      Numeric::I64LeS => ("i64.le_s", 0x57),
+      Numeric::I64GeS => ("i64.ge_s", 0x59),
+      Numeric::I64Add => ("i64.add", 0x7c),
+      Numeric::I64Sub => ("i64.sub", 0x7d),
+      Numeric::I64Mul => ("i64.mul", 0x7e),
+      Numeric::I64DivS => ("i64.div_s", 0x7f),
+      Numeric::I64RemS => ("i64.rem_s", 0x81),
+      Numeric::I64And => ("i64.and", 0x83),
+      Numeric::I64Or => ("i64.or", 0x84),
+      Numeric::I64Xor => ("i64.xor", 0x85),
+      Numeric::I64Shl => ("i64.shl", 0x86),
+      Numeric::I64ShrS => ("i64.shr_s", 0x87),
+      Numeric::I64ShrU => ("i64.shr_u", 0x88),
+      Numeric::I32WrapI64 => ("i32.wrap_i64", 0xa7),
+      Numeric::I64ExtendI32U => ("i64.extend_i32_u", 0xad),
+    }
+  }
+}
+
+///The instructions the code generator uses. Blocks, loops and ifs have no parameters or results;
+///branch targets are relative depths, and memory offsets are in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+  Unreachable,
+  Block,
+  Loop,
+  If,
+  Else,
+  End,
+  Br(u32),
+  BrTable { targets: Vec<u32>, default: u32 },
+  Return,
+  Call(u32), //function index
+  CallIndirect(u32), //type index, through table 0
+  Select,
+  LocalGet(u32),
+  LocalSet(u32),
+  LocalTee(u32),
+  GlobalGet(u32),
+  GlobalSet(u32),
+  I64Load(u32),
+  I64Store(u32),
+  I32Const(i32),
+  I64Const(i64),
+  Numeric(Numeric),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+  pub module: String,
+  pub name: String,
+  pub type_index: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Func {
+  pub name: String, //for the text format; the binary has no names
+  pub type_index: u32,
+  pub locals: Vec<(u32, ValType)>, //runs of locals after the params
+  pub body: Vec<Instr>, //without the final End
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportKind {
+  Func,
+  Memory,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+  pub name: String,
+  pub kind: ExportKind,
+  pub index: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Global {
+  pub name: String,
+  pub ty: ValType,
+  pub mutable: bool,
+  pub init: Instr, //a constant
+}
+
+///A module with one memory and one funcref table. Function indexes count the imports first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+  pub types: Vec<FuncType>,
+  pub imports: Vec<Import>, //all functions
+  pub functions: Vec<Func>,
+  pub table: Vec<u32>, //function indexes in table slots 1..
+  pub memory_pages: u32,
+  pub globals: Vec<Global>,
+  pub exports: Vec<Export>,
+  pub data_offset: u32, //byte address of `data`
+  pub data: Vec<u8>,
+}
+
+impl Module {
+  ///The text-format name of a function index: `module.name` for an import. B names have no dots,
+  ///so these never clash with defined functions.
+  pub fn function_name(&self, index: u32) -> String {
+    match self.imports.get(index as usize) {
+      Some(import) => format!("{}.{}", import.module, import.name),
+      None => self.functions[index as usize - self.imports.len()].name.clone(),
+    }
+  }
+}
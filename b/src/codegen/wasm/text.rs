@@ -0,0 +1,115 @@
+use super::{ExportKind, FuncType, Instr, Module, ValType};
+
+//the WebAssembly text format. Instructions are written one per line in the linear (unfolded) form,
+//indented by block nesting; functions and the global have names, everything else is numbered.
+//Data is one string with every byte as two hex digits after a `\`.
+
+pub fn print(module: &Module) -> String {
+  let mut out = String::from("(module\n");
+  for (i, ty) in module.types.iter().enumerate() {
+    out.push_str(&format!("  (type (;{};) (func{}))\n", i, signature(ty)));
+  }
+  for (i, import) in module.imports.iter().enumerate() {
+    out.push_str(&format!("  (import \"{}\" \"{}\" (func ${} (type {})))\n", import.module, import.name, module.function_name(i as u32), import.type_index));
+  }
+  for (i, f) in module.functions.iter().enumerate() {
+    let index = (module.imports.len() + i) as u32;
+    out.push_str(&format!("  (func ${} (;{};) (type {})", f.name, index, f.type_index));
+    for (count, ty) in &f.locals {
+      if *count > 0 {
+        out.push_str(&format!(" (local{})", format!(" {}", name(*ty)).repeat(*count as usize)));
+      }
+    }
+    out.push('\n');
+    let mut depth = 2;
+    for instr in &f.body {
+      if matches!(instr, Instr::End | Instr::Else) {
+        depth -= 1;
+      }
+      out.push_str(&"  ".repeat(depth));
+      out.push_str(&instruction(module, instr));
+      out.push('\n');
+      if matches!(instr, Instr::Block | Instr::Loop | Instr::If | Instr::Else) {
+        depth += 1;
+      }
+    }
+    out.push_str("  )\n");
+  }
+  out.push_str(&format!("  (table {} funcref)\n", module.table.len() + 1));
+  out.push_str(&format!("  (memory {})\n", module.memory_pages));
+  for global in &module.globals {
+    let ty = if global.mutable { format!("(mut {})", name(global.ty)) } else { name(global.ty).to_string() };
+    out.push_str(&format!("  (global ${} {} ({}))\n", global.name, ty, instruction(module, &global.init)));
+  }
+  for export in &module.exports {
+    let item = match export.kind {
+      ExportKind::Func => format!("func ${}", module.function_name(export.index)),
+      ExportKind::Memory => format!("memory {}", export.index),
+    };
+    out.push_str(&format!("  (export \"{}\" ({}))\n", export.name, item));
+  }
+  if !module.table.is_empty() {
+    let functions: Vec<String> = module.table.iter().map(|&f| format!("${}", module.function_name(f))).collect();
+    out.push_str(&format!("  (elem (i32.const 1) func {})\n", functions.join(" ")));
+  }
+  if !module.data.is_empty() {
+    let bytes: String = module.data.iter().map(|b| format!("\\{:02x}", b)).collect();
+    out.push_str(&format!("  (data (i32.const {}) \"{}\")\n", module.data_offset, bytes));
+  }
+  out.push_str(")\n");
+  out
+}
+
+fn name(ty: ValType) -> &'static str {
+  match ty {
+    ValType::I32 => "i32",
+    ValType::I64 => "i64",
+  }
+}
+
+fn signature(ty: &FuncType) -> String {
+  let mut out = String::new();
+  if !ty.params.is_empty() {
+    out.push_str(" (param");
+    ty.params.iter().for_each(|&t| out.push_str(&format!(" {}", name(t))));
+    out.push(')');
+  }
+  if !ty.results.is_empty() {
+    out.push_str(" (result");
+    ty.results.iter().for_each(|&t| out.push_str(&format!(" {}", name(t))));
+    out.push(')');
+  }
+  out
+}
+
+fn instruction(module: &Module, instr: &Instr) -> String {
+  match instr {
+    Instr::Unreachable => "unreachable".to_string(),
+    Instr::Block => "block".to_string(),
+    Instr::Loop => "loop".to_string(),
+    Instr::If => "if".to_string(),
+    Instr::Else => "else".to_string(),
+    Instr::End => "end".to_string(),
+    Instr::Br(depth) => format!("br {}", depth),
+    Instr::BrTable { targets, default } => {
+      let targets: String = targets.iter().map(|t| format!(" {}", t)).collect();
+      format!("br_table{} {}", targets, default)
+    }
+    Instr::Return => "return".to_string(),
+    Instr::Call(f) => format!("call ${}", module.function_name(*f)),
+    Instr::CallIndirect(ty) => format!("call_indirect (type {})", ty),
+    Instr::Select => "select".to_string(),
+    Instr::LocalGet(i) => format!("local.get {}", i),
+    Instr::LocalSet(i) => format!("local.set {}", i),
+    Instr::LocalTee(i) => format!("local.tee {}", i),
+    Instr::GlobalGet(i) => format!("global.get ${}", module.globals[*i as usize].name),
+    Instr::GlobalSet(i) => format!("global.set ${}", module.globals[*i as usize].name),
+    Instr::I64Load(0) => "i64.load".to_string(),
+    Instr::I64Load(offset) => format!("i64.load offset={}", offset),
+    Instr::I64Store(0) => "i64.store".to_string(),
+    Instr::I64Store(offset) => format!("i64.store offset={}", offset),
+    Instr::I32Const(c) => format!("i32.const {}", c),
+    Instr::I64Const(c) => format!("i64.const {}", c),
+    Instr::Numeric(op) => op.name().to_string(),
+  }
+}
@@ -0,0 +1,424 @@
+use rustc_hash::FxHashSet;
+
+use crate::ir::{self, BinaryOp, BlockId, Callee, Function, Init, Instr, Operand, Terminator, UnaryOp, Word, WORD_BYTES};
+use crate::ir::ssa::without_phis;
+use crate::libb;
+
+//x86-64 code generator: GNU as (AT&T syntax) for System V x86-64 Linux, one assembly file per IR
+//module, linked with runtime(), libb and the C library by link::link.
+//
+//B values are word addresses, so a pointer is a byte address shifted right by 3 and every load and
+//store scales it back (`(,%rax,8)`). Frames, data, functions and computed-goto targets are all
+//8-byte aligned so their addresses survive the shift. Every virtual register gets a stack slot;
+//instructions load operands into %rax/%rcx, compute, and store the result.
+//
+//Frame of a function, growing down from %rbp: IR frame words in increasing address order (word k
+//at %rbp - 8 * (frame_size - k), so params are contiguous and `&x1 + 1` is x2), then one slot per
+//register. Calls follow System V: the first six arguments in %rdi..%r9, the rest pushed right to
+//left, the result in %rax. As for C varargs, %rax carries the argument count on entry, so a callee
+//sets params the caller left out to 0.
+//
+//Symbols are the B names with a `b_` prefix, keeping them apart from the C library's. Word-address
+//constants in data (a global vector's pointer, a string in an initializer) cannot be expressed as
+//relocations, so a per-module routine in .init_array stores them before main runs.
+
+///Argument registers in order.
+const ARG_REGS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+///The assembler symbol of a B global or function.
+pub fn symbol(name: &str) -> String {
+  format!("b_{}", name)
+}
+
+///Trampolines from B's calling convention to libb::c: each b_NAME stores the arguments its libb
+///function reads, from registers and the caller's stack, in a word array, and calls libb_NAME with
+///%rax's count and the array. They are weak, so a program can define its own printf. Assemble it
+///once per executable; link::link adds libb itself.
+pub fn runtime() -> String {
+  let mut out = String::from("  .text\n");
+  for (name, params) in libb::FUNCTIONS {
+    let array = WORD_BYTES as usize * params;
+    out.push_str(&format!("  .p2align 3\n  .weak {0}\n  .type {0}, @function\n{0}:\n", symbol(name)));
+    out.push_str("  pushq %rbp\n  movq %rsp, %rbp\n");
+    if params > 0 {
+      out.push_str(&format!("  subq ${}, %rsp\n", array.next_multiple_of(16)));
+    }
+    for i in 0..params {
+      let slot = 8 * i as isize - array as isize;
+      match ARG_REGS.get(i) {
+        Some(reg) => out.push_str(&format!("  movq {}, {}(%rbp)\n", reg, slot)),
+        //stack arguments start above the saved %rbp and the return address
+        None => out.push_str(&format!("  movq {}(%rbp), %r10\n  movq %r10, {}(%rbp)\n", 16 + 8 * (i - ARG_REGS.len()), slot)),
+      }
+    }
+    out.push_str(&format!("  movl %eax, %edi\n  leaq -{}(%rbp), %rsi\n  call libb_{}@PLT\n  leave\n  ret\n\n", array, name));
+  }
+  out.push_str("  .section .note.GNU-stack,\"\",@progbits\n");
+  out
+}
+
+///Assembly for a module. It defines the C entry point `main` if the module has a B main, whose
+///result becomes the exit status.
+pub fn emit(module: &ir::Module) -> String {
+  let mut asm = Asm::default();
+  asm.ins(".text");
+  for function in &module.functions {
+    FunctionEmitter::new(&without_phis(function), &mut asm).emit();
+  }
+  if module.function("main").is_some() {
+    asm.ins(".p2align 3");
+    asm.ins(".globl main");
+    asm.ins(".type main, @function");
+    asm.label("main");
+    asm.ins("pushq %rbp");
+    asm.ins("movq %rsp, %rbp");
+    asm.ins("xorl %eax, %eax");
+    asm.ins(format!("call {}", symbol("main")));
+    asm.ins("popq %rbp");
+    asm.ins("ret");
+  }
+  data(module, &mut asm);
+  asm.ins(".section .note.GNU-stack,\"\",@progbits");
+  asm.out
+}
+
+#[derive(Default)]
+struct Asm {
+  out: String,
+}
+
+impl Asm {
+  fn ins(&mut self, text: impl AsRef<str>) {
+    self.out.push_str("  ");
+    self.out.push_str(text.as_ref());
+    self.out.push('\n');
+  }
+
+  fn label(&mut self, name: impl AsRef<str>) {
+    self.out.push_str(name.as_ref());
+    self.out.push_str(":\n");
+  }
+}
+
+//strings and globals, and the routine that fills in their word addresses
+fn data(module: &ir::Module, asm: &mut Asm) {
+  let mut fixups: Vec<(String, String)> = Vec::new(); //(word to set, what its address is of)
+  asm.ins(".data");
+  asm.ins(".p2align 3");
+  for (i, s) in module.strings.iter().enumerate() {
+    asm.label(format!(".Lstr{}", i));
+    asm.ins(format!(".quad {}", join(&ir::pack_string(s))));
+  }
+  for global in &module.globals {
+    let name = symbol(&global.name);
+    asm.ins(format!(".globl {}", name));
+    asm.label(&name);
+    //a vector's storage follows its pointer word
+    let first = global.vector.is_some() as usize;
+    let mut words = vec![0; first];
+    if first == 1 {
+      fixups.push((name.clone(), format!("{}+{}", name, WORD_BYTES)));
+    }
+    for (i, init) in global.init.iter().enumerate() {
+      match init {
+        Init::Word(w) => words.push(*w),
+        Init::String(index) => {
+          words.push(0);
+          fixups.push((format!("{}+{}", name, (first + i) as u32 * WORD_BYTES), format!(".Lstr{}", index)));
+        }
+      }
+    }
+    let size = first + global.vector.map_or(1, |storage| storage as usize);
+    words.resize(words.len().max(size), 0);
+    asm.ins(format!(".quad {}", join(&words)));
+  }
+  if fixups.is_empty() {
+    return;
+  }
+  asm.ins(".text");
+  asm.ins(".p2align 3");
+  asm.label(".Linit");
+  for (word, target) in &fixups {
+    asm.ins(format!("leaq {}(%rip), %rax", target));
+    asm.ins("shrq $3, %rax");
+    asm.ins(format!("movq %rax, {}(%rip)", word));
+  }
+  asm.ins("ret");
+  asm.ins(".section .init_array,\"aw\"");
+  asm.ins(".p2align 3");
+  asm.ins(".quad .Linit");
+}
+
+fn join(words: &[Word]) -> String {
+  words.iter().map(|w| w.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+struct FunctionEmitter<'a> {
+  function: &'a Function,
+  name: String,
+  asm: &'a mut Asm,
+  label_targets: FxHashSet<BlockId>, //blocks whose address is taken; they must be aligned
+  divisions: u32, //labels handed out to the -1 guards of `/` and `%`
+}
+
+impl<'a> FunctionEmitter<'a> {
+
+  fn new(function: &'a Function, asm: &'a mut Asm) -> Self {
+    let label_targets = function.blocks.iter().flat_map(|b| &b.instrs).filter_map(|instr| match instr {
+      Instr::LabelAddr { block, .. } => Some(*block),
+      _ => None,
+    }).collect();
+    FunctionEmitter { function, name: symbol(&function.name), asm, label_targets, divisions: 0 }
+  }
+
+  fn block_label(&self, block: BlockId) -> String {
+    format!(".L{}.{}", self.name, block.0)
+  }
+
+  //offset from %rbp of frame word `k`
+  fn word(&self, k: u32) -> i64 {
+    -8 * (self.function.frame_size as i64 - k as i64)
+  }
+
+  fn slot(&self, r: ir::VReg) -> String {
+    format!("{}(%rbp)", -8 * (self.function.frame_size as i64 + 1 + r.0 as i64))
+  }
+
+  fn load(&mut self, operand: &Operand, reg: &str) {
+    let text = match operand {
+      Operand::Const(c) if i32::try_from(*c).is_ok() => format!("movq ${}, {}", c, reg),
+      Operand::Const(c) => format!("movabsq ${}, {}", c, reg),
+      Operand::Reg(r) => format!("movq {}, {}", self.slot(*r), reg),
+    };
+    self.asm.ins(text);
+  }
+
+  fn store(&mut self, dest: ir::VReg) {
+    let slot = self.slot(dest);
+    self.asm.ins(format!("movq %rax, {}", slot));
+  }
+
+  fn emit(mut self) {
+    let f = self.function;
+    self.asm.ins(".p2align 3");
+    self.asm.ins(format!(".globl {}", self.name));
+    self.asm.ins(format!(".type {}, @function", self.name));
+    self.asm.label(self.name.clone());
+    self.asm.ins("pushq %rbp");
+    self.asm.ins("movq %rsp, %rbp");
+    let bytes = (8 * (f.frame_size as u64 + f.reg_count as u64)).next_multiple_of(16);
+    if bytes > 0 {
+      self.asm.ins(format!("subq ${}, %rsp", bytes));
+    }
+    self.params();
+
+    for (i, block) in f.blocks.iter().enumerate() {
+      let id = BlockId(i as u32);
+      if self.label_targets.contains(&id) {
+        self.asm.ins(".p2align 3");
+      }
+      self.asm.label(self.block_label(id));
+      for instr in &block.instrs {
+        self.instr(instr);
+      }
+      self.terminator(&block.term, BlockId(id.0 + 1));
+    }
+    self.asm.ins(format!(".size {}, .-{}", self.name, self.name));
+  }
+
+  //copy the arguments into the frame, then zero the params the caller did not pass
+  fn params(&mut self) {
+    let params = self.function.params;
+    if params == 0 {
+      return;
+    }
+    for i in 0..params {
+      let word = self.word(i);
+      match ARG_REGS.get(i as usize) {
+        Some(reg) => self.asm.ins(format!("movq {}, {}(%rbp)", reg, word)),
+        None => {
+          self.asm.ins(format!("movq {}(%rbp), %r11", 16 + 8 * (i as usize - ARG_REGS.len())));
+          self.asm.ins(format!("movq %r11, {}(%rbp)", word));
+        }
+      }
+    }
+    let (zero, done) = (format!(".L{}.zero", self.name), format!(".L{}.args", self.name));
+    self.asm.label(&zero);
+    self.asm.ins(format!("cmpq ${}, %rax", params));
+    self.asm.ins(format!("jge {}", done));
+    self.asm.ins(format!("movq $0, {}(%rbp,%rax,8)", self.word(0)));
+    self.asm.ins("incq %rax");
+    self.asm.ins(format!("jmp {}", zero));
+    self.asm.label(&done);
+  }
+
+  fn instr(&mut self, instr: &Instr) {
+    match instr {
+      Instr::Copy { dest, src } => {
+        self.load(src, "%rax");
+        self.store(*dest);
+      }
+      Instr::Unary { dest, op, src } => {
+        self.load(src, "%rax");
+        match op {
+          UnaryOp::Neg => self.asm.ins("negq %rax"),
+          UnaryOp::BitNot => self.asm.ins("notq %rax"),
+          UnaryOp::Not => {
+            self.asm.ins("testq %rax, %rax");
+            self.asm.ins("sete %al");
+            self.asm.ins("movzbq %al, %rax");
+          }
+        }
+        self.store(*dest);
+      }
+      Instr::Binary { dest, op, lhs, rhs } => {
+        self.load(lhs, "%rax");
+        self.load(rhs, "%rcx");
+        self.binary(*op);
+        self.store(*dest);
+      }
+      Instr::Load { dest, addr } => {
+        self.load(addr, "%rax");
+        self.asm.ins("movq (,%rax,8), %rax");
+        self.store(*dest);
+      }
+      Instr::Store { addr, value } => {
+        self.load(addr, "%rax");
+        self.load(value, "%rcx");
+        self.asm.ins("movq %rcx, (,%rax,8)");
+      }
+      Instr::LocalAddr { dest, offset } => {
+        let word = self.word(*offset);
+        self.address(*dest, format!("{}(%rbp)", word));
+      }
+      Instr::GlobalAddr { dest, name } => self.address(*dest, format!("{}(%rip)", symbol(name))),
+      Instr::StringAddr { dest, index } => self.address(*dest, format!(".Lstr{}(%rip)", index)),
+      Instr::LabelAddr { dest, block } => {
+        let label = self.block_label(*block);
+        self.address(*dest, format!("{}(%rip)", label));
+      }
+      Instr::Call { dest, callee, args } => self.call(*dest, callee, args),
+      Instr::Phi { .. } => unreachable!("phis are removed before codegen"),
+    }
+  }
+
+  //the word address of a memory operand
+  fn address(&mut self, dest: ir::VReg, memory: String) {
+    self.asm.ins(format!("leaq {}, %rax", memory));
+    self.asm.ins("shrq $3, %rax");
+    self.store(dest);
+  }
+
+  //%rax = %rax op %rcx
+  fn binary(&mut self, op: BinaryOp) {
+    let condition = match op {
+      BinaryOp::Add => return self.asm.ins("addq %rcx, %rax"),
+      BinaryOp::Sub => return self.asm.ins("subq %rcx, %rax"),
+      BinaryOp::Mul => return self.asm.ins("imulq %rcx, %rax"),
+      BinaryOp::And => return self.asm.ins("andq %rcx, %rax"),
+      BinaryOp::Or => return self.asm.ins("orq %rcx, %rax"),
+      BinaryOp::Xor => return self.asm.ins("xorq %rcx, %rax"),
+      BinaryOp::Shl => return self.asm.ins("shlq %cl, %rax"),
+      BinaryOp::Shr => return self.asm.ins("sarq %cl, %rax"),
+      //idivq faults on the one quotient that overflows, min / -1; dividing by -1 wraps like the IR
+      BinaryOp::Div | BinaryOp::Rem => {
+        let (divide, done) = (format!(".L{}.div{}", self.name, self.divisions), format!(".L{}.div{}.done", self.name, self.divisions));
+        self.divisions += 1;
+        self.asm.ins("cmpq $-1, %rcx");
+        self.asm.ins(format!("jne {}", divide));
+        self.asm.ins(if op == BinaryOp::Div { "negq %rax" } else { "movq $0, %rax" });
+        self.asm.ins(format!("jmp {}", done));
+        self.asm.label(&divide);
+        self.asm.ins("cqto");
+        self.asm.ins("idivq %rcx");
+        if op == BinaryOp::Rem {
+          self.asm.ins("movq %rdx, %rax");
+        }
+        self.asm.label(&done);
+        return;
+      }
+      BinaryOp::Eq => "e",
+      BinaryOp::Ne => "ne",
+      BinaryOp::Lt => "l",
+      BinaryOp::Le => "le",
+      BinaryOp::Gt => "g",
+      BinaryOp::Ge => "ge",
+    };
+    self.asm.ins("cmpq %rcx, %rax");
+    self.asm.ins(format!("set{} %al", condition));
+    self.asm.ins("movzbq %al, %rax");
+  }
+
+  fn call(&mut self, dest: ir::VReg, callee: &Callee, args: &[Operand]) {
+    //stack arguments are pushed right to left, keeping %rsp 16-byte aligned at the call
+    let pushed = args.len().saturating_sub(ARG_REGS.len());
+    let pad = pushed % 2;
+    if pad == 1 {
+      self.asm.ins("subq $8, %rsp");
+    }
+    for arg in args.iter().skip(ARG_REGS.len()).rev() {
+      self.load(arg, "%rax");
+      self.asm.ins("pushq %rax");
+    }
+    for (arg, reg) in args.iter().zip(ARG_REGS) {
+      self.load(arg, reg);
+    }
+    self.asm.ins(format!("movl ${}, %eax", args.len()));
+    match callee {
+      Callee::Direct(name) => self.asm.ins(format!("call {}", symbol(name))),
+      Callee::Indirect(target) => {
+        self.load(target, "%r11");
+        self.asm.ins("shlq $3, %r11");
+        self.asm.ins("call *%r11");
+      }
+    }
+    if pushed + pad > 0 {
+      self.asm.ins(format!("addq ${}, %rsp", 8 * (pushed + pad)));
+    }
+    self.store(dest);
+  }
+
+  fn jump(&mut self, target: BlockId, next: BlockId) {
+    if target != next {
+      let label = self.block_label(target);
+      self.asm.ins(format!("jmp {}", label));
+    }
+  }
+
+  fn terminator(&mut self, term: &Terminator, next: BlockId) {
+    match term {
+      Terminator::Jump(target) => self.jump(*target, next),
+      Terminator::Branch { cond, then_block, else_block } => {
+        self.load(cond, "%rax");
+        self.asm.ins("testq %rax, %rax");
+        let label = self.block_label(*then_block);
+        self.asm.ins(format!("jnz {}", label));
+        self.jump(*else_block, next);
+      }
+      Terminator::Switch { value, cases, default } => {
+        self.load(value, "%rax");
+        for (case, target) in cases {
+          if i32::try_from(*case).is_ok() {
+            self.asm.ins(format!("cmpq ${}, %rax", case));
+          } else {
+            self.asm.ins(format!("movabsq ${}, %rcx", case));
+            self.asm.ins("cmpq %rcx, %rax");
+          }
+          let label = self.block_label(*target);
+          self.asm.ins(format!("je {}", label));
+        }
+        self.jump(*default, next);
+      }
+      Terminator::Return(value) => {
+        self.load(value, "%rax");
+        self.asm.ins("leave");
+        self.asm.ins("ret");
+      }
+      Terminator::IndirectJump { addr, .. } => {
+        self.load(addr, "%rax");
+        self.asm.ins("shlq $3, %rax");
+        self.asm.ins("jmp *%rax");
+      }
+    }
+  }
+}
@@ -1 +1,2 @@
 pub mod span;
+pub mod source_map;
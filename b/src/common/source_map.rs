@@ -0,0 +1,149 @@
+use crate::common::span::Span;
+
+//line/column resolution for spans. The scanner records the offset of every line start as it lexes, so
+//a LineIndex is just that table; lookups are a binary search over it. Spans stay raw byte offsets on
+//the hot path and are only converted here, when something needs to be shown to a human.
+
+///Handle for a file registered in a SourceMap. Spans are file-relative, so anything that reports
+///a span across several files carries the FileId alongside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub u32);
+
+///1-based line and column. Columns count chars, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LineIndex {
+    line_starts: Vec<u32>, //byte offset of the first char of each line; always starts with 0
+}
+
+impl LineIndex {
+
+    ///Build from the scanner's newline table (offsets just past each '\n').
+    pub fn from_newlines(newlines: &[u32]) -> Self {
+        let mut line_starts = Vec::with_capacity(newlines.len() + 1);
+        line_starts.push(0);
+        line_starts.extend_from_slice(newlines);
+        Self { line_starts }
+    }
+
+    ///Build by scanning source directly, for callers that never ran the lexer over it.
+    pub fn new(source: &str) -> Self {
+        let newlines: Vec<u32> = source.bytes()
+            .enumerate()
+            .filter(|&(_, b)| b == b'\n')
+            .map(|(i, _)| i as u32 + 1)
+            .collect();
+        Self::from_newlines(&newlines)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    ///0-based line containing the byte offset. Offsets past the end resolve to the last line.
+    pub fn line_of(&self, offset: u32) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1, //line_starts[0] == 0, so next is never 0
+        }
+    }
+
+    ///byte offset where a 0-based line starts
+    pub fn line_start(&self, line: usize) -> Option<u32> {
+        self.line_starts.get(line).copied()
+    }
+
+    ///Resolve a byte offset to a 1-based line and column.
+    pub fn line_col(&self, source: &str, offset: u32) -> LineCol {
+        let offset = offset.min(source.len() as u32);
+        let line = self.line_of(offset);
+        let start = self.line_starts[line] as usize;
+        let col = source.get(start..offset as usize)
+            .map(|s| s.chars().count())
+            .unwrap_or(offset as usize - start); //offset not on a char boundary; fall back to bytes
+        LineCol { line: line as u32 + 1, col: col as u32 + 1 }
+    }
+
+    ///Text of a 0-based line, without the trailing newline.
+    pub fn line_text<'s>(&self, source: &'s str, line: usize) -> &'s str {
+        let Some(start) = self.line_start(line) else { return "" };
+        let end = self.line_start(line + 1).map(|e| e as usize).unwrap_or(source.len());
+        let text = source.get(start as usize..end).unwrap_or("");
+        text.trim_end_matches(['\n', '\r'])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceFile<'a> {
+    pub name: String,
+    pub source: &'a str,
+    pub lines: LineIndex,
+}
+
+///Resolved start of a span, plus the text of the line it starts on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanLocation<'a> {
+    pub file: FileId,
+    pub start: LineCol,
+    pub end: LineCol,
+    pub line_text: &'a str,
+}
+
+///All source files in a compilation. Sources are borrowed, same as the tokens lexed from them.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap<'a> {
+    files: Vec<SourceFile<'a>>,
+}
+
+impl<'a> SourceMap<'a> {
+
+    pub fn new() -> Self {
+        Self { files: Vec::new() }
+    }
+
+    ///Register a file with the line table produced while lexing it (Scanner::line_index).
+    pub fn add_file(&mut self, name: impl Into<String>, source: &'a str, lines: LineIndex) -> FileId {
+        let id = FileId(self.files.len() as u32);
+        self.files.push(SourceFile { name: name.into(), source, lines });
+        id
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile<'a> {
+        //FileIds are only handed out by add_file, so this never panics for ids from this map
+        &self.files[id.0 as usize]
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile<'a>)> {
+        self.files.iter().enumerate().map(|(i, f)| (FileId(i as u32), f))
+    }
+
+    pub fn name(&self, id: FileId) -> &str {
+        &self.file(id).name
+    }
+
+    pub fn source(&self, id: FileId) -> &'a str {
+        self.file(id).source
+    }
+
+    pub fn line_col(&self, id: FileId, offset: u32) -> LineCol {
+        let file = self.file(id);
+        file.lines.line_col(file.source, offset)
+    }
+
+    ///Text of a 1-based line, without the trailing newline.
+    pub fn line_text(&self, id: FileId, line: u32) -> &'a str {
+        let file = self.file(id);
+        file.lines.line_text(file.source, line.saturating_sub(1) as usize)
+    }
+
+    pub fn lookup(&self, id: FileId, span: Span) -> SpanLocation<'a> {
+        let start = self.line_col(id, span.start);
+        let end = self.line_col(id, span.end);
+        SpanLocation { file: id, start, end, line_text: self.line_text(id, start.line) }
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt::Write;
+
+use super::{Diagnostic, Label, Suggestion};
+use crate::common::source_map::{FileId, SourceMap};
+use crate::common::span::Span;
+
+//one JSON object per diagnostic, one diagnostic per line (JSON lines). Schema, with every key always present:
+//
+//{"severity":"error","code":"E0107","message":"...",
+// "file":"main.b","span":SPAN,               <- primary label's location; both null if there is none
+// "labels":[{"file":"main.b","span":SPAN,"message":"...","primary":true}],
+// "notes":["..."],"help":["..."],
+// "suggestions":[{"file":"main.b","span":SPAN,"replacement":"=+","message":"..."}]}
+//
+//SPAN = {"start":13,"end":15,"line":2,"column":3,"end_line":2,"end_column":5}
+//start/end are byte offsets, lines and columns are 1-based (columns count chars). code may be null.
+//The tests in tests/json_diagnostics.rs pin this schema; extend it by adding keys, never by renaming.
+
+pub fn to_json(diag: &Diagnostic, sources: &SourceMap) -> String {
+    let mut out = String::from("{");
+
+    let _ = write!(out, "\"severity\":{},", string(&diag.severity.to_string()));
+    match diag.code {
+        Some(code) => { let _ = write!(out, "\"code\":{},", string(code)); }
+        None => out.push_str("\"code\":null,"),
+    }
+    let _ = write!(out, "\"message\":{},", string(&diag.message));
+
+    match diag.primary_label() {
+        Some(label) => {
+            let _ = write!(out, "\"file\":{},\"span\":{},", string(sources.name(label.file)), span(sources, label.file, label.span));
+        }
+        None => out.push_str("\"file\":null,\"span\":null,"),
+    }
+
+    out.push_str("\"labels\":");
+    array(&mut out, &diag.labels, |out, l| label(out, sources, l));
+    out.push_str(",\"notes\":");
+    array(&mut out, &diag.notes, |out, n| out.push_str(&string(n)));
+    out.push_str(",\"help\":");
+    array(&mut out, &diag.help, |out, h| out.push_str(&string(h)));
+    out.push_str(",\"suggestions\":");
+    array(&mut out, &diag.suggestions, |out, s| suggestion(out, sources, s));
+
+    out.push('}');
+    out
+}
+
+fn label(out: &mut String, sources: &SourceMap, label: &Label) {
+    let _ = write!(out, "{{\"file\":{},\"span\":{},\"message\":{},\"primary\":{}}}",
+        string(sources.name(label.file)),
+        span(sources, label.file, label.span),
+        string(&label.message),
+        label.primary);
+}
+
+fn suggestion(out: &mut String, sources: &SourceMap, s: &Suggestion) {
+    let _ = write!(out, "{{\"file\":{},\"span\":{},\"replacement\":{},\"message\":{}}}",
+        string(sources.name(s.file)),
+        span(sources, s.file, s.span),
+        string(&s.replacement),
+        string(&s.message));
+}
+
+fn span(sources: &SourceMap, file: FileId, span: Span) -> String {
+    let start = sources.line_col(file, span.start);
+    let end = sources.line_col(file, span.end);
+    format!("{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}}}",
+        span.start, span.end, start.line, start.col, end.line, end.col)
+}
+
+fn array<T>(out: &mut String, items: &[T], mut write_item: impl FnMut(&mut String, &T)) {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_item(out, item);
+    }
+    out.push(']');
+}
+
+//JSON string literal with escapes
+fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
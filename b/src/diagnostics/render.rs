@@ -0,0 +1,211 @@
+use std::fmt::Write;
+use std::io::IsTerminal;
+
+use super::{Diagnostic, Label, Severity};
+use crate::common::source_map::{FileId, SourceMap};
+
+//rustc-style rendering:
+//
+//error[E0101]: expected `;`, found `}`
+// --> main.b:3:8
+//  |
+//3 |   x = 1
+//  |        ^ expected `;`
+//  |
+//  = help: ...
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto, //color when stderr is a terminal and NO_COLOR is unset
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn use_color(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal(),
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const GUTTER: &str = "\x1b[1;34m"; //line numbers, pipes, secondary underlines
+
+fn severity_color(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "\x1b[1;31m",
+        Severity::Warning => "\x1b[1;33m",
+        Severity::Note => "\x1b[1;32m",
+        Severity::Help => "\x1b[1;36m",
+    }
+}
+
+pub struct Emitter<'m, 'a> {
+    sources: &'m SourceMap<'a>,
+    color: bool,
+}
+
+impl<'m, 'a> Emitter<'m, 'a> {
+
+    pub fn new(sources: &'m SourceMap<'a>, color: ColorChoice) -> Self {
+        Self { sources, color: color.use_color() }
+    }
+
+    ///render and print to stderr
+    pub fn emit(&self, diag: &Diagnostic) {
+        eprint!("{}", self.render(diag));
+    }
+
+    pub fn render(&self, diag: &Diagnostic) -> String {
+        let mut out = String::new();
+        let sev = severity_color(diag.severity);
+
+        //header
+        self.paint(&mut out, sev, &diag.severity.to_string());
+        if let Some(code) = diag.code {
+            self.paint(&mut out, sev, &format!("[{}]", code));
+        }
+        self.paint(&mut out, BOLD, &format!(": {}", diag.message));
+        out.push('\n');
+
+        let width = self.gutter_width(diag);
+        let pad = " ".repeat(width);
+
+        //labels, grouped by file with the primary label's file first
+        let mut files: Vec<FileId> = Vec::new();
+        if let Some(primary) = diag.primary_label() {
+            files.push(primary.file);
+        }
+        for label in &diag.labels {
+            if !files.contains(&label.file) {
+                files.push(label.file);
+            }
+        }
+
+        for (i, &file) in files.iter().enumerate() {
+            let labels: Vec<&Label> = diag.labels.iter().filter(|l| l.file == file).collect();
+            let anchor = labels.iter().find(|l| l.primary).unwrap_or(&labels[0]);
+            let pos = self.sources.line_col(file, anchor.span.start);
+
+            out.push_str(&pad);
+            self.paint(&mut out, GUTTER, if i == 0 { "--> " } else { "::: " });
+            let _ = writeln!(out, "{}:{}:{}", self.sources.name(file), pos.line, pos.col);
+            self.gutter(&mut out, &pad, "");
+            self.render_file_labels(&mut out, diag.severity, file, &labels, width);
+        }
+
+        //trailing notes and help
+        if !diag.notes.is_empty() || !diag.help.is_empty() {
+            if !diag.labels.is_empty() {
+                self.gutter(&mut out, &pad, "");
+            }
+            for note in &diag.notes {
+                out.push_str(&pad);
+                self.paint(&mut out, GUTTER, " = ");
+                self.paint(&mut out, BOLD, "note");
+                let _ = writeln!(out, ": {}", note);
+            }
+            for help in &diag.help {
+                out.push_str(&pad);
+                self.paint(&mut out, GUTTER, " = ");
+                self.paint(&mut out, BOLD, "help");
+                let _ = writeln!(out, ": {}", help);
+            }
+        }
+
+        out
+    }
+
+    fn render_file_labels(&self, out: &mut String, severity: Severity, file: FileId, labels: &[&Label], width: usize) {
+        let pad = " ".repeat(width);
+
+        //one source line per distinct starting line, in order
+        let mut lines: Vec<u32> = labels.iter()
+            .map(|l| self.sources.line_col(file, l.span.start).line)
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let mut prev: Option<u32> = None;
+        for line in lines {
+            if prev.is_some_and(|p| line > p + 1) {
+                self.paint(out, GUTTER, "...");
+                out.push('\n');
+            }
+            prev = Some(line);
+
+            let text = self.sources.line_text(file, line);
+            out.push_str(&" ".repeat(width - digits(line)));
+            self.paint(out, GUTTER, &format!("{} |", line));
+            if !text.is_empty() {
+                out.push(' ');
+                out.push_str(text);
+            }
+            out.push('\n');
+
+            let mut on_line: Vec<&&Label> = labels.iter()
+                .filter(|l| self.sources.line_col(file, l.span.start).line == line)
+                .collect();
+            on_line.sort_by_key(|l| l.span.start);
+
+            for label in on_line {
+                let start = self.sources.line_col(file, label.span.start);
+                let end = self.sources.line_col(file, label.span.end);
+                let text_len = text.chars().count() as u32;
+                //spans that run past this line are underlined to the end of it
+                let end_col = if end.line == start.line { end.col } else { text_len + 1 };
+                let len = end_col.saturating_sub(start.col).max(1) as usize;
+
+                //reproduce tabs so the underline lines up with the source text above it
+                let lead: String = text.chars()
+                    .take(start.col as usize - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let (mark, color) = if label.primary { ('^', severity_color(severity)) } else { ('-', GUTTER) };
+                let mut underline: String = std::iter::repeat_n(mark, len).collect();
+                if !label.message.is_empty() {
+                    underline.push(' ');
+                    underline.push_str(&label.message);
+                }
+
+                out.push_str(&pad);
+                self.paint(out, GUTTER, " | ");
+                out.push_str(&lead);
+                self.paint(out, color, &underline);
+                out.push('\n');
+            }
+        }
+    }
+
+    fn gutter(&self, out: &mut String, pad: &str, rest: &str) {
+        out.push_str(pad);
+        self.paint(out, GUTTER, " |");
+        out.push_str(rest);
+        out.push('\n');
+    }
+
+    fn gutter_width(&self, diag: &Diagnostic) -> usize {
+        diag.labels.iter()
+            .map(|l| digits(self.sources.line_col(l.file, l.span.start).line))
+            .max()
+            .unwrap_or(1)
+    }
+
+    fn paint(&self, out: &mut String, color: &str, text: &str) {
+        if self.color {
+            out.push_str(color);
+            out.push_str(text);
+            out.push_str(RESET);
+        } else {
+            out.push_str(text);
+        }
+    }
+}
+
+fn digits(n: u32) -> usize {
+    n.max(1).ilog10() as usize + 1
+}
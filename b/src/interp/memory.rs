@@ -0,0 +1,94 @@
+use crate::ir::{pack_string, Word};
+
+//word-addressed memory for the interpreter. Two segments share one address space: static data
+//(globals, string literals, anything the runtime allocates) grows up from address 1, and the stack
+//of call frames grows up from STACK_BASE. Address 0 is never valid, so a null pointer faults.
+
+///First word of the stack segment.
+pub const STACK_BASE: Word = 1 << 40;
+
+///Words either segment may grow to before allocation fails.
+pub const SEGMENT_WORDS: usize = 1 << 24;
+
+#[derive(Debug, Clone)]
+pub struct Memory {
+  data: Vec<Word>, //data[0] is the null word
+  stack: Vec<Word>,
+}
+
+impl Default for Memory {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Memory {
+
+  pub fn new() -> Self {
+    Memory { data: vec![0], stack: Vec::new() }
+  }
+
+  fn index(&self, addr: Word) -> Option<(bool, usize)> {
+    if addr >= STACK_BASE {
+      let i = usize::try_from(addr - STACK_BASE).ok()?;
+      (i < self.stack.len()).then_some((true, i))
+    } else {
+      let i = usize::try_from(addr).ok()?;
+      (i > 0 && i < self.data.len()).then_some((false, i))
+    }
+  }
+
+  ///The word at `addr`, or None if nothing is allocated there.
+  pub fn load(&self, addr: Word) -> Option<Word> {
+    match self.index(addr)? {
+      (true, i) => Some(self.stack[i]),
+      (false, i) => Some(self.data[i]),
+    }
+  }
+
+  ///Write the word at `addr`; None if nothing is allocated there.
+  pub fn store(&mut self, addr: Word, value: Word) -> Option<()> {
+    match self.index(addr)? {
+      (true, i) => self.stack[i] = value,
+      (false, i) => self.data[i] = value,
+    }
+    Some(())
+  }
+
+  ///Zeroed static words, returning the address of the first; None when the segment is full.
+  pub fn alloc(&mut self, words: usize) -> Option<Word> {
+    if self.data.len() + words > SEGMENT_WORDS {
+      return None;
+    }
+    let addr = self.data.len() as Word;
+    self.data.resize(self.data.len() + words, 0);
+    Some(addr)
+  }
+
+  ///Static words initialized to `words`.
+  pub fn alloc_words(&mut self, words: &[Word]) -> Option<Word> {
+    let addr = self.alloc(words.len())?;
+    self.data[addr as usize..].copy_from_slice(words);
+    Some(addr)
+  }
+
+  ///Static words holding `bytes` packed as ir::pack_string lays them out.
+  pub fn alloc_string(&mut self, bytes: &[u8]) -> Option<Word> {
+    self.alloc_words(&pack_string(bytes))
+  }
+
+  ///A zeroed frame on top of the stack, returning its base address; None on stack overflow.
+  pub fn push_frame(&mut self, words: usize) -> Option<Word> {
+    if self.stack.len() + words > SEGMENT_WORDS {
+      return None;
+    }
+    let base = STACK_BASE + self.stack.len() as Word;
+    self.stack.resize(self.stack.len() + words, 0);
+    Some(base)
+  }
+
+  ///Free the frame at `base` and every frame above it.
+  pub fn pop_frame(&mut self, base: Word) {
+    self.stack.truncate((base - STACK_BASE) as usize);
+  }
+}
@@ -0,0 +1,694 @@
+use rustc_hash::FxHashMap;
+
+use crate::ast::{self, AutoDecl, Expr, Item, Program, Stmt};
+use crate::ast::visitor::*;
+use crate::common::source_map::FileId;
+use crate::common::span::Span;
+use crate::diagnostics::{Diagnostic, Label};
+use crate::ir::{BinaryOp, UnaryOp, Word};
+use crate::libb::{self, Runtime, Trap};
+use crate::lexer::token::{string_bytes, Operator, SpannedToken, Token};
+use crate::sema::symbol_table::{Location, Symbol};
+use crate::sema::{GotoTarget, SemaOutput};
+
+pub mod memory;
+
+use memory::Memory;
+
+//tree-walking interpreter. It runs a program sema accepted straight from the AST, with the memory
+//model lowering uses: untyped words, word addresses, and every param and auto in a frame word so
+//that `&` works on them. Frames are laid out like IR frames: params, autos by slot, vector storage.
+//
+//Structured statements run recursively. goto and switch enter statements in the middle, which a
+//tree walker cannot do directly, so they seek instead: the enclosing statement list runs again with
+//a target set, skipping to the statement that contains the label, case or default, and execution
+//resumes at the target. A goto first unwinds to the function body, so every label is reachable.
+//
+//Functions and labels are values too. They are numbered outside both memory segments, so using one
+//as a pointer faults, and calling a value that is not a function is an error.
+
+const FUNCTION_BASE: Word = 1 << 48; //+ function index; imports are numbered after the functions
+const LABEL_BASE: Word = 1 << 49; //+ FunctionEnv::labels index in the running function
+
+///Calls nested deeper than this fail with StackOverflow rather than exhaust the host's stack; each
+///B call takes a few kilobytes of it, and this stays inside a 2 MiB thread even in debug builds.
+pub const MAX_CALL_DEPTH: usize = 400;
+
+///A fault while running a program, with the source it happened at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+  OutOfBounds { address: Word, span: Span }, //load or store of a word nothing is allocated at
+  NotAFunction { value: Word, span: Span },
+  NotALabel { value: Word, span: Span }, //computed goto to something other than a label of the function
+  DivisionByZero { span: Span },
+  UnresolvedImport { name: String, span: Span }, //extrn the program does not define and the runtime lacks
+  StackOverflow { span: Span },
+  OutOfMemory { span: Span }, //static data too large, e.g. a huge global vector
+  NoFunction { name: String }, //entry point not defined
+  Exit { status: Word }, //exit() unwinding to the outermost call, which returns the status
+}
+
+impl RuntimeError {
+  pub fn span(&self) -> Option<Span> {
+    match self {
+      RuntimeError::OutOfBounds { span, .. } | RuntimeError::NotAFunction { span, .. } |
+      RuntimeError::NotALabel { span, .. } | RuntimeError::DivisionByZero { span } |
+      RuntimeError::UnresolvedImport { span, .. } | RuntimeError::StackOverflow { span } |
+      RuntimeError::OutOfMemory { span } => Some(*span),
+      RuntimeError::NoFunction { .. } | RuntimeError::Exit { .. } => None,
+    }
+  }
+
+  pub fn to_diagnostic(&self, file: FileId) -> Diagnostic {
+    match self {
+      RuntimeError::OutOfBounds { address, span } => Diagnostic::error(format!("no memory at word address {}", address))
+        .with_code("E0301")
+        .with_label(Label::primary(file, *span, "this access is out of bounds"))
+        .with_note(if *address == 0 { "the address is 0, a null pointer" } else { "the address is outside every global, string and stack frame" }),
+      RuntimeError::NotAFunction { value, span } => Diagnostic::error(format!("called {}, which is not a function", value))
+        .with_code("E0302")
+        .with_label(Label::primary(file, *span, "callee is not a function")),
+      RuntimeError::NotALabel { value, span } => Diagnostic::error(format!("goto {}, which is not a label of this function", value))
+        .with_code("E0303")
+        .with_label(Label::primary(file, *span, "computed goto target")),
+      RuntimeError::DivisionByZero { span } => Diagnostic::error("division by zero")
+        .with_code("E0304")
+        .with_label(Label::primary(file, *span, "the right operand is 0")),
+      RuntimeError::UnresolvedImport { name, span } => Diagnostic::error(format!("`{}` is not defined", name))
+        .with_code("E0305")
+        .with_label(Label::primary(file, *span, "used here"))
+        .with_note(format!("`{}` is an extrn the program does not define, and libb does not provide it", name)),
+      RuntimeError::StackOverflow { span } => Diagnostic::error("stack overflow")
+        .with_code("E0306")
+        .with_label(Label::primary(file, *span, format!("call nested more than {} deep", MAX_CALL_DEPTH))),
+      RuntimeError::OutOfMemory { span } => Diagnostic::error("out of memory")
+        .with_code("E0307")
+        .with_label(Label::primary(file, *span, "static data does not fit in memory")),
+      RuntimeError::NoFunction { name } => Diagnostic::error(format!("no function `{}` to run", name))
+        .with_code("E0308")
+        .with_help(format!("define `{}() {{ ... }}`", name)),
+      RuntimeError::Exit { status } => Diagnostic::error(format!("the program exited with status {}", status)),
+    }
+  }
+}
+
+type RunResult<T> = Result<T, RuntimeError>;
+
+//how a statement finished
+enum Flow {
+  Next,
+  Return(Word),
+  Goto(u32), //label index, handled by unwinding to the function body
+}
+
+//where seeking resumes
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+  Label(u32),
+  Case(Span), //span of the case value, or of the `default` keyword
+}
+
+//something callable
+enum Code {
+  Function(usize),
+  Import(usize),
+}
+
+struct FunctionInfo<'p> {
+  name: String,
+  body: &'p Stmt<'p>,
+  params: u32,
+  labels: u32,
+  frame_size: u32,
+  vectors: Vec<(u32, u32)>, //(slot of an auto vector, frame offset of its storage)
+}
+
+fn join(a: Span, b: Span) -> Span {
+  Span { start: a.start, end: b.end }
+}
+
+fn name_of(token: &SpannedToken) -> String {
+  match &token.token {
+    Token::Identifier(name) => name.to_string(),
+    _ => String::new(),
+  }
+}
+
+//auto vectors anywhere in a function body; autos are function scoped, so they are all set up on entry
+fn auto_vectors<'s>(statement: &'s Stmt<'s>, found: &mut Vec<&'s AutoDecl<'s>>) {
+  match statement {
+    Stmt::Auto { declarations } => found.extend(declarations.iter().filter(|d| d.size.is_some())),
+    Stmt::Block { statements } | Stmt::Switch { cases: statements, .. } => statements.iter().for_each(|s| auto_vectors(s, found)),
+    Stmt::If { then_branch, else_branch, .. } => {
+      auto_vectors(then_branch, found);
+      if let Some(else_branch) = else_branch {
+        auto_vectors(else_branch, found);
+      }
+    }
+    Stmt::While { body, .. } | Stmt::Case { body, .. } | Stmt::Default { body, .. } | Stmt::Label { body, .. } => auto_vectors(body, found),
+    _ => {}
+  }
+}
+
+///Runs a checked program, with libb::Runtime for the library and its input and output.
+pub struct Interpreter<'p> {
+  sema: &'p SemaOutput,
+  functions: Vec<FunctionInfo<'p>>, //indexed by Location::Function
+  globals: Vec<Word>, //address of each global, indexed by Location::Global
+  strings: FxHashMap<Span, Word>, //string literal -> its words, allocated the first time it runs
+  switches: FxHashMap<Span, Vec<(Span, Option<Word>)>>, //switch condition -> ast::switch_labels of its body
+  memory: Memory,
+  frame: Word, //base address of the running function's frame
+  current: usize, //index of the running function
+  depth: usize,
+  seeking: Option<Target>,
+  runtime: Runtime,
+}
+
+impl<'p> Interpreter<'p> {
+
+  ///Lay out the program's globals. `program` must be the one `sema` analyzed, with no errors.
+  pub fn new(program: &'p Program<'p>, sema: &'p SemaOutput) -> RunResult<Self> {
+    let mut interpreter = Self {
+      sema,
+      functions: Vec::new(),
+      globals: vec![0; sema.table.global.globals.len()],
+      strings: FxHashMap::default(),
+      switches: FxHashMap::default(),
+      memory: Memory::new(),
+      frame: 0,
+      current: 0,
+      depth: 0,
+      seeking: None,
+      runtime: Runtime::default(),
+    };
+    let mut functions: Vec<Option<FunctionInfo>> = (0..sema.table.functions.len()).map(|_| None).collect();
+    for item in &program.items {
+      match item {
+        Item::Function(f) => {
+          let Location::Function { func_index } = interpreter.symbol(f.name).slot else { continue };
+          functions[func_index as usize] = Some(interpreter.function_info(f));
+        }
+        Item::Global(decls) => {
+          for decl in decls {
+            let Location::Global { index } = interpreter.symbol(decl.name).slot else { continue };
+            interpreter.globals[index as usize] = interpreter.lay_out_global(decl)?;
+          }
+        }
+      }
+    }
+    interpreter.functions = functions.into_iter()
+      .map(|f| f.expect("every function sema declared is in the program"))
+      .collect();
+    Ok(interpreter)
+  }
+
+  ///Bytes getchar and read(0, ...) take; after the last one getchar returns `*e`.
+  pub fn with_input(mut self, input: impl Into<Vec<u8>>) -> Self {
+    self.runtime = Runtime::new(input.into());
+    self
+  }
+
+  ///Call `main()` and return its result.
+  pub fn run(&mut self) -> RunResult<Word> {
+    self.call("main", &[])
+  }
+
+  ///Call the function named `name` with `args`. If the program calls exit, its status is the result.
+  pub fn call(&mut self, name: &str, args: &[Word]) -> RunResult<Word> {
+    let result = match self.functions.iter().position(|f| f.name == name) {
+      Some(index) => self.call_function(index, args, Span::default()),
+      None => Err(RuntimeError::NoFunction { name: name.to_string() }),
+    };
+    match result {
+      Err(RuntimeError::Exit { status }) => Ok(status),
+      result => result,
+    }
+  }
+
+  ///Everything the program has written to standard output so far.
+  pub fn output(&self) -> &[u8] {
+    self.runtime.output()
+  }
+
+  pub fn memory(&self) -> &Memory {
+    &self.memory
+  }
+
+  fn function_info(&self, f: &'p ast::Function<'p>) -> FunctionInfo<'p> {
+    let Location::Function { func_index } = self.symbol(f.name).slot else { unreachable!("checked by the caller") };
+    let env = self.sema.table.function(func_index);
+    let mut decls = Vec::new();
+    auto_vectors(&f.body, &mut decls);
+    let mut frame_size = env.slot_count;
+    let mut vectors = Vec::new();
+    for decl in decls {
+      let Location::Local { slot } = self.symbol(decl.name).slot else { continue };
+      let words = match decl.size.map(|t| &t.token) {
+        Some(Token::Integer(n)) => *n as u32 + 1,
+        _ => 1,
+      };
+      vectors.push((slot, frame_size));
+      frame_size += words;
+    }
+    FunctionInfo {
+      name: name_of(f.name),
+      body: &f.body,
+      params: env.params.len() as u32,
+      labels: env.labels.len() as u32,
+      frame_size,
+      vectors,
+    }
+  }
+
+  //a global's words, laid out as in ir::Global: a vector's word points at the storage after it
+  fn lay_out_global(&mut self, decl: &ast::GlobalDecl) -> RunResult<Word> {
+    let out_of_memory = RuntimeError::OutOfMemory { span: decl.name.span };
+    let mut init = Vec::new();
+    for value in decl.initializer.iter().flatten() {
+      init.push(match value {
+        Expr::Literal { value: SpannedToken { token: Token::StringLiteral(s), .. } } => {
+          self.memory.alloc_string(&string_bytes(s)).ok_or(out_of_memory.clone())?
+        }
+        _ => value.const_value().unwrap_or(0),
+      });
+    }
+    let (addr, data) = match decl.size.map(|t| &t.token) {
+      Some(size) => {
+        let bound = match size {
+          Token::Integer(n) => *n as usize + 1,
+          _ => 0,
+        };
+        let words = bound.max(init.len());
+        let addr = self.memory.alloc(1 + words).ok_or(out_of_memory)?;
+        self.memory.store(addr, addr + 1);
+        (addr, addr + 1)
+      }
+      None => {
+        let addr = self.memory.alloc(init.len().max(1)).ok_or(out_of_memory)?;
+        (addr, addr)
+      }
+    };
+    for (i, word) in init.into_iter().enumerate() {
+      self.memory.store(data + i as Word, word);
+    }
+    Ok(addr)
+  }
+
+  fn symbol(&self, token: &SpannedToken) -> Symbol {
+    match self.sema.resolved.get(&token.span) {
+      Some(symbol) => *symbol,
+      None => panic!("`{}` was not resolved; run only programs sema accepted", name_of(token)),
+    }
+  }
+
+  fn import_name(&self, index: usize) -> &'p str {
+    self.sema.table.name(self.sema.table.global.imports[index])
+  }
+
+  fn load(&self, addr: Word, span: Span) -> RunResult<Word> {
+    self.memory.load(addr).ok_or(RuntimeError::OutOfBounds { address: addr, span })
+  }
+
+  fn store(&mut self, addr: Word, value: Word, span: Span) -> RunResult<()> {
+    self.memory.store(addr, value).ok_or(RuntimeError::OutOfBounds { address: addr, span })
+  }
+
+  //where a name's value lives; for functions, imports and labels, the value itself
+  fn symbol_address(&self, token: &SpannedToken) -> RunResult<Word> {
+    Ok(match self.symbol(token).slot {
+      Location::Local { slot } => self.frame + slot as Word,
+      Location::Global { index } => self.globals[index as usize],
+      Location::Function { func_index } => FUNCTION_BASE + func_index as Word,
+      Location::Import { import_index } => {
+        let name = self.import_name(import_index as usize);
+        if !libb::provides(name) {
+          return Err(RuntimeError::UnresolvedImport { name: name.to_string(), span: token.span });
+        }
+        FUNCTION_BASE + (self.functions.len() + import_index as usize) as Word
+      }
+      Location::Label { index } => LABEL_BASE + index as Word,
+    })
+  }
+
+  //address of an lvalue: a name, `*p` or `v[i]`
+  fn address(&mut self, expr: &Expr) -> RunResult<Word> {
+    match expr {
+      Expr::Variable { name } => self.symbol_address(name),
+      Expr::Unary { operator: SpannedToken { token: Token::Operator(Operator::Star), .. }, right } => walk_expr(self, right),
+      Expr::Get { target, index } => {
+        let base = walk_expr(self, target)?;
+        Ok(base.wrapping_add(walk_expr(self, index)?))
+      }
+      Expr::Grouping { expression } => self.address(expression),
+      //not an lvalue; `&` of a value is the value, as in lowering
+      _ => walk_expr(self, expr),
+    }
+  }
+
+  fn binary(&self, operator: &SpannedToken, lhs: Word, rhs: Word, span: Span) -> RunResult<Word> {
+    let op = match operator.token {
+      Token::Operator(op) => BinaryOp::from_operator(op),
+      _ => None,
+    };
+    match op {
+      Some(op) => op.eval(lhs, rhs).ok_or(RuntimeError::DivisionByZero { span }),
+      None => panic!("binary expression without a binary operator"),
+    }
+  }
+
+  //`++x` and `x--` style updates: (old value, new value)
+  fn increment(&mut self, target: &Expr, operator: &SpannedToken) -> RunResult<(Word, Word)> {
+    let addr = self.address(target)?;
+    let old = self.load(addr, target.span())?;
+    let new = match operator.token {
+      Token::Operator(Operator::Dec) => old.wrapping_sub(1),
+      _ => old.wrapping_add(1),
+    };
+    self.store(addr, new, target.span())?;
+    Ok((old, new))
+  }
+
+  fn call_function(&mut self, index: usize, args: &[Word], span: Span) -> RunResult<Word> {
+    let overflow = RuntimeError::StackOverflow { span };
+    if self.depth >= MAX_CALL_DEPTH {
+      return Err(overflow);
+    }
+    let info = &self.functions[index];
+    let body = info.body;
+    let base = self.memory.push_frame(info.frame_size as usize).ok_or(overflow)?;
+    //missing arguments are 0 and extra ones are dropped
+    for (i, &arg) in args.iter().take(info.params as usize).enumerate() {
+      self.memory.store(base + i as Word, arg);
+    }
+    for &(slot, storage) in &info.vectors {
+      self.memory.store(base + slot as Word, base + storage as Word);
+    }
+
+    let saved = (self.frame, self.current, self.seeking.take());
+    (self.frame, self.current) = (base, index);
+    self.depth += 1;
+    let result = self.run_body(body);
+    self.depth -= 1;
+    (self.frame, self.current, self.seeking) = saved;
+    self.memory.pop_frame(base);
+    result
+  }
+
+  fn run_body(&mut self, body: &Stmt) -> RunResult<Word> {
+    loop {
+      match walk_stmt(self, body)? {
+        Flow::Return(value) => return Ok(value),
+        Flow::Goto(label) => self.seeking = Some(Target::Label(label)),
+        //falling off the end returns 0
+        Flow::Next => {
+          self.seeking = None;
+          return Ok(0);
+        }
+      }
+    }
+  }
+
+  fn callee(&mut self, callee: &Expr, span: Span) -> RunResult<Code> {
+    if let Expr::Variable { name } = callee {
+      match self.symbol(name).slot {
+        Location::Function { func_index } => return Ok(Code::Function(func_index as usize)),
+        Location::Import { import_index } => return Ok(Code::Import(import_index as usize)),
+        _ => {}
+      }
+    }
+    let value = walk_expr(self, callee)?;
+    let index = value.wrapping_sub(FUNCTION_BASE);
+    let imports = self.sema.table.global.imports.len();
+    match usize::try_from(index) {
+      Ok(i) if i < self.functions.len() => Ok(Code::Function(i)),
+      Ok(i) if i < self.functions.len() + imports => Ok(Code::Import(i - self.functions.len())),
+      _ => Err(RuntimeError::NotAFunction { value, span }),
+    }
+  }
+
+  //whether control can seek to `target` inside `statement`
+  fn contains(&self, statement: &Stmt, target: Target) -> bool {
+    match statement {
+      Stmt::Case { value, body } => target == Target::Case(value.span()) || self.contains(body, target),
+      Stmt::Default { keyword, body } => target == Target::Case(keyword.span) || self.contains(body, target),
+      Stmt::Label { name, body } => {
+        matches!(self.symbol(name).slot, Location::Label { index } if target == Target::Label(index)) || self.contains(body, target)
+      }
+      Stmt::Block { statements } | Stmt::Switch { cases: statements, .. } => statements.iter().any(|s| self.contains(s, target)),
+      Stmt::If { then_branch, else_branch, .. } => {
+        self.contains(then_branch, target) || else_branch.as_ref().is_some_and(|s| self.contains(s, target))
+      }
+      Stmt::While { body, .. } => self.contains(body, target),
+      _ => false,
+    }
+  }
+
+  //run statements in order; when seeking, start at the one holding the target
+  fn run_list(&mut self, statements: &[Stmt]) -> RunResult<Flow> {
+    let start = match self.seeking {
+      Some(target) => match statements.iter().position(|s| self.contains(s, target)) {
+        Some(i) => i,
+        None => return Ok(Flow::Next),
+      },
+      None => 0,
+    };
+    for statement in &statements[start..] {
+      match walk_stmt(self, statement)? {
+        Flow::Next => {}
+        flow => return Ok(flow),
+      }
+    }
+    Ok(Flow::Next)
+  }
+
+  //reached a label, case or default: stop seeking if it was the target
+  fn arrive(&mut self, at: Target) {
+    if self.seeking == Some(at) {
+      self.seeking = None;
+    }
+  }
+}
+
+impl ExprVisitor<RunResult<Word>> for Interpreter<'_> {
+
+  fn visit_assign(&mut self, lvalue: &Expr, operator: &SpannedToken, value: &Expr) -> RunResult<Word> {
+    let addr = self.address(lvalue)?;
+    let base = match operator.token {
+      Token::Operator(op) => op.compound_base(),
+      _ => None,
+    };
+    //x =op y stores x op y; x is read before y is evaluated
+    let result = match base {
+      Some(op) => {
+        let old = self.load(addr, lvalue.span())?;
+        let rhs = walk_expr(self, value)?;
+        let op = SpannedToken { token: Token::Operator(op), span: operator.span };
+        self.binary(&op, old, rhs, join(lvalue.span(), value.span()))?
+      }
+      None => walk_expr(self, value)?,
+    };
+    self.store(addr, result, lvalue.span())?;
+    Ok(result)
+  }
+
+  fn visit_binary(&mut self, left: &Expr, operator: &SpannedToken, right: &Expr) -> RunResult<Word> {
+    let lhs = walk_expr(self, left)?;
+    let rhs = walk_expr(self, right)?;
+    self.binary(operator, lhs, rhs, join(left.span(), right.span()))
+  }
+
+  fn visit_call(&mut self, callee: &Expr, arguments: &[Expr]) -> RunResult<Word> {
+    let span = arguments.last().map_or(callee.span(), |last| join(callee.span(), last.span()));
+    let code = self.callee(callee, span)?;
+    let mut args = Vec::with_capacity(arguments.len());
+    for argument in arguments {
+      args.push(walk_expr(self, argument)?);
+    }
+    match code {
+      Code::Function(index) => self.call_function(index, &args, span),
+      Code::Import(index) => {
+        let name = self.import_name(index);
+        match self.runtime.call(name, &args, &mut self.memory) {
+          Some(Ok(result)) => Ok(result),
+          Some(Err(Trap::OutOfBounds(address))) => Err(RuntimeError::OutOfBounds { address, span }),
+          Some(Err(Trap::Exit(status))) => Err(RuntimeError::Exit { status }),
+          None => Err(RuntimeError::UnresolvedImport { name: name.to_string(), span }),
+        }
+      }
+    }
+  }
+
+  fn visit_grouping(&mut self, expression: &Expr) -> RunResult<Word> {
+    walk_expr(self, expression)
+  }
+
+  fn visit_literal(&mut self, value: &SpannedToken) -> RunResult<Word> {
+    match &value.token {
+      Token::Integer(n) | Token::CharLiteral(n) => Ok(*n),
+      Token::StringLiteral(s) => {
+        if let Some(&addr) = self.strings.get(&value.span) {
+          return Ok(addr);
+        }
+        let addr = self.memory.alloc_string(&string_bytes(s)).ok_or(RuntimeError::OutOfMemory { span: value.span })?;
+        self.strings.insert(value.span, addr);
+        Ok(addr)
+      }
+      _ => Ok(0),
+    }
+  }
+
+  fn visit_unary(&mut self, operator: &SpannedToken, right: &Expr) -> RunResult<Word> {
+    let Token::Operator(op) = operator.token else { return walk_expr(self, right) };
+    let unary = |interpreter: &mut Self, op: UnaryOp| Ok(op.eval(walk_expr(interpreter, right)?));
+    match op {
+      Operator::Minus => unary(self, UnaryOp::Neg),
+      Operator::Bang => unary(self, UnaryOp::Not),
+      Operator::Tilde => unary(self, UnaryOp::BitNot),
+      Operator::Star => {
+        let addr = walk_expr(self, right)?;
+        self.load(addr, join(operator.span, right.span()))
+      }
+      Operator::Amp => self.address(right),
+      Operator::Inc | Operator::Dec => Ok(self.increment(right, operator)?.1),
+      _ => walk_expr(self, right),
+    }
+  }
+
+  fn visit_logical(&mut self, left: &Expr, operator: &SpannedToken, right: &Expr) -> RunResult<Word> {
+    self.visit_binary(left, operator, right)
+  }
+
+  fn visit_variable(&mut self, name: &SpannedToken) -> RunResult<Word> {
+    let addr = self.symbol_address(name)?;
+    match self.symbol(name).slot {
+      //a function's or label's value is its address
+      Location::Function { .. } | Location::Import { .. } | Location::Label { .. } => Ok(addr),
+      _ => self.load(addr, name.span),
+    }
+  }
+
+  fn visit_get(&mut self, target: &Expr, index: &Expr) -> RunResult<Word> {
+    let base = walk_expr(self, target)?;
+    let addr = base.wrapping_add(walk_expr(self, index)?);
+    self.load(addr, join(target.span(), index.span()))
+  }
+
+  fn visit_ternary(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) -> RunResult<Word> {
+    if walk_expr(self, condition)? != 0 {
+      walk_expr(self, then_branch)
+    } else {
+      walk_expr(self, else_branch)
+    }
+  }
+
+  fn visit_postfix(&mut self, left: &Expr, operator: &SpannedToken) -> RunResult<Word> {
+    Ok(self.increment(left, operator)?.0)
+  }
+
+  fn visit_error_expr(&mut self, _span: Span) -> RunResult<Word> {
+    Ok(0)
+  }
+}
+
+impl StmtVisitor<RunResult<Flow>> for Interpreter<'_> {
+
+  fn visit_block(&mut self, statements: &[Stmt]) -> RunResult<Flow> {
+    self.run_list(statements)
+  }
+
+  fn visit_auto(&mut self, _declarations: &[AutoDecl]) -> RunResult<Flow> {
+    Ok(Flow::Next)
+  }
+
+  fn visit_extrn(&mut self, _names: &[&SpannedToken]) -> RunResult<Flow> {
+    Ok(Flow::Next)
+  }
+
+  fn visit_expression(&mut self, expression: &Expr) -> RunResult<Flow> {
+    walk_expr(self, expression)?;
+    Ok(Flow::Next)
+  }
+
+  fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> RunResult<Flow> {
+    let take_then = match self.seeking {
+      Some(target) => self.contains(then_branch, target),
+      None => walk_expr(self, condition)? != 0,
+    };
+    match (take_then, else_branch) {
+      (true, _) => walk_stmt(self, then_branch),
+      (false, Some(else_branch)) => walk_stmt(self, else_branch),
+      (false, None) => Ok(Flow::Next),
+    }
+  }
+
+  fn visit_while(&mut self, condition: &Expr, body: &Stmt) -> RunResult<Flow> {
+    loop {
+      //seeking into the body skips the first test
+      if self.seeking.is_none() && walk_expr(self, condition)? == 0 {
+        return Ok(Flow::Next);
+      }
+      match walk_stmt(self, body)? {
+        Flow::Next => {}
+        flow => return Ok(flow),
+      }
+    }
+  }
+
+  fn visit_switch(&mut self, condition: &Expr, cases: &[Stmt]) -> RunResult<Flow> {
+    if self.seeking.is_none() {
+      let value = walk_expr(self, condition)?;
+      let labels = self.switches.entry(condition.span()).or_insert_with(|| ast::switch_labels(cases));
+      let target = labels.iter().find(|&&(_, case)| case == Some(value))
+        .or_else(|| labels.iter().find(|&&(_, case)| case.is_none()));
+      match target {
+        Some(&(span, _)) => self.seeking = Some(Target::Case(span)),
+        None => return Ok(Flow::Next),
+      }
+    }
+    self.run_list(cases)
+  }
+
+  fn visit_case(&mut self, value: &Expr, body: &Stmt) -> RunResult<Flow> {
+    self.arrive(Target::Case(value.span()));
+    walk_stmt(self, body)
+  }
+
+  fn visit_default(&mut self, keyword: &SpannedToken, body: &Stmt) -> RunResult<Flow> {
+    self.arrive(Target::Case(keyword.span));
+    walk_stmt(self, body)
+  }
+
+  fn visit_label(&mut self, name: &SpannedToken, body: &Stmt) -> RunResult<Flow> {
+    if let Location::Label { index } = self.symbol(name).slot {
+      self.arrive(Target::Label(index));
+    }
+    walk_stmt(self, body)
+  }
+
+  fn visit_goto(&mut self, expression: &Expr) -> RunResult<Flow> {
+    if let Some(&GotoTarget::Label { index }) = self.sema.gotos.get(&expression.span()) {
+      return Ok(Flow::Goto(index));
+    }
+    let value = walk_expr(self, expression)?;
+    match u32::try_from(value.wrapping_sub(LABEL_BASE)) {
+      Ok(index) if index < self.functions[self.current].labels => Ok(Flow::Goto(index)),
+      _ => Err(RuntimeError::NotALabel { value, span: expression.span() }),
+    }
+  }
+
+  fn visit_return(&mut self, value: Option<&Expr>) -> RunResult<Flow> {
+    match value {
+      Some(value) => Ok(Flow::Return(walk_expr(self, value)?)),
+      None => Ok(Flow::Return(0)),
+    }
+  }
+
+  fn visit_null(&mut self) -> RunResult<Flow> {
+    Ok(Flow::Next)
+  }
+
+  fn visit_error_stmt(&mut self, _span: Span) -> RunResult<Flow> {
+    Ok(Flow::Next)
+  }
+}
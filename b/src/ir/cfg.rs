@@ -0,0 +1,334 @@
+use crate::ir::*;
+
+//control-flow graph of one function. Edges come from block terminators; block 0 is the entry.
+//Blocks no path from the entry reaches (code after a return or goto) are kept in the graph but have
+//no dominator and are absent from the reverse postorder; remove_unreachable() deletes them.
+
+pub struct Cfg {
+  succs: Vec<Vec<BlockId>>,
+  preds: Vec<Vec<BlockId>>,
+}
+
+impl Cfg {
+
+  pub fn new(function: &Function) -> Self {
+    let succs: Vec<Vec<BlockId>> = function.blocks.iter().map(|b| b.term.successors()).collect();
+    let mut preds = vec![Vec::new(); succs.len()];
+    for (from, targets) in succs.iter().enumerate() {
+      for target in targets {
+        preds[target.0 as usize].push(BlockId(from as u32));
+      }
+    }
+    Cfg { succs, preds }
+  }
+
+  pub fn len(&self) -> usize {
+    self.succs.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.succs.is_empty()
+  }
+
+  pub fn successors(&self, block: BlockId) -> &[BlockId] {
+    &self.succs[block.0 as usize]
+  }
+
+  pub fn predecessors(&self, block: BlockId) -> &[BlockId] {
+    &self.preds[block.0 as usize]
+  }
+
+  ///Blocks reachable from the entry, each before its successors except along back edges.
+  pub fn reverse_postorder(&self) -> Vec<BlockId> {
+    if self.is_empty() {
+      return Vec::new();
+    }
+    let mut visited = vec![false; self.len()];
+    let mut postorder = Vec::with_capacity(self.len());
+    //iterative dfs: (block, index of the next successor to visit)
+    let mut stack = vec![(BlockId(0), 0)];
+    visited[0] = true;
+    while let Some((block, next)) = stack.last_mut() {
+      let block = *block;
+      match self.successors(block).get(*next) {
+        Some(&succ) => {
+          *next += 1;
+          if !visited[succ.0 as usize] {
+            visited[succ.0 as usize] = true;
+            stack.push((succ, 0));
+          }
+        }
+        None => {
+          postorder.push(block);
+          stack.pop();
+        }
+      }
+    }
+    postorder.reverse();
+    postorder
+  }
+}
+
+///Dominator tree, computed with the Cooper-Harvey-Kennedy iterative algorithm.
+pub struct Dominators {
+  idom: Vec<Option<BlockId>>, //the entry is its own idom here; unreachable blocks have none
+  children: Vec<Vec<BlockId>>,
+  rpo: Vec<BlockId>,
+}
+
+impl Dominators {
+
+  pub fn new(cfg: &Cfg) -> Self {
+    let rpo = cfg.reverse_postorder();
+    let mut order = vec![usize::MAX; cfg.len()]; //reverse postorder index; MAX for unreachable
+    for (i, block) in rpo.iter().enumerate() {
+      order[block.0 as usize] = i;
+    }
+    let mut idom: Vec<Option<BlockId>> = vec![None; cfg.len()];
+    if let Some(&entry) = rpo.first() {
+      idom[entry.0 as usize] = Some(entry);
+    }
+
+    //walk both fingers up the tree until they meet; a lower rpo index is closer to the entry
+    let intersect = |idom: &[Option<BlockId>], mut a: BlockId, mut b: BlockId| {
+      while a != b {
+        while order[a.0 as usize] > order[b.0 as usize] {
+          a = idom[a.0 as usize].expect("processed block has an idom");
+        }
+        while order[b.0 as usize] > order[a.0 as usize] {
+          b = idom[b.0 as usize].expect("processed block has an idom");
+        }
+      }
+      a
+    };
+
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for &block in rpo.iter().skip(1) {
+        let mut new_idom = None;
+        for &pred in cfg.predecessors(block) {
+          if idom[pred.0 as usize].is_none() {
+            continue; //unreachable, or not processed yet on this pass
+          }
+          new_idom = Some(match new_idom {
+            None => pred,
+            Some(current) => intersect(&idom, pred, current),
+          });
+        }
+        if new_idom.is_some() && idom[block.0 as usize] != new_idom {
+          idom[block.0 as usize] = new_idom;
+          changed = true;
+        }
+      }
+    }
+
+    let mut children = vec![Vec::new(); cfg.len()];
+    for &block in rpo.iter().skip(1) {
+      if let Some(parent) = idom[block.0 as usize] {
+        children[parent.0 as usize].push(block);
+      }
+    }
+    Dominators { idom, children, rpo }
+  }
+
+  ///Immediate dominator; None for the entry and for unreachable blocks.
+  pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+    self.idom[block.0 as usize].filter(|&d| d != block)
+  }
+
+  ///Blocks whose immediate dominator is `block`, in reverse postorder.
+  pub fn children(&self, block: BlockId) -> &[BlockId] {
+    &self.children[block.0 as usize]
+  }
+
+  pub fn is_reachable(&self, block: BlockId) -> bool {
+    self.idom[block.0 as usize].is_some()
+  }
+
+  ///Whether every path from the entry to `b` goes through `a`. A block dominates itself.
+  pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+    if !self.is_reachable(b) {
+      return false;
+    }
+    let mut block = b;
+    loop {
+      if block == a {
+        return true;
+      }
+      match self.idom(block) {
+        Some(parent) => block = parent,
+        None => return false,
+      }
+    }
+  }
+
+  ///Reachable blocks in reverse postorder, which visits every block after its dominator.
+  pub fn reverse_postorder(&self) -> &[BlockId] {
+    &self.rpo
+  }
+
+  ///Dominance frontier of every block, sorted: the blocks where its dominance ends, which is where
+  ///SSA construction places phis for values defined in it.
+  pub fn frontiers(&self, cfg: &Cfg) -> Vec<Vec<BlockId>> {
+    let mut frontiers: Vec<Vec<BlockId>> = vec![Vec::new(); cfg.len()];
+    for &block in &self.rpo {
+      let preds: Vec<BlockId> = cfg.predecessors(block).iter().copied().filter(|&p| self.is_reachable(p)).collect();
+      //None for the entry, which is also entered from outside; any predecessor it has is a back edge
+      let idom = self.idom(block);
+      if preds.len() < 2 && idom.is_some() {
+        continue;
+      }
+      for pred in preds {
+        let mut runner = Some(pred);
+        while let Some(current) = runner && runner != idom {
+          let frontier = &mut frontiers[current.0 as usize];
+          if !frontier.contains(&block) {
+            frontier.push(block);
+          }
+          runner = self.idom(current);
+        }
+      }
+    }
+    frontiers.iter_mut().for_each(|frontier| frontier.sort());
+    frontiers
+  }
+}
+
+///A natural loop: the header and every block that reaches a back edge to it without passing
+///through the header. Loops sharing a header are merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+  pub header: BlockId,
+  pub latches: Vec<BlockId>, //sources of the back edges, sorted
+  pub blocks: Vec<BlockId>, //sorted; includes the header
+}
+
+impl Loop {
+  pub fn contains(&self, block: BlockId) -> bool {
+    self.blocks.binary_search(&block).is_ok()
+  }
+}
+
+///Natural loops of the function, ordered by header in reverse postorder (outer loops first).
+pub fn natural_loops(cfg: &Cfg, doms: &Dominators) -> Vec<Loop> {
+  let mut loops: Vec<Loop> = Vec::new();
+  for &header in doms.reverse_postorder() {
+    let latches: Vec<BlockId> = cfg.predecessors(header).iter().copied()
+      .filter(|&pred| doms.dominates(header, pred))
+      .collect();
+    if latches.is_empty() {
+      continue;
+    }
+    let mut blocks = vec![header];
+    let mut stack = latches.clone();
+    while let Some(block) = stack.pop() {
+      if blocks.contains(&block) {
+        continue;
+      }
+      blocks.push(block);
+      stack.extend(cfg.predecessors(block).iter().copied().filter(|&p| doms.is_reachable(p)));
+    }
+    let mut latches = latches;
+    latches.sort();
+    blocks.sort();
+    loops.push(Loop { header, latches, blocks });
+  }
+  loops
+}
+
+///Delete blocks the entry cannot reach and renumber the rest in order, returning how many were
+///removed. A block whose address a kept block takes (`label`) is kept too, so the value stays valid.
+pub fn remove_unreachable(function: &mut Function) -> usize {
+  let cfg = Cfg::new(function);
+  let mut keep = vec![false; function.blocks.len()];
+  let mut stack = vec![BlockId(0)];
+  while let Some(block) = stack.pop() {
+    if std::mem::replace(&mut keep[block.0 as usize], true) {
+      continue;
+    }
+    stack.extend(cfg.successors(block));
+    for instr in &function.blocks[block.0 as usize].instrs {
+      if let Instr::LabelAddr { block: target, .. } = instr {
+        stack.push(*target);
+      }
+    }
+  }
+
+  let mut renumber = vec![None; keep.len()];
+  let mut next = 0;
+  for (old, &kept) in keep.iter().enumerate() {
+    if kept {
+      renumber[old] = Some(BlockId(next));
+      next += 1;
+    }
+  }
+  let removed = keep.len() - next as usize;
+  if removed == 0 {
+    return 0;
+  }
+
+  let map = |block: &mut BlockId| *block = renumber[block.0 as usize].expect("kept blocks only reach kept blocks");
+  let mut index = 0;
+  function.blocks.retain(|_| {
+    index += 1;
+    keep[index - 1]
+  });
+  for block in &mut function.blocks {
+    for instr in &mut block.instrs {
+      match instr {
+        Instr::LabelAddr { block: target, .. } => map(target),
+        //phi arguments from removed predecessors go with them
+        Instr::Phi { args, .. } => {
+          args.retain(|(pred, _)| keep[pred.0 as usize]);
+          args.iter_mut().for_each(|(pred, _)| map(pred));
+        }
+        _ => {}
+      }
+    }
+    block.term.targets_mut().into_iter().for_each(map);
+  }
+  removed
+}
+
+fn dot_escape(text: &str) -> String {
+  text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+///Graphviz source for the function's CFG: one box per block listing its code, with branch edges
+///labelled by the condition or case value that takes them.
+pub fn to_dot(function: &Function) -> String {
+  let mut out = format!("digraph \"{}\" {{\n  node [shape=box, fontname=monospace];\n", dot_escape(&function.name));
+  for (i, block) in function.blocks.iter().enumerate() {
+    let id = BlockId(i as u32);
+    let mut label = match &block.label {
+      Some(name) => format!("{} {}:\\l", id, name),
+      None => format!("{}:\\l", id),
+    };
+    for instr in &block.instrs {
+      label.push_str(&format!("  {}\\l", dot_escape(&instr.to_string())));
+    }
+    label.push_str(&format!("  {}\\l", dot_escape(&block.term.to_string())));
+    out.push_str(&format!("  {} [label=\"{}\"];\n", id, label));
+  }
+  for (i, block) in function.blocks.iter().enumerate() {
+    let from = BlockId(i as u32);
+    let edges: Vec<(BlockId, String)> = match &block.term {
+      Terminator::Branch { then_block, else_block, .. } => vec![(*then_block, "T".to_string()), (*else_block, "F".to_string())],
+      Terminator::Switch { cases, default, .. } => cases.iter()
+        .map(|(n, target)| (*target, n.to_string()))
+        .chain(std::iter::once((*default, "default".to_string())))
+        .collect(),
+      term => term.successors().into_iter().map(|target| (target, String::new())).collect(),
+    };
+    for (to, label) in edges {
+      if label.is_empty() {
+        out.push_str(&format!("  {} -> {};\n", from, to));
+      } else {
+        out.push_str(&format!("  {} -> {} [label=\"{}\"];\n", from, to, label));
+      }
+    }
+  }
+  out.push_str("}\n");
+  out
+}
@@ -0,0 +1,43 @@
+use rustc_hash::FxHashSet;
+
+use crate::ir::{Init, Instr, Module};
+
+//joining the modules of several files into one. The IR names globals and functions, not slots, so
+//a GlobalAddr or call of a name another module defines needs no rewriting; only string indices are
+//per module and shift by the strings before them. The files must not define a name twice, which
+//sema::link checks with their spans.
+
+///One module with every global, function and string of `modules`, in order. Its imports are the
+///names some module imports and none defines, in order of first import.
+pub fn link(modules: Vec<Module>) -> Module {
+  let mut linked = Module::default();
+  for mut module in modules {
+    let base = linked.strings.len() as u32;
+    for global in &mut module.globals {
+      for init in &mut global.init {
+        if let Init::String(index) = init {
+          *index += base;
+        }
+      }
+    }
+    for function in &mut module.functions {
+      for block in &mut function.blocks {
+        for instr in &mut block.instrs {
+          if let Instr::StringAddr { index, .. } = instr {
+            *index += base;
+          }
+        }
+      }
+    }
+    linked.globals.append(&mut module.globals);
+    linked.functions.append(&mut module.functions);
+    linked.strings.append(&mut module.strings);
+    linked.imports.append(&mut module.imports);
+  }
+  let defined: FxHashSet<String> = linked.globals.iter().map(|g| g.name.clone())
+    .chain(linked.functions.iter().map(|f| f.name.clone()))
+    .collect();
+  let mut seen = FxHashSet::default();
+  linked.imports.retain(|name| !defined.contains(name) && seen.insert(name.clone()));
+  linked
+}
@@ -0,0 +1,539 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::ast::{self, AutoDecl, Expr, GlobalDecl, Item, Program, Stmt};
+use crate::ast::visitor::*;
+use crate::common::span::Span;
+use crate::ir::*;
+use crate::lexer::token::{string_bytes, Operator, SpannedToken, Token};
+use crate::sema::symbol_table::{Location, Symbol};
+use crate::sema::{GotoTarget, SemaOutput};
+
+//lowering from the AST to IR. It expects a program sema accepted without errors: every identifier
+//is in SemaOutput::resolved and every goto in SemaOutput::gotos.
+//
+//Statements lower into blocks in source order. After a terminator (return, goto) there is no current
+//block; code that follows is unreachable and goes into a fresh block nothing jumps to, which the CFG
+//passes remove. case, default and labels each start a pre-made block that the code before them falls
+//through to, which is how switch fallthrough and backward gotos come out.
+
+///Lower a checked program to an IR module.
+pub fn lower(program: &Program, sema: &SemaOutput) -> Module {
+  lower_file(program, sema, &FxHashSet::default())
+}
+
+///Lower one file of a program whose other files define the data `globals` (sema::link::globals).
+///An import of any other name is a function, whose value is its address.
+pub fn lower_file(program: &Program, sema: &SemaOutput, globals: &FxHashSet<String>) -> Module {
+  let mut module = Module {
+    imports: sema.table.global.imports.iter().map(|&id| sema.table.name(id).to_string()).collect(),
+    ..Module::default()
+  };
+  for item in &program.items {
+    match item {
+      Item::Function(f) => {
+        let function = FunctionLowering::new(sema, globals, &mut module.strings, f).finish(&f.body);
+        module.functions.push(function);
+      }
+      Item::Global(decls) => {
+        for decl in decls {
+          let global = lower_global(decl, &mut module.strings);
+          module.globals.push(global);
+        }
+      }
+    }
+  }
+  module
+}
+
+fn name_of(token: &SpannedToken) -> String {
+  match &token.token {
+    Token::Identifier(name) => name.to_string(),
+    _ => String::new(), //only reachable for programs sema rejected
+  }
+}
+
+//index of a string literal in Module::strings, adding it on first use
+fn add_string(strings: &mut Vec<Vec<u8>>, content: &str) -> u32 {
+  let bytes = string_bytes(content);
+  match strings.iter().position(|s| *s == bytes) {
+    Some(index) => index as u32,
+    None => {
+      strings.push(bytes);
+      (strings.len() - 1) as u32
+    }
+  }
+}
+
+fn lower_global(decl: &GlobalDecl, strings: &mut Vec<Vec<u8>>) -> Global {
+  let init: Vec<Init> = decl.initializer.iter().flatten().map(|value| match value {
+    Expr::Literal { value: SpannedToken { token: Token::StringLiteral(s), .. } } => Init::String(add_string(strings, s)),
+    _ => Init::Word(value.const_value().unwrap_or(0)),
+  }).collect();
+  //a vector's bound is its last index, so `v[n]` has n + 1 words
+  let vector = decl.size.map(|size| match size.token {
+    Token::Integer(n) => (n as u32 + 1).max(init.len() as u32),
+    _ => init.len() as u32,
+  });
+  Global { name: name_of(decl.name), vector, init }
+}
+
+struct FunctionLowering<'s> {
+  sema: &'s SemaOutput,
+  globals: &'s FxHashSet<String>, //data other files define
+  strings: &'s mut Vec<Vec<u8>>,
+  function: Function,
+  current: Option<BlockId>, //None after a terminator
+  prologue: Vec<Instr>, //vector set-up, placed at the top of the entry block
+  labels: Vec<BlockId>, //indexed by Location::Label
+  switches: Vec<FxHashMap<Span, BlockId>>, //case/default span -> block, innermost switch last
+}
+
+impl<'s> FunctionLowering<'s> {
+
+  fn new(sema: &'s SemaOutput, globals: &'s FxHashSet<String>, strings: &'s mut Vec<Vec<u8>>, f: &ast::Function) -> Self {
+    let env = match sema.resolved.get(&f.name.span).map(|s| s.slot) {
+      Some(Location::Function { func_index }) => sema.table.function(func_index),
+      _ => panic!("function `{}` was not resolved; lower only programs sema accepted", name_of(f.name)),
+    };
+    let mut lowering = Self {
+      sema,
+      globals,
+      strings,
+      function: Function {
+        name: name_of(f.name),
+        params: env.params.len() as u32,
+        frame_size: env.slot_count,
+        reg_count: 0,
+        blocks: Vec::new(),
+      },
+      current: None,
+      prologue: Vec::new(),
+      labels: Vec::new(),
+      switches: Vec::new(),
+    };
+    let entry = lowering.new_block(None);
+    lowering.current = Some(entry);
+    for &label in &env.labels {
+      let block = lowering.new_block(Some(sema.table.name(label).to_string()));
+      lowering.labels.push(block);
+    }
+    lowering
+  }
+
+  fn finish(mut self, body: &Stmt) -> Function {
+    walk_stmt(&mut self, body);
+    //falling off the end returns 0
+    self.terminate(Terminator::Return(Operand::Const(0)));
+    let entry = &mut self.function.blocks[0].instrs;
+    entry.splice(0..0, self.prologue);
+    self.function
+  }
+
+  fn new_block(&mut self, label: Option<String>) -> BlockId {
+    let id = BlockId(self.function.blocks.len() as u32);
+    self.function.blocks.push(Block { label, instrs: Vec::new(), term: Terminator::Return(Operand::Const(0)) });
+    id
+  }
+
+  fn new_reg(&mut self) -> VReg {
+    let reg = VReg(self.function.reg_count);
+    self.function.reg_count += 1;
+    reg
+  }
+
+  fn emit(&mut self, instr: Instr) {
+    let block = match self.current {
+      Some(block) => block,
+      None => {
+        let block = self.new_block(None);
+        self.current = Some(block);
+        block
+      }
+    };
+    self.function.blocks[block.0 as usize].instrs.push(instr);
+  }
+
+  //end the current block; nothing to do in unreachable code
+  fn terminate(&mut self, term: Terminator) {
+    if let Some(block) = self.current.take() {
+      self.function.blocks[block.0 as usize].term = term;
+    }
+  }
+
+  //continue in `block`, falling through to it from the current block
+  fn start(&mut self, block: BlockId) {
+    self.terminate(Terminator::Jump(block));
+    self.current = Some(block);
+  }
+
+  fn symbol(&self, token: &SpannedToken) -> Symbol {
+    match self.sema.resolved.get(&token.span) {
+      Some(symbol) => *symbol,
+      None => panic!("`{}` was not resolved; lower only programs sema accepted", name_of(token)),
+    }
+  }
+
+  fn unary(&mut self, op: UnaryOp, src: Operand) -> Operand {
+    if let Operand::Const(x) = src {
+      return Operand::Const(op.eval(x));
+    }
+    let dest = self.new_reg();
+    self.emit(Instr::Unary { dest, op, src });
+    Operand::Reg(dest)
+  }
+
+  fn binary(&mut self, op: BinaryOp, lhs: Operand, rhs: Operand) -> Operand {
+    if let (Operand::Const(l), Operand::Const(r)) = (lhs, rhs)
+      && let Some(value) = op.eval(l, r) {
+      return Operand::Const(value);
+    }
+    let dest = self.new_reg();
+    self.emit(Instr::Binary { dest, op, lhs, rhs });
+    Operand::Reg(dest)
+  }
+
+  fn load(&mut self, addr: Operand) -> Operand {
+    let dest = self.new_reg();
+    self.emit(Instr::Load { dest, addr });
+    Operand::Reg(dest)
+  }
+
+  fn store(&mut self, addr: Operand, value: Operand) {
+    self.emit(Instr::Store { addr, value });
+  }
+
+  //where a name's value lives; for functions and labels, the value itself
+  fn symbol_address(&mut self, symbol: Symbol) -> Operand {
+    let dest = self.new_reg();
+    let instr = match symbol.slot {
+      Location::Local { slot } => Instr::LocalAddr { dest, offset: slot },
+      Location::Label { index } => Instr::LabelAddr { dest, block: self.labels[index as usize] },
+      Location::Global { .. } | Location::Function { .. } | Location::Import { .. } => {
+        Instr::GlobalAddr { dest, name: self.sema.table.name(symbol.name).to_string() }
+      }
+    };
+    self.emit(instr);
+    Operand::Reg(dest)
+  }
+
+  //address of an lvalue: a name, `*p` or `v[i]`
+  fn address(&mut self, expr: &Expr) -> Operand {
+    match expr {
+      Expr::Variable { name } => {
+        let symbol = self.symbol(name);
+        self.symbol_address(symbol)
+      }
+      Expr::Unary { operator: SpannedToken { token: Token::Operator(Operator::Star), .. }, right } => walk_expr(self, right),
+      Expr::Get { target, index } => {
+        let base = walk_expr(self, target);
+        let index = walk_expr(self, index);
+        self.binary(BinaryOp::Add, base, index)
+      }
+      Expr::Grouping { expression } => self.address(expression),
+      //not an lvalue (`&1`); the parser reports assignments to these, so this is only `&` of a value
+      _ => walk_expr(self, expr),
+    }
+  }
+
+  //`++x` and `x--` style updates: (old value, new value)
+  fn increment(&mut self, target: &Expr, operator: &SpannedToken) -> (Operand, Operand) {
+    let op = match operator.token {
+      Token::Operator(Operator::Dec) => BinaryOp::Sub,
+      _ => BinaryOp::Add,
+    };
+    let addr = self.address(target);
+    let old = self.load(addr);
+    let new = self.binary(op, old, Operand::Const(1));
+    self.store(addr, new);
+    (old, new)
+  }
+
+  fn binary_expr(&mut self, left: &Expr, operator: &SpannedToken, right: &Expr) -> Operand {
+    let lhs = walk_expr(self, left);
+    let rhs = walk_expr(self, right);
+    match operator.token {
+      Token::Operator(op) => match BinaryOp::from_operator(op) {
+        Some(op) => self.binary(op, lhs, rhs),
+        None => panic!("`{}` is not a binary operator", op),
+      },
+      _ => panic!("binary expression without an operator"),
+    }
+  }
+}
+
+impl ExprVisitor<Operand> for FunctionLowering<'_> {
+
+  fn visit_assign(&mut self, lvalue: &Expr, operator: &SpannedToken, value: &Expr) -> Operand {
+    let addr = self.address(lvalue);
+    let base = match operator.token {
+      Token::Operator(op) => op.compound_base().and_then(BinaryOp::from_operator),
+      _ => None,
+    };
+    //x =op y stores x op y; x is read before y is evaluated
+    let result = match base {
+      Some(op) => {
+        let old = self.load(addr);
+        let rhs = walk_expr(self, value);
+        self.binary(op, old, rhs)
+      }
+      None => walk_expr(self, value),
+    };
+    self.store(addr, result);
+    result
+  }
+
+  fn visit_binary(&mut self, left: &Expr, operator: &SpannedToken, right: &Expr) -> Operand {
+    self.binary_expr(left, operator, right)
+  }
+
+  fn visit_call(&mut self, callee: &Expr, arguments: &[Expr]) -> Operand {
+    //functions and imports are called by name; anything else is a computed address
+    let direct = match callee {
+      Expr::Variable { name } => {
+        let symbol = self.symbol(name);
+        match symbol.slot {
+          Location::Function { .. } | Location::Import { .. } => Some(self.sema.table.name(symbol.name).to_string()),
+          _ => None,
+        }
+      }
+      _ => None,
+    };
+    let callee = match direct {
+      Some(name) => Callee::Direct(name),
+      None => Callee::Indirect(walk_expr(self, callee)),
+    };
+    let mut args = Vec::with_capacity(arguments.len());
+    for argument in arguments {
+      args.push(walk_expr(self, argument));
+    }
+    let dest = self.new_reg();
+    self.emit(Instr::Call { dest, callee, args });
+    Operand::Reg(dest)
+  }
+
+  fn visit_grouping(&mut self, expression: &Expr) -> Operand {
+    walk_expr(self, expression)
+  }
+
+  fn visit_literal(&mut self, value: &SpannedToken) -> Operand {
+    match &value.token {
+      Token::Integer(n) | Token::CharLiteral(n) => Operand::Const(*n),
+      Token::StringLiteral(s) => {
+        let index = add_string(self.strings, s);
+        let dest = self.new_reg();
+        self.emit(Instr::StringAddr { dest, index });
+        Operand::Reg(dest)
+      }
+      _ => Operand::Const(0),
+    }
+  }
+
+  fn visit_unary(&mut self, operator: &SpannedToken, right: &Expr) -> Operand {
+    let Token::Operator(op) = operator.token else { return walk_expr(self, right) };
+    match op {
+      Operator::Minus => {
+        let value = walk_expr(self, right);
+        self.unary(UnaryOp::Neg, value)
+      }
+      Operator::Bang => {
+        let value = walk_expr(self, right);
+        self.unary(UnaryOp::Not, value)
+      }
+      Operator::Tilde => {
+        let value = walk_expr(self, right);
+        self.unary(UnaryOp::BitNot, value)
+      }
+      Operator::Star => {
+        let addr = walk_expr(self, right);
+        self.load(addr)
+      }
+      Operator::Amp => self.address(right),
+      Operator::Inc | Operator::Dec => self.increment(right, operator).1,
+      _ => walk_expr(self, right),
+    }
+  }
+
+  fn visit_logical(&mut self, left: &Expr, operator: &SpannedToken, right: &Expr) -> Operand {
+    self.binary_expr(left, operator, right)
+  }
+
+  fn visit_variable(&mut self, name: &SpannedToken) -> Operand {
+    let symbol = self.symbol(name);
+    let addr = self.symbol_address(symbol);
+    match symbol.slot {
+      //a function's or label's value is its address, and so is an import's unless it is data
+      Location::Function { .. } | Location::Label { .. } => addr,
+      Location::Import { .. } if !self.globals.contains(self.sema.table.name(symbol.name)) => addr,
+      _ => self.load(addr),
+    }
+  }
+
+  fn visit_get(&mut self, target: &Expr, index: &Expr) -> Operand {
+    let base = walk_expr(self, target);
+    let index = walk_expr(self, index);
+    let addr = self.binary(BinaryOp::Add, base, index);
+    self.load(addr)
+  }
+
+  fn visit_ternary(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) -> Operand {
+    let cond = walk_expr(self, condition);
+    if let Operand::Const(c) = cond {
+      return walk_expr(self, if c != 0 { then_branch } else { else_branch });
+    }
+    let (then_block, else_block, join) = (self.new_block(None), self.new_block(None), self.new_block(None));
+    let dest = self.new_reg();
+    self.terminate(Terminator::Branch { cond, then_block, else_block });
+    for (block, branch) in [(then_block, then_branch), (else_block, else_branch)] {
+      self.current = Some(block);
+      let src = walk_expr(self, branch);
+      self.emit(Instr::Copy { dest, src });
+      self.terminate(Terminator::Jump(join));
+    }
+    self.current = Some(join);
+    Operand::Reg(dest)
+  }
+
+  fn visit_postfix(&mut self, left: &Expr, operator: &SpannedToken) -> Operand {
+    self.increment(left, operator).0
+  }
+
+  fn visit_error_expr(&mut self, _span: Span) -> Operand {
+    Operand::Const(0)
+  }
+}
+
+impl StmtVisitor<()> for FunctionLowering<'_> {
+
+  fn visit_block(&mut self, statements: &[Stmt]) {
+    for statement in statements {
+      walk_stmt(self, statement);
+    }
+  }
+
+  fn visit_auto(&mut self, declarations: &[AutoDecl]) {
+    //a vector's word points at storage after the named slots; set once on entry since autos are
+    //function scoped
+    for decl in declarations {
+      let Some(size) = decl.size else { continue };
+      let Location::Local { slot } = self.symbol(decl.name).slot else { continue };
+      let words = match size.token {
+        Token::Integer(n) => n as u32 + 1,
+        _ => 1,
+      };
+      let offset = self.function.frame_size;
+      self.function.frame_size += words;
+      let (storage, pointer) = (self.new_reg(), self.new_reg());
+      self.prologue.push(Instr::LocalAddr { dest: storage, offset });
+      self.prologue.push(Instr::LocalAddr { dest: pointer, offset: slot });
+      self.prologue.push(Instr::Store { addr: Operand::Reg(pointer), value: Operand::Reg(storage) });
+    }
+  }
+
+  fn visit_extrn(&mut self, _names: &[&SpannedToken]) {}
+
+  fn visit_expression(&mut self, expression: &Expr) {
+    walk_expr(self, expression);
+  }
+
+  fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+    let cond = walk_expr(self, condition);
+    let then_block = self.new_block(None);
+    let else_block = else_branch.map(|_| self.new_block(None));
+    let join = self.new_block(None);
+    self.terminate(Terminator::Branch { cond, then_block, else_block: else_block.unwrap_or(join) });
+
+    self.current = Some(then_block);
+    walk_stmt(self, then_branch);
+    self.terminate(Terminator::Jump(join));
+    if let (Some(block), Some(branch)) = (else_block, else_branch) {
+      self.current = Some(block);
+      walk_stmt(self, branch);
+      self.terminate(Terminator::Jump(join));
+    }
+    self.current = Some(join);
+  }
+
+  fn visit_while(&mut self, condition: &Expr, body: &Stmt) {
+    let head = self.new_block(None);
+    self.start(head);
+    let cond = walk_expr(self, condition);
+    let (body_block, exit) = (self.new_block(None), self.new_block(None));
+    self.terminate(Terminator::Branch { cond, then_block: body_block, else_block: exit });
+
+    self.current = Some(body_block);
+    walk_stmt(self, body);
+    self.terminate(Terminator::Jump(head));
+    self.current = Some(exit);
+  }
+
+  fn visit_switch(&mut self, condition: &Expr, cases: &[Stmt]) {
+    let value = walk_expr(self, condition);
+    let labels = ast::switch_labels(cases);
+
+    let mut targets = FxHashMap::default();
+    let (mut values, mut default) = (Vec::new(), None);
+    for (span, case) in labels {
+      let block = self.new_block(None);
+      targets.insert(span, block);
+      match case {
+        Some(n) => values.push((n, block)),
+        None => default = Some(block),
+      }
+    }
+    let exit = self.new_block(None);
+    self.terminate(Terminator::Switch { value, cases: values, default: default.unwrap_or(exit) });
+
+    //the body starts unreachable; each case label is a way in
+    self.switches.push(targets);
+    for statement in cases {
+      walk_stmt(self, statement);
+    }
+    self.switches.pop();
+    self.start(exit);
+  }
+
+  fn visit_case(&mut self, value: &Expr, body: &Stmt) {
+    if let Some(&block) = self.switches.last().and_then(|targets| targets.get(&value.span())) {
+      self.start(block);
+    }
+    walk_stmt(self, body);
+  }
+
+  fn visit_default(&mut self, keyword: &SpannedToken, body: &Stmt) {
+    if let Some(&block) = self.switches.last().and_then(|targets| targets.get(&keyword.span)) {
+      self.start(block);
+    }
+    walk_stmt(self, body);
+  }
+
+  fn visit_label(&mut self, name: &SpannedToken, body: &Stmt) {
+    if let Location::Label { index } = self.symbol(name).slot {
+      self.start(self.labels[index as usize]);
+    }
+    walk_stmt(self, body);
+  }
+
+  fn visit_goto(&mut self, expression: &Expr) {
+    match self.sema.gotos.get(&expression.span()) {
+      Some(&GotoTarget::Label { index }) => self.terminate(Terminator::Jump(self.labels[index as usize])),
+      _ => {
+        //a computed goto can land on any label of the function
+        let addr = walk_expr(self, expression);
+        let targets = self.labels.clone();
+        self.terminate(Terminator::IndirectJump { addr, targets });
+      }
+    }
+  }
+
+  fn visit_return(&mut self, value: Option<&Expr>) {
+    let value = match value {
+      Some(value) => walk_expr(self, value),
+      None => Operand::Const(0),
+    };
+    self.terminate(Terminator::Return(value));
+  }
+
+  fn visit_null(&mut self) {}
+
+  fn visit_error_stmt(&mut self, _span: Span) {}
+}
@@ -0,0 +1,305 @@
+use crate::lexer::token::Operator;
+
+pub mod lower;
+pub mod text;
+pub mod cfg;
+pub mod ssa;
+pub mod link;
+
+//linear three-address IR. Values are untyped machine words held in virtual registers; memory is
+//addressed in words, so `p + 1` is the word after `p` and backends scale addresses to bytes.
+//Every auto and parameter lives in a frame word (reached with LocalAddr + Load/Store) so that
+//`&x` works, and params take words 0..params in order so that `&x1 + 1` is the next param.
+//ssa::to_ssa promotes the ones whose address is never taken into registers.
+//Blocks are stored in a Vec and a BlockId is an index into it; blocks[0] is the entry. A register
+//is written once, except the result of `?:` which each arm copies into.
+
+pub type Word = i64;
+
+///Bytes per word on every target.
+pub const WORD_BYTES: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VReg(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+  Reg(VReg),
+  Const(Word),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+  Neg,
+  Not, //logical: 1 if zero, else 0
+  BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+  Add, Sub, Mul, Div, Rem,
+  And, Or, Xor, Shl, Shr,
+  Eq, Ne, Lt, Le, Gt, Ge, //comparisons give 1 or 0
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callee {
+  Direct(String), //a function of the module or an import, by name
+  Indirect(Operand), //a computed function address
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+  Copy { dest: VReg, src: Operand }, //also joins the arms of `?:`, so its dest may be written in two blocks
+  Unary { dest: VReg, op: UnaryOp, src: Operand },
+  Binary { dest: VReg, op: BinaryOp, lhs: Operand, rhs: Operand },
+  Load { dest: VReg, addr: Operand }, //one word from a word address
+  Store { addr: Operand, value: Operand },
+  LocalAddr { dest: VReg, offset: u32 }, //address of word `offset` of the current frame
+  GlobalAddr { dest: VReg, name: String }, //address of a global's first word, or of a function
+  StringAddr { dest: VReg, index: u32 }, //address of Module::strings[index]
+  LabelAddr { dest: VReg, block: BlockId }, //a label's value, for computed gotos
+  Call { dest: VReg, callee: Callee, args: Vec<Operand> },
+  Phi { dest: VReg, args: Vec<(BlockId, Operand)> }, //only in SSA form, at the top of a block; one arg per predecessor
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+  Jump(BlockId),
+  Branch { cond: Operand, then_block: BlockId, else_block: BlockId }, //then_block if cond != 0
+  Switch { value: Operand, cases: Vec<(Word, BlockId)>, default: BlockId },
+  Return(Operand),
+  IndirectJump { addr: Operand, targets: Vec<BlockId> }, //addr is a LabelAddr value; targets are every block it can be
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+  pub label: Option<String>, //the B label that starts this block, if any
+  pub instrs: Vec<Instr>,
+  pub term: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+  pub name: String,
+  pub params: u32, //arguments arrive in frame words 0..params
+  pub frame_size: u32, //words: params, autos, then vector storage
+  pub reg_count: u32, //VRegs are numbered 0..reg_count
+  pub blocks: Vec<Block>,
+}
+
+///A word of global initializer data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Init {
+  Word(Word),
+  String(u32), //address of Module::strings[index]
+}
+
+///A global definition. A scalar `x 1;` is one word at `x`, zero when it has no initializer. A vector
+///`v[n] ...;` is one word at `v` holding the address of `n + 1` storage words, which the initializers
+///fill from the start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Global {
+  pub name: String,
+  pub vector: Option<u32>, //storage words of a vector
+  pub init: Vec<Init>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Module {
+  pub globals: Vec<Global>,
+  pub functions: Vec<Function>,
+  pub strings: Vec<Vec<u8>>, //contents with the `*e` terminator, see token::string_bytes
+  pub imports: Vec<String>, //extrn names the module uses but does not define
+}
+
+impl UnaryOp {
+  pub const ALL: [UnaryOp; 3] = [UnaryOp::Neg, UnaryOp::Not, UnaryOp::BitNot];
+
+  pub fn eval(self, x: Word) -> Word {
+    match self {
+      UnaryOp::Neg => x.wrapping_neg(),
+      UnaryOp::Not => (x == 0) as Word,
+      UnaryOp::BitNot => !x,
+    }
+  }
+}
+
+impl BinaryOp {
+  pub const ALL: [BinaryOp; 16] = [
+    BinaryOp::Add, BinaryOp::Sub, BinaryOp::Mul, BinaryOp::Div, BinaryOp::Rem,
+    BinaryOp::And, BinaryOp::Or, BinaryOp::Xor, BinaryOp::Shl, BinaryOp::Shr,
+    BinaryOp::Eq, BinaryOp::Ne, BinaryOp::Lt, BinaryOp::Le, BinaryOp::Gt, BinaryOp::Ge,
+  ];
+
+
+  ///The IR operation for a B binary operator.
+  pub fn from_operator(op: Operator) -> Option<BinaryOp> {
+    Some(match op {
+      Operator::Plus => BinaryOp::Add,
+      Operator::Minus => BinaryOp::Sub,
+      Operator::Star => BinaryOp::Mul,
+      Operator::Slash => BinaryOp::Div,
+      Operator::Percent => BinaryOp::Rem,
+      Operator::Amp => BinaryOp::And,
+      Operator::Bar => BinaryOp::Or,
+      Operator::Caret => BinaryOp::Xor,
+      Operator::LShift => BinaryOp::Shl,
+      Operator::RShift => BinaryOp::Shr,
+      Operator::Equal => BinaryOp::Eq,
+      Operator::NotEqual => BinaryOp::Ne,
+      Operator::Less => BinaryOp::Lt,
+      Operator::LessEq => BinaryOp::Le,
+      Operator::Greater => BinaryOp::Gt,
+      Operator::GreaterEq => BinaryOp::Ge,
+      _ => return None,
+    })
+  }
+
+  ///Result of the operation on two words; None for division or remainder by zero. Arithmetic wraps
+  ///and shift counts are taken modulo the word size, as on the native targets.
+  pub fn eval(self, l: Word, r: Word) -> Option<Word> {
+    Some(match self {
+      BinaryOp::Add => l.wrapping_add(r),
+      BinaryOp::Sub => l.wrapping_sub(r),
+      BinaryOp::Mul => l.wrapping_mul(r),
+      BinaryOp::Div => if r == 0 { return None } else { l.wrapping_div(r) },
+      BinaryOp::Rem => if r == 0 { return None } else { l.wrapping_rem(r) },
+      BinaryOp::And => l & r,
+      BinaryOp::Or => l | r,
+      BinaryOp::Xor => l ^ r,
+      BinaryOp::Shl => l.wrapping_shl(r as u32),
+      BinaryOp::Shr => l.wrapping_shr(r as u32),
+      BinaryOp::Eq => (l == r) as Word,
+      BinaryOp::Ne => (l != r) as Word,
+      BinaryOp::Lt => (l < r) as Word,
+      BinaryOp::Le => (l <= r) as Word,
+      BinaryOp::Gt => (l > r) as Word,
+      BinaryOp::Ge => (l >= r) as Word,
+    })
+  }
+}
+
+impl Instr {
+
+  ///The register the instruction writes, if any.
+  pub fn dest(&self) -> Option<VReg> {
+    match self {
+      Instr::Copy { dest, .. } | Instr::Unary { dest, .. } | Instr::Binary { dest, .. } |
+      Instr::Load { dest, .. } | Instr::LocalAddr { dest, .. } | Instr::GlobalAddr { dest, .. } |
+      Instr::StringAddr { dest, .. } | Instr::LabelAddr { dest, .. } | Instr::Call { dest, .. } |
+      Instr::Phi { dest, .. } => Some(*dest),
+      Instr::Store { .. } => None,
+    }
+  }
+
+  ///Operands the instruction reads, in evaluation order.
+  pub fn operands(&self) -> Vec<Operand> {
+    match self {
+      Instr::Copy { src, .. } | Instr::Unary { src, .. } => vec![*src],
+      Instr::Binary { lhs, rhs, .. } => vec![*lhs, *rhs],
+      Instr::Load { addr, .. } => vec![*addr],
+      Instr::Store { addr, value } => vec![*addr, *value],
+      Instr::Call { callee, args, .. } => {
+        let mut operands = Vec::with_capacity(args.len() + 1);
+        if let Callee::Indirect(target) = callee {
+          operands.push(*target);
+        }
+        operands.extend(args.iter().copied());
+        operands
+      }
+      Instr::Phi { args, .. } => args.iter().map(|&(_, value)| value).collect(),
+      Instr::LocalAddr { .. } | Instr::GlobalAddr { .. } | Instr::StringAddr { .. } | Instr::LabelAddr { .. } => Vec::new(),
+    }
+  }
+
+  ///The operands, for rewriting in place.
+  pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+    match self {
+      Instr::Copy { src, .. } | Instr::Unary { src, .. } => vec![src],
+      Instr::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+      Instr::Load { addr, .. } => vec![addr],
+      Instr::Store { addr, value } => vec![addr, value],
+      Instr::Call { callee, args, .. } => {
+        let mut operands = Vec::with_capacity(args.len() + 1);
+        if let Callee::Indirect(target) = callee {
+          operands.push(target);
+        }
+        operands.extend(args.iter_mut());
+        operands
+      }
+      Instr::Phi { args, .. } => args.iter_mut().map(|(_, value)| value).collect(),
+      Instr::LocalAddr { .. } | Instr::GlobalAddr { .. } | Instr::StringAddr { .. } | Instr::LabelAddr { .. } => Vec::new(),
+    }
+  }
+}
+
+impl Terminator {
+
+  ///Blocks control can go to next, in order and without duplicates.
+  pub fn successors(&self) -> Vec<BlockId> {
+    let mut blocks = match self {
+      Terminator::Jump(target) => vec![*target],
+      Terminator::Branch { then_block, else_block, .. } => vec![*then_block, *else_block],
+      Terminator::Switch { cases, default, .. } => {
+        cases.iter().map(|&(_, block)| block).chain(std::iter::once(*default)).collect()
+      }
+      Terminator::Return(_) => Vec::new(),
+      Terminator::IndirectJump { targets, .. } => targets.clone(),
+    };
+    let mut seen = Vec::with_capacity(blocks.len());
+    blocks.retain(|b| if seen.contains(b) { false } else { seen.push(*b); true });
+    blocks
+  }
+
+  ///Operands the terminator reads.
+  pub fn operands(&self) -> Vec<Operand> {
+    match self {
+      Terminator::Jump(_) => Vec::new(),
+      Terminator::Branch { cond: value, .. } | Terminator::Switch { value, .. } |
+      Terminator::Return(value) | Terminator::IndirectJump { addr: value, .. } => vec![*value],
+    }
+  }
+
+  pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+    match self {
+      Terminator::Jump(_) => Vec::new(),
+      Terminator::Branch { cond: value, .. } | Terminator::Switch { value, .. } |
+      Terminator::Return(value) | Terminator::IndirectJump { addr: value, .. } => vec![value],
+    }
+  }
+
+  ///Every block reference in the terminator, duplicates included, for retargeting edges.
+  pub fn targets_mut(&mut self) -> Vec<&mut BlockId> {
+    match self {
+      Terminator::Jump(target) => vec![target],
+      Terminator::Branch { then_block, else_block, .. } => vec![then_block, else_block],
+      Terminator::Switch { cases, default, .. } => cases.iter_mut().map(|(_, target)| target).chain(std::iter::once(default)).collect(),
+      Terminator::Return(_) => Vec::new(),
+      Terminator::IndirectJump { targets, .. } => targets.iter_mut().collect(),
+    }
+  }
+}
+
+impl Function {
+  pub fn block(&self, id: BlockId) -> &Block {
+    &self.blocks[id.0 as usize]
+  }
+}
+
+impl Module {
+  pub fn function(&self, name: &str) -> Option<&Function> {
+    self.functions.iter().find(|f| f.name == name)
+  }
+}
+
+///Words holding a string's bytes, packed little-endian (byte i is bits 8*(i%8).. of word i/8)
+///and zero padded, as every target lays strings out in memory.
+pub fn pack_string(bytes: &[u8]) -> Vec<Word> {
+  bytes.chunks(WORD_BYTES as usize)
+    .map(|chunk| chunk.iter().rev().fold(0, |word, &b| (word << 8) | b as Word))
+    .collect()
+}
@@ -0,0 +1,376 @@
+use std::borrow::Cow;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::ir::*;
+use crate::ir::cfg::{remove_unreachable, Cfg, Dominators};
+
+//SSA form. to_ssa keeps autos whose address never escapes in registers instead of frame words:
+//each store makes a new value, each load uses the value reaching it, and where values from
+//different paths meet a phi picks one by predecessor. The register `?:` writes from both arms is
+//renamed the same way, so afterwards every register has exactly one definition. Phis go on the
+//iterated dominance frontier of each variable's definitions (Cytron et al.), values are renamed
+//walking the dominator tree, and phis nothing reads are dropped at the end.
+//
+//from_ssa replaces phis with copies at the end of each predecessor, splitting critical edges so
+//the copies only run on their own edge. A phi's copies happen in parallel, so they are ordered
+//to read every source before its register is overwritten.
+//
+//Promoted autos keep their frame words, so the frame layout lowering chose stays valid.
+
+///Frame words to_ssa can keep in registers: autos (not parameters) whose address is only ever
+///loaded from or stored to. The address of an auto only becomes a value through `&`, so these are
+///exactly the autos the source never takes the address of.
+fn promotable_slots(function: &Function) -> Vec<u32> {
+  let mut addrs: FxHashMap<VReg, u32> = FxHashMap::default();
+  for instr in function.blocks.iter().flat_map(|b| &b.instrs) {
+    if let Instr::LocalAddr { dest, offset } = instr {
+      addrs.insert(*dest, *offset);
+    }
+  }
+  let mut escaped = FxHashSet::default();
+  let mut escape = |operand: Operand| {
+    if let Operand::Reg(reg) = operand && let Some(&offset) = addrs.get(&reg) {
+      escaped.insert(offset);
+    }
+  };
+  for block in &function.blocks {
+    for instr in &block.instrs {
+      match instr {
+        Instr::Load { .. } => {}
+        Instr::Store { value, .. } => escape(*value),
+        instr => instr.operands().into_iter().for_each(&mut escape),
+      }
+    }
+    block.term.operands().into_iter().for_each(&mut escape);
+  }
+  let mut slots: Vec<u32> = addrs.values().copied()
+    .filter(|&offset| offset >= function.params && !escaped.contains(&offset))
+    .collect();
+  slots.sort();
+  slots.dedup();
+  slots
+}
+
+///Put a function fresh from lowering into SSA form and return the frame words promoted to
+///registers. Unreachable blocks are removed first. A function with a computed goto is left as it
+///is, since from_ssa could not split an edge into a label block to hold its copies.
+pub fn to_ssa(function: &mut Function) -> Vec<u32> {
+  if function.blocks.iter().any(|b| matches!(b.term, Terminator::IndirectJump { .. })) {
+    return Vec::new();
+  }
+  remove_unreachable(function);
+  let slots = promotable_slots(function);
+
+  //the variables renamed: promoted slots first, numbered in order, then registers written twice
+  let slot_var: FxHashMap<u32, usize> = slots.iter().enumerate().map(|(var, &offset)| (offset, var)).collect();
+  let mut addr_var: FxHashMap<VReg, usize> = FxHashMap::default();
+  let mut writes: FxHashMap<VReg, u32> = FxHashMap::default();
+  for instr in function.blocks.iter().flat_map(|b| &b.instrs) {
+    debug_assert!(!matches!(instr, Instr::Phi { .. }), "to_ssa expects a function without phis");
+    if let Instr::LocalAddr { dest, offset } = instr && let Some(&var) = slot_var.get(offset) {
+      addr_var.insert(*dest, var);
+    }
+    if let Some(dest) = instr.dest() {
+      *writes.entry(dest).or_default() += 1;
+    }
+  }
+  let mut joined: Vec<VReg> = writes.into_iter().filter(|&(_, n)| n > 1).map(|(reg, _)| reg).collect();
+  joined.sort();
+  let reg_var: FxHashMap<VReg, usize> = joined.iter().enumerate().map(|(i, &reg)| (reg, slots.len() + i)).collect();
+  let vars = slots.len() + joined.len();
+  if vars == 0 {
+    return slots;
+  }
+
+  //blocks defining each variable
+  let mut defs: Vec<Vec<BlockId>> = vec![Vec::new(); vars];
+  for (i, block) in function.blocks.iter().enumerate() {
+    for instr in &block.instrs {
+      let var = match instr {
+        Instr::Store { addr: Operand::Reg(addr), .. } => addr_var.get(addr),
+        instr => instr.dest().and_then(|dest| reg_var.get(&dest)),
+      };
+      if let Some(&var) = var && defs[var].last() != Some(&BlockId(i as u32)) {
+        defs[var].push(BlockId(i as u32));
+      }
+    }
+  }
+
+  let cfg = Cfg::new(function);
+  let doms = Dominators::new(&cfg);
+  let frontiers = doms.frontiers(&cfg);
+  let mut reg_count = function.reg_count;
+  let mut phis: Vec<Vec<(usize, VReg)>> = vec![Vec::new(); cfg.len()];
+  for (var, var_defs) in defs.iter().enumerate() {
+    let mut has_phi = FxHashSet::default();
+    let mut queued: FxHashSet<BlockId> = var_defs.iter().copied().collect();
+    let mut work = var_defs.clone();
+    while let Some(block) = work.pop() {
+      for &frontier in &frontiers[block.0 as usize] {
+        if has_phi.insert(frontier) {
+          phis[frontier.0 as usize].push((var, VReg(reg_count)));
+          reg_count += 1;
+          //a phi is a definition too
+          if queued.insert(frontier) {
+            work.push(frontier);
+          }
+        }
+      }
+    }
+  }
+
+  let mut renamer = Renamer {
+    phi_args: phis.iter().map(|block| vec![Vec::new(); block.len()]).collect(),
+    phis,
+    stacks: vec![Vec::new(); vars],
+    loaded: FxHashMap::default(),
+    addr_var,
+    reg_var,
+    reg_count,
+    cfg: &cfg,
+    doms: &doms,
+  };
+  if !function.blocks.is_empty() {
+    renamer.rename(&mut function.blocks, BlockId(0));
+  }
+  function.reg_count = renamer.reg_count;
+  for ((block, phis), args) in function.blocks.iter_mut().zip(renamer.phis).zip(renamer.phi_args) {
+    let phis = phis.into_iter().zip(args).map(|((_, dest), mut args)| {
+      args.sort_by_key(|&(pred, _)| pred);
+      Instr::Phi { dest, args }
+    });
+    block.instrs.splice(0..0, phis);
+  }
+  prune_phis(function);
+  slots
+}
+
+struct Renamer<'c> {
+  phis: Vec<Vec<(usize, VReg)>>, //per block: (variable, dest)
+  phi_args: Vec<Vec<Vec<(BlockId, Operand)>>>, //per block, per phi
+  stacks: Vec<Vec<Operand>>, //per variable: the values reaching the block being renamed, innermost last
+  loaded: FxHashMap<VReg, Operand>, //result of a removed load -> the value it loaded
+  addr_var: FxHashMap<VReg, usize>, //LocalAddr result -> its promoted slot
+  reg_var: FxHashMap<VReg, usize>,
+  reg_count: u32,
+  cfg: &'c Cfg,
+  doms: &'c Dominators,
+}
+
+impl Renamer<'_> {
+  //reading an auto before any assignment gives 0, as with a frame word nothing has stored to
+  fn current(&self, var: usize) -> Operand {
+    self.stacks[var].last().copied().unwrap_or(Operand::Const(0))
+  }
+
+  fn rewrite(&self, operand: &mut Operand) {
+    if let Operand::Reg(reg) = *operand {
+      if let Some(&value) = self.loaded.get(&reg) {
+        *operand = value;
+      } else if let Some(&var) = self.reg_var.get(&reg) {
+        *operand = self.current(var);
+      }
+    }
+  }
+
+  fn rename(&mut self, blocks: &mut [Block], block: BlockId) {
+    let mut pushed = Vec::new();
+    for &(var, dest) in &self.phis[block.0 as usize] {
+      self.stacks[var].push(Operand::Reg(dest));
+      pushed.push(var);
+    }
+
+    let instrs = std::mem::take(&mut blocks[block.0 as usize].instrs);
+    let mut kept = Vec::with_capacity(instrs.len());
+    for mut instr in instrs {
+      instr.operands_mut().into_iter().for_each(|operand| self.rewrite(operand));
+      match &mut instr {
+        Instr::LocalAddr { dest, .. } if self.addr_var.contains_key(dest) => continue,
+        Instr::Load { dest, addr: Operand::Reg(addr) } if self.addr_var.contains_key(addr) => {
+          let value = self.current(self.addr_var[addr]);
+          self.loaded.insert(*dest, value);
+          continue;
+        }
+        Instr::Store { addr: Operand::Reg(addr), value } if self.addr_var.contains_key(addr) => {
+          let var = self.addr_var[addr];
+          self.stacks[var].push(*value);
+          pushed.push(var);
+          continue;
+        }
+        _ => {}
+      }
+      if let Some(dest) = instr.dest() && let Some(&var) = self.reg_var.get(&dest) {
+        //an arm of `?:` copying its value in: the phi can take the value itself
+        if let Instr::Copy { src, .. } = instr {
+          self.stacks[var].push(src);
+          pushed.push(var);
+          continue;
+        }
+        let fresh = VReg(self.reg_count);
+        self.reg_count += 1;
+        set_dest(&mut instr, fresh);
+        self.stacks[var].push(Operand::Reg(fresh));
+        pushed.push(var);
+      }
+      kept.push(instr);
+    }
+    blocks[block.0 as usize].instrs = kept;
+    let mut term = std::mem::replace(&mut blocks[block.0 as usize].term, Terminator::Return(Operand::Const(0)));
+    term.operands_mut().into_iter().for_each(|operand| self.rewrite(operand));
+    blocks[block.0 as usize].term = term;
+
+    for &succ in self.cfg.successors(block) {
+      for i in 0..self.phis[succ.0 as usize].len() {
+        let value = self.current(self.phis[succ.0 as usize][i].0);
+        self.phi_args[succ.0 as usize][i].push((block, value));
+      }
+    }
+    for &child in self.doms.children(block) {
+      self.rename(blocks, child);
+    }
+    for var in pushed {
+      self.stacks[var].pop();
+    }
+  }
+}
+
+fn set_dest(instr: &mut Instr, reg: VReg) {
+  match instr {
+    Instr::Copy { dest, .. } | Instr::Unary { dest, .. } | Instr::Binary { dest, .. } |
+    Instr::Load { dest, .. } | Instr::LocalAddr { dest, .. } | Instr::GlobalAddr { dest, .. } |
+    Instr::StringAddr { dest, .. } | Instr::LabelAddr { dest, .. } | Instr::Call { dest, .. } |
+    Instr::Phi { dest, .. } => *dest = reg,
+    Instr::Store { .. } => {}
+  }
+}
+
+//drop phis no instruction needs: a phi is live if a non-phi instruction, a terminator or a live
+//phi reads it
+fn prune_phis(function: &mut Function) {
+  let mut phi_reads: FxHashMap<VReg, Vec<VReg>> = FxHashMap::default();
+  let mut live = FxHashSet::default();
+  let mut work = Vec::new();
+  let mut read = |operand: Operand, live: &mut FxHashSet<VReg>| {
+    if let Operand::Reg(reg) = operand && live.insert(reg) {
+      work.push(reg);
+    }
+  };
+  for block in &function.blocks {
+    for instr in &block.instrs {
+      match instr {
+        Instr::Phi { dest, args } => {
+          let regs = args.iter().filter_map(|&(_, value)| match value {
+            Operand::Reg(reg) => Some(reg),
+            Operand::Const(_) => None,
+          });
+          phi_reads.insert(*dest, regs.collect());
+        }
+        instr => instr.operands().into_iter().for_each(|operand| read(operand, &mut live)),
+      }
+    }
+    block.term.operands().into_iter().for_each(|operand| read(operand, &mut live));
+  }
+  while let Some(reg) = work.pop() {
+    for &arg in phi_reads.get(&reg).into_iter().flatten() {
+      if live.insert(arg) {
+        work.push(arg);
+      }
+    }
+  }
+  for block in &mut function.blocks {
+    block.instrs.retain(|instr| match instr {
+      Instr::Phi { dest, .. } => live.contains(dest),
+      _ => true,
+    });
+  }
+}
+
+///Take a function out of SSA form: each phi becomes copies at the end of its predecessors, on a
+///new block when the predecessor has other successors. The result writes a phi's register in
+///several blocks, like lowering does for `?:`, and has no phis left for codegen to handle.
+pub fn from_ssa(function: &mut Function) {
+  let cfg = Cfg::new(function);
+  for b in 0..cfg.len() {
+    let target = BlockId(b as u32);
+    let count = function.blocks[b].instrs.iter().take_while(|i| matches!(i, Instr::Phi { .. })).count();
+    if count == 0 {
+      continue;
+    }
+    let phis: Vec<(VReg, Vec<(BlockId, Operand)>)> = function.blocks[b].instrs.drain(..count)
+      .filter_map(|instr| match instr {
+        Instr::Phi { dest, args } => Some((dest, args)),
+        _ => None,
+      })
+      .collect();
+    for &pred in cfg.predecessors(target) {
+      //a predecessor the phi has no argument for is unreachable, so any value will do
+      let copies = phis.iter().map(|(dest, args)| {
+        let value = args.iter().find(|&&(from, _)| from == pred).map_or(Operand::Const(0), |&(_, value)| value);
+        (*dest, value)
+      }).collect();
+      let pred_block = &function.blocks[pred.0 as usize];
+      //an indirect jump's edges cannot be split; to_ssa never gives its targets phis
+      let at = if cfg.successors(pred).len() == 1 || matches!(pred_block.term, Terminator::IndirectJump { .. }) {
+        pred
+      } else {
+        split_edge(function, pred, target)
+      };
+      let copies = sequentialize(copies, &mut function.reg_count);
+      function.blocks[at.0 as usize].instrs.extend(copies);
+    }
+  }
+}
+
+///The function with no phis: taken out of SSA form if it has any, else borrowed unchanged. Backends
+///call this so they accept lowered and SSA-form IR alike.
+pub fn without_phis(function: &Function) -> Cow<'_, Function> {
+  if function.blocks.iter().any(|b| b.instrs.iter().any(|i| matches!(i, Instr::Phi { .. }))) {
+    let mut function = function.clone();
+    from_ssa(&mut function);
+    Cow::Owned(function)
+  } else {
+    Cow::Borrowed(function)
+  }
+}
+
+//put a new block on the edge from -> to and return it
+fn split_edge(function: &mut Function, from: BlockId, to: BlockId) -> BlockId {
+  let block = BlockId(function.blocks.len() as u32);
+  function.blocks.push(Block { label: None, instrs: Vec::new(), term: Terminator::Jump(to) });
+  for target in function.blocks[from.0 as usize].term.targets_mut() {
+    if *target == to {
+      *target = block;
+    }
+  }
+  block
+}
+
+///Order a parallel copy, where every source is read before any register is written, as a sequence
+///of copies. A copy goes once no other pending copy reads its register; when only cycles (like a
+///swap) are left, one register is saved to a fresh one and read from there.
+pub fn sequentialize(mut copies: Vec<(VReg, Operand)>, reg_count: &mut u32) -> Vec<Instr> {
+  copies.retain(|&(dest, src)| src != Operand::Reg(dest));
+  let mut out = Vec::with_capacity(copies.len());
+  while !copies.is_empty() {
+    let ready = copies.iter().position(|&(dest, _)| copies.iter().all(|&(_, src)| src != Operand::Reg(dest)));
+    match ready {
+      Some(i) => {
+        let (dest, src) = copies.remove(i);
+        out.push(Instr::Copy { dest, src });
+      }
+      None => {
+        let saved = copies[0].0;
+        let temp = VReg(*reg_count);
+        *reg_count += 1;
+        out.push(Instr::Copy { dest: temp, src: Operand::Reg(saved) });
+        for (_, src) in &mut copies {
+          if *src == Operand::Reg(saved) {
+            *src = Operand::Reg(temp);
+          }
+        }
+      }
+    }
+  }
+  out
+}
@@ -0,0 +1,602 @@
+use std::fmt;
+
+use crate::ir::*;
+
+//textual IR. One item per line, `;` starts a comment. print() writes the canonical form and parse()
+//reads it back, so print(parse(text)) == text for printed text and IR tests can be written by hand.
+//
+//  import putchar
+//  string "hi\04"
+//  global x = 5
+//  global v[4] = 97, str 0
+//
+//  func main params 1 frame 2 {
+//  bb0:
+//    %0 = local 1
+//    %1 = add 2, 3
+//    store %0, %1
+//    %2 = call putchar(%1)
+//    br %2, bb1, bb2
+//  bb1 top:
+//    switch %1 [1: bb2, -1: bb2] default bb1
+//  bb2:
+//    ret 0
+//  }
+//
+//Blocks are numbered in order and registers are `%n`; a function's register count is one more
+//than the highest register it mentions. Strings are numbered in order too and printed with
+//non-printable bytes, `"` and `\` as two hex digits after a `\`.
+
+impl UnaryOp {
+  pub fn mnemonic(self) -> &'static str {
+    match self {
+      UnaryOp::Neg => "neg",
+      UnaryOp::Not => "not",
+      UnaryOp::BitNot => "bitnot",
+    }
+  }
+}
+
+impl BinaryOp {
+  pub fn mnemonic(self) -> &'static str {
+    match self {
+      BinaryOp::Add => "add",
+      BinaryOp::Sub => "sub",
+      BinaryOp::Mul => "mul",
+      BinaryOp::Div => "div",
+      BinaryOp::Rem => "rem",
+      BinaryOp::And => "and",
+      BinaryOp::Or => "or",
+      BinaryOp::Xor => "xor",
+      BinaryOp::Shl => "shl",
+      BinaryOp::Shr => "shr",
+      BinaryOp::Eq => "eq",
+      BinaryOp::Ne => "ne",
+      BinaryOp::Lt => "lt",
+      BinaryOp::Le => "le",
+      BinaryOp::Gt => "gt",
+      BinaryOp::Ge => "ge",
+    }
+  }
+}
+
+impl fmt::Display for VReg {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "%{}", self.0)
+  }
+}
+
+impl fmt::Display for BlockId {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "bb{}", self.0)
+  }
+}
+
+impl fmt::Display for Operand {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Operand::Reg(reg) => write!(f, "{}", reg),
+      Operand::Const(n) => write!(f, "{}", n),
+    }
+  }
+}
+
+fn join<T: fmt::Display>(items: &[T]) -> String {
+  items.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+impl fmt::Display for Instr {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Instr::Copy { dest, src } => write!(f, "{} = copy {}", dest, src),
+      Instr::Unary { dest, op, src } => write!(f, "{} = {} {}", dest, op.mnemonic(), src),
+      Instr::Binary { dest, op, lhs, rhs } => write!(f, "{} = {} {}, {}", dest, op.mnemonic(), lhs, rhs),
+      Instr::Load { dest, addr } => write!(f, "{} = load {}", dest, addr),
+      Instr::Store { addr, value } => write!(f, "store {}, {}", addr, value),
+      Instr::LocalAddr { dest, offset } => write!(f, "{} = local {}", dest, offset),
+      Instr::GlobalAddr { dest, name } => write!(f, "{} = global {}", dest, name),
+      Instr::StringAddr { dest, index } => write!(f, "{} = str {}", dest, index),
+      Instr::LabelAddr { dest, block } => write!(f, "{} = label {}", dest, block),
+      Instr::Call { dest, callee: Callee::Direct(name), args } => write!(f, "{} = call {}({})", dest, name, join(args)),
+      Instr::Call { dest, callee: Callee::Indirect(target), args } => write!(f, "{} = call *{}({})", dest, target, join(args)),
+      Instr::Phi { dest, args } => {
+        let args: Vec<String> = args.iter().map(|(block, value)| format!("{}: {}", block, value)).collect();
+        write!(f, "{} = phi [{}]", dest, args.join(", "))
+      }
+    }
+  }
+}
+
+impl fmt::Display for Terminator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Terminator::Jump(target) => write!(f, "jump {}", target),
+      Terminator::Branch { cond, then_block, else_block } => write!(f, "br {}, {}, {}", cond, then_block, else_block),
+      Terminator::Switch { value, cases, default } => {
+        let cases: Vec<String> = cases.iter().map(|(n, block)| format!("{}: {}", n, block)).collect();
+        write!(f, "switch {} [{}] default {}", value, cases.join(", "), default)
+      }
+      Terminator::Return(value) => write!(f, "ret {}", value),
+      Terminator::IndirectJump { addr, targets } => write!(f, "ijump {} [{}]", addr, join(targets)),
+    }
+  }
+}
+
+impl fmt::Display for Init {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Init::Word(n) => write!(f, "{}", n),
+      Init::String(index) => write!(f, "str {}", index),
+    }
+  }
+}
+
+fn quote(bytes: &[u8]) -> String {
+  let mut quoted = String::from("\"");
+  for &b in bytes {
+    if (b' '..=b'~').contains(&b) && b != b'"' && b != b'\\' {
+      quoted.push(b as char);
+    } else {
+      quoted.push_str(&format!("\\{:02x}", b));
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+///The canonical text of a module.
+pub fn print(module: &Module) -> String {
+  let mut out = String::new();
+  for name in &module.imports {
+    out.push_str(&format!("import {}\n", name));
+  }
+  for bytes in &module.strings {
+    out.push_str(&format!("string {}\n", quote(bytes)));
+  }
+  for global in &module.globals {
+    out.push_str(&format!("global {}", global.name));
+    if let Some(words) = global.vector {
+      out.push_str(&format!("[{}]", words));
+    }
+    if !global.init.is_empty() {
+      out.push_str(&format!(" = {}", join(&global.init)));
+    }
+    out.push('\n');
+  }
+  for function in &module.functions {
+    if !out.is_empty() {
+      out.push('\n');
+    }
+    out.push_str(&print_function(function));
+  }
+  out
+}
+
+pub fn print_function(function: &Function) -> String {
+  let mut out = format!("func {} params {} frame {} {{\n", function.name, function.params, function.frame_size);
+  for (i, block) in function.blocks.iter().enumerate() {
+    match &block.label {
+      Some(label) => out.push_str(&format!("{} {}:\n", BlockId(i as u32), label)),
+      None => out.push_str(&format!("{}:\n", BlockId(i as u32))),
+    }
+    for instr in &block.instrs {
+      out.push_str(&format!("  {}\n", instr));
+    }
+    out.push_str(&format!("  {}\n", block.term));
+  }
+  out.push_str("}\n");
+  out
+}
+
+///A syntax error in IR text, with its 1-based line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseIrError {
+  pub line: u32,
+  pub message: String,
+}
+
+impl fmt::Display for ParseIrError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "line {}: {}", self.line, self.message)
+  }
+}
+
+impl std::error::Error for ParseIrError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+  Word(String), //names, mnemonics and bbN
+  Reg(u32),
+  Int(Word),
+  Str(Vec<u8>),
+  Punct(char),
+}
+
+impl fmt::Display for Tok {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Tok::Word(word) => write!(f, "`{}`", word),
+      Tok::Reg(n) => write!(f, "`%{}`", n),
+      Tok::Int(n) => write!(f, "`{}`", n),
+      Tok::Str(bytes) => write!(f, "string {}", quote(bytes)),
+      Tok::Punct(c) => write!(f, "`{}`", c),
+    }
+  }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Tok>, String> {
+  let mut tokens = Vec::new();
+  let mut chars = line.char_indices().peekable();
+  while let Some(&(start, c)) = chars.peek() {
+    match c {
+      ';' => break,
+      c if c.is_whitespace() => { chars.next(); }
+      '%' => {
+        chars.next();
+        let digits = take_while(&mut chars, line, |c| c.is_ascii_digit());
+        tokens.push(Tok::Reg(digits.parse().map_err(|_| "expected a register number after `%`".to_string())?));
+      }
+      '-' | '0'..='9' => {
+        chars.next();
+        let rest = take_while(&mut chars, line, |c| c.is_ascii_digit());
+        let text = &line[start..start + 1 + rest.len()];
+        tokens.push(Tok::Int(text.parse().map_err(|_| format!("bad integer `{}`", text))?));
+      }
+      '"' => {
+        chars.next();
+        let mut bytes = Vec::new();
+        loop {
+          match chars.next() {
+            Some((_, '"')) => break,
+            Some((i, '\\')) => {
+              let hex = line.get(i + 1..i + 3).ok_or("unfinished `\\` escape")?;
+              bytes.push(u8::from_str_radix(hex, 16).map_err(|_| format!("bad escape `\\{}`", hex))?);
+              chars.next();
+              chars.next();
+            }
+            Some((_, c)) if c.is_ascii() => bytes.push(c as u8),
+            Some((_, c)) => return Err(format!("non-ASCII `{}` in string; write its bytes as `\\hh`", c)),
+            None => return Err("unterminated string".to_string()),
+          }
+        }
+        tokens.push(Tok::Str(bytes));
+      }
+      c if c.is_alphanumeric() || c == '_' || c == '.' => {
+        let word = take_while(&mut chars, line, |c| c.is_alphanumeric() || c == '_' || c == '.');
+        tokens.push(Tok::Word(word.to_string()));
+      }
+      '(' | ')' | '[' | ']' | '{' | '}' | ',' | ':' | '=' | '*' => {
+        chars.next();
+        tokens.push(Tok::Punct(c));
+      }
+      other => return Err(format!("unexpected character `{}`", other)),
+    }
+  }
+  Ok(tokens)
+}
+
+fn take_while<'l>(chars: &mut std::iter::Peekable<std::str::CharIndices>, line: &'l str, pred: impl Fn(char) -> bool) -> &'l str {
+  let start = chars.peek().map_or(line.len(), |&(i, _)| i);
+  let mut end = start;
+  while let Some(&(i, c)) = chars.peek() {
+    if !pred(c) {
+      break;
+    }
+    end = i + c.len_utf8();
+    chars.next();
+  }
+  &line[start..end]
+}
+
+//tokens of one line
+struct Line {
+  number: u32,
+  tokens: Vec<Tok>,
+  pos: usize,
+}
+
+impl Line {
+  fn error(&self, message: impl Into<String>) -> ParseIrError {
+    ParseIrError { line: self.number, message: message.into() }
+  }
+
+  fn peek(&self) -> Option<&Tok> {
+    self.tokens.get(self.pos)
+  }
+
+  fn next(&mut self) -> Result<Tok, ParseIrError> {
+    let tok = self.tokens.get(self.pos).cloned().ok_or_else(|| self.error("unexpected end of line"))?;
+    self.pos += 1;
+    Ok(tok)
+  }
+
+  fn eat(&mut self, punct: char) -> bool {
+    if self.peek() == Some(&Tok::Punct(punct)) {
+      self.pos += 1;
+      return true;
+    }
+    false
+  }
+
+  fn expect(&mut self, punct: char) -> Result<(), ParseIrError> {
+    match self.next()? {
+      Tok::Punct(c) if c == punct => Ok(()),
+      other => Err(self.error(format!("expected `{}`, found {}", punct, other))),
+    }
+  }
+
+  fn word(&mut self) -> Result<String, ParseIrError> {
+    match self.next()? {
+      Tok::Word(word) => Ok(word),
+      other => Err(self.error(format!("expected a name, found {}", other))),
+    }
+  }
+
+  fn keyword(&mut self, keyword: &str) -> Result<(), ParseIrError> {
+    match self.next()? {
+      Tok::Word(word) if word == keyword => Ok(()),
+      other => Err(self.error(format!("expected `{}`, found {}", keyword, other))),
+    }
+  }
+
+  fn int(&mut self) -> Result<Word, ParseIrError> {
+    match self.next()? {
+      Tok::Int(n) => Ok(n),
+      other => Err(self.error(format!("expected an integer, found {}", other))),
+    }
+  }
+
+  fn count(&mut self) -> Result<u32, ParseIrError> {
+    let n = self.int()?;
+    u32::try_from(n).map_err(|_| self.error(format!("`{}` is out of range", n)))
+  }
+
+  fn operand(&mut self) -> Result<Operand, ParseIrError> {
+    match self.next()? {
+      Tok::Reg(n) => Ok(Operand::Reg(VReg(n))),
+      Tok::Int(n) => Ok(Operand::Const(n)),
+      other => Err(self.error(format!("expected a register or integer, found {}", other))),
+    }
+  }
+
+  fn block(&mut self) -> Result<BlockId, ParseIrError> {
+    let word = self.word()?;
+    word.strip_prefix("bb").and_then(|n| n.parse().ok()).map(BlockId)
+      .ok_or_else(|| self.error(format!("expected a block like `bb0`, found `{}`", word)))
+  }
+
+  //`a, b, c` up to a closing delimiter, which is consumed
+  fn list<T>(&mut self, close: char, mut item: impl FnMut(&mut Self) -> Result<T, ParseIrError>) -> Result<Vec<T>, ParseIrError> {
+    let mut items = Vec::new();
+    if self.eat(close) {
+      return Ok(items);
+    }
+    loop {
+      items.push(item(self)?);
+      if self.eat(close) {
+        return Ok(items);
+      }
+      self.expect(',')?;
+    }
+  }
+
+  fn end(&self) -> Result<(), ParseIrError> {
+    match self.peek() {
+      None => Ok(()),
+      Some(tok) => Err(self.error(format!("unexpected {} at end of line", tok))),
+    }
+  }
+}
+
+///Read a module from its text form.
+pub fn parse(text: &str) -> Result<Module, ParseIrError> {
+  let mut lines = Vec::new();
+  for (i, source) in text.lines().enumerate() {
+    let number = i as u32 + 1;
+    let tokens = tokenize(source).map_err(|message| ParseIrError { line: number, message })?;
+    if !tokens.is_empty() {
+      lines.push(Line { number, tokens, pos: 0 });
+    }
+  }
+
+  let mut module = Module::default();
+  let mut lines = lines.into_iter();
+  while let Some(mut line) = lines.next() {
+    match line.word()?.as_str() {
+      "import" => module.imports.push(line.word()?),
+      "string" => match line.next()? {
+        Tok::Str(bytes) => module.strings.push(bytes),
+        other => return Err(line.error(format!("expected a string, found {}", other))),
+      },
+      "global" => module.globals.push(parse_global(&mut line)?),
+      "func" => {
+        let function = parse_function(&mut line, &mut lines)?;
+        module.functions.push(function);
+      }
+      other => return Err(line.error(format!("expected `import`, `string`, `global` or `func`, found `{}`", other))),
+    }
+    line.end()?;
+  }
+  Ok(module)
+}
+
+fn parse_global(line: &mut Line) -> Result<Global, ParseIrError> {
+  let name = line.word()?;
+  let vector = if line.eat('[') {
+    let words = line.count()?;
+    line.expect(']')?;
+    Some(words)
+  } else {
+    None
+  };
+  let mut init = Vec::new();
+  if line.eat('=') {
+    loop {
+      init.push(match line.peek() {
+        Some(Tok::Word(word)) if word == "str" => {
+          line.next()?;
+          Init::String(line.count()?)
+        }
+        _ => Init::Word(line.int()?),
+      });
+      if !line.eat(',') {
+        break;
+      }
+    }
+  }
+  Ok(Global { name, vector, init })
+}
+
+fn parse_function(header: &mut Line, lines: &mut impl Iterator<Item = Line>) -> Result<Function, ParseIrError> {
+  let name = header.word()?;
+  header.keyword("params")?;
+  let params = header.count()?;
+  header.keyword("frame")?;
+  let frame_size = header.count()?;
+  header.expect('{')?;
+  let mut function = Function { name, params, frame_size, reg_count: 0, blocks: Vec::new() };
+
+  //block being filled: (label, instructions); it ends at its terminator
+  let mut open: Option<(Option<String>, Vec<Instr>)> = None;
+  for mut line in lines.by_ref() {
+    if line.eat('}') {
+      line.end()?;
+      if open.is_some() {
+        return Err(line.error("block has no terminator"));
+      }
+      if function.blocks.is_empty() {
+        return Err(line.error(format!("function `{}` has no blocks", function.name)));
+      }
+      function.reg_count = max_reg(&function).map_or(0, |r| r + 1);
+      return Ok(function);
+    }
+
+    let Some((label, instrs)) = open.as_mut() else {
+      //a block header: bbN or bbN label, then `:`
+      let id = line.block()?;
+      if id.0 as usize != function.blocks.len() {
+        return Err(line.error(format!("expected block bb{}, found {}", function.blocks.len(), id)));
+      }
+      let label = match line.peek() {
+        Some(Tok::Word(_)) => Some(line.word()?),
+        _ => None,
+      };
+      line.expect(':')?;
+      line.end()?;
+      open = Some((label, Vec::new()));
+      continue;
+    };
+
+    if let Some(term) = parse_terminator(&mut line)? {
+      line.end()?;
+      function.blocks.push(Block { label: label.take(), instrs: std::mem::take(instrs), term });
+      open = None;
+      continue;
+    }
+    let instr = parse_instr(&mut line)?;
+    line.end()?;
+    instrs.push(instr);
+  }
+  Err(header.error(format!("function `{}` is missing its closing `}}`", function.name)))
+}
+
+fn parse_terminator(line: &mut Line) -> Result<Option<Terminator>, ParseIrError> {
+  let Some(Tok::Word(word)) = line.peek() else { return Ok(None) };
+  let term = match word.as_str() {
+    "jump" => {
+      line.next()?;
+      Terminator::Jump(line.block()?)
+    }
+    "br" => {
+      line.next()?;
+      let cond = line.operand()?;
+      line.expect(',')?;
+      let then_block = line.block()?;
+      line.expect(',')?;
+      let else_block = line.block()?;
+      Terminator::Branch { cond, then_block, else_block }
+    }
+    "switch" => {
+      line.next()?;
+      let value = line.operand()?;
+      line.expect('[')?;
+      let cases = line.list(']', |line| {
+        let n = line.int()?;
+        line.expect(':')?;
+        Ok((n, line.block()?))
+      })?;
+      line.keyword("default")?;
+      Terminator::Switch { value, cases, default: line.block()? }
+    }
+    "ret" => {
+      line.next()?;
+      Terminator::Return(line.operand()?)
+    }
+    "ijump" => {
+      line.next()?;
+      let addr = line.operand()?;
+      line.expect('[')?;
+      Terminator::IndirectJump { addr, targets: line.list(']', Line::block)? }
+    }
+    _ => return Ok(None),
+  };
+  Ok(Some(term))
+}
+
+fn parse_instr(line: &mut Line) -> Result<Instr, ParseIrError> {
+  let dest = match line.next()? {
+    Tok::Reg(n) => VReg(n),
+    Tok::Word(word) if word == "store" => {
+      let addr = line.operand()?;
+      line.expect(',')?;
+      return Ok(Instr::Store { addr, value: line.operand()? });
+    }
+    other => return Err(line.error(format!("expected an instruction, found {}", other))),
+  };
+  line.expect('=')?;
+  let mnemonic = line.word()?;
+  if let Some(&op) = UnaryOp::ALL.iter().find(|op| op.mnemonic() == mnemonic) {
+    return Ok(Instr::Unary { dest, op, src: line.operand()? });
+  }
+  if let Some(&op) = BinaryOp::ALL.iter().find(|op| op.mnemonic() == mnemonic) {
+    let lhs = line.operand()?;
+    line.expect(',')?;
+    return Ok(Instr::Binary { dest, op, lhs, rhs: line.operand()? });
+  }
+  Ok(match mnemonic.as_str() {
+    "copy" => Instr::Copy { dest, src: line.operand()? },
+    "load" => Instr::Load { dest, addr: line.operand()? },
+    "local" => Instr::LocalAddr { dest, offset: line.count()? },
+    "global" => Instr::GlobalAddr { dest, name: line.word()? },
+    "str" => Instr::StringAddr { dest, index: line.count()? },
+    "label" => Instr::LabelAddr { dest, block: line.block()? },
+    "call" => {
+      let callee = if line.eat('*') { Callee::Indirect(line.operand()?) } else { Callee::Direct(line.word()?) };
+      line.expect('(')?;
+      Instr::Call { dest, callee, args: line.list(')', Line::operand)? }
+    }
+    "phi" => {
+      line.expect('[')?;
+      let args = line.list(']', |line| {
+        let block = line.block()?;
+        line.expect(':')?;
+        Ok((block, line.operand()?))
+      })?;
+      Instr::Phi { dest, args }
+    }
+    other => return Err(line.error(format!("unknown instruction `{}`", other))),
+  })
+}
+
+fn max_reg(function: &Function) -> Option<u32> {
+  let regs = |operands: Vec<Operand>| operands.into_iter().filter_map(|o| match o {
+    Operand::Reg(reg) => Some(reg.0),
+    Operand::Const(_) => None,
+  });
+  function.blocks.iter().flat_map(|block| {
+    block.instrs.iter()
+      .flat_map(|instr| instr.dest().map(|r| r.0).into_iter().chain(regs(instr.operands())))
+      .chain(regs(block.term.operands()))
+      .collect::<Vec<_>>()
+  }).max()
+}
@@ -1,7 +1,70 @@
 use crate::common::span::Span;
+use crate::common::source_map::{FileId, LineIndex};
+use crate::lexer::scanner::Scanner;
+use crate::lexer::token::{SpannedToken, Token, Operator};
+use crate::diagnostics::{Diagnostic, Label};
 pub mod scanner;
 pub mod token;
 
+///Everything lexing a file produces. tokens always ends with an EOF token spanning the end of the
+///source, so it can be handed straight to the parser, even when there were errors.
+#[derive(Debug)]
+pub struct LexOutput<'a> {
+    pub tokens: Vec<SpannedToken<'a>>,
+    pub errors: Vec<LexError>,
+    pub warnings: Vec<LexWarning>,
+    pub lines: LineIndex,
+}
+
+///Lex a whole source file. Bad characters and unterminated literals/comments are recorded in
+///errors and skipped, and lexing carries on, so one pass reports every lexical error.
+pub fn lex_all(source: &str) -> LexOutput<'_> {
+    let mut scanner = Scanner::new(source);
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for result in scanner.by_ref() {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(e) => errors.push(e),
+        }
+    }
+    let end = source.len() as u32;
+    tokens.push(SpannedToken { token: Token::EOF, span: Span { start: end, end } });
+    LexOutput { tokens, errors, warnings: scanner.take_warnings(), lines: scanner.line_index() }
+}
+
+///Lexical problems that do not stop a token from being produced.
+#[derive(Debug)]
+pub enum LexWarning {
+    UnknownEscape(char, Span), //span covers the `*` and the character after it
+    AmbiguousCompoundAssign(Operator, Span), //`x =-1`: compound operator glued to its operand
+}
+
+impl LexWarning {
+    pub fn span(&self) -> Span {
+        match self {
+            LexWarning::UnknownEscape(_, span) | LexWarning::AmbiguousCompoundAssign(_, span) => *span,
+        }
+    }
+
+    pub fn to_diagnostic(&self, file: FileId) -> Diagnostic {
+        match self {
+            LexWarning::UnknownEscape(c, span) => Diagnostic::warning(format!("unknown escape `*{}`", c.escape_debug()))
+                .with_code("W0001")
+                .with_label(Label::primary(file, *span, format!("treated as a plain `{}`", c.escape_debug())))
+                .with_note("B escapes are *n *t *r *0 *e *( *) ** *' and *\""),
+            LexWarning::AmbiguousCompoundAssign(op, span) => {
+                let unary = &op.to_string()[1..];
+                Diagnostic::warning(format!("ambiguous `{}`: parsed as a compound assignment", op))
+                    .with_code("W0002")
+                    .with_label(Label::primary(file, *span, format!("this is `{}`, not `= {}`", op, unary)))
+                    .with_help(format!("write `{} ` with a space to keep the compound assignment", op))
+                    .with_help(format!("write `= {}` to assign the operand with a prefix `{}`", unary, unary))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum LexError {
     UnexpectedChar(char, Span),
@@ -10,3 +73,55 @@ pub enum LexError {
     UnterminatedComment(Span),
     UnterminatedChar(Span)
 }
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedChar(_, span) | LexError::UnterminatedString(span) |
+            LexError::InvalidNumber(_, span) | LexError::UnterminatedComment(span) |
+            LexError::UnterminatedChar(span) => *span,
+        }
+    }
+
+    pub fn to_diagnostic(&self, file: FileId) -> Diagnostic {
+        //literals and comments carry a span from their opening delimiter to wherever lexing stopped;
+        //point at the opener, which is what needs closing
+        let span = self.span();
+        let opener = Span { start: span.start, end: span.start + 1 };
+        match self {
+            LexError::UnexpectedChar(c, _) => {
+                let diag = Diagnostic::error(format!("unexpected character `{}`", c.escape_debug()))
+                    .with_code("E0001")
+                    .with_label(Label::primary(file, span, "not valid in any B token"));
+                if *c == '\\' {
+                    diag.with_help("B escapes use `*`, not `\\` (e.g. `*n` for a newline)")
+                } else {
+                    diag
+                }
+            }
+            LexError::UnterminatedString(_) => Diagnostic::error("unterminated string literal")
+                .with_code("E0002")
+                .with_label(Label::primary(file, opener, "string starts here"))
+                .with_note("`*\"` is an escaped quote and does not end the string")
+                .with_help("add a closing `\"`"),
+            LexError::InvalidNumber(lexeme, _) => {
+                let diag = Diagnostic::error(format!("invalid numeric literal `{}`", lexeme))
+                    .with_code("E0003")
+                    .with_label(Label::primary(file, span, "cannot be represented as a word"));
+                if lexeme.starts_with('0') && lexeme.len() > 1 {
+                    diag.with_note("a leading `0` makes a literal octal")
+                } else {
+                    diag.with_note("B words are 64-bit signed integers")
+                }
+            }
+            LexError::UnterminatedComment(_) => Diagnostic::error("unterminated block comment")
+                .with_code("E0004")
+                .with_label(Label::primary(file, Span { start: span.start, end: span.start + 2 }, "comment starts here"))
+                .with_note("B comments do not nest; the first `*/` closes the comment"),
+            LexError::UnterminatedChar(_) => Diagnostic::error("unterminated character literal")
+                .with_code("E0005")
+                .with_label(Label::primary(file, opener, "character literal starts here"))
+                .with_help("add a closing `'`"),
+        }
+    }
+}
@@ -1,9 +1,28 @@
 use crate::common::span::Span;
-use crate::lexer::token::{SpannedToken, Token, Keyword, Delimiter, Operator};
-use super::LexError;
+use crate::common::source_map::LineIndex;
+use crate::lexer::token::{SpannedToken, Token, Keyword, Delimiter, Operator, STRING_TERMINATOR};
+use super::{LexError, LexWarning};
+
+//the B escape table: *x inside a string or character literal
+fn escape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        'e' => Some(STRING_TERMINATOR),
+        '(' => Some('{'),
+        ')' => Some('}'),
+        '*' => Some('*'),
+        '\'' => Some('\''),
+        '"' => Some('"'),
+        _ => None,
+    }
+}
 
 pub struct Scanner<'a> {
-    cols: Vec<u32>,
+    cols: Vec<u32>, //offset just past every \n seen so far
+    warnings: Vec<LexWarning>,
     chars: std::iter::Peekable<std::str::Chars<'a>>,
     current_loc: Span,
     source: &'a str,
@@ -14,12 +33,23 @@ impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
         Self {
             cols: Vec::new(),
+            warnings: Vec::new(),
             chars: source.chars().peekable(),
             current_loc: Span { start: 0, end: 0},
             source
         }
     }
 
+    ///Line table for everything lexed so far; complete once the iterator is exhausted.
+    pub fn line_index(&self) -> LineIndex {
+        LineIndex::from_newlines(&self.cols)
+    }
+
+    ///Warnings raised so far (unknown escapes); drains them.
+    pub fn take_warnings(&mut self) -> Vec<LexWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
     //advances the scanner, consuming lexeme
     fn advance(&mut self) -> Option<char> {
 
@@ -34,6 +64,7 @@ impl<'a> Scanner<'a> {
 
         Some(c)
     }
+
     //check next character
     fn peek(&mut self) -> Option<char> {
         self.chars.peek().copied()
@@ -55,7 +86,9 @@ impl<'a> Scanner<'a> {
     }
 
     //scan numeric literals; in B, octal numbers are denoted with a leading 0. 
-    fn read_number(&mut self, first_char: char, is_negative: bool) -> Result<Token<'a>, LexError> {
+    //A leading - is never part of the literal: `a-1` is a subtraction, and `-1` is unary minus applied
+    //to 1, folded back into a constant by the parser where one is required.
+    fn read_number(&mut self, first_char: char) -> Result<Token<'a>, LexError> {
         let start_offset = self.current_loc.end - (first_char.len_utf8() as u32);
         let radix = if first_char =='0' { 8 } else { 10 };
         
@@ -69,13 +102,7 @@ impl<'a> Scanner<'a> {
         }
 
         let lexeme = &self.source[start_offset as usize .. self.current_loc.end as usize];
-        if is_negative {
-            let mut num = i64::from_str_radix(lexeme, radix as u32).map_err(|_| LexError::InvalidNumber(lexeme.to_string(), self.current_loc))?;
-            num = -num;
-            return Ok(Token::Integer(num));
-
-        }
-        i64::from_str_radix(lexeme, radix as u32)
+        i64::from_str_radix(lexeme, radix)
             .map(Token::Integer) //if okay return integer token
             .map_err(|_| LexError::InvalidNumber(lexeme.to_string(), self.current_loc)) //else take parseinterror and
                                                                       //raise LexError
@@ -115,26 +142,39 @@ impl<'a> Scanner<'a> {
 
     }
 
+    //decode the escape after a * (already consumed). Unknown escapes keep the character as is, like
+    //the original compiler did, but are warned about. None if the source ends first.
+    fn read_escape(&mut self) -> Option<char> {
+        let star = self.current_loc.end - 1;
+        let c = self.advance()?;
+        Some(escape(c).unwrap_or_else(|| {
+            self.warnings.push(LexWarning::UnknownEscape(c, Span { start: star, end: self.current_loc.end }));
+            c
+        }))
+    }
+
+    //`x =-1` lexes as `x =- 1` but reads like `x = -1`. Warn when a compound assignment whose operator
+    //is also a prefix operator (- * &) runs straight into its operand.
+    fn check_compound_spacing(&mut self, op: Operator, start: u32) {
+        let unary = matches!(op, Operator::AssignMinus | Operator::AssignStar | Operator::AssignAmp);
+        if unary && self.peek().is_some_and(|c| !c.is_whitespace()) {
+            self.warnings.push(LexWarning::AmbiguousCompoundAssign(op, Span { start, end: self.current_loc.end }));
+        }
+    }
+
     fn read_str(&mut self) -> Result<Token<'a>, LexError> {
         let mut content = String::new();
 
         while let Some(c) = self.advance() {
             match c {
 
-                '"' => { 
-                    self.advance();
-                    return Ok(Token::StringLiteral(content)); 
-                }, //matching ", return string literal
+                //closing " has already been consumed by advance()
+                '"' => return Ok(Token::StringLiteral(content)),
 
 
                 '*' => { // * used as escape character 
-                    match self.advance() {
-                        Some('n') => content.push('\n'),
-                        Some('t') => content.push('\t'),
-                        Some('0') => content.push('\0'),
-                        Some('*') => content.push('*'),
-                        Some('"') => content.push('"'),
-                        Some(other) => content.push(other),
+                    match self.read_escape() {
+                        Some(c) => content.push(c),
                         None => return Err(LexError::UnterminatedString(self.current_loc)),
                     }
                 },
@@ -159,6 +199,7 @@ impl<'a> Scanner<'a> {
                 //comments
                 Some('/') => {
                     if self.peek_next() == Some('*') {
+                        self.current_loc.start = self.current_loc.end; //error span starts at the /*
                         self.consume_comment()?;
                     } else {
                         break; //no matching * implies division operator
@@ -204,13 +245,8 @@ impl<'a> Scanner<'a> {
                 },
 
                 '*' => {
-                    match self.advance() {
-                        Some('n') => chars.push('\n'),
-                        Some('t') => chars.push('\t'),
-                        Some('0') => chars.push('\0'),
-                        Some('*') => chars.push('*'),
-                        Some('"') => chars.push('"'),
-                        Some(other) => chars.push(other),
+                    match self.read_escape() {
+                        Some(c) => chars.push(c),
                         None => return Err(LexError::UnterminatedChar(self.current_loc)), 
                     }
                 },
@@ -238,6 +274,8 @@ impl<'a> Iterator for Scanner<'a> {
         //check for EOF
         let _ = self.peek()?;
 
+        //token (and any error raised while reading it) starts here
+        self.current_loc.start = self.current_loc.end;
         let start_loc = self.current_loc.start;
 
         //consume first char
@@ -255,20 +293,44 @@ impl<'a> Iterator for Scanner<'a> {
             ':' => Ok(Token::Delimiter(Delimiter::Colon)),
             '?' => Ok(Token::Delimiter(Delimiter::QMark)),
 
-            '0'..='9' => self.read_number(c, false),
+            '0'..='9' => self.read_number(c),
             'a'..='z' | 'A'..='Z' | '_' => self.read_identifier(c),
             '"' => self.read_str(),
             '\'' => self.read_char_literal(),
 
+            //B compound assignments are `=op`, matched greedily: `x=-1` is `x =- 1`, as in the original
+            //compiler. Write `x = -1` to assign a negative value.
             '=' => {
-                if self.match_char('=') { Ok(Token::Operator(Operator::Equal)) }
-                else if self.match_char('+') { Ok(Token::Operator(Operator::AssignPlus)) }
-                else if self.match_char('-') { Ok(Token::Operator(Operator::AssignMinus)) }
-                else if self.match_char('*') { Ok(Token::Operator(Operator::AssignStar)) }
-                else if self.match_char('/') { Ok(Token::Operator(Operator::AssignSlash)) }
-                else if self.match_char('%') { Ok(Token::Operator(Operator::AssignPercent)) }
-                else if self.match_char('&') { Ok(Token::Operator(Operator::AssignAmp)) }
-                else { Ok(Token::Operator(Operator::Assign)) }
+                let op = if self.match_char('=') {
+                    if self.match_char('=') { Operator::AssignEqual } else { Operator::Equal }
+                }
+                else if self.match_char('+') { Operator::AssignPlus }
+                else if self.match_char('-') { Operator::AssignMinus }
+                else if self.match_char('*') { Operator::AssignStar }
+                else if self.match_char('/') { Operator::AssignSlash }
+                else if self.match_char('%') { Operator::AssignPercent }
+                else if self.match_char('&') { Operator::AssignAmp }
+                else if self.match_char('|') { Operator::AssignBar }
+                else if self.match_char('^') { Operator::AssignCaret }
+                else if self.match_char('<') {
+                    if self.match_char('<') { Operator::AssignLShift }
+                    else if self.match_char('=') { Operator::AssignLessEq }
+                    else { Operator::AssignLess }
+                }
+                else if self.match_char('>') {
+                    if self.match_char('>') { Operator::AssignRShift }
+                    else if self.match_char('=') { Operator::AssignGreaterEq }
+                    else { Operator::AssignGreater }
+                }
+                //`=!` alone is `= !x`
+                else if self.peek() == Some('!') && self.peek_next() == Some('=') {
+                    self.advance();
+                    self.advance();
+                    Operator::AssignNotEqual
+                }
+                else { Operator::Assign };
+                self.check_compound_spacing(op, start_loc);
+                Ok(Token::Operator(op))
             }
 
             '+' => {
@@ -278,11 +340,6 @@ impl<'a> Iterator for Scanner<'a> {
 
             '-' => {
                 if self.match_char('-') { Ok(Token::Operator(Operator::Dec)) }
-                else if matches!(self.peek(), Some('0'..='9')) {
-                    let char = self.advance()?;
-                    let num = self.read_number(char, true);
-                    num
-                }
                 else { Ok(Token::Operator(Operator::Minus)) }
             }
 
@@ -291,7 +348,10 @@ impl<'a> Iterator for Scanner<'a> {
             '%' => Ok(Token::Operator(Operator::Percent)),
             '|' => Ok(Token::Operator(Operator::Bar)),
             '^' => Ok(Token::Operator(Operator::Caret)),
-            '!' => Ok(Token::Operator(Operator::Bang)),
+            '!' => {
+                if self.match_char('=') { Ok(Token::Operator(Operator::NotEqual)) }
+                else { Ok(Token::Operator(Operator::Bang)) }
+            }
             '~' => Ok(Token::Operator(Operator::Tilde)),
             '&' => Ok(Token::Operator(Operator::Amp)),
 
@@ -52,6 +52,48 @@ pub enum Operator {
     AssignSlash, //=/
     AssignPercent, //=%
     AssignAmp, //=&
+    AssignBar, //=|
+    AssignCaret, //=^
+    AssignLShift, //=<<
+    AssignRShift, //=>>
+    AssignLess, //=<
+    AssignLessEq, //=<=
+    AssignGreater, //=>
+    AssignGreaterEq, //=>=
+    AssignEqual, //===
+    AssignNotEqual, //=!=
+}
+
+impl Operator {
+
+    ///`=` or any compound assignment
+    pub fn is_assignment(self) -> bool {
+        self == Operator::Assign || self.compound_base().is_some()
+    }
+
+    ///The binary operator a compound assignment applies: `x =op y` is `x = x op y`.
+    ///None for plain `=` and for operators that are not assignments.
+    pub fn compound_base(self) -> Option<Operator> {
+        match self {
+            Operator::AssignPlus => Some(Operator::Plus),
+            Operator::AssignMinus => Some(Operator::Minus),
+            Operator::AssignStar => Some(Operator::Star),
+            Operator::AssignSlash => Some(Operator::Slash),
+            Operator::AssignPercent => Some(Operator::Percent),
+            Operator::AssignAmp => Some(Operator::Amp),
+            Operator::AssignBar => Some(Operator::Bar),
+            Operator::AssignCaret => Some(Operator::Caret),
+            Operator::AssignLShift => Some(Operator::LShift),
+            Operator::AssignRShift => Some(Operator::RShift),
+            Operator::AssignLess => Some(Operator::Less),
+            Operator::AssignLessEq => Some(Operator::LessEq),
+            Operator::AssignGreater => Some(Operator::Greater),
+            Operator::AssignGreaterEq => Some(Operator::GreaterEq),
+            Operator::AssignEqual => Some(Operator::Equal),
+            Operator::AssignNotEqual => Some(Operator::NotEqual),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Copy)]
@@ -72,6 +114,19 @@ pub enum Delimiter {
     QMark
 }
 
+///B's end-of-string marker (`*e`, ASCII EOT). Strings are stored with it appended rather than
+///with a trailing NUL, and library routines like char() and printf() stop at it.
+pub const STRING_TERMINATOR: char = '\x04';
+
+///Stored form of a string literal's contents: its bytes followed by the `*e` terminator.
+///Every later stage that lays a string out in memory goes through this.
+pub fn string_bytes(content: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(content.len() + 1);
+    bytes.extend_from_slice(content.as_bytes());
+    bytes.push(STRING_TERMINATOR as u8);
+    bytes
+}
+
 //main Token Enum
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token<'a> {
@@ -80,7 +135,8 @@ pub enum Token<'a> {
     Delimiter(Delimiter),
     Identifier(&'a str),
     Integer(i64),
-    // * instead of / used for escape characters
+    // * instead of / used for escape characters. Holds the decoded text without the *e terminator;
+    // see string_bytes for the stored form.
     StringLiteral(String),
     CharLiteral(i64),
     EOF, //End of file
@@ -141,6 +197,16 @@ impl fmt::Display for Operator {
             Operator::AssignSlash => "=/",
             Operator::AssignPercent => "=%",
             Operator::AssignAmp => "=&",
+            Operator::AssignBar => "=|",
+            Operator::AssignCaret => "=^",
+            Operator::AssignLShift => "=<<",
+            Operator::AssignRShift => "=>>",
+            Operator::AssignLess => "=<",
+            Operator::AssignLessEq => "=<=",
+            Operator::AssignGreater => "=>",
+            Operator::AssignGreaterEq => "=>=",
+            Operator::AssignEqual => "===",
+            Operator::AssignNotEqual => "=!=",
         };
         write!(f, "{}", s)
     }
@@ -1,4 +1,11 @@
 pub mod common;
 pub mod lexer;
 pub mod ast;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod diagnostics;
+pub mod sema;
+pub mod ir;
+pub mod interp;
+pub mod vm;
+pub mod codegen;
+pub mod libb;
@@ -0,0 +1,94 @@
+use crate::ir::{self, lower::lower};
+use crate::lexer::lex_all;
+use crate::parser::Parser;
+use crate::sema::analyze;
+
+//libb in B, for the wasm backend: the functions that need nothing from the host but putchar, so a
+//module carries them instead of importing them. They behave as the Runtime's do. Each stands alone,
+//without calling another library function, so a program that defines its own char or printn does
+//not change what printf prints. The rest of libb reads files and memory the host owns, and a wasm
+//module imports it (codegen::wasm::compile).
+
+///The library's functions written in B.
+pub const SOURCE: &str = "
+char(s, i) return(s[i / 8] >> (i % 8 * 8) & 0377);
+
+lchar(s, i, c) {
+  auto shift;
+  shift = i % 8 * 8;
+  s[i / 8] = s[i / 8] & ~(0377 << shift) | (c & 0377) << shift;
+  return(c);
+}
+
+printn(n, b) {
+  auto digits[64], m, i, d;
+  m = n;
+  if (b < 2 | b > 36) b = 10;
+  if (n < 0) putchar('-'); else n = -n;
+  i = 0;
+  while (!i | n) {
+    d = -(n % b);
+    digits[i++] = d < 10 ? '0' + d : 'a' + d - 10;
+    n = n / b;
+  }
+  while (i) putchar(digits[--i]);
+  return(m);
+}
+
+printf(fmt, x1, x2, x3, x4, x5, x6, x7, x8, x9) {
+  auto adx, x, c, b, i, j, digits[22];
+  adx = &x1;
+  i = 0;
+  while ((c = fmt[i / 8] >> (i % 8 * 8) & 0377) != '*e') {
+    i++;
+    if (c != '%')
+      putchar(c);
+    else {
+      c = fmt[i / 8] >> (i % 8 * 8) & 0377;
+      x = *adx;
+      if (c == 'd' | c == 'o') {
+        b = c == 'd' ? 10 : 8;
+        if (x < 0) putchar('-'); else x = -x;
+        j = 0;
+        while (!j | x) {
+          digits[j++] = '0' - x % b;
+          x = x / b;
+        }
+        while (j) putchar(digits[--j]);
+      } else if (c == 'c')
+        putchar(x);
+      else if (c == 's') {
+        j = 0;
+        while ((c = x[j / 8] >> (j % 8 * 8) & 0377) != '*e') {
+          putchar(c);
+          j++;
+        }
+      } else {
+        putchar('%');
+        i--;
+        adx--;
+      }
+      i++;
+      adx++;
+    }
+  }
+  return(0);
+}
+";
+
+///Whether the library in B defines `name`.
+pub fn provides(name: &str) -> bool {
+  ["char", "lchar", "printn", "printf"].contains(&name)
+}
+
+///`module` with the library's functions it imports defined in it; putchar stays an import.
+pub fn link(module: &ir::Module) -> ir::Module {
+  if !module.imports.iter().any(|name| provides(name)) {
+    return module.clone();
+  }
+  let lexed = lex_all(SOURCE);
+  let program = Parser::new(&lexed.tokens).parse_program().expect("libb in B parses");
+  let mut library = lower(&program, &analyze(&program));
+  library.functions.retain(|f| module.imports.contains(&f.name));
+  ir::link::link(vec![module.clone(), library])
+}
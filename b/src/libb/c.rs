@@ -0,0 +1,168 @@
+//libb in C, for executables: the same functions as the Runtime, on real memory. A B word is an
+//intptr_t and a B address is a byte address divided by the word size, as in codegen::c.
+//
+//Each function is libb_NAME(argc, args), with its arguments in a word array, so it does not care
+//how the caller passed them: the C backend calls it straight from a b_fn, and the assembly backends
+//through a small per-target trampoline (x86_64::runtime, aarch64::runtime) that gathers register
+//and stack arguments into an array. Memory is the process's own, so getvec is calloc, and open
+//returns the system's descriptors.
+
+///The library's functions and the helpers they share; it needs POSIX for open and read.
+pub const CORE: &str = "#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <fcntl.h>
+#include <unistd.h>
+
+#define LIBB_WORDS(a) ((intptr_t *)((uintptr_t)(a) * sizeof(intptr_t)))
+
+static intptr_t libb_arg(int argc, intptr_t *args, int i) {
+  return i < argc ? args[i] : 0;
+}
+
+static intptr_t libb_byte(intptr_t s, intptr_t i) {
+  return (LIBB_WORDS(s)[i / 8] >> (i % 8 * 8)) & 0377;
+}
+
+static void libb_set_byte(intptr_t s, intptr_t i, intptr_t c) {
+  intptr_t *word = &LIBB_WORDS(s)[i / 8];
+  int shift = (int)(i % 8 * 8);
+  *word = (intptr_t)(((uintptr_t)*word & ~((uintptr_t)0377 << shift)) | ((uintptr_t)(c & 0377) << shift));
+}
+
+static void libb_put(intptr_t c) {
+  int shift;
+  for (shift = 56; shift >= 0; shift -= 8)
+    if ((c >> shift) & 0377) putchar((int)((c >> shift) & 0377));
+}
+
+static void libb_putn(intptr_t n, intptr_t b) {
+  uintptr_t u = n < 0 ? 0 - (uintptr_t)n : (uintptr_t)n, base = b >= 2 && b <= 36 ? (uintptr_t)b : 10;
+  char digits[64];
+  int k = 0;
+  if (n < 0) putchar('-');
+  do {
+    digits[k++] = \"0123456789abcdefghijklmnopqrstuvwxyz\"[u % base];
+    u /= base;
+  } while (u);
+  while (k) putchar(digits[--k]);
+}
+
+intptr_t libb_putchar(int argc, intptr_t *args) {
+  intptr_t c = libb_arg(argc, args, 0);
+  libb_put(c);
+  return c;
+}
+
+intptr_t libb_getchar(int argc, intptr_t *args) {
+  int c = getchar();
+  (void)argc; (void)args;
+  return c == EOF ? 4 : c;
+}
+
+intptr_t libb_char(int argc, intptr_t *args) {
+  return libb_byte(libb_arg(argc, args, 0), libb_arg(argc, args, 1));
+}
+
+intptr_t libb_lchar(int argc, intptr_t *args) {
+  intptr_t c = libb_arg(argc, args, 2);
+  libb_set_byte(libb_arg(argc, args, 0), libb_arg(argc, args, 1), c);
+  return c;
+}
+
+intptr_t libb_printf(int argc, intptr_t *args) {
+  intptr_t fmt = libb_arg(argc, args, 0), i = 0, c, x, j;
+  int next = 1;
+  for (;;) {
+    c = libb_byte(fmt, i++);
+    if (c == 4) return 0;
+    if (c != '%') {
+      putchar((int)c);
+      continue;
+    }
+    x = libb_arg(argc, args, next);
+    switch (libb_byte(fmt, i)) {
+    case 'd': libb_putn(x, 10); break;
+    case 'o': libb_putn(x, 8); break;
+    case 'c': libb_put(x); break;
+    case 's':
+      for (j = 0; (c = libb_byte(x, j)) != 4; j++) putchar((int)c);
+      break;
+    default:
+      putchar('%');
+      continue;
+    }
+    i++;
+    next++;
+  }
+}
+
+intptr_t libb_printn(int argc, intptr_t *args) {
+  intptr_t n = libb_arg(argc, args, 0);
+  libb_putn(n, libb_arg(argc, args, 1));
+  return n;
+}
+
+intptr_t libb_exit(int argc, intptr_t *args) {
+  exit((int)libb_arg(argc, args, 0));
+}
+
+intptr_t libb_getvec(int argc, intptr_t *args) {
+  intptr_t n = libb_arg(argc, args, 0);
+  intptr_t *v = n < 0 ? NULL : calloc((size_t)n + 1, sizeof(intptr_t));
+  return v ? (intptr_t)((uintptr_t)v / sizeof(intptr_t)) : 0;
+}
+
+intptr_t libb_rlsevec(int argc, intptr_t *args) {
+  intptr_t v = libb_arg(argc, args, 0);
+  if (v) free(LIBB_WORDS(v));
+  return 0;
+}
+
+intptr_t libb_open(int argc, intptr_t *args) {
+  intptr_t name = libb_arg(argc, args, 0), mode = libb_arg(argc, args, 1), n = 0, i;
+  char *path;
+  int fd;
+  while (libb_byte(name, n) != 4) n++;
+  if (!(path = malloc((size_t)n + 1))) return -1;
+  for (i = 0; i < n; i++) path[i] = (char)libb_byte(name, i);
+  path[n] = 0;
+  fd = open(path, mode == 0 ? O_RDONLY : mode == 1 ? O_WRONLY | O_CREAT | O_TRUNC : O_RDWR, 0666);
+  free(path);
+  return fd;
+}
+
+intptr_t libb_read(int argc, intptr_t *args) {
+  intptr_t fd = libb_arg(argc, args, 0), buffer = libb_arg(argc, args, 1), count = libb_arg(argc, args, 2), got, i;
+  unsigned char chunk[1 << 16];
+  size_t want = count < 0 ? 0 : count < (intptr_t)sizeof chunk ? (size_t)count : sizeof chunk;
+  got = fd == 0 ? (intptr_t)fread(chunk, 1, want, stdin) : (intptr_t)read((int)fd, chunk, want);
+  for (i = 0; i < got; i++) libb_set_byte(buffer, i, chunk[i]);
+  return got;
+}
+
+intptr_t libb_write(int argc, intptr_t *args) {
+  intptr_t fd = libb_arg(argc, args, 0), buffer = libb_arg(argc, args, 1), count = libb_arg(argc, args, 2), done, i;
+  unsigned char *bytes;
+  if (count <= 0) return 0;
+  if (!(bytes = malloc((size_t)count))) return -1;
+  for (i = 0; i < count; i++) bytes[i] = (unsigned char)libb_byte(buffer, i);
+  if (fd == 1 || fd == 2) {
+    done = (intptr_t)fwrite(bytes, 1, (size_t)count, fd == 1 ? stdout : stderr);
+  } else {
+    done = (intptr_t)write((int)fd, bytes, (size_t)count);
+  }
+  free(bytes);
+  return done;
+}
+
+intptr_t libb_close(int argc, intptr_t *args) {
+  intptr_t fd = libb_arg(argc, args, 0);
+  return fd >= 0 && fd <= 2 ? 0 : close((int)fd);
+}
+";
+
+///libb as a translation unit of its own, for link to compile beside the assembly.
+pub fn source() -> String {
+  format!("#define _POSIX_C_SOURCE 200809L\n{}", CORE)
+}
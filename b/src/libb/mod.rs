@@ -0,0 +1,323 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+
+use rustc_hash::FxHashMap;
+
+use crate::interp::memory::Memory;
+use crate::ir::Word;
+use crate::lexer::token::STRING_TERMINATOR;
+
+pub mod b;
+pub mod c;
+
+//libb, the B library: the functions a program gets without defining them. This is the version the
+//interpreter and the vm call, working on their word memory; libb::c is the same library in C for
+//executables, and libb::b the part of it a wasm module carries, in B. By default input is given up
+//front and output is collected, so runs are repeatable and easy to test; Runtime::stdio uses the
+//process's console instead. Files other than the console are real files.
+//
+//Strings are packed as ir::pack_string lays them out, so byte i of a string or buffer at `s` is
+//`char(s, i)`. A missing argument is 0, as for B functions.
+
+///The library's functions, each with the most arguments it reads.
+pub const FUNCTIONS: [(&str, usize); 13] = [
+  ("putchar", 1), ("getchar", 0), ("char", 2), ("lchar", 3), ("printf", 10), ("printn", 2), ("exit", 1),
+  ("getvec", 1), ("rlsevec", 2), ("open", 2), ("read", 3), ("write", 3), ("close", 1),
+];
+
+///Whether libb defines `name`.
+pub fn provides(name: &str) -> bool {
+  FUNCTIONS.iter().any(|&(f, _)| f == name)
+}
+
+///Descriptors of the console; open numbers files from FIRST_FILE.
+pub const STDIN: Word = 0;
+pub const STDOUT: Word = 1;
+pub const STDERR: Word = 2;
+const FIRST_FILE: Word = 3;
+
+///Most bytes one read from a file takes.
+const READ_CHUNK: usize = 1 << 16;
+
+///Why a library call did not return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+  OutOfBounds(Word), //a string or buffer reached a word nothing is allocated at
+  Exit(Word), //exit() with this status
+}
+
+type LibResult = Result<Word, Trap>;
+
+#[derive(Debug, Default)]
+pub struct Runtime {
+  input: Vec<u8>,
+  read: usize, //bytes of input getchar and read have taken
+  output: Vec<u8>,
+  stdio: bool, //input is read from stdin as it is needed, and output goes straight to stdout
+  files: Vec<Option<File>>, //descriptor FIRST_FILE + i, None once closed
+  released: FxHashMap<usize, Vec<Word>>, //vectors rlsevec gave back, by size in words
+}
+
+impl Runtime {
+
+  ///A runtime whose standard input is `input`; after the last byte getchar returns `*e`.
+  pub fn new(input: Vec<u8>) -> Self {
+    Runtime { input, ..Runtime::default() }
+  }
+
+  ///A runtime on the process's standard input and output, as an executable has.
+  pub fn stdio() -> Self {
+    Runtime { stdio: true, ..Runtime::default() }
+  }
+
+  ///Everything written to standard output so far; always empty for a stdio runtime.
+  pub fn output(&self) -> &[u8] {
+    &self.output
+  }
+
+  ///Run the library function `name`, or None if there is none by that name.
+  pub fn call(&mut self, name: &str, args: &[Word], memory: &mut Memory) -> Option<LibResult> {
+    let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+    Some(match name {
+      //a packed character constant like 'ab' writes its characters in order
+      "putchar" => {
+        let mut out = Vec::new();
+        put(&mut out, arg(0));
+        self.print(&out);
+        Ok(arg(0))
+      }
+      "getchar" => Ok(match self.input(1).first() {
+        Some(&b) => b as Word,
+        None => STRING_TERMINATOR as Word,
+      }),
+      "char" => byte(memory, arg(0), arg(1)),
+      "lchar" => set_byte(memory, arg(0), arg(1), arg(2)).map(|()| arg(2)),
+      "printf" => self.printf(memory, args),
+      "printn" => {
+        let mut out = Vec::new();
+        printn(&mut out, arg(0), arg(1));
+        self.print(&out);
+        Ok(arg(0))
+      }
+      "exit" => Err(Trap::Exit(arg(0))),
+      "getvec" => Ok(self.getvec(memory, arg(0))),
+      "rlsevec" => {
+        if let Ok(words) = usize::try_from(arg(1).wrapping_add(1)) && arg(0) != 0 {
+          self.released.entry(words).or_default().push(arg(0));
+        }
+        Ok(0)
+      }
+      "open" => self.open(memory, arg(0), arg(1)),
+      "read" => self.read(memory, arg(0), arg(1), arg(2)),
+      "write" => self.write(memory, arg(0), arg(1), arg(2)),
+      "close" => Ok(self.close(arg(0))),
+      _ => return None,
+    })
+  }
+
+  //bytes of standard input from the next unread one, at most `count`
+  fn input(&mut self, count: usize) -> &[u8] {
+    if self.stdio && self.read == self.input.len() && count > 0 {
+      //one read, as an executable's would: a line from a terminal, or what a pipe has ready. A
+      //prompt written before it should show first
+      let _ = io::stdout().flush();
+      let mut buf = vec![0; count.min(READ_CHUNK)];
+      let n = io::stdin().read(&mut buf).unwrap_or(0);
+      self.input.clear();
+      self.read = 0;
+      self.input.extend(&buf[..n]);
+    }
+    let end = self.input.len().min(self.read.saturating_add(count));
+    let bytes = &self.input[self.read..end];
+    self.read = end;
+    bytes
+  }
+
+  fn print(&mut self, bytes: &[u8]) {
+    if self.stdio {
+      let _ = io::stdout().write_all(bytes);
+    } else {
+      self.output.extend(bytes);
+    }
+  }
+
+  //%d and %o print a number in decimal and octal, %c a character and %s a string, each taking the
+  //next argument; `%` before anything else is printed as it is
+  fn printf(&mut self, memory: &Memory, args: &[Word]) -> LibResult {
+    let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+    let fmt = arg(0);
+    let (mut i, mut next) = (0, 1);
+    let mut out = Vec::new();
+    loop {
+      let c = byte(memory, fmt, i)?;
+      i += 1;
+      if c == STRING_TERMINATOR as Word {
+        self.print(&out);
+        return Ok(0);
+      }
+      if c != '%' as Word {
+        out.push(c as u8);
+        continue;
+      }
+      match byte(memory, fmt, i)? as u8 {
+        b'd' => printn(&mut out, arg(next), 10),
+        b'o' => printn(&mut out, arg(next), 8),
+        b'c' => put(&mut out, arg(next)),
+        b's' => {
+          let s = arg(next);
+          let mut j = 0;
+          loop {
+            let c = byte(memory, s, j)?;
+            if c == STRING_TERMINATOR as Word {
+              break;
+            }
+            out.push(c as u8);
+            j += 1;
+          }
+        }
+        _ => {
+          out.push(b'%');
+          continue;
+        }
+      }
+      i += 1;
+      next += 1;
+    }
+  }
+
+  //n + 1 zeroed words, as B vectors have one more word than their bound; 0 when memory is full
+  fn getvec(&mut self, memory: &mut Memory, n: Word) -> Word {
+    let Ok(words) = usize::try_from(n.wrapping_add(1)) else { return 0 };
+    if let Some(addr) = self.released.get_mut(&words).and_then(Vec::pop) {
+      for i in 0..words {
+        memory.store(addr + i as Word, 0);
+      }
+      return addr;
+    }
+    memory.alloc(words).unwrap_or(0)
+  }
+
+  //mode 0 reads, 1 writes (creating or truncating the file) and 2 does both; -1 if it cannot
+  fn open(&mut self, memory: &Memory, name: Word, mode: Word) -> LibResult {
+    let mut path = Vec::new();
+    loop {
+      let c = byte(memory, name, path.len() as Word)?;
+      if c == STRING_TERMINATOR as Word {
+        break;
+      }
+      path.push(c as u8);
+    }
+    let Ok(path) = String::from_utf8(path) else { return Ok(-1) };
+    let mut options = OpenOptions::new();
+    match mode {
+      0 => options.read(true),
+      1 => options.write(true).create(true).truncate(true),
+      _ => options.read(true).write(true),
+    };
+    Ok(match options.open(path) {
+      Ok(file) => {
+        self.files.push(Some(file));
+        FIRST_FILE + self.files.len() as Word - 1
+      }
+      Err(_) => -1,
+    })
+  }
+
+  //the slot of an opened descriptor
+  fn slot(&mut self, fd: Word) -> Option<&mut Option<File>> {
+    let index = usize::try_from(fd.checked_sub(FIRST_FILE)?).ok()?;
+    self.files.get_mut(index)
+  }
+
+  fn file(&mut self, fd: Word) -> Option<&mut File> {
+    self.slot(fd)?.as_mut()
+  }
+
+  //up to `count` bytes into `buffer`, returning how many; 0 at the end of the file, -1 on an error
+  fn read(&mut self, memory: &mut Memory, fd: Word, buffer: Word, count: Word) -> LibResult {
+    let count = count.max(0) as usize;
+    let bytes = if fd == STDIN {
+      self.input(count).to_vec()
+    } else {
+      let Some(file) = self.file(fd) else { return Ok(-1) };
+      let mut bytes = vec![0; count.min(READ_CHUNK)];
+      match file.read(&mut bytes) {
+        Ok(n) => bytes.truncate(n),
+        Err(_) => return Ok(-1),
+      }
+      bytes
+    };
+    for (i, &b) in bytes.iter().enumerate() {
+      set_byte(memory, buffer, i as Word, b as Word)?;
+    }
+    Ok(bytes.len() as Word)
+  }
+
+  //`count` bytes from `buffer`, returning how many were written or -1 on an error
+  fn write(&mut self, memory: &Memory, fd: Word, buffer: Word, count: Word) -> LibResult {
+    let mut bytes = Vec::new();
+    for i in 0..count.max(0) {
+      bytes.push(byte(memory, buffer, i)? as u8);
+    }
+    let written = match fd {
+      STDOUT => {
+        self.print(&bytes);
+        Ok(())
+      }
+      STDERR => io::stderr().write_all(&bytes),
+      _ => match self.file(fd) {
+        Some(file) => file.write_all(&bytes),
+        None => return Ok(-1),
+      },
+    };
+    Ok(if written.is_ok() { bytes.len() as Word } else { -1 })
+  }
+
+  //the console stays open; -1 for a descriptor that is not open
+  fn close(&mut self, fd: Word) -> Word {
+    if (STDIN..FIRST_FILE).contains(&fd) {
+      return 0;
+    }
+    match self.slot(fd).and_then(Option::take) {
+      Some(_) => 0,
+      None => -1,
+    }
+  }
+}
+
+fn put(output: &mut Vec<u8>, c: Word) {
+  output.extend(c.to_be_bytes().into_iter().filter(|&b| b != 0));
+}
+
+//n in base b, from 2 to 36 and otherwise 10, with a `-` if it is negative
+fn printn(output: &mut Vec<u8>, n: Word, b: Word) {
+  let base = if (2..=36).contains(&b) { b as u64 } else { 10 };
+  if n < 0 {
+    output.push(b'-');
+  }
+  let mut digits = Vec::new();
+  let mut u = n.unsigned_abs();
+  loop {
+    digits.push(b"0123456789abcdefghijklmnopqrstuvwxyz"[(u % base) as usize]);
+    u /= base;
+    if u == 0 {
+      break;
+    }
+  }
+  output.extend(digits.iter().rev());
+}
+
+//byte i of the string at s
+fn byte(memory: &Memory, s: Word, i: Word) -> LibResult {
+  let addr = s.wrapping_add(i.div_euclid(8));
+  let word = memory.load(addr).ok_or(Trap::OutOfBounds(addr))?;
+  Ok((word >> (i.rem_euclid(8) * 8)) & 0xff)
+}
+
+fn set_byte(memory: &mut Memory, s: Word, i: Word, c: Word) -> Result<(), Trap> {
+  let addr = s.wrapping_add(i.div_euclid(8));
+  let word = memory.load(addr).ok_or(Trap::OutOfBounds(addr))?;
+  let shift = i.rem_euclid(8) * 8;
+  memory.store(addr, (word & !(0xff << shift)) | ((c & 0xff) << shift));
+  Ok(())
+}
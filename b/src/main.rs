@@ -1,10 +1,435 @@
-#![allow(dead_code)]
-mod common;
-mod parser;
-mod ast;
-mod lexer;
-mod sema;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use b::ast::Program;
+use b::ast::pretty_printer::AstPrinter;
+use b::codegen::{aarch64, c, link, wasm, x86_64};
+use b::common::source_map::{FileId, SourceMap};
+use b::diagnostics::Diagnostic;
+use b::diagnostics::json;
+use b::diagnostics::render::{ColorChoice, Emitter};
+use b::ir;
+use b::lexer::{lex_all, LexOutput};
+use b::parser::Parser;
+use b::sema::{self, SemaOutput};
+use b::sema::link::Unit;
+use b::vm::{self, machine::Vm};
+
+const USAGE: &str = "usage: b [check|build|run] [options] [<file.b>|-]...
+
+commands:
+  check    report diagnostics; the default
+  build    compile the files into one executable, or one wasm or vm module
+  run      build and run main, exiting with its result
+
+options:
+  --emit=tokens|ast|sexpr|ir|dot|asm|obj|wat|c
+                       print a stage of every file; obj writes object files instead of linking
+  -o <file>            what build writes; by default named after the first file
+  -O                   optimize: put functions into SSA form
+  --target=x86_64|aarch64|wasm|vm
+                       what build and run compile for; by default the host, and the vm for run
+  --error-format=human|json
+  --color=auto|always|never
+
+With no files, or `-`, the source is read from standard input. The exit status is 1 if any file
+has errors and 2 for bad usage; run exits with main's result.";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Subcommand {
+    Check,
+    Build,
+    Run,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Emit {
+    Tokens,
+    Ast, //the parser's tree, as Rust debug output
+    Sexpr, //the tree as S-expressions
+    Ir,
+    Dot, //control-flow graph of every function, in Graphviz syntax
+    Asm, //assembly for the target
+    Obj, //an object file per input, assembled for the target
+    Wat, //WebAssembly text
+    C,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    X86_64,
+    Aarch64,
+    Wasm,
+    Vm,
+}
+
+impl Target {
+    fn host() -> Target {
+        if cfg!(target_arch = "aarch64") { Target::Aarch64 } else { Target::X86_64 }
+    }
+
+    fn is_native(self) -> bool {
+        matches!(self, Target::X86_64 | Target::Aarch64)
+    }
+}
+
+struct Options {
+    command: Subcommand,
+    error_format: ErrorFormat,
+    color: ColorChoice,
+    emit: Option<Emit>,
+    output: Option<PathBuf>,
+    optimize: bool,
+    target: Target,
+    files: Vec<String>, //`-` is standard input
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        command: Subcommand::Check,
+        error_format: ErrorFormat::Human,
+        color: ColorChoice::Auto,
+        emit: None,
+        output: None,
+        optimize: false,
+        target: Target::host(),
+        files: Vec::new(),
+    };
+    let mut target = None;
+    let mut args = args.iter().peekable();
+    if let Some(command) = args.peek().and_then(|arg| match arg.as_str() {
+        "check" => Some(Subcommand::Check),
+        "build" => Some(Subcommand::Build),
+        "run" => Some(Subcommand::Run),
+        _ => None,
+    }) {
+        opts.command = command;
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        if let Some(format) = arg.strip_prefix("--error-format=") {
+            opts.error_format = match format {
+                "human" => ErrorFormat::Human,
+                "json" => ErrorFormat::Json,
+                other => return Err(format!("unknown error format `{}`", other)),
+            };
+        } else if let Some(color) = arg.strip_prefix("--color=") {
+            opts.color = match color {
+                "auto" => ColorChoice::Auto,
+                "always" => ColorChoice::Always,
+                "never" => ColorChoice::Never,
+                other => return Err(format!("unknown color choice `{}`", other)),
+            };
+        } else if let Some(emit) = arg.strip_prefix("--emit=") {
+            opts.emit = Some(match emit {
+                "tokens" => Emit::Tokens,
+                "ast" => Emit::Ast,
+                "sexpr" => Emit::Sexpr,
+                "ir" => Emit::Ir,
+                "dot" => Emit::Dot,
+                "asm" => Emit::Asm,
+                "obj" => Emit::Obj,
+                "wat" => Emit::Wat,
+                "c" => Emit::C,
+                other => return Err(format!("unknown emit kind `{}`", other)),
+            });
+        } else if let Some(name) = arg.strip_prefix("--target=") {
+            target = Some(match name {
+                "x86_64" => Target::X86_64,
+                "aarch64" => Target::Aarch64,
+                "wasm" | "wasm32" => Target::Wasm,
+                "vm" => Target::Vm,
+                other => return Err(format!("unknown target `{}`", other)),
+            });
+        } else if arg == "-o" {
+            let path = args.next().ok_or("`-o` needs a file name")?;
+            opts.output = Some(PathBuf::from(path));
+        } else if arg == "-O" {
+            opts.optimize = true;
+        } else if arg.starts_with("-") && arg != "-" {
+            return Err(format!("unknown option `{}`", arg));
+        } else {
+            opts.files.push(arg.clone());
+        }
+    }
+    if opts.files.is_empty() {
+        opts.files.push("-".to_string());
+    }
+    if opts.files.iter().filter(|f| *f == "-").count() > 1 {
+        return Err("standard input can be read only once".to_string());
+    }
+    //`b -o prog f.b` builds, as it did before there were commands
+    if opts.command == Subcommand::Check && opts.output.is_some() && opts.emit != Some(Emit::Obj) {
+        opts.command = Subcommand::Build;
+    }
+    //an explicit target wins, then the output's extension, then the command's default
+    opts.target = match (target, opts.output.as_deref().and_then(Path::extension)) {
+        (Some(target), _) => target,
+        (None, Some(ext)) if ext == "wasm" => Target::Wasm,
+        (None, Some(ext)) if ext == "bvm" => Target::Vm,
+        (None, _) if opts.command == Subcommand::Run => Target::Vm,
+        (None, _) => Target::host(),
+    };
+    if matches!(opts.emit, Some(Emit::Asm | Emit::Obj)) && !opts.target.is_native() {
+        return Err("`--emit=asm` and `--emit=obj` need a native target".to_string());
+    }
+    if opts.emit == Some(Emit::Obj) && opts.output.is_some() && opts.files.len() > 1 {
+        return Err("`-o` names one object file, but there are several inputs".to_string());
+    }
+    if opts.command == Subcommand::Run && opts.target == Target::Wasm {
+        return Err("run cannot run a wasm module; build it and load it in a host".to_string());
+    }
+    Ok(opts)
+}
 
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = parse_args(&args).unwrap_or_else(|err| {
+        eprintln!("error: {}\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    let mut contents = Vec::new();
+    for name in &opts.files {
+        let source = if name == "-" {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source).map(|_| source)
+        } else {
+            fs::read_to_string(name)
+        };
+        match source {
+            Ok(source) => contents.push(source),
+            Err(err) => {
+                eprintln!("error: cannot read {}: {}", display_name(name), err);
+                process::exit(1);
+            }
+        }
+    }
+
+    let lower = opts.command != Subcommand::Check || matches!(opts.emit, Some(Emit::Ir | Emit::Dot | Emit::Asm | Emit::Obj | Emit::Wat | Emit::C));
+    let mut failed = false;
+    //every file is lexed first: its checked tree borrows the tokens until the whole program is linked
+    let lexed: Vec<LexOutput> = contents.iter().map(|source| lex_all(source)).collect();
+    let mut units = Vec::new();
+    let mut checked = Vec::new();
+    //one map for every file, so a diagnostic can point into two of them
+    let mut sources = SourceMap::new();
+    for ((name, source), lexed) in opts.files.iter().zip(&contents).zip(&lexed) {
+        let (diagnostics, file) = check_file(&opts, &mut sources, display_name(name), source, lexed);
+        failed |= diagnostics.iter().any(Diagnostic::is_error);
+        report(&opts, &sources, &diagnostics);
+        if let Some((unit, program, sema)) = file {
+            units.push(unit);
+            checked.push((name, program, sema));
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+
+    //the files make one program; check only requires it to be complete when asked to build it
+    let building = opts.command != Subcommand::Check && opts.emit != Some(Emit::Obj);
+    let errors: Vec<Diagnostic> = sema::link::link(&units).iter()
+        .filter(|err| building || !matches!(err, sema::link::LinkError::Unresolved { .. }))
+        .map(sema::link::LinkError::to_diagnostic)
+        .collect();
+    report(&opts, &sources, &errors);
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+
+    //an import is lowered as data or as a function by what the other files define it as
+    let globals = sema::link::globals(&units);
+    let mut modules = Vec::new();
+    if lower {
+        for (name, program, sema) in &checked {
+            let mut module = ir::lower::lower_file(program, sema, &globals);
+            if opts.optimize {
+                module.functions.iter_mut().for_each(|f| { ir::ssa::to_ssa(f); });
+            }
+            failed |= !emit(&opts, name, &module);
+            modules.push(module);
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+
+    let status = match opts.command {
+        _ if !building => Ok(0),
+        Subcommand::Build => build(&opts, &ir::link::link(modules), &output_path(&opts)).map(|()| 0),
+        _ => run(&opts, &ir::link::link(modules)),
+    };
+    process::exit(status.unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        1
+    }));
+}
+
+fn display_name(name: &str) -> &str {
+    if name == "-" { "<stdin>" } else { name }
+}
+
+//parse and check a lexed file, printing the stages --emit asks for, and return every diagnostic
+//produced and, if the file has no errors, its top-level names with the tree and what sema found
+fn check_file<'a>(opts: &Options, sources: &mut SourceMap<'a>, name: &str, source: &'a str, lexed: &'a LexOutput<'a>)
+    -> (Vec<Diagnostic>, Option<(Unit, Program<'a>, SemaOutput)>) {
+    let file: FileId = sources.add_file(name, source, lexed.lines.clone());
+    if opts.emit == Some(Emit::Tokens) {
+        for token in &lexed.tokens {
+            let pos = sources.line_col(file, token.span.start);
+            println!("[{}:{}] {:?}", pos.line, pos.col, token.token);
+        }
+    }
+
+    //lexer warnings and errors, in source order
+    let mut diagnostics: Vec<Diagnostic> = lexed.warnings.iter().map(|w| w.to_diagnostic(file))
+        .chain(lexed.errors.iter().map(|e| e.to_diagnostic(file)))
+        .collect();
+    diagnostics.sort_by_key(|d| d.primary_label().map(|l| l.span.start));
+
+    //parse errors after skipped bad tokens are mostly fallout; only parse clean token streams
+    if !lexed.errors.is_empty() {
+        return (diagnostics, None);
+    }
+
+    let mut parser = Parser::new(&lexed.tokens);
+    let output = parser.parse();
+    diagnostics.extend(output.errors.iter().map(|e| e.to_diagnostic(file)));
+    //the tree is printed even after parse errors, with (error) where recovery kicked in
+    match opts.emit {
+        Some(Emit::Ast) => println!("{:#?}", output.program),
+        Some(Emit::Sexpr) => println!("{}", AstPrinter::new().print_program(&output.program)),
+        _ => {}
+    }
+    //names are only checked on a complete program; Error placeholders would hide declarations
+    if !output.errors.is_empty() {
+        return (diagnostics, None);
+    }
+
+    let sema = sema::analyze(&output.program);
+    let mut checked: Vec<Diagnostic> = sema.errors.iter().map(|e| e.to_diagnostic(file))
+        .chain(sema.warnings.iter().map(|w| w.to_diagnostic(file)))
+        .collect();
+    checked.sort_by_key(|d| d.primary_label().map(|l| l.span.start));
+    diagnostics.extend(checked);
+    if !sema.errors.is_empty() {
+        return (diagnostics, None);
+    }
+    (diagnostics, Some((Unit::new(file, &sema), output.program, sema)))
+}
+
+//print the stage --emit asks for, or write an object file; false if that failed
+fn emit(opts: &Options, name: &str, module: &ir::Module) -> bool {
+    match opts.emit {
+        Some(Emit::Ir) => print!("{}", ir::text::print(module)),
+        Some(Emit::Dot) => {
+            for function in &module.functions {
+                print!("{}", ir::cfg::to_dot(function));
+            }
+        }
+        Some(Emit::Asm) => print!("{}", assembly(opts.target, module)),
+        Some(Emit::Obj) => {
+            let object = opts.output.clone().unwrap_or_else(|| PathBuf::from(stem(name).unwrap_or("a")).with_extension("o"));
+            if let Err(err) = link::assemble_with(&cc(opts.target), &assembly(opts.target, module), &object) {
+                eprintln!("error: {}", err);
+                return false;
+            }
+        }
+        Some(Emit::Wat) => print!("{}", wasm::text::print(&wasm::compile::compile(module))),
+        Some(Emit::C) => print!("{}", c::emit(module)),
+        Some(Emit::Tokens | Emit::Ast | Emit::Sexpr) | None => {}
+    }
+    true
+}
+
+fn assembly(target: Target, module: &ir::Module) -> String {
+    match target {
+        Target::Aarch64 => aarch64::emit(module),
+        _ => x86_64::emit(module),
+    }
+}
+
+//the C compiler that assembles and links for a native target: $CC, or the system's for the host
+//and a cross compiler otherwise
+fn cc(target: Target) -> String {
+    env::var("CC").unwrap_or_else(|_| {
+        if target == Target::host() { "cc" } else if target == Target::Aarch64 { "aarch64-linux-gnu-gcc" } else { "x86_64-linux-gnu-gcc" }.to_string()
+    })
+}
+
+//the name of a `.b` file without its directory and extension, which outputs are named after
+fn stem(name: &str) -> Option<&str> {
+    let path = Path::new(name);
+    path.extension().is_some_and(|ext| ext == "b").then(|| path.file_stem()?.to_str()).flatten()
+}
+
+fn output_path(opts: &Options) -> PathBuf {
+    let stem = stem(&opts.files[0]);
+    opts.output.clone().unwrap_or_else(|| match opts.target {
+        Target::Wasm => PathBuf::from(stem.unwrap_or("a")).with_extension("wasm"),
+        Target::Vm => PathBuf::from(stem.unwrap_or("a")).with_extension("bvm"),
+        Target::X86_64 | Target::Aarch64 => PathBuf::from(stem.unwrap_or("a.out")),
+    })
+}
+
+fn build(opts: &Options, module: &ir::Module, output: &Path) -> Result<(), String> {
+    let write = |bytes: Vec<u8>| fs::write(output, bytes).map_err(|err| format!("cannot write {}: {}", output.display(), err));
+    match opts.target {
+        Target::Wasm => write(wasm::binary::encode(&wasm::compile::compile(module))),
+        Target::Vm => write(vm::format::write(&vm::compile::compile(module))),
+        Target::X86_64 | Target::Aarch64 => {
+            let runtime = if opts.target == Target::Aarch64 { aarch64::runtime() } else { x86_64::runtime() };
+            link::link_with(&cc(opts.target), &[&assembly(opts.target, module), &runtime], output).map_err(|err| err.to_string())
+        }
+    }
+}
+
+//run main on the vm, or as an executable built in a scratch file, and return the exit status
+fn run(opts: &Options, module: &ir::Module) -> Result<i32, String> {
+    if opts.target.is_native() {
+        let exe = env::temp_dir().join(format!("b-run-{}", process::id()));
+        build(opts, module, &exe)?;
+        let status = Command::new(&exe).status();
+        let _ = fs::remove_file(&exe);
+        let status = status.map_err(|err| format!("cannot run the program: {}", err))?;
+        return Ok(status.code().unwrap_or(1));
+    }
+    //the program has the console as it would running as an executable; when the source came from
+    //standard input, that is already at its end
+    let module = vm::compile::compile(module);
+    let result = Vm::new(&module).and_then(|machine| machine.with_stdio().run());
+    let _ = io::stdout().flush();
+    match result {
+        Ok(status) => Ok((status & 0xff) as i32),
+        Err(err) => {
+            report(opts, &SourceMap::new(), &[err.to_diagnostic()]);
+            Ok(1)
+        }
+    }
+}
+
+fn report(opts: &Options, sources: &SourceMap, diagnostics: &[Diagnostic]) {
+    match opts.error_format {
+        ErrorFormat::Human => {
+            let emitter = Emitter::new(sources, opts.color);
+            for diag in diagnostics {
+                emitter.emit(diag);
+            }
+        }
+        ErrorFormat::Json => {
+            for diag in diagnostics {
+                eprintln!("{}", json::to_json(diag, sources));
+            }
+        }
+    }
 }
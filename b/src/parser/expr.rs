@@ -3,9 +3,21 @@ use crate::ast::Expr;
 use crate::lexer::token::*;
 
 
+//operators that have a B compound assignment form (=op)
+fn is_compound_base(token: &Token) -> bool {
+  matches!(token, Token::Operator(Operator::Plus | Operator::Minus | Operator::Star | Operator::Slash |
+    Operator::Percent | Operator::Amp | Operator::Bar | Operator::Caret | Operator::LShift | Operator::RShift))
+}
+
 //expression parsing: pratt parser
 impl <'a> Parser<'a> {
 
+  //token immediately before `t` if nothing (not even whitespace) separates them
+  fn previous_adjacent(&self, t: &SpannedToken<'a>) -> Option<&'a SpannedToken<'a>> {
+    let prev = self.tokens.get(self.position.checked_sub(2)?)?;
+    (prev.span.end == t.span.start).then_some(prev)
+  }
+
 
   fn is_lvalue(&self, expr: &Expr<'a>) -> bool {
     //lvalues are only variables, array indicies, and dereferences
@@ -20,7 +32,11 @@ impl <'a> Parser<'a> {
   /// negation, etc.)
   fn nud(&mut self) -> Result<Expr<'a>, ParseError<'a>> {
 
-    let t = self.advance(); 
+    //advance() at EOF hands back the token before it, which would start the expression over
+    if self.is_at_end() {
+      return Err(ParseError::UnexpectedEOF);
+    }
+    let t = self.advance();
     match &t.token {
 
       Token::Integer(_) | Token::CharLiteral(_) |
@@ -42,6 +58,11 @@ impl <'a> Parser<'a> {
         Ok(Expr::Grouping{ expression: Box::new(inner) })
       },
 
+      //C habit: `a += 1` lexes as `a + = 1`, so the `=` shows up here as an operand
+      Token::Operator(Operator::Assign) => match self.previous_adjacent(t) {
+        Some(op) if is_compound_base(&op.token) => Err(ParseError::ReversedCompoundAssign(op, t)),
+        _ => Err(ParseError::UnknownToken(t)),
+      },
 
 
       _ => Err(ParseError::UnknownToken(t)),
@@ -116,9 +137,7 @@ impl <'a> Parser<'a> {
           Operator::RShift | Operator::Tilde => Ok(Expr::Bitwise {left: Box::new(left), operator: op, right: Box::new(right)}),
 
           //assign: left must be lvalue
-          Operator::Assign | Operator::AssignAmp | Operator::AssignMinus |
-          Operator::AssignPercent | Operator::AssignPlus | Operator::AssignSlash |
-          Operator::AssignStar => {
+          t if t.is_assignment() => {
             if self.is_lvalue(&left) {
               return Ok(Expr::Assign{lvalue: Box::new(left), operator: op, value: Box::new(right)});
             }
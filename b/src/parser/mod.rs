@@ -1,7 +1,10 @@
 #![allow(dead_code, unused)]
 use crate::lexer::token::{SpannedToken,Token, Operator, Delimiter};
 use crate::common::span::Span;
+use crate::common::source_map::FileId;
+use crate::diagnostics::{Diagnostic, Label, Suggestion};
 use crate::parser::precedence::Precedence;
+use crate::ast::Program;
 
 pub mod precedence;
 pub mod expr;
@@ -16,6 +19,8 @@ pub enum ParseError<'a> {
   RValueAssign(&'a SpannedToken<'a>),
   UnspecifiedArraySizeInitialization(&'a SpannedToken<'a>),
   AutoRedecl(&'a SpannedToken<'a>),
+  ReversedCompoundAssign(&'a SpannedToken<'a>, &'a SpannedToken<'a>), //C-style `+=`: (operator, =)
+  ExpectedConstant(&'a SpannedToken<'a>), //global initializer that is not a literal
 }
 
 #[derive(Debug)]
@@ -24,10 +29,85 @@ pub struct Expected<'a> {
   found: &'a SpannedToken<'a>,
 }
 
+impl<'a> ParseError<'a> {
+  ///span of the offending token; None for errors that have no token to point at (EOF)
+  pub fn span(&self) -> Option<Span> {
+    match self {
+      ParseError::UnexpectedToken(e) => Some(e.found.span),
+      ParseError::UnknownToken(t) | ParseError::RValueAssign(t) |
+      ParseError::UnspecifiedArraySizeInitialization(t) | ParseError::AutoRedecl(t) |
+      ParseError::ExpectedConstant(t) => Some(t.span),
+      ParseError::ReversedCompoundAssign(_, assign) => Some(assign.span),
+      ParseError::UnexpectedEOF => None,
+    }
+  }
+
+  pub fn to_diagnostic(&self, file: FileId) -> Diagnostic {
+    match self {
+      ParseError::UnexpectedToken(e) => Diagnostic::error(format!("expected `{}`, found {}", e.expected, describe(&e.found.token)))
+        .with_code("E0101")
+        .with_label(Label::primary(file, e.found.span, format!("expected `{}`", e.expected))),
+      ParseError::UnknownToken(t) => Diagnostic::error(format!("unexpected {}", describe(&t.token)))
+        .with_code("E0102")
+        .with_label(Label::primary(file, t.span, "not valid here")),
+      ParseError::UnexpectedEOF => Diagnostic::error("unexpected end of file")
+        .with_code("E0103"),
+      ParseError::RValueAssign(op) => Diagnostic::error("invalid left-hand side of assignment")
+        .with_code("E0104")
+        .with_label(Label::primary(file, op.span, "cannot assign to the expression on the left"))
+        .with_note("only names, vector elements (`v[i]`) and indirections (`*p`) can be assigned to"),
+      ParseError::UnspecifiedArraySizeInitialization(t) => Diagnostic::error("vector initializer without a size")
+        .with_code("E0105")
+        .with_label(Label::primary(file, t.span, "initializer for a vector declared with `[]`"))
+        .with_help("give the vector a size, e.g. `v[3] 1, 2, 3;`"),
+      ParseError::AutoRedecl(t) => Diagnostic::error("`auto` declaration after a statement")
+        .with_code("E0106")
+        .with_label(Label::primary(file, t.span, "declaration is not at the start of the block"))
+        .with_note("B requires `auto` declarations before any other statement in a block"),
+      ParseError::ReversedCompoundAssign(op, assign) => {
+        let c_style = format!("{}=", op.token);
+        Diagnostic::error(format!("`{}` is not a B operator", c_style))
+          .with_code("E0107")
+          .with_label(Label::primary(file, assign.span, "expected an expression"))
+          .with_label(Label::secondary(file, op.span, "parsed as a binary operator"))
+          .with_help(format!("B uses `={}`, not `{}`", op.token, c_style))
+          .with_suggestion(Suggestion {
+            file,
+            span: Span { start: op.span.start, end: assign.span.end },
+            replacement: format!("={}", op.token),
+            message: "use the B operator".to_string(),
+          })
+      }
+      ParseError::ExpectedConstant(t) => Diagnostic::error(format!("expected a constant, found {}", describe(&t.token)))
+        .with_code("E0108")
+        .with_label(Label::primary(file, t.span, "expected a number, character or string literal"))
+        .with_note("case values and global initializers must be constants, optionally negated (`-1`)"),
+    }
+  }
+}
+
+//how a token reads in an error message
+fn describe(token: &Token) -> String {
+  match token {
+    Token::EOF => "end of file".to_string(),
+    Token::Identifier(name) => format!("identifier `{}`", name),
+    other => format!("`{}`", other),
+  }
+}
+
 #[derive(Debug)]
 pub struct Parser<'a> {
   tokens: &'a [SpannedToken<'a>],
   position: usize,
+  errors: Vec<ParseError<'a>>, //errors recovered from so far
+}
+
+///Result of a recovering parse: whatever could be parsed, with Error nodes where it couldn't,
+///and every error hit along the way (in source order).
+#[derive(Debug)]
+pub struct ParseOutput<'a> {
+  pub program: Program<'a>,
+  pub errors: Vec<ParseError<'a>>,
 }
 
 
@@ -38,6 +118,7 @@ impl<'a> Parser<'a> {
     Parser {
       tokens,
       position: 0,
+      errors: Vec::new(),
     }
   }
 
@@ -78,7 +159,7 @@ impl<'a> Parser<'a> {
       return Ok(self.advance());
     }
     Err(ParseError::UnexpectedToken(Expected {
-      expected: expected,
+      expected,
       found: tok
     }))
   }
@@ -86,10 +167,89 @@ impl<'a> Parser<'a> {
   //cur span
   fn current_span(&self) -> Span {
     self.tokens.get(self.position)
-      .map(|t| t.span.clone())
+      .map(|t| t.span)
       .unwrap_or_default()
   }
 
+  //span from the token at `start` up to the last consumed token
+  fn span_from(&self, start: usize) -> Span {
+    let first = self.tokens.get(start).map(|t| t.span).unwrap_or_default();
+    let last = if self.position > start { self.tokens[self.position - 1].span } else { first };
+    Span { start: first.start, end: last.end.max(first.start) }
+  }
+
+  //error recovery (panic mode): record the error and skip to a point where parsing can resume.
+  //Within a function that is just past the next `;`, or just before the next `}` so the enclosing
+  //block still sees its closing brace.
+  fn recover_statement(&mut self, error: ParseError<'a>, start: usize) {
+    self.errors.push(error);
+    //nud/led consume the token they fail on. If that was the statement's `;` we are already past the
+    //end of it, and a block's closing brace must never be swallowed.
+    if self.position > start {
+      match self.tokens[self.position - 1].token {
+        Token::Delimiter(Delimiter::Semicolon) => return,
+        Token::Delimiter(Delimiter::RBrace) => { self.position -= 1; return; }
+        _ => {}
+      }
+    }
+    loop {
+      match self.peek().token {
+        Token::Delimiter(Delimiter::Semicolon) => { self.advance(); break; }
+        Token::Delimiter(Delimiter::RBrace) | Token::EOF => break,
+        _ => { self.advance(); }
+      }
+    }
+  }
+
+  //at the top level, skip whole braced bodies and resume after a `;` or `}` at depth 0, or at the
+  //next thing that looks like a function head (`name(`)
+  fn recover_top_level(&mut self, error: ParseError<'a>) {
+    self.errors.push(error);
+    let mut depth = 0usize;
+    while !self.is_at_end() {
+      let at_head = depth == 0
+        && matches!(self.peek().token, Token::Identifier(_))
+        && *self.peek_next() == Token::Delimiter(Delimiter::LParen);
+      if at_head {
+        break;
+      }
+      match self.advance().token {
+        Token::Delimiter(Delimiter::LBrace) => depth += 1,
+        Token::Delimiter(Delimiter::RBrace) => {
+          depth = depth.saturating_sub(1);
+          if depth == 0 { break; }
+        }
+        Token::Delimiter(Delimiter::Semicolon) if depth == 0 => break,
+        _ => {}
+      }
+    }
+  }
+
+  //inside a parenthesized expression: skip to the matching `)` and consume it. Gives up (restoring the
+  //position) at a `;`, brace or EOF, so statement-level recovery can take over.
+  fn recover_to_close_paren(&mut self, start: usize) -> bool {
+    let saved = self.position;
+    if self.position > start && self.tokens[self.position - 1].token == Token::Delimiter(Delimiter::RParen) {
+      //failed on the `)` itself, e.g. `if ()`
+      return true;
+    }
+    let mut depth = 0usize;
+    loop {
+      match self.peek().token {
+        Token::Delimiter(Delimiter::LParen) => depth += 1,
+        Token::Delimiter(Delimiter::RParen) if depth == 0 => { self.advance(); return true; }
+        Token::Delimiter(Delimiter::RParen) => depth -= 1,
+        Token::Delimiter(Delimiter::Semicolon) | Token::Delimiter(Delimiter::LBrace) |
+        Token::Delimiter(Delimiter::RBrace) | Token::EOF => {
+          self.position = saved;
+          return false;
+        }
+        _ => {}
+      }
+      self.advance();
+    }
+  }
+
   fn peek_next(&self) -> &Token<'a> {
     self.tokens.get(self.position + 1)
       .map(|t| &t.token)
@@ -108,9 +268,7 @@ impl<'a> Parser<'a> {
   fn get_precedence(&self, tok: &Token) -> Precedence {
      match tok {
       Token::Operator(op) => match op {
-        Operator::Assign | Operator::AssignPlus | Operator::AssignMinus |
-        Operator::AssignStar | Operator::AssignSlash | Operator::AssignPercent |
-        Operator::AssignAmp => Precedence::Assignment,
+        op if op.is_assignment() => Precedence::Assignment,
         Operator::Bar => Precedence::BitOr,
         Operator::Caret => Precedence::BitXor,
         Operator::Amp => Precedence::BitAnd,
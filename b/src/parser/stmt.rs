@@ -1,7 +1,7 @@
 use crate::ast::*;
 use crate::lexer::token::*;
 
-use super::{Parser, ParseError, precedence};
+use super::{Parser, ParseError, ParseOutput, precedence};
 
 //statement parsing: recursive descent
 impl <'a> Parser<'a> {
@@ -38,13 +38,13 @@ impl <'a> Parser<'a> {
         //check for array initialization
         match self.peek().token {
           //Array initializers can only be constant rvalues (no ident)
-          Token::Integer(..) | Token::CharLiteral(..) | Token::StringLiteral(..) => {
-            if array_size == None {
+          Token::Integer(..) | Token::CharLiteral(..) | Token::StringLiteral(..) | Token::Operator(Operator::Minus) => {
+            if array_size.is_none() {
               return Err(ParseError::UnspecifiedArraySizeInitialization(self.peek()));
             }
             //parse initializers
             loop {
-              initializer_list.push(self.advance());
+              initializer_list.push(self.parse_constant()?);
               match self.peek().token {
 
                 Token::Delimiter(Delimiter::Comma) => {
@@ -52,7 +52,8 @@ impl <'a> Parser<'a> {
                   match self.peek().token {
                     //if the token after the comma is an ident, then we have finished initializing.
                     Token::Identifier(..) => break,
-                    Token::Integer(..) | Token::CharLiteral(..) | Token::StringLiteral(..) => continue,
+                    Token::Integer(..) | Token::CharLiteral(..) | Token::StringLiteral(..) |
+                    Token::Operator(Operator::Minus) => continue,
                     _ => is_err = true,
                   }
                 }
@@ -66,7 +67,7 @@ impl <'a> Parser<'a> {
               }
             }
             Ok(GlobalDecl {
-              name: name,
+              name,
               size: array_size,
               initializer: Some(initializer_list),
             })
@@ -75,24 +76,23 @@ impl <'a> Parser<'a> {
           Token::Delimiter(Delimiter::Semicolon) | Token::Delimiter(Delimiter::Comma) => {
             //no initializers
             Ok(GlobalDecl {
-              name: name,
+              name,
               size: array_size,
               initializer: None
             })
           }
 
           _ => {
-            return Err(ParseError::UnknownToken(self.peek()));
+            Err(ParseError::UnknownToken(self.peek()))
           }
         }
       }
 
-      Token::Integer(..) | Token::CharLiteral(..) | Token::StringLiteral(..) => {
+      Token::Integer(..) | Token::CharLiteral(..) | Token::StringLiteral(..) | Token::Operator(Operator::Minus) => {
         //scalar with initializer
-        let mut initializer = Vec::new();
-        initializer.push(self.advance());
+        let initializer = vec![self.parse_constant()?];
         Ok(GlobalDecl {
-          name: name,
+          name,
           size: None,
           initializer: Some(initializer),
         })
@@ -101,14 +101,14 @@ impl <'a> Parser<'a> {
       Token::Delimiter(Delimiter::Semicolon) | Token::Delimiter(Delimiter::Comma) => {
         //scalar with no initializer
         Ok(GlobalDecl {
-          name: name,
+          name,
           size: None,
           initializer: None
         })
       }
 
       _ => {
-        return Err(ParseError::UnknownToken(self.peek()));
+        Err(ParseError::UnknownToken(self.peek()))
       }
     }
   }
@@ -127,13 +127,30 @@ impl <'a> Parser<'a> {
   //simple declaration is also valid within a function scope, which is internal declaration.
   //do top level extrn later im too lazy but it wont be hard (add an is_extrn field to global node)
 
-  pub fn parse_program(&mut self) -> Result<Program<'a>, ParseError<'a>> {
+  ///Parse the whole token stream, recovering from errors: a broken statement becomes Stmt::Error and
+  ///parsing resumes after the next `;` or before the next `}`; a broken top-level item is skipped up to
+  ///the next `;`, closing `}` or function head. Every error is collected in the output.
+  pub fn parse(&mut self) -> ParseOutput<'a> {
     let mut tops = Vec::new();
     while !self.is_at_end() {
-      let top = self.parse_top_level()?;
-      tops.push(top)
-    }  
-    Ok(Program{items: tops})
+      match self.parse_top_level() {
+        Ok(top) => tops.push(top),
+        Err(e) => self.recover_top_level(e),
+      }
+    }
+    ParseOutput {
+      program: Program{items: tops},
+      errors: std::mem::take(&mut self.errors),
+    }
+  }
+
+  ///Parse the whole token stream, failing with the first error (see parse() for all of them).
+  pub fn parse_program(&mut self) -> Result<Program<'a>, ParseError<'a>> {
+    let output = self.parse();
+    match output.errors.into_iter().next() {
+      Some(e) => Err(e),
+      None => Ok(output.program),
+    }
   }
 
   pub fn parse_top_level(&mut self) -> Result<Item<'a>, ParseError<'a>> {
@@ -159,8 +176,8 @@ impl <'a> Parser<'a> {
         self.expect(&Token::Delimiter(Delimiter::RParen))?;
         let body = self.parse_statement()?;
         Ok(Item::Function(Function {
-          name: name,
-          params: params,
+          name,
+          params,
           body: Box::new(body),
         }))
       }
@@ -170,7 +187,7 @@ impl <'a> Parser<'a> {
       Token::Delimiter(Delimiter::Semicolon) => {
         self.advance();
         Ok(Item::Global(vec![GlobalDecl {
-          name: name,
+          name,
           size: None,
           initializer: None,
         }]))
@@ -178,7 +195,7 @@ impl <'a> Parser<'a> {
 
       //multiple/array
       Token::Delimiter(Delimiter::LBrack) | Token::Delimiter(Delimiter::Comma) | Token::Integer(..) |
-      Token::StringLiteral(..) | Token::CharLiteral(..) => {
+      Token::StringLiteral(..) | Token::CharLiteral(..) | Token::Operator(Operator::Minus) => {
         let mut decls = Vec::new();
         let mut decl = self.parse_decl(name)?;
         decls.push(decl);
@@ -209,6 +226,7 @@ impl <'a> Parser<'a> {
       Token::Keyword(Keyword::While) => {self.advance(); self.parse_while()},
       Token::Keyword(Keyword::Switch) => {self.advance(); self.parse_switch()},
       Token::Keyword(Keyword::Case) => {self.advance(); self.parse_case()},
+      Token::Keyword(Keyword::Default) => {let keyword = self.advance(); self.parse_default(keyword)},
       Token::Keyword(Keyword::Goto) => {self.advance(); self.parse_goto()},
       Token::Keyword(Keyword::Return) => {self.advance(); self.parse_return()},
       _ => {
@@ -225,34 +243,50 @@ impl <'a> Parser<'a> {
     let mut valid_auto_decl = true;
     let mut statements = Vec::new();
     while self.peek().token != Token::Delimiter(Delimiter::RBrace) {
-
-      //auto declared not as first statement
-      if (self.peek().token == Token::Keyword(Keyword::Auto) && valid_auto_decl == false) {
-        return Err(ParseError::AutoRedecl(self.peek()));
+      if self.is_at_end() {
+        //unclosed block; expect() reports the missing brace at EOF
+        break;
       }
 
-      //valid auto
-      if (self.peek().token == Token::Keyword(Keyword::Auto) && valid_auto_decl == true) {
-        statements.push(self.parse_auto()?);
-        continue;
-      }
+      let start = self.position;
+      let result = match self.peek().token {
+        Token::Keyword(Keyword::Auto) => {
+          //auto declared not as first statement: report, but still parse it so its names are known
+          if !valid_auto_decl {
+            self.errors.push(ParseError::AutoRedecl(self.peek()));
+          }
+          self.parse_auto()
+        }
 
-      //extrn keeps auto valid
-      if (self.peek().token == Token::Keyword(Keyword::Extrn)) {
-        self.advance();
-        let statement = self.parse_extrn()?;
-        statements.push(statement);
-        continue;
-      }
+        //extrn keeps auto valid
+        Token::Keyword(Keyword::Extrn) => {
+          self.advance();
+          self.parse_extrn()
+        }
 
+        _ => {
+          valid_auto_decl = false;
+          self.parse_statement()
+        }
+      };
 
-      let statement = self.parse_statement()?;
-      valid_auto_decl = false;
-      statements.push(statement);
+      match result {
+        Ok(statement) => statements.push(statement),
+        Err(e) => {
+          self.recover_statement(e, start);
+          statements.push(Stmt::Error { span: self.span_from(start) });
+        }
+      }
+    }
+    //an unclosed block at EOF keeps what it parsed; nested unclosed blocks, or a statement cut off
+    //by it, report the EOF only once
+    if let Err(e) = self.expect(&Token::Delimiter(Delimiter::RBrace))
+      && !matches!(self.errors.last(), Some(ParseError::UnexpectedEOF))
+      && self.errors.last().and_then(|last| last.span()) != e.span() {
+      self.errors.push(e);
     }
-    self.expect(&Token::Delimiter(Delimiter::RBrace));
     Ok(Stmt::Block {
-      statements: statements,
+      statements,
     })
 
   }
@@ -265,11 +299,11 @@ impl <'a> Parser<'a> {
     if self.peek().token == Token::Delimiter(Delimiter::LBrack) {
       self.advance();
       let size = self.advance();
-      self.expect(&Token::Delimiter(Delimiter::RBrack));
-      decls.push(AutoDecl{name: name, size: Some(size)});
+      self.expect(&Token::Delimiter(Delimiter::RBrack))?;
+      decls.push(AutoDecl{name, size: Some(size)});
     }
     else {
-      decls.push(AutoDecl{name: name, size: None});
+      decls.push(AutoDecl{name, size: None});
     }
     loop {
       match self.peek().token {
@@ -283,15 +317,15 @@ impl <'a> Parser<'a> {
           match self.peek().token {
 
             Token::Delimiter(Delimiter::Comma) | Token::Delimiter(Delimiter::Semicolon) => {
-              decls.push(AutoDecl{name: name, size: None});
+              decls.push(AutoDecl{name, size: None});
               continue;
             }
 
             Token::Delimiter(Delimiter::LBrack) => {
               self.advance();
               let size = self.advance();
-              self.expect(&Token::Delimiter(Delimiter::RBrack));
-              decls.push(AutoDecl{name: name, size: Some(size)});
+              self.expect(&Token::Delimiter(Delimiter::RBrack))?;
+              decls.push(AutoDecl{name, size: Some(size)});
             }
 
             _ => {is_err = true;}
@@ -307,7 +341,7 @@ impl <'a> Parser<'a> {
         return Err(ParseError::UnknownToken(self.peek()));
       }
     }
-    self.expect(&Token::Delimiter(Delimiter::Semicolon));
+    self.expect(&Token::Delimiter(Delimiter::Semicolon))?;
     Ok(Stmt::Auto {
       declarations: decls,
     })
@@ -330,11 +364,28 @@ impl <'a> Parser<'a> {
     
   }
 
+  //`( expr )` of an if/while. A broken condition becomes Expr::Error so the body is still parsed.
+  fn parse_condition(&mut self) -> Result<Expr<'a>, ParseError<'a>> {
+    self.expect(&Token::Delimiter(Delimiter::LParen))?;
+    let start = self.position;
+    match self.parse_expression(0) {
+      Ok(condition) => {
+        self.expect(&Token::Delimiter(Delimiter::RParen))?;
+        Ok(condition)
+      }
+      Err(e) => {
+        if !self.recover_to_close_paren(start) {
+          return Err(e);
+        }
+        self.errors.push(e);
+        Ok(Expr::Error { span: self.span_from(start) })
+      }
+    }
+  }
+
   pub fn parse_if(&mut self) -> Result<Stmt<'a>, ParseError<'a>> {
     //Syntax: if(condition) then_statement else_statement (optional)
-    self.expect(&Token::Delimiter(Delimiter::LParen))?;
-    let condition = self.parse_expression(0)?;
-    self.expect(&Token::Delimiter(Delimiter::RParen))?;
+    let condition = self.parse_condition()?;
     let then_branch = self.parse_statement()?;
     if self.peek().token == Token::Keyword(Keyword::Else) {
       self.advance();
@@ -356,9 +407,7 @@ impl <'a> Parser<'a> {
 
   pub fn parse_while(&mut self) -> Result<Stmt<'a>, ParseError<'a>> {
     //Syntax: while(condition) body
-    self.expect(&Token::Delimiter(Delimiter::LParen))?;
-    let condition = self.parse_expression(0)?;
-    self.expect(&Token::Delimiter(Delimiter::RParen))?;
+    let condition = self.parse_condition()?;
     let body = self.parse_statement()?;
     Ok(Stmt::While {
       condition: Box::new(condition),
@@ -367,53 +416,72 @@ impl <'a> Parser<'a> {
   }
 
   pub fn parse_switch(&mut self) -> Result<Stmt<'a>, ParseError<'a>> {
-    //Syntax: switch expr {cases}
-    let mut is_err = false;
+    //Syntax: switch expr {statements}, with case/default labelling statements in the body
     let mut cases = Vec::new();
     let condition = self.parse_expression(0)?;
-    //dispatch body through parse_case instead of parse_statement
     self.expect(&Token::Delimiter(Delimiter::LBrace))?;
     loop {
-      match self.peek().token {
-        Token::Keyword(Keyword::Case) => {
-          //consume Case keyword, parse body
-          self.advance();
-          let case = self.parse_case()?;
-          cases.push(case);
-        }
-        Token::Keyword(Keyword::Default) => {
-          self.advance();
-          self.expect(&Token::Delimiter(Delimiter::Colon));
-          let body = self.parse_statement()?;
-          cases.push(Stmt::Default{body: Box::new(body)});
+      let start = self.position;
+      let result = match self.peek().token {
+        Token::Delimiter(Delimiter::RBrace) | Token::EOF => break,
+        _ => self.parse_statement(),
+      };
+
+      match result {
+        Ok(case) => cases.push(case),
+        Err(e) => {
+          self.recover_statement(e, start);
+          cases.push(Stmt::Error { span: self.span_from(start) });
         }
-        Token::Delimiter(Delimiter::RBrace) => break,
-        _ => is_err = {is_err = true; break;}
-
       }
     }
-    if is_err {
-      return Err(ParseError::UnknownToken(self.peek()))
-    }
-    self.advance();
+    self.expect(&Token::Delimiter(Delimiter::RBrace))?;
     Ok(Stmt::Switch {
       condition: Box::new(condition),
-      cases: cases,
+      cases,
     })
 
 
   }
 
+  fn parse_default(&mut self, keyword: &'a SpannedToken<'a>) -> Result<Stmt<'a>, ParseError<'a>> {
+    self.expect(&Token::Delimiter(Delimiter::Colon))?;
+    let body = self.parse_statement()?;
+    Ok(Stmt::Default { keyword, body: Box::new(body) })
+  }
+
   pub fn parse_case(&mut self) -> Result<Stmt<'a>, ParseError<'a>> {
-    let value = self.advance();
-    self.expect(&Token::Delimiter(Delimiter::Colon));
+    //any expression; sema checks that it folds to a constant
+    let value = self.parse_expression(0)?;
+    self.expect(&Token::Delimiter(Delimiter::Colon))?;
     let body = self.parse_statement()?;
     Ok(Stmt::Case {
-      value: value,
+      value: Box::new(value),
       body: Box::new(body),
     })
   }
 
+  //a literal where the grammar requires a constant (global initializers). The lexer
+  //no longer folds signs into numbers, so `-1` arrives as two tokens and is rebuilt here as unary
+  //minus over the literal; Expr::const_value folds it back to a number.
+  fn parse_constant(&mut self) -> Result<Expr<'a>, ParseError<'a>> {
+    let t = self.advance();
+    match t.token {
+      Token::Integer(..) | Token::CharLiteral(..) | Token::StringLiteral(..) => Ok(Expr::Literal { value: t }),
+      Token::Operator(Operator::Minus) => {
+        let literal = self.advance();
+        match literal.token {
+          Token::Integer(..) | Token::CharLiteral(..) => Ok(Expr::Unary {
+            operator: t,
+            right: Box::new(Expr::Literal { value: literal }),
+          }),
+          _ => Err(ParseError::ExpectedConstant(literal)),
+        }
+      }
+      _ => Err(ParseError::ExpectedConstant(t)),
+    }
+  }
+
   pub fn parse_goto(&mut self) -> Result<Stmt<'a>, ParseError<'a>> {
     let expr = self.parse_expression(0)?;
     self.expect(&Token::Delimiter(Delimiter::Semicolon))?;
@@ -438,7 +506,7 @@ impl <'a> Parser<'a> {
 
   pub fn parse_expr_stmt(&mut self) -> Result<Stmt<'a>, ParseError<'a>> {
     let expr = self.parse_expression(0)?;
-    self.expect(&Token::Delimiter(Delimiter::Semicolon));
+    self.expect(&Token::Delimiter(Delimiter::Semicolon))?;
     Ok(Stmt::Expression {
       expression: Box::new(expr),
     })
@@ -449,7 +517,7 @@ impl <'a> Parser<'a> {
     self.advance(); //consume colon
     let body = self.parse_statement()?;
     Ok(Stmt::Label {
-      name: name,
+      name,
       body: Box::new(body),
     })
   }
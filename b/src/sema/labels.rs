@@ -0,0 +1,74 @@
+use crate::ast::*;
+use crate::ast::visitor::*;
+use crate::common::span::Span;
+use crate::lexer::token::{SpannedToken, Token};
+
+//labels are function scoped and `goto` may jump forward to one, so every body is scanned for its
+//labels before it is resolved. Labels can sit anywhere a statement can, including inside loops,
+//if branches and switch arms.
+
+///Every label defined in a function body, in source order: (name, span of the name).
+pub fn collect_labels(body: &Stmt) -> Vec<(String, Span)> {
+  let mut collector = LabelCollector { labels: Vec::new() };
+  walk_stmt(&mut collector, body);
+  collector.labels
+}
+
+struct LabelCollector {
+  labels: Vec<(String, Span)>,
+}
+
+impl StmtVisitor<()> for LabelCollector {
+
+  fn visit_block(&mut self, statements: &[Stmt]) {
+    for statement in statements {
+      walk_stmt(self, statement);
+    }
+  }
+
+  fn visit_auto(&mut self, _declarations: &[AutoDecl]) {}
+
+  fn visit_extrn(&mut self, _names: &[&SpannedToken]) {}
+
+  fn visit_expression(&mut self, _expression: &Expr) {}
+
+  fn visit_if(&mut self, _condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+    walk_stmt(self, then_branch);
+    if let Some(else_branch) = else_branch {
+      walk_stmt(self, else_branch);
+    }
+  }
+
+  fn visit_while(&mut self, _condition: &Expr, body: &Stmt) {
+    walk_stmt(self, body);
+  }
+
+  fn visit_switch(&mut self, _condition: &Expr, cases: &[Stmt]) {
+    for case in cases {
+      walk_stmt(self, case);
+    }
+  }
+
+  fn visit_case(&mut self, _value: &Expr, body: &Stmt) {
+    walk_stmt(self, body);
+  }
+
+  fn visit_default(&mut self, _keyword: &SpannedToken, body: &Stmt) {
+    walk_stmt(self, body);
+  }
+
+  fn visit_label(&mut self, name: &SpannedToken, body: &Stmt) {
+    if let Token::Identifier(label) = name.token {
+      self.labels.push((label.to_string(), name.span));
+    }
+    walk_stmt(self, body);
+  }
+
+  fn visit_goto(&mut self, _expression: &Expr) {}
+
+  fn visit_return(&mut self, _value: Option<&Expr>) {}
+
+  fn visit_null(&mut self) {}
+
+  fn visit_error_stmt(&mut self, _span: Span) {}
+}
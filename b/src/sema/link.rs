@@ -0,0 +1,92 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::common::source_map::FileId;
+use crate::common::span::Span;
+use crate::diagnostics::{Diagnostic, Label};
+use crate::libb;
+use crate::sema::SemaOutput;
+
+//symbol resolution across files. Each file is analyzed on its own, and an extrn of a name it does
+//not define is an import (Location::Import). A program is a set of files whose top-level names
+//share one namespace, as B has no file-local names: every import must be defined by another file
+//or by libb, and no name may be defined twice. A definition may replace one of libb's.
+//
+//Whether an import names data or a function is only known here: the value of a global is the word
+//it holds, and of a function its address, so lowering needs `globals` to tell them apart.
+
+///The top-level names one file defines and imports, with where it does so.
+#[derive(Debug, Clone)]
+pub struct Unit {
+  pub file: FileId,
+  pub definitions: Vec<(String, Span)>, //functions and globals, at their names
+  pub globals: Vec<String>, //the definitions that are data, not functions
+  pub imports: Vec<(String, Span)>, //at the first extrn of the name, or its first call
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkError {
+  Unresolved { name: String, file: FileId, span: Span },
+  MultiplyDefined { name: String, file: FileId, span: Span, previous_file: FileId, previous: Span },
+}
+
+impl Unit {
+  pub fn new(file: FileId, sema: &SemaOutput) -> Self {
+    let global = &sema.table.global;
+    let at = |&id| (sema.table.name(id).to_string(), global.symbols[&id].span);
+    Unit {
+      file,
+      definitions: global.functions.iter().chain(&global.globals).map(at).collect(),
+      globals: global.globals.iter().map(|&id| sema.table.name(id).to_string()).collect(),
+      imports: global.imports.iter().map(at).collect(),
+    }
+  }
+}
+
+///Check that the files make one program, reporting errors in file order.
+pub fn link(units: &[Unit]) -> Vec<LinkError> {
+  let mut errors = Vec::new();
+  let mut defined: FxHashMap<&str, (FileId, Span)> = FxHashMap::default();
+  for unit in units {
+    let mut definitions: Vec<&(String, Span)> = unit.definitions.iter().collect();
+    definitions.sort_by_key(|(_, span)| span.start);
+    for (name, span) in definitions {
+      match defined.get(name.as_str()) {
+        Some(&(previous_file, previous)) => errors.push(LinkError::MultiplyDefined {
+          name: name.clone(), file: unit.file, span: *span, previous_file, previous,
+        }),
+        None => {
+          defined.insert(name, (unit.file, *span));
+        }
+      }
+    }
+  }
+  for unit in units {
+    for (name, span) in &unit.imports {
+      if !defined.contains_key(name.as_str()) && !libb::provides(name) {
+        errors.push(LinkError::Unresolved { name: name.clone(), file: unit.file, span: *span });
+      }
+    }
+  }
+  errors
+}
+
+///Every name some file defines as data.
+pub fn globals(units: &[Unit]) -> FxHashSet<String> {
+  units.iter().flat_map(|unit| unit.globals.iter().cloned()).collect()
+}
+
+impl LinkError {
+  pub fn to_diagnostic(&self) -> Diagnostic {
+    match self {
+      LinkError::Unresolved { name, file, span } => Diagnostic::error(format!("`{}` is not defined in any file", name))
+        .with_code("E0210")
+        .with_label(Label::primary(*file, *span, "used here"))
+        .with_help(format!("define `{}` at the top level of one of the files, or add the file that does", name)),
+      LinkError::MultiplyDefined { name, file, span, previous_file, previous } => Diagnostic::error(format!("`{}` is defined in more than one file", name))
+        .with_code("E0211")
+        .with_label(Label::primary(*file, *span, "defined here"))
+        .with_label(Label::secondary(*previous_file, *previous, "and here"))
+        .with_note("top-level names are shared by every file of a program"),
+    }
+  }
+}
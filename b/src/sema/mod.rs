@@ -1 +1,142 @@
-//pub mod symbol_table;
\ No newline at end of file
+use rustc_hash::FxHashMap;
+
+use crate::ast::Program;
+use crate::common::source_map::FileId;
+use crate::common::span::Span;
+use crate::diagnostics::{Diagnostic, Label};
+use crate::sema::symbol_table::{Symbol, SymbolTable};
+
+pub mod symbol_table;
+pub mod resolve;
+pub mod labels;
+pub mod switch;
+pub mod link;
+
+//semantic analysis. Runs on a parsed Program and never changes it; everything it learns goes into
+//side tables keyed by the span of the identifier token a node names, which is unique within a file.
+
+///Everything semantic analysis produces for one program.
+#[derive(Debug)]
+pub struct SemaOutput {
+  pub table: SymbolTable,
+  pub resolved: FxHashMap<Span, Symbol>, //identifier span (use or declaration) -> symbol it names
+  pub gotos: FxHashMap<Span, GotoTarget>, //span of a goto's expression (Expr::span) -> how it jumps
+  pub errors: Vec<SemaError>,
+  pub warnings: Vec<SemaWarning>,
+}
+
+///How a `goto` jumps. B allows any expression after `goto`; only a bare label name is a direct jump,
+///anything else (a variable holding a label, `goto table[i]`) is an indirect jump to a computed address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GotoTarget {
+  Label { index: u32 }, //FunctionEnv::labels index of the current function
+  Computed,
+}
+
+///Resolve every identifier in the program and check switch statements, reporting all errors found.
+pub fn analyze(program: &Program) -> SemaOutput {
+  let mut output = resolve::Resolver::new().resolve(program);
+  switch::check_switches(program, &mut output.errors, &mut output.warnings);
+  output
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SemaError {
+  UndefinedName { name: String, span: Span },
+  NotDeclared { name: String, span: Span, global: Span }, //global used in a function without extrn
+  DuplicateDefinition { name: String, span: Span, previous: Span },
+  DuplicateLabel { name: String, span: Span, previous: Span },
+  UndefinedLabel { name: String, span: Span }, //goto to a name that is neither a label nor a variable
+  NonConstantCase { span: Span },
+  DuplicateCase { value: i64, span: Span, previous: Span, packed: bool }, //packed: either side is a char constant
+  MultipleDefault { span: Span, previous: Span },
+  LabelOutsideSwitch { keyword: &'static str, span: Span }, //`case` or `default` with no enclosing switch
+}
+
+impl SemaError {
+  pub fn span(&self) -> Span {
+    match self {
+      SemaError::UndefinedName { span, .. } | SemaError::NotDeclared { span, .. } |
+      SemaError::DuplicateDefinition { span, .. } | SemaError::DuplicateLabel { span, .. } |
+      SemaError::UndefinedLabel { span, .. } | SemaError::NonConstantCase { span } |
+      SemaError::DuplicateCase { span, .. } | SemaError::MultipleDefault { span, .. } |
+      SemaError::LabelOutsideSwitch { span, .. } => *span,
+    }
+  }
+
+  pub fn to_diagnostic(&self, file: FileId) -> Diagnostic {
+    match self {
+      SemaError::UndefinedName { name, span } => Diagnostic::error(format!("undefined name `{}`", name))
+        .with_code("E0201")
+        .with_label(Label::primary(file, *span, "not declared in this function or at the top level"))
+        .with_help(format!("declare it with `auto {};`, or define it at the top level and use `extrn {};`", name, name)),
+      SemaError::NotDeclared { name, span, global } => Diagnostic::error(format!("`{}` is not declared in this function", name))
+        .with_code("E0202")
+        .with_label(Label::primary(file, *span, "used here"))
+        .with_label(Label::secondary(file, *global, "defined at the top level here"))
+        .with_note("B functions only see globals they declare with `extrn`")
+        .with_help(format!("add `extrn {};` at the start of the function", name)),
+      SemaError::DuplicateDefinition { name, span, previous } => Diagnostic::error(format!("`{}` is defined more than once", name))
+        .with_code("E0203")
+        .with_label(Label::primary(file, *span, "redefined here"))
+        .with_label(Label::secondary(file, *previous, "first defined here")),
+      SemaError::DuplicateLabel { name, span, previous } => Diagnostic::error(format!("label `{}` is defined more than once", name))
+        .with_code("E0204")
+        .with_label(Label::primary(file, *span, "redefined here"))
+        .with_label(Label::secondary(file, *previous, "first defined here"))
+        .with_note("labels are visible in the whole function, so they must be unique within it"),
+      SemaError::UndefinedLabel { name, span } => Diagnostic::error(format!("no label `{}` in this function", name))
+        .with_code("E0205")
+        .with_label(Label::primary(file, *span, "goto target not found"))
+        .with_help(format!("define it with `{}: statement` in this function", name)),
+      SemaError::NonConstantCase { span } => Diagnostic::error("case value is not an integer or character constant")
+        .with_code("E0206")
+        .with_label(Label::primary(file, *span, "expected a constant like `97` or `'a'`")),
+      SemaError::DuplicateCase { value, span, previous, packed } => {
+        let diag = Diagnostic::error(format!("duplicate case value {}", value))
+          .with_code("E0207")
+          .with_label(Label::primary(file, *span, "this case can never be reached"))
+          .with_label(Label::secondary(file, *previous, "same value handled here"));
+        if *packed {
+          diag.with_note(format!("character constants are compared by their packed value, which here is {}", value))
+        } else {
+          diag
+        }
+      }
+      SemaError::MultipleDefault { span, previous } => Diagnostic::error("switch has more than one default")
+        .with_code("E0208")
+        .with_label(Label::primary(file, *span, "second default here"))
+        .with_label(Label::secondary(file, *previous, "first default here")),
+      SemaError::LabelOutsideSwitch { keyword, span } => Diagnostic::error(format!("`{}` outside of a switch", keyword))
+        .with_code("E0209")
+        .with_label(Label::primary(file, *span, "not inside any switch body")),
+    }
+  }
+}
+
+///Semantic problems that do not stop compilation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SemaWarning {
+  UnusedLabel { name: String, span: Span },
+  UnreachableBeforeCase { span: Span }, //statements in a switch body ahead of its first label
+}
+
+impl SemaWarning {
+  pub fn span(&self) -> Span {
+    match self {
+      SemaWarning::UnusedLabel { span, .. } | SemaWarning::UnreachableBeforeCase { span } => *span,
+    }
+  }
+
+  pub fn to_diagnostic(&self, file: FileId) -> Diagnostic {
+    match self {
+      SemaWarning::UnusedLabel { name, span } => Diagnostic::warning(format!("unused label `{}`", name))
+        .with_code("W0201")
+        .with_label(Label::primary(file, *span, "no goto jumps here")),
+      SemaWarning::UnreachableBeforeCase { span } => Diagnostic::warning("unreachable code before the first case")
+        .with_code("W0202")
+        .with_label(Label::primary(file, *span, "never executed"))
+        .with_note("a switch jumps straight to the matching case or default"),
+    }
+  }
+}
@@ -0,0 +1,402 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::ast::*;
+use crate::ast::visitor::*;
+use crate::common::span::Span;
+use crate::lexer::token::{Operator, SpannedToken, Token};
+use crate::sema::{GotoTarget, SemaError, SemaOutput, SemaWarning};
+use crate::sema::labels::collect_labels;
+use crate::sema::symbol_table::*;
+
+//name resolution. Two passes: every top level name is declared first, so functions can use globals
+//and call functions defined further down the file; then each function body is walked in order,
+//declaring params, autos and extrns as they appear and resolving each use against them.
+//
+//B scoping rules enforced here:
+//- a function only sees its params, autos and extrn declarations. A global used without `extrn`
+//  is an error even though it exists.
+//- a name called as a function that the function has not declared is implicitly extrn (`putchar(c)`).
+//- an extrn (explicit or implicit) of a name the program does not define becomes an import, to be
+//  satisfied by libb or another file.
+//- labels are declared before the body is walked, so a goto can jump forward. A goto to a label name
+//  is direct; a goto to anything else is computed (an indirect jump through a label value).
+
+pub struct Resolver {
+  table: SymbolTable,
+  resolved: FxHashMap<Span, Symbol>,
+  gotos: FxHashMap<Span, GotoTarget>,
+  errors: Vec<SemaError>,
+  warnings: Vec<SemaWarning>,
+  env: Option<FunctionEnv>, //function being walked
+  used_labels: FxHashSet<SymbolId>, //labels of the current function something refers to
+}
+
+fn ident<'t>(token: &'t SpannedToken) -> Option<&'t str> {
+  match &token.token {
+    Token::Identifier(name) => Some(name),
+    _ => None, //the parser takes any token as a name in a few places and has reported those
+  }
+}
+
+//words of storage for a declaration: the bound for vectors, 1 for scalars
+fn declared_size(size: Option<&SpannedToken>) -> u32 {
+  match size.map(|t| &t.token) {
+    Some(Token::Integer(n)) => *n as u32,
+    _ => 1,
+  }
+}
+
+impl Default for Resolver {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Resolver {
+
+  pub fn new() -> Self {
+    Self {
+      table: SymbolTable::new(),
+      resolved: FxHashMap::default(),
+      gotos: FxHashMap::default(),
+      errors: Vec::new(),
+      warnings: Vec::new(),
+      env: None,
+      used_labels: FxHashSet::default(),
+    }
+  }
+
+  pub fn resolve(mut self, program: &Program) -> SemaOutput {
+    for item in &program.items {
+      self.declare_item(item);
+    }
+    for item in &program.items {
+      walk_item(&mut self, item);
+    }
+    SemaOutput {
+      table: self.table,
+      resolved: self.resolved,
+      gotos: self.gotos,
+      errors: self.errors,
+      warnings: self.warnings,
+    }
+  }
+
+  //pass 1: top level names
+  fn declare_item(&mut self, item: &Item) {
+    match item {
+      Item::Function(f) => {
+        let func_index = self.table.global.functions.len() as u32;
+        if let Some(id) = self.declare_global(f.name, SymbolKind::Function, 1, Location::Function { func_index }) {
+          self.table.global.functions.push(id);
+          self.table.functions.push(FunctionEnv::new(id));
+        }
+      }
+      Item::Global(decls) => {
+        for decl in decls {
+          let count = decl.initializer.as_ref().map_or(1, |inits| inits.len() as u32);
+          let size = if decl.size.is_some() { declared_size(decl.size) } else { count.max(1) };
+          let index = self.table.global.globals.len() as u32;
+          if let Some(id) = self.declare_global(decl.name, SymbolKind::Global, size, Location::Global { index }) {
+            self.table.global.globals.push(id);
+          }
+        }
+      }
+    }
+  }
+
+  fn declare_global(&mut self, token: &SpannedToken, kind: SymbolKind, size: u32, slot: Location) -> Option<SymbolId> {
+    let name = ident(token)?;
+    let id = self.table.interner.intern(name);
+    if let Some(previous) = self.table.global.lookup(id) {
+      self.errors.push(SemaError::DuplicateDefinition { name: name.to_string(), span: token.span, previous: previous.span });
+      return None;
+    }
+    let symbol = Symbol { name: id, kind, size, slot, span: token.span };
+    self.table.global.symbols.insert(id, symbol);
+    self.resolved.insert(token.span, symbol);
+    Some(id)
+  }
+
+  //where an extrn'd name lives: the program's own definition, or an import created on first use
+  fn external(&mut self, id: SymbolId, span: Span) -> Symbol {
+    if let Some(symbol) = self.table.global.lookup(id) {
+      return *symbol;
+    }
+    let import_index = self.table.global.imports.len() as u32;
+    let symbol = Symbol { name: id, kind: SymbolKind::Extrn, size: 1, slot: Location::Import { import_index }, span };
+    self.table.global.imports.push(id);
+    self.table.global.symbols.insert(id, symbol);
+    symbol
+  }
+
+  fn local(&self, token: &SpannedToken) -> Option<Symbol> {
+    let id = self.table.interner.id_of(ident(token)?)?;
+    self.env.as_ref()?.lookup(id).copied()
+  }
+
+  fn declare_local(&mut self, token: &SpannedToken, kind: SymbolKind, size: u32) {
+    let Some(name) = ident(token) else { return };
+    let id = self.table.interner.intern(name);
+    let Some(env) = self.env.as_ref() else { return };
+    if let Some(previous) = env.lookup(id) {
+      self.errors.push(SemaError::DuplicateDefinition { name: name.to_string(), span: token.span, previous: previous.span });
+      return;
+    }
+
+    let symbol = match kind {
+      SymbolKind::Extrn => {
+        let target = self.external(id, token.span);
+        Symbol { kind, span: token.span, ..target }
+      }
+      _ => {
+        let Some(env) = self.env.as_mut() else { return };
+        Symbol { name: id, kind, size, slot: env.next_slot(), span: token.span }
+      }
+    };
+    self.insert_local(id, symbol);
+  }
+
+  fn insert_local(&mut self, id: SymbolId, symbol: Symbol) {
+    let Some(env) = self.env.as_mut() else { return };
+    env.locals.insert(id, symbol);
+    match symbol.kind {
+      SymbolKind::Param => env.params.push(id),
+      SymbolKind::Label => env.labels.push(id),
+      _ => {}
+    }
+    self.resolved.insert(symbol.span, symbol);
+  }
+
+  fn declare_label(&mut self, name: &str, span: Span) {
+    let id = self.table.interner.intern(name);
+    let Some(env) = self.env.as_ref() else { return };
+    if let Some(previous) = env.lookup(id).copied() {
+      let name = name.to_string();
+      self.errors.push(match previous.kind {
+        SymbolKind::Label => SemaError::DuplicateLabel { name, span, previous: previous.span },
+        _ => SemaError::DuplicateDefinition { name, span, previous: previous.span },
+      });
+      return;
+    }
+    let index = env.labels.len() as u32;
+    self.insert_local(id, Symbol { name: id, kind: SymbolKind::Label, size: 1, slot: Location::Label { index }, span });
+  }
+
+  fn use_name(&mut self, token: &SpannedToken) {
+    let Some(name) = ident(token) else { return };
+    if let Some(symbol) = self.local(token) {
+      if symbol.kind == SymbolKind::Label {
+        self.used_labels.insert(symbol.name);
+      }
+      self.resolved.insert(token.span, symbol);
+      return;
+    }
+    let id = self.table.interner.intern(name);
+    let error = match self.table.global.lookup(id) {
+      Some(global) if global.kind != SymbolKind::Extrn => {
+        SemaError::NotDeclared { name: name.to_string(), span: token.span, global: global.span }
+      }
+      _ => SemaError::UndefinedName { name: name.to_string(), span: token.span },
+    };
+    self.errors.push(error);
+  }
+
+  //callee that the function has not declared: implicitly extrn
+  fn use_callee(&mut self, token: &SpannedToken) {
+    let Some(name) = ident(token) else { return };
+    let id = self.table.interner.intern(name);
+    let symbol = self.external(id, token.span);
+    self.resolved.insert(token.span, symbol);
+  }
+}
+
+impl ItemVisitor<()> for Resolver {
+
+  fn visit_function(&mut self, name: &SpannedToken, params: &[&SpannedToken], body: &Stmt) {
+    let Some(fn_name) = ident(name) else { return };
+    let id = self.table.interner.intern(fn_name);
+    self.env = Some(FunctionEnv::new(id));
+    self.used_labels.clear();
+    for param in params {
+      self.declare_local(param, SymbolKind::Param, 1);
+    }
+    for (label, span) in collect_labels(body) {
+      self.declare_label(&label, span);
+    }
+    walk_stmt(self, body);
+
+    let env = self.env.take().expect("function env set above");
+    for label in &env.labels {
+      if !self.used_labels.contains(label) {
+        let span = env.locals[label].span;
+        self.warnings.push(SemaWarning::UnusedLabel { name: self.table.name(*label).to_string(), span });
+      }
+    }
+    //only the defining occurrence owns the env; a duplicate was reported in pass 1 and is dropped
+    if let Some(Symbol { slot: Location::Function { func_index }, span, .. }) = self.table.global.lookup(id).copied()
+      && span == name.span {
+      self.table.functions[func_index as usize] = env;
+    }
+  }
+
+  fn visit_global(&mut self, _decls: &[GlobalDecl]) {
+    //declared in pass 1; initializers are constants and name nothing
+  }
+}
+
+impl StmtVisitor<()> for Resolver {
+
+  fn visit_block(&mut self, statements: &[Stmt]) {
+    for statement in statements {
+      walk_stmt(self, statement);
+    }
+  }
+
+  fn visit_auto(&mut self, declarations: &[AutoDecl]) {
+    for decl in declarations {
+      self.declare_local(decl.name, SymbolKind::Auto, declared_size(decl.size));
+    }
+  }
+
+  fn visit_extrn(&mut self, names: &[&SpannedToken]) {
+    for name in names {
+      self.declare_local(name, SymbolKind::Extrn, 1);
+    }
+  }
+
+  fn visit_expression(&mut self, expression: &Expr) {
+    walk_expr(self, expression);
+  }
+
+  fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+    walk_expr(self, condition);
+    walk_stmt(self, then_branch);
+    if let Some(else_branch) = else_branch {
+      walk_stmt(self, else_branch);
+    }
+  }
+
+  fn visit_while(&mut self, condition: &Expr, body: &Stmt) {
+    walk_expr(self, condition);
+    walk_stmt(self, body);
+  }
+
+  fn visit_switch(&mut self, condition: &Expr, cases: &[Stmt]) {
+    walk_expr(self, condition);
+    for case in cases {
+      walk_stmt(self, case);
+    }
+  }
+
+  fn visit_case(&mut self, value: &Expr, body: &Stmt) {
+    walk_expr(self, value);
+    walk_stmt(self, body);
+  }
+
+  fn visit_default(&mut self, _keyword: &SpannedToken, body: &Stmt) {
+    walk_stmt(self, body);
+  }
+
+  fn visit_label(&mut self, _name: &SpannedToken, body: &Stmt) {
+    walk_stmt(self, body);
+  }
+
+  fn visit_goto(&mut self, expression: &Expr) {
+    let target = match expression {
+      Expr::Variable { name } => match self.local(name) {
+        Some(Symbol { kind: SymbolKind::Label, slot: Location::Label { index }, .. }) => GotoTarget::Label { index },
+        Some(_) => GotoTarget::Computed, //a variable holding a label value
+        None => {
+          if let Token::Identifier(label) = name.token {
+            self.errors.push(SemaError::UndefinedLabel { name: label.to_string(), span: name.span });
+          }
+          return;
+        }
+      },
+      _ => GotoTarget::Computed,
+    };
+    walk_expr(self, expression);
+    self.gotos.insert(expression.span(), target);
+  }
+
+  fn visit_return(&mut self, value: Option<&Expr>) {
+    if let Some(value) = value {
+      walk_expr(self, value);
+    }
+  }
+
+  fn visit_null(&mut self) {}
+
+  fn visit_error_stmt(&mut self, _span: Span) {}
+}
+
+impl ExprVisitor<()> for Resolver {
+
+  fn visit_assign(&mut self, lvalue: &Expr, _operator: &SpannedToken, value: &Expr) {
+    walk_expr(self, lvalue);
+    walk_expr(self, value);
+  }
+
+  fn visit_binary(&mut self, left: &Expr, _operator: &SpannedToken, right: &Expr) {
+    walk_expr(self, left);
+    walk_expr(self, right);
+  }
+
+  fn visit_call(&mut self, callee: &Expr, arguments: &[Expr]) {
+    match callee {
+      Expr::Variable { name } if self.local(name).is_none() => self.use_callee(name),
+      _ => walk_expr(self, callee),
+    }
+    for argument in arguments {
+      walk_expr(self, argument);
+    }
+  }
+
+  fn visit_grouping(&mut self, expression: &Expr) {
+    walk_expr(self, expression);
+  }
+
+  fn visit_literal(&mut self, _value: &SpannedToken) {}
+
+  fn visit_unary(&mut self, operator: &SpannedToken, right: &Expr) {
+    //`&(x1)` takes the same address as `&x1`
+    let mut operand = right;
+    while let Expr::Grouping { expression } = operand {
+      operand = expression;
+    }
+    if let (Token::Operator(Operator::Amp), Expr::Variable { name }) = (&operator.token, operand)
+      && let Some(Symbol { kind: SymbolKind::Param, name: id, .. }) = self.local(name)
+      && let Some(env) = self.env.as_mut()
+      && !env.address_taken.contains(&id) {
+      env.address_taken.push(id);
+    }
+    walk_expr(self, right);
+  }
+
+  fn visit_logical(&mut self, left: &Expr, _operator: &SpannedToken, right: &Expr) {
+    walk_expr(self, left);
+    walk_expr(self, right);
+  }
+
+  fn visit_variable(&mut self, name: &SpannedToken) {
+    self.use_name(name);
+  }
+
+  fn visit_get(&mut self, target: &Expr, index: &Expr) {
+    walk_expr(self, target);
+    walk_expr(self, index);
+  }
+
+  fn visit_ternary(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+    walk_expr(self, condition);
+    walk_expr(self, then_branch);
+    walk_expr(self, else_branch);
+  }
+
+  fn visit_postfix(&mut self, left: &Expr, _operator: &SpannedToken) {
+    walk_expr(self, left);
+  }
+
+  fn visit_error_expr(&mut self, _span: Span) {}
+}
@@ -0,0 +1,164 @@
+use rustc_hash::FxHashMap;
+
+use crate::ast::*;
+use crate::ast::visitor::*;
+use crate::common::span::Span;
+use crate::lexer::token::{SpannedToken, Token};
+use crate::sema::{SemaError, SemaWarning};
+
+//switch bodies are ordinary statements with case and default acting as labels, so they can sit
+//anywhere inside the body (even in a nested block or loop) and belong to the innermost switch.
+//Cases compare by value: character constants are packed into a word first, so `'a'` and `97`
+//are the same case.
+
+///Check every switch in the program: constant and unique case values, at most one default, and
+///no statements ahead of the first label.
+pub fn check_switches(program: &Program, errors: &mut Vec<SemaError>, warnings: &mut Vec<SemaWarning>) {
+  let mut checker = SwitchChecker { switches: Vec::new(), errors, warnings };
+  for item in &program.items {
+    if let Item::Function(f) = item {
+      walk_stmt(&mut checker, &f.body);
+    }
+  }
+}
+
+//labels seen so far in one switch body
+#[derive(Default)]
+struct SwitchArms {
+  values: FxHashMap<i64, (Span, bool)>, //case value -> (span, written as a character constant)
+  default: Option<Span>,
+}
+
+struct SwitchChecker<'e> {
+  switches: Vec<SwitchArms>, //innermost last
+  errors: &'e mut Vec<SemaError>,
+  warnings: &'e mut Vec<SemaWarning>,
+}
+
+fn is_char_constant(value: &Expr) -> bool {
+  match value {
+    Expr::Literal { value } => matches!(value.token, Token::CharLiteral(_)),
+    Expr::Unary { right, .. } | Expr::Grouping { expression: right } => is_char_constant(right),
+    _ => false,
+  }
+}
+
+//statements control can enter, at a case or default from the switch or a label from a goto, however
+//deeply nested it is (Duff's device puts the cases inside a loop). A nested switch's cases are its own
+fn is_entered(statement: &Stmt, cases: bool) -> bool {
+  match statement {
+    Stmt::Case { body, .. } | Stmt::Default { body, .. } => cases || is_entered(body, cases),
+    Stmt::Label { .. } => true,
+    Stmt::Block { statements } => statements.iter().any(|s| is_entered(s, cases)),
+    Stmt::If { then_branch, else_branch, .. } =>
+      is_entered(then_branch, cases) || else_branch.as_ref().is_some_and(|e| is_entered(e, cases)),
+    Stmt::While { body, .. } => is_entered(body, cases),
+    Stmt::Switch { cases: body, .. } => body.iter().any(|s| is_entered(s, false)),
+    _ => false,
+  }
+}
+
+//declarations and empty statements generate no code, so they are fine anywhere
+fn is_declaration(statement: &Stmt) -> bool {
+  matches!(statement, Stmt::Auto { .. } | Stmt::Extrn { .. } | Stmt::Null)
+}
+
+impl SwitchChecker<'_> {
+  fn outside_switch(&mut self, keyword: &'static str, span: Span) -> bool {
+    if self.switches.is_empty() {
+      self.errors.push(SemaError::LabelOutsideSwitch { keyword, span });
+      return true;
+    }
+    false
+  }
+}
+
+impl StmtVisitor<()> for SwitchChecker<'_> {
+
+  fn visit_block(&mut self, statements: &[Stmt]) {
+    for statement in statements {
+      walk_stmt(self, statement);
+    }
+  }
+
+  fn visit_auto(&mut self, _declarations: &[AutoDecl]) {}
+
+  fn visit_extrn(&mut self, _names: &[&SpannedToken]) {}
+
+  fn visit_expression(&mut self, _expression: &Expr) {}
+
+  fn visit_if(&mut self, _condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+    walk_stmt(self, then_branch);
+    if let Some(else_branch) = else_branch {
+      walk_stmt(self, else_branch);
+    }
+  }
+
+  fn visit_while(&mut self, _condition: &Expr, body: &Stmt) {
+    walk_stmt(self, body);
+  }
+
+  fn visit_switch(&mut self, _condition: &Expr, cases: &[Stmt]) {
+    //control enters the body at a label, so anything before the first statement holding one is dead
+    let dead: Vec<Span> = cases.iter()
+      .take_while(|s| !is_entered(s, true))
+      .filter(|s| !is_declaration(s))
+      .filter_map(Stmt::span)
+      .collect();
+    if let (Some(first), Some(last)) = (dead.first(), dead.last()) {
+      self.warnings.push(SemaWarning::UnreachableBeforeCase { span: Span { start: first.start, end: last.end } });
+    }
+
+    self.switches.push(SwitchArms::default());
+    for case in cases {
+      walk_stmt(self, case);
+    }
+    self.switches.pop();
+  }
+
+  fn visit_case(&mut self, value: &Expr, body: &Stmt) {
+    let span = value.span();
+    if !self.outside_switch("case", span) {
+      match value.const_value() {
+        Some(n) => {
+          let is_char = is_char_constant(value);
+          let arms = self.switches.last_mut().expect("inside a switch");
+          match arms.values.get(&n) {
+            Some(&(previous, previous_char)) => self.errors.push(SemaError::DuplicateCase {
+              value: n, span, previous, packed: is_char || previous_char,
+            }),
+            None => {
+              arms.values.insert(n, (span, is_char));
+            }
+          }
+        }
+        None => self.errors.push(SemaError::NonConstantCase { span }),
+      }
+    }
+    walk_stmt(self, body);
+  }
+
+  fn visit_default(&mut self, keyword: &SpannedToken, body: &Stmt) {
+    let span = keyword.span;
+    if !self.outside_switch("default", span) {
+      let arms = self.switches.last_mut().expect("inside a switch");
+      match arms.default {
+        Some(previous) => self.errors.push(SemaError::MultipleDefault { span, previous }),
+        None => arms.default = Some(span),
+      }
+    }
+    walk_stmt(self, body);
+  }
+
+  fn visit_label(&mut self, _name: &SpannedToken, body: &Stmt) {
+    walk_stmt(self, body);
+  }
+
+  fn visit_goto(&mut self, _expression: &Expr) {}
+
+  fn visit_return(&mut self, _value: Option<&Expr>) {}
+
+  fn visit_null(&mut self) {}
+
+  fn visit_error_stmt(&mut self, _span: Span) {}
+}
@@ -1,4 +1,5 @@
 use rustc_hash::FxHashMap;
+use crate::common::span::Span;
 
 //interner based symbol table. Each identifier (variable, parameter, function, label, extern declarations) 
 //are hashed for O(1) lookup and to avoid internal string copying. 
@@ -9,51 +10,67 @@ use rustc_hash::FxHashMap;
 //ex: a = 5. check if a exists in function env -> check if a exists in global env -> report error.
 //FxHashMap is used for its faster, non-cryptographic hashing function.
 
-pub enum SymbolError {
-  UndefinedSymbol,
-  DefinitionError,
-}
-pub enum SymbolKind { Auto, Extrn, Param, Function, Label }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind { Auto, Extrn, Param, Function, Label, Global }
 
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
 pub struct SymbolId(u32); //unique variables have unique internal id's
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Location {
   //location enum to define where variables are stored in VM. 
   Local {slot: u32}, //function parameters decay into local parameters when function is in scope
   Global {index: u32}, //globals should be stored in different address than locals
   Function {func_index: u32}, //functions are stored in a seperate lookup table; location indexes to position in table
   //TODO: Not like the B language, but maybe implement namespacing? Would need to store Import as (module, name), add name mangling and namespace op.
-  Import {import_index: u32}, //Extrn declarations. Same as functions, stored in import table
+  Import {import_index: u32}, //Extrn declarations. Same as functions, stored in import table; another file or libb defines them (sema::link)
+  Label {index: u32}, //jump target within the function; indexes FunctionEnv::labels
 }
 
+#[derive(Debug, Clone, Default)]
 pub struct Interner {
   strings: Vec<String>,
   lookup: FxHashMap<String, SymbolId>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Symbol {   
   //holds metadata for identifiers
-  name: SymbolId,
-  kind: SymbolKind,
-  size: u32, //1 for variables
-  slot: Location,
+  pub name: SymbolId,
+  pub kind: SymbolKind,
+  pub size: u32, //1 for variables, the declared bound for vectors
+  pub slot: Location,
+  pub span: Span, //where it was declared; extrn declarations point at the extrn, not the definition
 }
 
+#[derive(Debug, Clone)]
 pub struct FunctionEnv {
+  pub name: SymbolId,
   //map identifier internal id -> identifier metadata
-  locals: FxHashMap<SymbolId, Symbol>,
-  params: Vec<SymbolId>,
+  pub locals: FxHashMap<SymbolId, Symbol>,
+  pub params: Vec<SymbolId>,
+  pub labels: Vec<SymbolId>, //indexed by Location::Label
+  pub slot_count: u32, //Local slots handed out so far: params first, then autos in declaration order
+  //params whose address `&` takes, in order of first use. With any of them, code may walk from one
+  //param to the next through a pointer (`adx = &x1; x = *adx++;`), so every param must stay in its
+  //frame word
+  pub address_taken: Vec<SymbolId>,
 }
 
+#[derive(Debug, Clone, Default)]
 pub struct GlobalEnv {
   //top level declarations (global extrn, functions)
-  symbols: FxHashMap<SymbolId, Symbol>,
+  pub symbols: FxHashMap<SymbolId, Symbol>,
+  pub globals: Vec<SymbolId>, //indexed by Location::Global
+  pub functions: Vec<SymbolId>, //indexed by Location::Function
+  pub imports: Vec<SymbolId>, //indexed by Location::Import; extrn names defined outside the program
 }
 
+#[derive(Debug, Clone, Default)]
 pub struct SymbolTable {
-  interner: Interner,
-  global: GlobalEnv,
+  pub interner: Interner,
+  pub global: GlobalEnv,
+  pub functions: Vec<FunctionEnv>, //indexed by Location::Function
 }
 
 impl Interner {
@@ -90,26 +107,68 @@ impl Interner {
   ///query lookup without inserting
   pub fn id_of(&self, name: &str) -> Option<SymbolId> {
     self.lookup.get(name)
-    .map(|x| *x) //deref Some(&SymbolId); cheap clone
+    .copied() //deref Some(&SymbolId); cheap clone
   }
 
-  //helpers:
-  fn len(&self) -> usize {
+  pub fn len(&self) -> usize {
     self.strings.len()
   }
 
-  fn is_empty(&self) -> bool {
-    self.strings.len() == 0
+  pub fn is_empty(&self) -> bool {
+    self.strings.is_empty()
   }
 }
 
 
-impl SymbolTable {
-  pub fn new() -> Self {
-    Self
+impl FunctionEnv {
+
+  pub fn new(name: SymbolId) -> Self {
+    Self {
+      name,
+      locals: FxHashMap::default(),
+      params: Vec::new(),
+      labels: Vec::new(),
+      slot_count: 0,
+      address_taken: Vec::new(),
+    }
+  }
+
+  pub fn lookup(&self, id: SymbolId) -> Option<&Symbol> {
+    self.locals.get(&id)
+  }
+
+  ///whether params must be laid out in consecutive frame words rather than kept in registers
+  pub fn params_in_frame(&self) -> bool {
+    !self.address_taken.is_empty()
+  }
+
+  ///next free Local slot
+  pub fn next_slot(&mut self) -> Location {
+    let slot = self.slot_count;
+    self.slot_count += 1;
+    Location::Local { slot }
   }
 }
 
+impl GlobalEnv {
 
+  pub fn lookup(&self, id: SymbolId) -> Option<&Symbol> {
+    self.symbols.get(&id)
+  }
+}
 
+impl SymbolTable {
 
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn name(&self, id: SymbolId) -> &str {
+    self.interner.resolve(id)
+  }
+
+  ///environment of a function by its Location::Function index
+  pub fn function(&self, func_index: u32) -> &FunctionEnv {
+    &self.functions[func_index as usize]
+  }
+}
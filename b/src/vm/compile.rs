@@ -0,0 +1,281 @@
+use rustc_hash::FxHashMap;
+
+use crate::ir::{self, pack_string, BinaryOp, BlockId, Callee, Init, Instr, Operand, Terminator, Word};
+use crate::ir::ssa::without_phis;
+use super::{Function, Module, Op, Reg, SwitchTable, FUNCTION_BASE};
+
+//IR to bytecode. Static data is laid out here, so global, string and function addresses become
+//constants; a function in SSA form is taken out of it first.
+
+pub fn compile(module: &ir::Module) -> Module {
+  let mut data: Vec<Word> = Vec::new(); //data[i] is at address i + 1
+  let strings: Vec<Word> = module.strings.iter().map(|s| {
+    let addr = data.len() as Word + 1;
+    data.extend(pack_string(s));
+    addr
+  }).collect();
+
+  let mut symbols: FxHashMap<&str, Word> = FxHashMap::default();
+  for global in &module.globals {
+    let addr = data.len() as Word + 1;
+    let mut init: Vec<Word> = global.init.iter().map(|init| match init {
+      Init::Word(w) => *w,
+      Init::String(index) => strings[*index as usize],
+    }).collect();
+    match global.vector {
+      Some(words) => {
+        data.push(addr + 1);
+        init.resize(init.len().max(words as usize), 0);
+      }
+      None => init.resize(init.len().max(1), 0),
+    }
+    data.extend(init);
+    symbols.insert(&global.name, addr);
+  }
+  let mut calls: FxHashMap<&str, Code> = FxHashMap::default();
+  for (i, f) in module.functions.iter().enumerate() {
+    symbols.insert(&f.name, FUNCTION_BASE + i as Word);
+    calls.insert(&f.name, Code::Function(i as u32));
+  }
+  for (i, name) in module.imports.iter().enumerate() {
+    symbols.insert(name, FUNCTION_BASE + (module.functions.len() + i) as Word);
+    calls.insert(name, Code::Import(i as u32));
+  }
+
+  let functions = module.functions.iter().map(|f| {
+    let f = without_phis(f);
+    FunctionCompiler { symbols: &symbols, calls: &calls, strings: &strings, function: &f, uses: uses(&f), consts: Vec::new(), const_regs: FxHashMap::default(), code: Vec::new(), args: Vec::new(), switches: Vec::new(), jump_tables: Vec::new() }.compile()
+  }).collect();
+
+  Module { data, functions, imports: module.imports.clone() }
+}
+
+#[derive(Clone, Copy)]
+enum Code {
+  Function(u32),
+  Import(u32),
+}
+
+struct FunctionCompiler<'a> {
+  symbols: &'a FxHashMap<&'a str, Word>,
+  calls: &'a FxHashMap<&'a str, Code>,
+  strings: &'a [Word],
+  function: &'a ir::Function,
+  uses: Vec<u32>, //reads of each register
+  consts: Vec<Word>,
+  const_regs: FxHashMap<Word, Reg>,
+  code: Vec<Op>,
+  args: Vec<Reg>,
+  switches: Vec<SwitchTable>,
+  jump_tables: Vec<Vec<u32>>,
+}
+
+impl FunctionCompiler<'_> {
+
+  fn compile(mut self) -> Function {
+    //jump targets hold block ids until every block has a pc
+    let mut block_pc = Vec::with_capacity(self.function.blocks.len());
+    for (i, block) in self.function.blocks.iter().enumerate() {
+      block_pc.push(self.code.len() as u32);
+      let mut instrs = &block.instrs[..];
+      //a comparison only the branch reads fuses into it
+      let mut compare = None;
+      if let Terminator::Branch { cond: Operand::Reg(cond), .. } = block.term
+        && let Some((Instr::Binary { dest, op, lhs, rhs }, rest)) = instrs.split_last()
+        && *dest == cond && self.uses[cond.0 as usize] == 1 && negate(*op).is_some() {
+        compare = Some((*op, *lhs, *rhs));
+        instrs = rest;
+      }
+      while let Some((instr, rest)) = instrs.split_first() {
+        instrs = rest;
+        //so does a LocalAddr only the next load or store reads
+        let local = match (instr, rest.first()) {
+          (Instr::LocalAddr { dest, offset }, Some(next)) if self.uses[dest.0 as usize] == 1 => match next {
+            Instr::Load { dest: dst, addr: Operand::Reg(addr) } if addr == dest => Some(Op::LoadLocal { dst: dst.0, offset: *offset }),
+            Instr::Store { addr: Operand::Reg(addr), value } if addr == dest => Some(Op::StoreLocal { offset: *offset, value: self.operand(value) }),
+            _ => None,
+          },
+          _ => None,
+        };
+        match local {
+          Some(op) => {
+            self.code.push(op);
+            instrs = &rest[1..];
+          }
+          None => self.instr(instr),
+        }
+        //and a value only copied into another register is computed there directly
+        if let Some((Instr::Copy { dest, src: Operand::Reg(src) }, rest)) = instrs.split_first()
+          && self.uses[src.0 as usize] == 1
+          && let Some(dst) = self.code.last_mut().and_then(Op::dst_mut)
+          && *dst == src.0 {
+          *dst = dest.0;
+          instrs = rest;
+        }
+      }
+      match (compare, &block.term) {
+        (Some((op, lhs, rhs)), Terminator::Branch { then_block, else_block, .. }) => {
+          self.compare_branch(op, lhs, rhs, *then_block, *else_block, BlockId(i as u32 + 1));
+        }
+        _ => self.terminator(&block.term, BlockId(i as u32 + 1)),
+      }
+    }
+
+    let pc = |target: &mut u32| *target = block_pc[*target as usize];
+    for op in &mut self.code {
+      match op {
+        Op::Label { target, .. } | Op::Jump { target } | Op::JumpIf { target, .. } | Op::JumpUnless { target, .. } |
+        Op::JumpCmp { target, .. } => pc(target),
+        _ => {}
+      }
+    }
+    for table in &mut self.switches {
+      table.cases.iter_mut().for_each(|(_, target)| pc(target));
+      pc(&mut table.default);
+    }
+    for table in &mut self.jump_tables {
+      table.iter_mut().for_each(pc);
+    }
+
+    let f = self.function;
+    Function {
+      name: f.name.clone(),
+      params: f.params,
+      frame_size: f.frame_size,
+      reg_count: f.reg_count,
+      consts: self.consts,
+      code: self.code,
+      args: self.args,
+      switches: self.switches,
+      jump_tables: self.jump_tables,
+    }
+  }
+
+  //constants get registers after the function's own, one per distinct value
+  fn constant(&mut self, value: Word) -> Reg {
+    let next = self.function.reg_count + self.consts.len() as Reg;
+    *self.const_regs.entry(value).or_insert_with(|| {
+      self.consts.push(value);
+      next
+    })
+  }
+
+  fn operand(&mut self, operand: &Operand) -> Reg {
+    match operand {
+      Operand::Reg(r) => r.0,
+      Operand::Const(c) => self.constant(*c),
+    }
+  }
+
+  fn instr(&mut self, instr: &Instr) {
+    let op = match instr {
+      Instr::Copy { dest, src } => Op::Copy { dst: dest.0, src: self.operand(src) },
+      Instr::Unary { dest, op, src } => Op::Unary { op: *op, dst: dest.0, src: self.operand(src) },
+      Instr::Binary { dest, op, lhs, rhs } => Op::Binary { op: *op, dst: dest.0, lhs: self.operand(lhs), rhs: self.operand(rhs) },
+      Instr::Load { dest, addr } => Op::Load { dst: dest.0, addr: self.operand(addr) },
+      Instr::Store { addr, value } => Op::Store { addr: self.operand(addr), value: self.operand(value) },
+      Instr::LocalAddr { dest, offset } => Op::Local { dst: dest.0, offset: *offset },
+      Instr::GlobalAddr { dest, name } => {
+        //lowering only names symbols it has seen, so 0 never runs
+        let addr = self.symbols.get(name.as_str()).copied().unwrap_or(0);
+        Op::Copy { dst: dest.0, src: self.constant(addr) }
+      }
+      Instr::StringAddr { dest, index } => Op::Copy { dst: dest.0, src: self.constant(self.strings[*index as usize]) },
+      Instr::LabelAddr { dest, block } => Op::Label { dst: dest.0, target: block.0 },
+      Instr::Call { dest, callee, args } => {
+        let start = self.args.len() as u32;
+        let argc = args.len() as u32;
+        for arg in args {
+          let reg = self.operand(arg);
+          self.args.push(reg);
+        }
+        match callee {
+          Callee::Direct(name) => match self.calls.get(name.as_str()) {
+            Some(Code::Function(function)) => Op::Call { dst: dest.0, function: *function, args: start, argc },
+            Some(Code::Import(import)) => Op::CallImport { dst: dest.0, import: *import, args: start, argc },
+            None => Op::CallIndirect { dst: dest.0, callee: self.constant(0), args: start, argc },
+          },
+          Callee::Indirect(callee) => Op::CallIndirect { dst: dest.0, callee: self.operand(callee), args: start, argc },
+        }
+      }
+      Instr::Phi { .. } => unreachable!("phis are removed before compiling"),
+    };
+    self.code.push(op);
+  }
+
+  fn compare_branch(&mut self, op: BinaryOp, lhs: Operand, rhs: Operand, then_block: BlockId, else_block: BlockId, next: BlockId) {
+    let (lhs, rhs) = (self.operand(&lhs), self.operand(&rhs));
+    if then_block == next {
+      let op = negate(op).unwrap();
+      self.code.push(Op::JumpCmp { op, lhs, rhs, target: else_block.0 });
+    } else {
+      self.code.push(Op::JumpCmp { op, lhs, rhs, target: then_block.0 });
+      if else_block != next {
+        self.code.push(Op::Jump { target: else_block.0 });
+      }
+    }
+  }
+
+  fn terminator(&mut self, term: &Terminator, next: BlockId) {
+    match term {
+      Terminator::Jump(target) => {
+        if *target != next {
+          self.code.push(Op::Jump { target: target.0 });
+        }
+      }
+      Terminator::Branch { cond, then_block, else_block } => {
+        let cond = self.operand(cond);
+        if *then_block == next {
+          self.code.push(Op::JumpUnless { cond, target: else_block.0 });
+        } else {
+          self.code.push(Op::JumpIf { cond, target: then_block.0 });
+          if *else_block != next {
+            self.code.push(Op::Jump { target: else_block.0 });
+          }
+        }
+      }
+      Terminator::Switch { value, cases, default } => {
+        let value = self.operand(value);
+        let mut cases: Vec<(Word, u32)> = cases.iter().map(|(v, b)| (*v, b.0)).collect();
+        cases.sort_by_key(|(v, _)| *v);
+        let table = self.switches.len() as u32;
+        self.switches.push(SwitchTable { cases, default: default.0 });
+        self.code.push(Op::Switch { value, table });
+      }
+      Terminator::Return(value) => {
+        let value = self.operand(value);
+        self.code.push(Op::Return { value });
+      }
+      Terminator::IndirectJump { addr, targets } => {
+        let addr = self.operand(addr);
+        let table = self.jump_tables.len() as u32;
+        self.jump_tables.push(targets.iter().map(|b| b.0).collect());
+        self.code.push(Op::JumpIndirect { addr, table });
+      }
+    }
+  }
+}
+
+fn uses(function: &ir::Function) -> Vec<u32> {
+  let mut uses = vec![0; function.reg_count as usize];
+  let operands = function.blocks.iter().flat_map(|b| b.instrs.iter().flat_map(|i| i.operands()).chain(b.term.operands()));
+  for operand in operands {
+    if let Operand::Reg(r) = operand {
+      uses[r.0 as usize] += 1;
+    }
+  }
+  uses
+}
+
+//the comparison that holds exactly when `op` does not
+fn negate(op: BinaryOp) -> Option<BinaryOp> {
+  Some(match op {
+    BinaryOp::Eq => BinaryOp::Ne,
+    BinaryOp::Ne => BinaryOp::Eq,
+    BinaryOp::Lt => BinaryOp::Ge,
+    BinaryOp::Ge => BinaryOp::Lt,
+    BinaryOp::Le => BinaryOp::Gt,
+    BinaryOp::Gt => BinaryOp::Le,
+    _ => return None,
+  })
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+
+use crate::ir::{BinaryOp, UnaryOp, Word};
+use super::{Function, Module, Op, SwitchTable};
+
+//the module file format. A file is MAGIC, then the data words, imports and functions. Counts,
+//indexes and registers are unsigned LEB128 varints; words are zigzag encoded first so small
+//negative values stay short. Each op is a tag byte followed by its fields in declaration order, with
+//unary and binary operators as their index in UnaryOp::ALL / BinaryOp::ALL.
+//
+//read checks every register, pc and index against the module, so a Vm never indexes out of range on
+//a module that was read successfully.
+
+///The first bytes of every module file; the last one is the format version.
+pub const MAGIC: [u8; 4] = *b"BVM\x01";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatError {
+  pub offset: usize, //byte offset in the file
+  pub message: String,
+}
+
+impl fmt::Display for FormatError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid module at byte {}: {}", self.offset, self.message)
+  }
+}
+
+impl std::error::Error for FormatError {}
+
+pub fn write(module: &Module) -> Vec<u8> {
+  let mut w = Writer { out: MAGIC.to_vec() };
+  w.len(module.data.len());
+  module.data.iter().for_each(|&word| w.word(word));
+  w.len(module.imports.len());
+  module.imports.iter().for_each(|name| w.str(name));
+  w.len(module.functions.len());
+  for f in &module.functions {
+    w.str(&f.name);
+    w.u(f.params);
+    w.u(f.frame_size);
+    w.u(f.reg_count);
+    w.len(f.consts.len());
+    f.consts.iter().for_each(|&c| w.word(c));
+    w.len(f.code.len());
+    f.code.iter().for_each(|op| w.op(op));
+    w.len(f.args.len());
+    f.args.iter().for_each(|&r| w.u(r));
+    w.len(f.switches.len());
+    for table in &f.switches {
+      w.len(table.cases.len());
+      for &(value, target) in &table.cases {
+        w.word(value);
+        w.u(target);
+      }
+      w.u(table.default);
+    }
+    w.len(f.jump_tables.len());
+    for table in &f.jump_tables {
+      w.len(table.len());
+      table.iter().for_each(|&target| w.u(target));
+    }
+  }
+  w.out
+}
+
+struct Writer {
+  out: Vec<u8>,
+}
+
+impl Writer {
+  fn varint(&mut self, mut v: u64) {
+    loop {
+      let byte = (v & 0x7f) as u8;
+      v >>= 7;
+      if v == 0 {
+        self.out.push(byte);
+        return;
+      }
+      self.out.push(byte | 0x80);
+    }
+  }
+
+  fn u(&mut self, v: u32) {
+    self.varint(v as u64);
+  }
+
+  fn len(&mut self, n: usize) {
+    self.varint(n as u64);
+  }
+
+  fn word(&mut self, w: Word) {
+    self.varint(((w << 1) ^ (w >> 63)) as u64);
+  }
+
+  fn str(&mut self, s: &str) {
+    self.len(s.len());
+    self.out.extend_from_slice(s.as_bytes());
+  }
+
+  fn op(&mut self, op: &Op) {
+    match *op {
+      Op::Copy { dst, src } => self.fields(0, &[dst, src]),
+      Op::Unary { op, dst, src } => {
+        let op = UnaryOp::ALL.iter().position(|&o| o == op).unwrap() as u32;
+        self.fields(1, &[op, dst, src])
+      }
+      Op::Binary { op, dst, lhs, rhs } => {
+        let op = BinaryOp::ALL.iter().position(|&o| o == op).unwrap() as u32;
+        self.fields(2, &[op, dst, lhs, rhs])
+      }
+      Op::Load { dst, addr } => self.fields(3, &[dst, addr]),
+      Op::Store { addr, value } => self.fields(4, &[addr, value]),
+      Op::Local { dst, offset } => self.fields(5, &[dst, offset]),
+      Op::Label { dst, target } => self.fields(6, &[dst, target]),
+      Op::Call { dst, function, args, argc } => self.fields(7, &[dst, function, args, argc]),
+      Op::CallImport { dst, import, args, argc } => self.fields(8, &[dst, import, args, argc]),
+      Op::CallIndirect { dst, callee, args, argc } => self.fields(9, &[dst, callee, args, argc]),
+      Op::Jump { target } => self.fields(10, &[target]),
+      Op::JumpIf { cond, target } => self.fields(11, &[cond, target]),
+      Op::JumpUnless { cond, target } => self.fields(12, &[cond, target]),
+      Op::Switch { value, table } => self.fields(13, &[value, table]),
+      Op::JumpIndirect { addr, table } => self.fields(14, &[addr, table]),
+      Op::Return { value } => self.fields(15, &[value]),
+      Op::LoadLocal { dst, offset } => self.fields(16, &[dst, offset]),
+      Op::StoreLocal { offset, value } => self.fields(17, &[offset, value]),
+      Op::JumpCmp { op, lhs, rhs, target } => {
+        let op = BinaryOp::ALL.iter().position(|&o| o == op).unwrap() as u32;
+        self.fields(18, &[op, lhs, rhs, target])
+      }
+    }
+  }
+
+  fn fields(&mut self, tag: u8, fields: &[u32]) {
+    self.out.push(tag);
+    fields.iter().for_each(|&f| self.u(f));
+  }
+}
+
+pub fn read(bytes: &[u8]) -> Result<Module, FormatError> {
+  let mut r = Reader { bytes, pos: 0 };
+  if !bytes.starts_with(&MAGIC[..3]) {
+    return Err(r.error("not a module file"));
+  }
+  if bytes.get(3) != Some(&MAGIC[3]) {
+    return Err(FormatError { offset: 3, message: format!("unsupported version {}", bytes.get(3).copied().unwrap_or(0)) });
+  }
+  r.pos = MAGIC.len();
+
+  let data = r.list(|r| r.word())?;
+  let imports = r.list(|r| r.str())?;
+  let count = r.len()?;
+  let mut functions = Vec::new();
+  let mut ends = Vec::new(); //offset after each function, for errors found when checking
+  for _ in 0..count {
+    let name = r.str()?;
+    let params = r.u()?;
+    let frame_size = r.u()?;
+    let reg_count = r.u()?;
+    let consts = r.list(|r| r.word())?;
+    let code = r.list(|r| r.op())?;
+    let args = r.list(|r| r.u())?;
+    let switches = r.list(|r| {
+      let cases = r.list(|r| Ok((r.word()?, r.u()?)))?;
+      Ok(SwitchTable { cases, default: r.u()? })
+    })?;
+    let jump_tables = r.list(|r| r.list(|r| r.u()))?;
+    functions.push(Function { name, params, frame_size, reg_count, consts, code, args, switches, jump_tables });
+    ends.push(r.pos);
+  }
+  if r.pos != bytes.len() {
+    return Err(r.error("trailing bytes after the last function"));
+  }
+
+  let module = Module { data, functions, imports };
+  for (f, end) in module.functions.iter().zip(ends) {
+    check(&module, f).map_err(|message| FormatError { offset: end, message: format!("in function `{}`: {}", f.name, message) })?;
+  }
+  Ok(module)
+}
+
+struct Reader<'b> {
+  bytes: &'b [u8],
+  pos: usize,
+}
+
+impl Reader<'_> {
+  fn error(&self, message: impl Into<String>) -> FormatError {
+    FormatError { offset: self.pos, message: message.into() }
+  }
+
+  fn byte(&mut self) -> Result<u8, FormatError> {
+    let byte = *self.bytes.get(self.pos).ok_or_else(|| self.error("unexpected end of file"))?;
+    self.pos += 1;
+    Ok(byte)
+  }
+
+  fn varint(&mut self) -> Result<u64, FormatError> {
+    let start = self.pos;
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+      let byte = self.byte()?;
+      v |= ((byte & 0x7f) as u64) << shift;
+      if byte & 0x80 == 0 {
+        return Ok(v);
+      }
+    }
+    Err(FormatError { offset: start, message: "varint is too long".to_string() })
+  }
+
+  fn u(&mut self) -> Result<u32, FormatError> {
+    let start = self.pos;
+    let v = self.varint()?;
+    u32::try_from(v).map_err(|_| FormatError { offset: start, message: format!("{} does not fit in 32 bits", v) })
+  }
+
+  fn len(&mut self) -> Result<usize, FormatError> {
+    //every element takes at least a byte, so a longer count is corrupt and must not be allocated
+    let start = self.pos;
+    let n = self.u()? as usize;
+    if n > self.bytes.len() - self.pos {
+      return Err(FormatError { offset: start, message: format!("count {} is longer than the file", n) });
+    }
+    Ok(n)
+  }
+
+  fn word(&mut self) -> Result<Word, FormatError> {
+    let v = self.varint()?;
+    Ok((v >> 1) as Word ^ -((v & 1) as Word))
+  }
+
+  fn str(&mut self) -> Result<String, FormatError> {
+    let n = self.len()?;
+    let start = self.pos;
+    self.pos += n;
+    String::from_utf8(self.bytes[start..self.pos].to_vec()).map_err(|_| FormatError { offset: start, message: "name is not UTF-8".to_string() })
+  }
+
+  fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, FormatError>) -> Result<Vec<T>, FormatError> {
+    let n = self.len()?;
+    (0..n).map(|_| item(self)).collect()
+  }
+
+  fn op(&mut self) -> Result<Op, FormatError> {
+    let start = self.pos;
+    Ok(match self.byte()? {
+      0 => Op::Copy { dst: self.u()?, src: self.u()? },
+      1 => {
+        let op = *UnaryOp::ALL.get(self.u()? as usize).ok_or_else(|| self.error("unknown unary operator"))?;
+        Op::Unary { op, dst: self.u()?, src: self.u()? }
+      }
+      2 => {
+        let op = *BinaryOp::ALL.get(self.u()? as usize).ok_or_else(|| self.error("unknown binary operator"))?;
+        Op::Binary { op, dst: self.u()?, lhs: self.u()?, rhs: self.u()? }
+      }
+      3 => Op::Load { dst: self.u()?, addr: self.u()? },
+      4 => Op::Store { addr: self.u()?, value: self.u()? },
+      5 => Op::Local { dst: self.u()?, offset: self.u()? },
+      6 => Op::Label { dst: self.u()?, target: self.u()? },
+      7 => Op::Call { dst: self.u()?, function: self.u()?, args: self.u()?, argc: self.u()? },
+      8 => Op::CallImport { dst: self.u()?, import: self.u()?, args: self.u()?, argc: self.u()? },
+      9 => Op::CallIndirect { dst: self.u()?, callee: self.u()?, args: self.u()?, argc: self.u()? },
+      10 => Op::Jump { target: self.u()? },
+      11 => Op::JumpIf { cond: self.u()?, target: self.u()? },
+      12 => Op::JumpUnless { cond: self.u()?, target: self.u()? },
+      13 => Op::Switch { value: self.u()?, table: self.u()? },
+      14 => Op::JumpIndirect { addr: self.u()?, table: self.u()? },
+      15 => Op::Return { value: self.u()? },
+      16 => Op::LoadLocal { dst: self.u()?, offset: self.u()? },
+      17 => Op::StoreLocal { offset: self.u()?, value: self.u()? },
+      18 => {
+        let op = *BinaryOp::ALL.get(self.u()? as usize).ok_or_else(|| self.error("unknown binary operator"))?;
+        Op::JumpCmp { op, lhs: self.u()?, rhs: self.u()?, target: self.u()? }
+      }
+      tag => return Err(FormatError { offset: start, message: format!("unknown op {}", tag) }),
+    })
+  }
+}
+
+//everything an op refers to exists, and the code cannot run off its end
+fn check(module: &Module, f: &Function) -> Result<(), String> {
+  let registers = f.registers();
+  let reg = |r: u32| if (r as usize) < registers { Ok(()) } else { Err(format!("register {} out of {}", r, registers)) };
+  let pc = |t: u32| if (t as usize) < f.code.len() { Ok(()) } else { Err(format!("jump to {} past the end of the code", t)) };
+  let args = |start: u32, argc: u32| match f.args.get(start as usize..start as usize + argc as usize) {
+    Some(regs) => regs.iter().try_for_each(|&r| reg(r)),
+    None => Err(format!("arguments {}..{} out of {}", start, start as usize + argc as usize, f.args.len())),
+  };
+  if f.frame_size < f.params {
+    return Err(format!("frame of {} words cannot hold {} params", f.frame_size, f.params));
+  }
+  for op in &f.code {
+    match *op {
+      Op::Copy { dst, src } | Op::Unary { dst, src, .. } => { reg(dst)?; reg(src)? }
+      Op::Binary { dst, lhs, rhs, .. } => { reg(dst)?; reg(lhs)?; reg(rhs)? }
+      Op::Load { dst, addr } => { reg(dst)?; reg(addr)? }
+      Op::Store { addr, value } => { reg(addr)?; reg(value)? }
+      Op::Local { dst: r, offset } | Op::LoadLocal { dst: r, offset } | Op::StoreLocal { offset, value: r } => {
+        reg(r)?;
+        if offset >= f.frame_size {
+          return Err(format!("frame word {} out of {}", offset, f.frame_size));
+        }
+      }
+      Op::Label { dst, target } => { reg(dst)?; pc(target)? }
+      Op::Call { dst, function, args: start, argc } => {
+        reg(dst)?;
+        args(start, argc)?;
+        if function as usize >= module.functions.len() {
+          return Err(format!("call to function {} out of {}", function, module.functions.len()));
+        }
+      }
+      Op::CallImport { dst, import, args: start, argc } => {
+        reg(dst)?;
+        args(start, argc)?;
+        if import as usize >= module.imports.len() {
+          return Err(format!("call to import {} out of {}", import, module.imports.len()));
+        }
+      }
+      Op::CallIndirect { dst, callee, args: start, argc } => { reg(dst)?; reg(callee)?; args(start, argc)? }
+      Op::Jump { target } => pc(target)?,
+      Op::JumpIf { cond, target } | Op::JumpUnless { cond, target } => { reg(cond)?; pc(target)? }
+      Op::JumpCmp { lhs, rhs, target, .. } => { reg(lhs)?; reg(rhs)?; pc(target)? }
+      Op::Switch { value, table } => {
+        reg(value)?;
+        let table = f.switches.get(table as usize).ok_or_else(|| format!("switch table {} out of {}", table, f.switches.len()))?;
+        table.cases.iter().try_for_each(|&(_, t)| pc(t))?;
+        pc(table.default)?;
+        if !table.cases.is_sorted_by_key(|&(v, _)| v) {
+          return Err("switch cases are not sorted".to_string());
+        }
+      }
+      Op::JumpIndirect { addr, table } => {
+        reg(addr)?;
+        let table = f.jump_tables.get(table as usize).ok_or_else(|| format!("jump table {} out of {}", table, f.jump_tables.len()))?;
+        table.iter().try_for_each(|&t| pc(t))?;
+      }
+      Op::Return { value } => reg(value)?,
+    }
+  }
+  match f.code.last() {
+    Some(Op::Jump { .. } | Op::Switch { .. } | Op::JumpIndirect { .. } | Op::Return { .. }) => Ok(()),
+    _ => Err("code can run off its end".to_string()),
+  }
+}
@@ -0,0 +1,278 @@
+use crate::diagnostics::Diagnostic;
+use crate::interp::memory::{Memory, STACK_BASE};
+use crate::ir::Word;
+use crate::libb::{Runtime, Trap};
+use super::{Function, Module, Op, Reg, FUNCTION_BASE, LABEL_BASE};
+
+//the dispatch loop. B calls do not recurse on the host stack: the caller's state goes on an explicit
+//frame stack and the loop carries on in the callee, so recursion depth is bounded by MAX_FRAMES and
+//memory rather than by the host thread. Registers of every live call sit in one Vec, each call's at
+//its own base; params and autos live in memory frames as in the interpreter.
+
+///Calls nested deeper than this fail with StackOverflow.
+pub const MAX_FRAMES: usize = 100_000;
+
+///A fault while running a module. Bytecode carries no source positions, so errors name the function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+  OutOfBounds { address: Word, function: String },
+  NotAFunction { value: Word, function: String },
+  NotALabel { value: Word, function: String },
+  DivisionByZero { function: String },
+  UnresolvedImport { name: String, function: String },
+  StackOverflow { function: String },
+  OutOfMemory, //static data does not fit in memory
+  NoFunction { name: String },
+  Exit { status: Word }, //exit() unwinding to call, which returns the status
+}
+
+impl VmError {
+  ///Runtime errors share their codes with interp::RuntimeError.
+  pub fn to_diagnostic(&self) -> Diagnostic {
+    let within = |d: Diagnostic, function: &str| d.with_note(format!("in function `{}`", function));
+    match self {
+      VmError::OutOfBounds { address, function } => within(Diagnostic::error(format!("no memory at word address {}", address)).with_code("E0301"), function),
+      VmError::NotAFunction { value, function } => within(Diagnostic::error(format!("called {}, which is not a function", value)).with_code("E0302"), function),
+      VmError::NotALabel { value, function } => within(Diagnostic::error(format!("goto {}, which is not a label of this function", value)).with_code("E0303"), function),
+      VmError::DivisionByZero { function } => within(Diagnostic::error("division by zero").with_code("E0304"), function),
+      VmError::UnresolvedImport { name, function } => within(Diagnostic::error(format!("`{}` is not defined", name)).with_code("E0305"), function)
+        .with_note(format!("`{}` is an extrn the program does not define, and libb does not provide it", name)),
+      VmError::StackOverflow { function } => within(Diagnostic::error("stack overflow").with_code("E0306"), function)
+        .with_note(format!("calls nested more than {} deep", MAX_FRAMES)),
+      VmError::OutOfMemory => Diagnostic::error("out of memory").with_code("E0307")
+        .with_note("static data does not fit in memory"),
+      VmError::NoFunction { name } => Diagnostic::error(format!("no function `{}` to run", name)).with_code("E0308")
+        .with_help(format!("define `{}() {{ ... }}`", name)),
+      VmError::Exit { status } => Diagnostic::error(format!("the program exited with status {}", status)),
+    }
+  }
+}
+
+type VmResult<T> = Result<T, VmError>;
+
+//a suspended caller
+struct Frame {
+  function: usize,
+  pc: usize, //where it resumes
+  base: usize, //its first register
+  memory: Word, //its memory frame
+  dst: Reg, //where the result goes
+}
+
+//something callable
+enum Code {
+  Function(usize),
+  Import(usize),
+}
+
+pub struct Vm<'m> {
+  module: &'m Module,
+  memory: Memory,
+  runtime: Runtime,
+  regs: Vec<Word>,
+  frames: Vec<Frame>,
+  argv: Vec<Word>, //arguments to an import, reused between calls
+}
+
+impl<'m> Vm<'m> {
+
+  ///A machine with the module's static data loaded. Run it with `run` or `call`.
+  pub fn new(module: &'m Module) -> VmResult<Self> {
+    let mut memory = Memory::new();
+    memory.alloc_words(&module.data).ok_or(VmError::OutOfMemory)?;
+    Ok(Vm { module, memory, runtime: Runtime::default(), regs: Vec::new(), frames: Vec::new(), argv: Vec::new() })
+  }
+
+  ///Bytes getchar and read(0, ...) take, in order.
+  pub fn with_input(mut self, input: impl Into<Vec<u8>>) -> Self {
+    self.runtime = Runtime::new(input.into());
+    self
+  }
+
+  ///Use the process's standard input and output, read as the program asks and written as it goes.
+  pub fn with_stdio(mut self) -> Self {
+    self.runtime = Runtime::stdio();
+    self
+  }
+
+  ///Everything the program has written to standard output.
+  pub fn output(&self) -> &[u8] {
+    self.runtime.output()
+  }
+
+  pub fn memory(&self) -> &Memory {
+    &self.memory
+  }
+
+  ///Call main() and return its result.
+  pub fn run(&mut self) -> VmResult<Word> {
+    self.call("main", &[])
+  }
+
+  ///Call the function `name` with `args`. Memory and output persist between calls. If the program
+  ///calls exit, its status is the result.
+  pub fn call(&mut self, name: &str, args: &[Word]) -> VmResult<Word> {
+    let function = self.module.function(name).ok_or_else(|| VmError::NoFunction { name: name.to_string() })?;
+    let result = self.execute(function, args);
+    if result.is_err() {
+      //unwind whatever the fault or exit left on the stacks
+      self.memory.pop_frame(STACK_BASE);
+      self.frames.clear();
+      self.regs.clear();
+    }
+    match result {
+      Err(VmError::Exit { status }) => Ok(status),
+      result => result,
+    }
+  }
+
+  //a register window for a call of `f`, holding its constants; returns its base
+  fn registers(&mut self, f: &Function) -> usize {
+    let base = self.regs.len();
+    self.regs.resize(base + f.reg_count as usize, 0);
+    self.regs.extend_from_slice(&f.consts);
+    base
+  }
+
+  fn code_of(&self, value: Word) -> Option<Code> {
+    let index = usize::try_from(value.checked_sub(FUNCTION_BASE)?).ok()?;
+    let functions = self.module.functions.len();
+    if index < functions {
+      Some(Code::Function(index))
+    } else if index - functions < self.module.imports.len() {
+      Some(Code::Import(index - functions))
+    } else {
+      None
+    }
+  }
+
+  fn execute(&mut self, entry: usize, args: &[Word]) -> VmResult<Word> {
+    let module = self.module;
+    let mut function = entry;
+    let mut f = &module.functions[function];
+    let mut memory = self.memory.push_frame(f.frame_size as usize).ok_or_else(|| VmError::StackOverflow { function: f.name.clone() })?;
+    for (i, &arg) in args.iter().take(f.params as usize).enumerate() {
+      self.memory.store(memory + i as Word, arg);
+    }
+    let mut base = self.registers(f);
+    let mut pc = 0;
+
+    macro_rules! reg {
+      ($r:expr) => { self.regs[base + $r as usize] };
+    }
+
+    loop {
+      let op = f.code[pc];
+      pc += 1;
+      match op {
+        Op::Copy { dst, src } => reg!(dst) = reg!(src),
+        Op::Unary { op, dst, src } => reg!(dst) = op.eval(reg!(src)),
+        Op::Binary { op, dst, lhs, rhs } => {
+          reg!(dst) = op.eval(reg!(lhs), reg!(rhs)).ok_or_else(|| VmError::DivisionByZero { function: f.name.clone() })?;
+        }
+        Op::Load { dst, addr } => {
+          let address = reg!(addr);
+          reg!(dst) = self.memory.load(address).ok_or_else(|| VmError::OutOfBounds { address, function: f.name.clone() })?;
+        }
+        Op::Store { addr, value } => {
+          let address = reg!(addr);
+          self.memory.store(address, reg!(value)).ok_or_else(|| VmError::OutOfBounds { address, function: f.name.clone() })?;
+        }
+        Op::Local { dst, offset } => reg!(dst) = memory + offset as Word,
+        Op::LoadLocal { dst, offset } => {
+          let address = memory + offset as Word;
+          reg!(dst) = self.memory.load(address).ok_or_else(|| VmError::OutOfBounds { address, function: f.name.clone() })?;
+        }
+        Op::StoreLocal { offset, value } => {
+          let address = memory + offset as Word;
+          self.memory.store(address, reg!(value)).ok_or_else(|| VmError::OutOfBounds { address, function: f.name.clone() })?;
+        }
+        Op::Label { dst, target } => reg!(dst) = LABEL_BASE + target as Word,
+        Op::Call { dst, args, argc, .. } | Op::CallImport { dst, args, argc, .. } | Op::CallIndirect { dst, args, argc, .. } => {
+          let code = match op {
+            Op::Call { function, .. } => Code::Function(function as usize),
+            Op::CallImport { import, .. } => Code::Import(import as usize),
+            Op::CallIndirect { callee, .. } => {
+              let value = reg!(callee);
+              self.code_of(value).ok_or_else(|| VmError::NotAFunction { value, function: f.name.clone() })?
+            }
+            _ => unreachable!(),
+          };
+          let args = &f.args[args as usize..(args + argc) as usize];
+          match code {
+            Code::Import(import) => {
+              self.argv.clear();
+              for &arg in args {
+                self.argv.push(reg!(arg));
+              }
+              let name = &module.imports[import];
+              reg!(dst) = match self.runtime.call(name, &self.argv, &mut self.memory) {
+                Some(Ok(result)) => result,
+                Some(Err(Trap::OutOfBounds(address))) => return Err(VmError::OutOfBounds { address, function: f.name.clone() }),
+                Some(Err(Trap::Exit(status))) => return Err(VmError::Exit { status }),
+                None => return Err(VmError::UnresolvedImport { name: name.clone(), function: f.name.clone() }),
+              };
+            }
+            Code::Function(callee) => {
+              if self.frames.len() >= MAX_FRAMES {
+                return Err(VmError::StackOverflow { function: f.name.clone() });
+              }
+              let g = &module.functions[callee];
+              let frame = self.memory.push_frame(g.frame_size as usize).ok_or_else(|| VmError::StackOverflow { function: f.name.clone() })?;
+              for (i, &arg) in args.iter().take(g.params as usize).enumerate() {
+                self.memory.store(frame + i as Word, reg!(arg));
+              }
+              self.frames.push(Frame { function, pc, base, memory, dst });
+              (function, f, pc, memory) = (callee, g, 0, frame);
+              base = self.registers(f);
+            }
+          }
+        }
+        Op::Jump { target } => pc = target as usize,
+        Op::JumpIf { cond, target } => {
+          if reg!(cond) != 0 {
+            pc = target as usize;
+          }
+        }
+        Op::JumpUnless { cond, target } => {
+          if reg!(cond) == 0 {
+            pc = target as usize;
+          }
+        }
+        Op::JumpCmp { op, lhs, rhs, target } => {
+          if op.eval(reg!(lhs), reg!(rhs)) != Some(0) {
+            pc = target as usize;
+          }
+        }
+        Op::Switch { value, table } => {
+          let table = &f.switches[table as usize];
+          let value = reg!(value);
+          pc = match table.cases.binary_search_by_key(&value, |&(v, _)| v) {
+            Ok(i) => table.cases[i].1,
+            Err(_) => table.default,
+          } as usize;
+        }
+        Op::JumpIndirect { addr, table } => {
+          let value = reg!(addr);
+          let target = value.wrapping_sub(LABEL_BASE);
+          if !f.jump_tables[table as usize].iter().any(|&t| t as Word == target) {
+            return Err(VmError::NotALabel { value, function: f.name.clone() });
+          }
+          pc = target as usize;
+        }
+        Op::Return { value } => {
+          let result = reg!(value);
+          self.regs.truncate(base);
+          self.memory.pop_frame(memory);
+          let Some(caller) = self.frames.pop() else {
+            return Ok(result);
+          };
+          function = caller.function;
+          f = &module.functions[function];
+          (pc, base, memory) = (caller.pc, caller.base, caller.memory);
+          reg!(caller.dst) = result;
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,100 @@
+use crate::ir::{BinaryOp, UnaryOp, Word};
+
+pub mod compile;
+pub mod format;
+pub mod machine;
+
+//register bytecode. compile::compile translates an IR module into it, format reads and writes it as
+//a file, and machine::Vm runs it.
+//
+//A function's code is one flat Vec<Op> indexed by a pc, with IR blocks laid out in order and jumps
+//to the next block left out. Every operand is a register of the running frame: the function's IR
+//registers come first, then its constants, which a call copies in when it makes the frame, so no
+//instruction needs an immediate form. Memory is word addressed as in the IR, and params and autos
+//live in a frame of memory words so that `&` works on them. Common pairs of IR instructions fuse
+//into one op, which halves dispatch in typical code: frame loads and stores through a LocalAddr
+//nothing else uses, comparisons that only feed a branch, and values only copied elsewhere.
+//
+//Function values are FUNCTION_BASE + index, with imports numbered after the functions, and label
+//values are LABEL_BASE + pc. Neither is a memory address, so using one as a pointer faults.
+
+pub const FUNCTION_BASE: Word = 1 << 48;
+pub const LABEL_BASE: Word = 1 << 49;
+
+///Index of a register in the running frame.
+pub type Reg = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+  Copy { dst: Reg, src: Reg },
+  Unary { op: UnaryOp, dst: Reg, src: Reg },
+  Binary { op: BinaryOp, dst: Reg, lhs: Reg, rhs: Reg },
+  Load { dst: Reg, addr: Reg },
+  Store { addr: Reg, value: Reg },
+  Local { dst: Reg, offset: u32 }, //address of word `offset` of the frame
+  LoadLocal { dst: Reg, offset: u32 }, //Local then Load, when nothing else needs the address
+  StoreLocal { offset: u32, value: Reg },
+  Label { dst: Reg, target: u32 }, //the value of the label at pc `target`
+  //arguments are Function::args[args..args + argc]
+  Call { dst: Reg, function: u32, args: u32, argc: u32 },
+  CallImport { dst: Reg, import: u32, args: u32, argc: u32 },
+  CallIndirect { dst: Reg, callee: Reg, args: u32, argc: u32 },
+  Jump { target: u32 },
+  JumpIf { cond: Reg, target: u32 }, //taken when cond != 0, else falls through
+  JumpUnless { cond: Reg, target: u32 }, //taken when cond == 0
+  JumpCmp { op: BinaryOp, lhs: Reg, rhs: Reg, target: u32 }, //a comparison then JumpIf
+  Switch { value: Reg, table: u32 }, //Function::switches
+  JumpIndirect { addr: Reg, table: u32 }, //Function::jump_tables lists the pcs addr may name
+  Return { value: Reg },
+}
+
+impl Op {
+  ///The register the op writes, if any.
+  pub fn dst_mut(&mut self) -> Option<&mut Reg> {
+    match self {
+      Op::Copy { dst, .. } | Op::Unary { dst, .. } | Op::Binary { dst, .. } | Op::Load { dst, .. } |
+      Op::Local { dst, .. } | Op::LoadLocal { dst, .. } | Op::Label { dst, .. } | Op::Call { dst, .. } |
+      Op::CallImport { dst, .. } | Op::CallIndirect { dst, .. } => Some(dst),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchTable {
+  pub cases: Vec<(Word, u32)>, //(value, pc), sorted by value
+  pub default: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+  pub name: String,
+  pub params: u32, //arguments arrive in frame words 0..params
+  pub frame_size: u32, //memory words
+  pub reg_count: u32, //registers before the constants
+  pub consts: Vec<Word>, //values of registers reg_count..
+  pub code: Vec<Op>,
+  pub args: Vec<Reg>, //argument registers of every call, see Op::Call
+  pub switches: Vec<SwitchTable>,
+  pub jump_tables: Vec<Vec<u32>>,
+}
+
+impl Function {
+  ///Registers in a frame, constants included.
+  pub fn registers(&self) -> usize {
+    self.reg_count as usize + self.consts.len()
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Module {
+  pub data: Vec<Word>, //initial static memory from address 1: strings, then globals
+  pub functions: Vec<Function>,
+  pub imports: Vec<String>, //names the runtime must provide
+}
+
+impl Module {
+  pub fn function(&self, name: &str) -> Option<usize> {
+    self.functions.iter().position(|f| f.name == name)
+  }
+}
@@ -0,0 +1,102 @@
+//! AArch64 code generation, and running the linked executables natively or under qemu-aarch64
+//! where a toolchain for it is available.
+
+mod common;
+
+#[cfg(test)]
+mod aarch64_tests {
+    use std::process::Command;
+
+    use b::codegen::aarch64::{emit, runtime};
+    use b::codegen::link::link_with;
+
+    use crate::common::{self, check_assembly, execute, file, found, lower_and_interpret, skip, status, CALLS, ECHO, FACT, GLOBALS, PRINTF, PRINTN};
+
+    // the C compiler to link with and how to run what it builds: natively on an AArch64 Linux host,
+    // otherwise a cross compiler and qemu-aarch64
+    fn toolchain() -> Option<(&'static str, Option<&'static str>)> {
+        if cfg!(all(target_arch = "aarch64", target_os = "linux")) && found("cc") {
+            return Some(("cc", None));
+        }
+        (found("aarch64-linux-gnu-gcc") && found("qemu-aarch64")).then_some(("aarch64-linux-gnu-gcc", Some("qemu-aarch64")))
+    }
+
+    // checks the assembly from plain and from SSA-form IR, and where it can, links and runs both
+    // against the interpreter
+    fn check_against_interpreter(name: &str, src: &str, input: &str) {
+        common::check_against_interpreter(src, input, |module, (result, output), form| {
+            let asm = emit(module);
+            check_assembly(&asm);
+            let Some((cc, emulator)) = toolchain() else {
+                return skip(name, "AArch64 toolchain");
+            };
+            let exe = file("aarch64", name);
+            link_with(cc, &[&asm, &runtime()], &exe).unwrap();
+            let command = match emulator {
+                Some(emulator) => {
+                    let mut command = Command::new(emulator);
+                    command.env("QEMU_LD_PREFIX", "/usr/aarch64-linux-gnu").arg(&exe);
+                    command
+                }
+                None => Command::new(&exe),
+            };
+            let run = execute(command, input);
+            std::fs::remove_file(&exe).unwrap();
+            assert_eq!(run, (status(result), output.to_string()), "{}", form);
+        });
+    }
+
+    #[test]
+    fn output_is_gnu_assembly_for_aapcs64() {
+        let (module, _, _) = lower_and_interpret("v[2] 1, 2; f(a, b, c, d, e, f, g, h, i) return(i); main() { extrn v; return(f(v[1])); }", "");
+        let asm = emit(&module);
+        for expected in [".globl b_f", ".globl b_main", ".globl main", "bl b_main", "bl b_f", ".globl b_v", ".section .init_array",
+                         "stp x29, x30, [sp, #-16]!", "mov x9, #1", "ldr x17, [x29, #16]", "lsl x0, x0, #3\n  ldr x0, [x0]"] {
+            assert!(asm.contains(expected), "missing `{}` in\n{}", expected, asm);
+        }
+        let (module, _, _) = lower_and_interpret("f() return(1);", "");
+        let asm = emit(&module);
+        assert!(!asm.contains(".globl main") && !asm.contains(".init_array"), "{}", asm);
+        // offsets too big for an immediate go through a register
+        let (module, _, _) = lower_and_interpret("main() { auto v[5000], w[2]; w[1] = 1; return(w[1]); }", "");
+        let asm = emit(&module);
+        assert!(asm.contains("sub sp, sp, x16") && asm.contains("add x0, sp, x0"), "{}", asm);
+    }
+
+    #[test]
+    fn programs_run_like_the_interpreter() {
+        check_against_interpreter("fact", FACT, "");
+        check_against_interpreter("echo", ECHO, "banana split");
+        let src = format!("{}
+main() {{
+  auto big, min, v[5000];
+  big = 0777777777777777777777;
+  min = -big - 1;
+  v[4999] = 123456789012;
+  printn(big + 1 == min); putchar(' ');
+  printn(big * 3); putchar(' ');
+  printn(1 << 65); putchar(' ');
+  printn(-16 >> 2); putchar(' ');
+  printn(-7 / 2); putchar(' ');
+  printn(-7 % 2); putchar(' ');
+  printn(v[4999] - 70000);
+  return(!0 + ~0);
+}}", PRINTN);
+        check_against_interpreter("wrap", &src, "");
+    }
+
+    #[test]
+    fn globals_strings_pointers_and_vectors() {
+        check_against_interpreter("globals", GLOBALS, "");
+    }
+
+    #[test]
+    fn calls_switches_and_computed_gotos() {
+        check_against_interpreter("calls", CALLS, "");
+    }
+
+    #[test]
+    fn callees_walk_their_arguments_through_a_pointer() {
+        check_against_interpreter("printf", PRINTF, "");
+    }
+}
@@ -0,0 +1,89 @@
+//! C code generation, and compiling and running the output where a C compiler is available.
+
+mod common;
+
+#[cfg(test)]
+mod c_tests {
+    use std::process::Command;
+
+    use b::codegen::c::emit;
+
+    use crate::common::{self, check_c, execute, file, lower_and_interpret, skip, status, CALLS, ECHO, FACT, GLOBALS, PRINTF, PRINTN};
+
+    // checks the C from plain and from SSA-form IR, and where it can, compiles it as warning-free C99
+    // and runs both against the interpreter
+    fn check_against_interpreter(name: &str, src: &str, input: &str) {
+        common::check_against_interpreter(src, input, |module, (result, output), form| {
+            let c = emit(module);
+            check_c(&c);
+            if !common::found("cc") {
+                return skip(name, "C compiler");
+            }
+            let (source, exe) = (file("c", &format!("{}.c", name)), file("c", name));
+            std::fs::write(&source, c).unwrap();
+            let cc = Command::new("cc").args(["-std=c99", "-pedantic", "-Wall", "-Wextra", "-Werror", "-O2", "-o"]).arg(&exe).arg(&source).output().unwrap();
+            std::fs::remove_file(&source).unwrap();
+            assert!(cc.status.success(), "cc failed:\n{}", String::from_utf8_lossy(&cc.stderr));
+            let run = execute(Command::new(&exe), input);
+            std::fs::remove_file(&exe).unwrap();
+            assert_eq!(run, (status(result), output.to_string()), "{}", form);
+        });
+    }
+
+    #[test]
+    fn output_has_a_prelude_and_prefixed_symbols() {
+        let (module, _, _) = lower_and_interpret("v[2] 1, 2; f(x) return(x); main() { extrn v, g; putchar(g(f(v[1]))); }", "");
+        let c = emit(&module);
+        for expected in ["#include <stdint.h>", "typedef intptr_t b_fn(int argc, intptr_t *args);", "static intptr_t b_putchar(",
+                         "extern intptr_t b_g(int argc, intptr_t *args);", "intptr_t b_v[4] = { 0, 1, 2, 0 };",
+                         "b_v[0] = B_ADDR(&b_v[1]);", "intptr_t b_f(int argc, intptr_t *args) {", "frame[0] = argc > 0 ? args[0] : 0;",
+                         "int main(void) {\n  b_init();"] {
+            assert!(c.contains(expected), "missing `{}` in\n{}", expected, c);
+        }
+        assert!(!c.contains("static intptr_t b_getchar("), "{}", c);
+        let (module, _, _) = lower_and_interpret("f() return(1);", "");
+        let c = emit(&module);
+        assert!(!c.contains("int main(") && !c.contains("b_init"), "{}", c);
+    }
+
+    #[test]
+    fn programs_run_like_the_interpreter() {
+        check_against_interpreter("fact", FACT, "");
+        check_against_interpreter("echo", ECHO, "banana split");
+    }
+
+    #[test]
+    fn arithmetic_wraps_like_the_ir() {
+        let src = format!("{}
+main() {{
+  auto big, min;
+  big = 0777777777777777777777;
+  min = -big - 1;
+  printn(big + 1 == min); putchar(' ');
+  printn(big * 3); putchar(' ');
+  printn(min / -1 == min); putchar(' ');
+  printn(min % -1); putchar(' ');
+  printn(1 << 65); putchar(' ');
+  printn(-16 >> 2); putchar(' ');
+  printn(-7 / 2); putchar(' ');
+  printn(-7 % 2);
+  return(!0 + ~0);
+}}", PRINTN);
+        check_against_interpreter("wrap", &src, "");
+    }
+
+    #[test]
+    fn globals_strings_pointers_and_vectors() {
+        check_against_interpreter("globals", GLOBALS, "");
+    }
+
+    #[test]
+    fn calls_switches_and_computed_gotos() {
+        check_against_interpreter("calls", CALLS, "");
+    }
+
+    #[test]
+    fn callees_walk_their_arguments_through_a_pointer() {
+        check_against_interpreter("printf", PRINTF, "");
+    }
+}
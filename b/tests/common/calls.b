@@ -0,0 +1,25 @@
+many(a, b, c, d, e, f, g, h, i) return(a + b*2 + c*3 + d*4 + e*5 + f*6 + g*7 + h*8 + i*9);
+sw(x) {
+  switch (x) {
+  case 1: return(10);
+  case -3: return(30);
+  case 077777777777: return(40);
+  }
+  return(-1);
+}
+main() {
+  extrn many, sw, printn;
+  auto f, t, i;
+  printn(many(1, 2, 3, 4, 5, 6, 7, 8, 9)); putchar(' ');
+  printn(many(1, 2, 3, 4, 5, 6, 7)); putchar(' ');
+  printn(many(1, 2)); putchar(' ');
+  f = sw;
+  printn(f(1) + f(-3) + f(077777777777) + f(5)); putchar(' ');
+  i = 0;
+again:
+  t = ++i < 3 ? again : done;
+  goto t;
+done:
+  printn(i);
+  return(many());
+}
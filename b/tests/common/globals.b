@@ -0,0 +1,17 @@
+v[3] 10, 20;
+n 7;
+s[1] "hi", "there";
+swap(a, b) { auto t; t = *a; *a = *b; *b = t; }
+puts(s) { auto i, c; i = 0; while ((c = s[i / 8] >> (i % 8 * 8) & 0377) != '*e') { putchar(c); i++; } }
+main() {
+  extrn v, n, s, puts;
+  auto x, y, w[4], p;
+  x = 1; y = 2;
+  swap(&x, &y);
+  p = w;
+  *p++ = 5; *p++ = 6;
+  v[3] = n;
+  puts(s[0]); puts(s[1]); puts("!*n");
+  printn(x * 1000 + y * 100 + w[0] + w[1] + (p - w) + v[0] + v[1] + v[3]);
+  return(-1);
+}
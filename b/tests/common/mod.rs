@@ -0,0 +1,135 @@
+//! Helpers and programs shared by the backend tests. Every backend runs the same programs, checked
+//! against the interpreter, and checks what it generates even where nothing can run it.
+
+// each test crate uses its own share of these
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use rustc_hash::FxHashSet;
+
+use b::interp::Interpreter;
+use b::ir::lower::lower;
+use b::ir::ssa::to_ssa;
+use b::ir::{Module, Word};
+use b::lexer::lex_all;
+use b::parser::Parser;
+use b::sema::analyze;
+
+pub const PRINTN: &str = include_str!("printn.b");
+
+pub const FACT: &str = concat!(include_str!("printn.b"),
+    "fact(n) return(n <= 1 ? 1 : n * fact(n - 1)); main() { printn(fact(20)); putchar('*n'); putchar('ok'); return(fact(5)); }");
+
+// counts its input, echoing it with each `a` capitalized
+pub const ECHO: &str = "main() { auto c, n; n = 0; while ((c = getchar()) != '*e') { putchar(c == 'a' ? 'A' : c); n++; } return(n); }";
+
+// globals, strings, vectors and pointers to autos
+pub const GLOBALS: &str = concat!(include_str!("printn.b"), include_str!("globals.b"));
+
+// calls with missing and register-spilling arguments, switches, function values and computed gotos
+pub const CALLS: &str = concat!(include_str!("printn.b"), include_str!("calls.b"));
+
+// printf from printer_test2.b: it takes the address of its first argument and walks the rest, past
+// the ones any backend passes in registers
+pub const PRINTF: &str = concat!(include_str!("printn.b"), include_str!("printf.b"));
+
+// the IR, and main's result and output under the interpreter
+pub fn lower_and_interpret(src: &str, input: &str) -> (Module, Option<Word>, String) {
+    let lexed = lex_all(src);
+    assert!(lexed.errors.is_empty(), "lex error in test input");
+    let mut parser = Parser::new(&lexed.tokens);
+    let program = parser.parse_program().expect("parse error in test input");
+    let sema = analyze(&program);
+    assert_eq!(sema.errors, vec![], "sema error in test input");
+    let mut interpreter = Interpreter::new(&program, &sema).unwrap().with_input(input);
+    let result = interpreter.run().ok();
+    (lower(&program, &sema), result, String::from_utf8(interpreter.output().to_vec()).unwrap())
+}
+
+// hands `check` the module from plain and from SSA-form IR, with main's result and output under the
+// interpreter and which form it is
+pub fn check_against_interpreter(src: &str, input: &str, mut check: impl FnMut(&Module, (Word, &str), &str)) {
+    let (mut module, result, output) = lower_and_interpret(src, input);
+    let result = result.expect("interpreter fault in test input");
+    check(&module, (result, &output), "plain IR");
+    module.functions.iter_mut().for_each(|f| { to_ssa(f); });
+    check(&module, (result, &output), "SSA form");
+}
+
+pub fn found(program: &str) -> bool {
+    Command::new(program).arg("--version").output().is_ok()
+}
+
+pub fn skip(test: &str, missing: &str) {
+    eprintln!("not running {}: no {}", test, missing);
+}
+
+pub fn file(backend: &str, name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("b-{}-test-{}-{}", backend, std::process::id(), name))
+}
+
+// exit status and output of `command` given `input`
+pub fn execute(mut command: Command, input: &str) -> (i32, String) {
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let out = child.wait_with_output().unwrap();
+    (out.status.code().unwrap(), String::from_utf8(out.stdout).unwrap())
+}
+
+// the exit status of an executable whose main returned `result`
+pub fn status(result: Word) -> i32 {
+    (result & 0xff) as i32
+}
+
+// GNU assembly as the backends write it: labels at the start of a line, directives and instructions
+// indented, no label defined twice, and every local label jumped to or taken the address of defined
+pub fn check_assembly(asm: &str) {
+    let is_name = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    let mut defined = FxHashSet::default();
+    for line in asm.lines().filter(|l| !l.is_empty()) {
+        if let Some(label) = line.strip_suffix(':') {
+            assert!(is_name(label), "bad label `{}`", line);
+            assert!(defined.insert(label), "label `{}` defined twice", label);
+        } else {
+            let body = line.strip_prefix("  ").unwrap_or_else(|| panic!("line `{}` is not indented", line));
+            assert!(body.starts_with('.') || body.starts_with(|c: char| c.is_ascii_lowercase()), "bad line `{}`", line);
+        }
+    }
+    for (at, _) in asm.match_indices(".L") {
+        let name: String = asm[at..].chars().take_while(|&c| c.is_ascii_alphanumeric() || c == '_' || c == '.').collect();
+        assert!(defined.contains(name.as_str()), "local label `{}` is not defined", name);
+    }
+}
+
+// C whose braces and parentheses balance outside literals and comments
+pub fn check_c(c: &str) {
+    let (mut depth, mut chars) = (Vec::new(), c.chars());
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' | '\'' => {
+                while let Some(next) = chars.next() {
+                    if next == '\\' {
+                        chars.next();
+                    } else if next == ch {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.clone().next() == Some('*') => {
+                let rest = chars.as_str();
+                let end = rest.find("*/").expect("unterminated comment");
+                chars = rest[end + 2..].chars();
+            }
+            '(' | '{' | '[' => depth.push(ch),
+            ')' | '}' | ']' => {
+                let open = depth.pop().unwrap_or_else(|| panic!("unmatched `{}`", ch));
+                assert_eq!(open, match ch { ')' => '(', '}' => '{', _ => '[' }, "`{}` closes `{}`", ch, open);
+            }
+            _ => {}
+        }
+    }
+    assert!(depth.is_empty(), "unclosed {:?}", depth);
+}
@@ -0,0 +1,33 @@
+char(s, i) return(s[i / 8] >> (i % 8 * 8) & 0377);
+printf(fmt, x1, x2, x3, x4, x5, x6, x7, x8, x9) {
+  auto adx, x, c, i, j;
+  i = 0;
+  adx = &x1;
+loop:
+  while ((c = char(fmt, i++)) != '%') {
+    if (c == '*e') return;
+    putchar(c);
+  }
+  x = *adx++;
+  switch (c = char(fmt, i++)) {
+  case 'd':
+    printn(x);
+    goto loop;
+  case 'c':
+    putchar(x);
+    goto loop;
+  case 's':
+    j = 0;
+    while ((c = char(x, j++)) != '*e') putchar(c);
+    goto loop;
+  }
+  putchar('%');
+  i--;
+  adx--;
+  goto loop;
+}
+main() {
+  printf("%d %d %s %c%c %d %d %d %d!*n", 1, -2, "three", 'f', 'o', 6, 7, 8, 9);
+  printf("%d and %d, 100%%*n", 11, 12);
+  return(0);
+}
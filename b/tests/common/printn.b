@@ -0,0 +1,6 @@
+printn(n) {
+  auto a;
+  if (n < 0) { putchar('-'); n = -n; }
+  if (a = n / 10) printn(a);
+  putchar(n % 10 + '0');
+}
@@ -0,0 +1,181 @@
+//! Rendering of lexer and parser errors as diagnostics.
+
+#[cfg(test)]
+mod render_tests {
+    use b::common::source_map::{FileId, LineIndex, SourceMap};
+    use b::common::span::Span;
+    use b::diagnostics::render::{ColorChoice, Emitter};
+    use b::diagnostics::{Diagnostic, Label, Severity};
+    use b::lexer::scanner::Scanner;
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+
+    fn source_map(src: &str) -> (SourceMap<'_>, FileId) {
+        let mut map = SourceMap::new();
+        let file = map.add_file("test.b", src, LineIndex::new(src));
+        (map, file)
+    }
+
+    /// Render the first lexer error in `src` as plain text.
+    fn render_lex_error(src: &str) -> String {
+        let (map, file) = source_map(src);
+        let err = Scanner::new(src).find_map(|r| r.err()).expect("expected a lex error");
+        Emitter::new(&map, ColorChoice::Never).render(&err.to_diagnostic(file))
+    }
+
+    /// Render the parse error in `src` as plain text.
+    fn render_parse_error(src: &str) -> String {
+        let (map, file) = source_map(src);
+        let tokens = lex_all(src).tokens;
+        let mut parser = Parser::new(&tokens);
+        let err = parser.parse_program().expect_err("expected a parse error");
+        Emitter::new(&map, ColorChoice::Never).render(&err.to_diagnostic(file))
+    }
+
+    #[test]
+    fn unexpected_char_points_at_char() {
+        let out = render_lex_error("main() {\n  x = 1 @ 2;\n}");
+        assert_eq!(out, "\
+error[E0001]: unexpected character `@`
+ --> test.b:2:9
+  |
+2 |   x = 1 @ 2;
+  |         ^ not valid in any B token
+");
+    }
+
+    #[test]
+    fn unterminated_string_points_at_open_quote() {
+        let out = render_lex_error("x = \"abc;");
+        assert!(out.starts_with("error[E0002]: unterminated string literal\n --> test.b:1:5\n"));
+        assert!(out.contains("1 | x = \"abc;\n  |     ^ string starts here\n"));
+        assert!(out.contains("  = help: add a closing `\"`\n"));
+    }
+
+    #[test]
+    fn unexpected_token_names_expected_and_found() {
+        let out = render_parse_error("main() {\n  if x) ;\n}");
+        assert_eq!(out, "\
+error[E0101]: expected `(`, found identifier `x`
+ --> test.b:2:6
+  |
+2 |   if x) ;
+  |      ^ expected `(`
+");
+    }
+
+    #[test]
+    fn c_style_compound_assignment_gets_help() {
+        let out = render_parse_error("main() {\n  x += 1;\n}");
+        assert_eq!(out, "\
+error[E0107]: `+=` is not a B operator
+ --> test.b:2:6
+  |
+2 |   x += 1;
+  |     - parsed as a binary operator
+  |      ^ expected an expression
+  |
+  = help: B uses `=+`, not `+=`
+");
+    }
+
+    #[test]
+    fn every_c_style_compound_form_gets_help() {
+        for (c, b) in [("|=", "=|"), ("^=", "=^"), ("<<=", "=<<"), (">>=", "=>>")] {
+            let out = render_parse_error(&format!("main() x {} 1;", c));
+            assert!(out.contains(&format!("`{}` is not a B operator", c)) && out.contains(&format!("B uses `{}`", b)), "{}", out);
+        }
+    }
+
+    #[test]
+    fn multi_char_span_is_underlined_fully() {
+        let out = render_parse_error("main() {\n  x = 1;\n  auto y;\n}");
+        assert!(out.contains("3 |   auto y;\n  |   ^^^^ declaration is not at the start of the block\n"));
+    }
+
+    #[test]
+    fn gutter_widens_for_large_line_numbers() {
+        let src = format!("{}@", "\n".repeat(11));
+        let out = render_lex_error(&src);
+        assert!(out.contains("  --> test.b:12:1\n   |\n12 | @\n   | ^"));
+    }
+
+    #[test]
+    fn labels_on_separate_lines_are_elided_between() {
+        let src = "a\nb\nc\nd";
+        let (map, file) = source_map(src);
+        let diag = Diagnostic::new(Severity::Warning, "two places")
+            .with_label(Label::primary(file, Span { start: 0, end: 1 }, "here"))
+            .with_label(Label::secondary(file, Span { start: 6, end: 7 }, "and here"))
+            .with_note("a note");
+        let out = Emitter::new(&map, ColorChoice::Never).render(&diag);
+        assert_eq!(out, "\
+warning: two places
+ --> test.b:1:1
+  |
+1 | a
+  | ^ here
+...
+4 | d
+  | - and here
+  |
+  = note: a note
+");
+    }
+
+    #[test]
+    fn unknown_escape_is_a_warning() {
+        let src = "x = \"*q\";";
+        let (map, file) = source_map(src);
+        let lexed = lex_all(src);
+        let out = Emitter::new(&map, ColorChoice::Never).render(&lexed.warnings[0].to_diagnostic(file));
+        assert_eq!(out, "\
+warning[W0001]: unknown escape `*q`
+ --> test.b:1:6
+  |
+1 | x = \"*q\";
+  |      ^^ treated as a plain `q`
+  |
+  = note: B escapes are *n *t *r *0 *e *( *) ** *' and *\"
+");
+    }
+
+    #[test]
+    fn glued_compound_minus_is_a_warning() {
+        let src = "x =-1;";
+        let (map, file) = source_map(src);
+        let lexed = lex_all(src);
+        let out = Emitter::new(&map, ColorChoice::Never).render(&lexed.warnings[0].to_diagnostic(file));
+        assert_eq!(out, "\
+warning[W0002]: ambiguous `=-`: parsed as a compound assignment
+ --> test.b:1:3
+  |
+1 | x =-1;
+  |   ^^ this is `=-`, not `= -`
+  |
+  = help: write `=- ` with a space to keep the compound assignment
+  = help: write `= -` to assign the operand with a prefix `-`
+");
+    }
+
+    #[test]
+    fn color_output_uses_ansi_and_strips_to_plain() {
+        let src = "@";
+        let (map, file) = source_map(src);
+        let err = Scanner::new(src).find_map(|r| r.err()).unwrap();
+        let colored = Emitter::new(&map, ColorChoice::Always).render(&err.to_diagnostic(file));
+        assert!(colored.starts_with("\x1b[1;31merror"));
+
+        // removing escape sequences gives exactly the plain rendering
+        let mut stripped = String::new();
+        let mut chars = colored.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                stripped.push(c);
+            }
+        }
+        assert_eq!(stripped, render_lex_error(src));
+    }
+}
@@ -0,0 +1,157 @@
+//! The `b` driver: commands, emitted stages, targets and exit statuses.
+
+#[cfg(test)]
+mod driver_tests {
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+
+    use b::vm::format;
+    use b::vm::machine::Vm;
+
+    // exit status, stdout and stderr of `b args`, with `stdin` as its input
+    fn b(args: &[&str], stdin: &str) -> (i32, String, String) {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_b")).args(args)
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+            .spawn().unwrap();
+        child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+        let out = child.wait_with_output().unwrap();
+        (out.status.code().unwrap(), String::from_utf8(out.stdout).unwrap(), String::from_utf8(out.stderr).unwrap())
+    }
+
+    fn file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("b-driver-test-{}-{}", std::process::id(), name))
+    }
+
+    fn have_cc() -> bool {
+        cfg!(all(target_arch = "x86_64", target_os = "linux")) && Command::new("cc").arg("--version").output().is_ok()
+    }
+
+    const COUNT: &str = "main() { auto n; n = 0; while (getchar() != '*e') n++; printf(\"%d bytes*n\", n); return(n); }";
+
+    #[test]
+    fn check_reports_errors_in_every_file() {
+        let (good, bad) = (file("good.b"), file("bad.b"));
+        std::fs::write(&good, "main() return(0);").unwrap();
+        std::fs::write(&bad, "main() { x = 1; }").unwrap();
+        let (status, stdout, stderr) = b(&["check", good.to_str().unwrap()], "");
+        assert_eq!((status, stdout.as_str(), stderr.as_str()), (0, "", ""));
+        let (status, _, stderr) = b(&[bad.to_str().unwrap(), good.to_str().unwrap(), "-"], "main() return(y);");
+        assert_eq!(status, 1);
+        assert!(stderr.contains("bad.b:1:10") && stderr.contains("<stdin>:1:15"), "{}", stderr);
+        std::fs::remove_file(&good).unwrap();
+        std::fs::remove_file(&bad).unwrap();
+    }
+
+    #[test]
+    fn stages_are_printed_from_standard_input() {
+        let src = "main() return(7);";
+        let (status, tokens, _) = b(&["--emit=tokens"], src);
+        assert_eq!(status, 0);
+        assert!(tokens.starts_with("[1:1] Identifier(\"main\")\n[1:5] Delimiter(LParen)\n"), "{}", tokens);
+        assert_eq!(b(&["--emit=sexpr", "-"], src).1, "(fn main ()\n  (return (group 7))\n)\n");
+        assert!(b(&["--emit=ast"], src).1.contains("Return"));
+        let (_, ir, _) = b(&["check", "--emit=ir", "-O"], src);
+        assert!(ir.contains("func main") && ir.contains("ret 7"), "{}", ir);
+    }
+
+    #[test]
+    fn run_exits_with_mains_result() {
+        let src = file("count.b");
+        std::fs::write(&src, COUNT).unwrap();
+        assert_eq!(b(&["run", src.to_str().unwrap()], "abc"), (3, "3 bytes\n".to_string(), String::new()));
+        assert_eq!(b(&["run", "-O", "--target=vm", src.to_str().unwrap()], "abcdef"), (6, "6 bytes\n".to_string(), String::new()));
+        if have_cc() {
+            assert_eq!(b(&["run", "--target=x86_64", src.to_str().unwrap()], "ab"), (2, "2 bytes\n".to_string(), String::new()));
+        }
+        //a program that never reads does not wait for the end of its input
+        let quick = file("quick.b");
+        std::fs::write(&quick, "main() return(5);").unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_b")).args(["run", quick.to_str().unwrap()])
+            .stdin(Stdio::piped()).spawn().unwrap();
+        let stdin = child.stdin.take();
+        assert_eq!(child.wait().unwrap().code(), Some(5));
+        drop(stdin);
+        std::fs::remove_file(&quick).unwrap();
+        let (status, _, stderr) = b(&["run"], "main() { auto p; p = 0; return(*p); }");
+        assert_eq!(status, 1);
+        assert!(stderr.contains("error[E0301]") && stderr.contains("in function `main`"), "{}", stderr);
+        std::fs::remove_file(&src).unwrap();
+    }
+
+    #[test]
+    fn build_writes_modules_and_executables() {
+        let module = file("count.bvm");
+        assert_eq!(b(&["build", "-o", module.to_str().unwrap()], COUNT).0, 0);
+        let module = format::read(&std::fs::read(&module).unwrap()).unwrap();
+        let mut vm = Vm::new(&module).unwrap().with_input("xyz");
+        assert_eq!((vm.run(), vm.output()), (Ok(3), &b"3 bytes\n"[..]));
+        let wasm = file("count.wasm");
+        assert_eq!(b(&["build", "--target=wasm", "-o", wasm.to_str().unwrap()], COUNT).0, 0);
+        assert!(std::fs::read(&wasm).unwrap().starts_with(b"\0asm"));
+        std::fs::remove_file(file("count.bvm")).unwrap();
+        std::fs::remove_file(&wasm).unwrap();
+        if !have_cc() {
+            return;
+        }
+        let (exe, object) = (file("count"), file("count.o"));
+        assert_eq!(b(&["build", "-O", "-o", exe.to_str().unwrap()], COUNT).0, 0);
+        let out = Command::new(&exe).stdin(Stdio::null()).output().unwrap();
+        assert_eq!((out.status.code(), out.stdout), (Some(0), b"0 bytes\n".to_vec()));
+        assert_eq!(b(&["build", "--emit=obj", "-o", object.to_str().unwrap()], COUNT).0, 0);
+        assert!(std::fs::read(&object).unwrap().starts_with(b"\x7fELF"));
+        std::fs::remove_file(&exe).unwrap();
+        std::fs::remove_file(&object).unwrap();
+    }
+
+    #[test]
+    fn files_link_into_one_program() {
+        let (main, lib, dup) = (file("main.b"), file("lib.b"), file("dup.b"));
+        std::fs::write(&main, "main() {\n  extrn total, add, names;\n  add(3); add(4);\n  printf(\"%s: %d*n\", names[1], total);\n  return(total);\n}").unwrap();
+        std::fs::write(&lib, "total;\nnames[2] \"zero\", \"one\";\nadd(n) {\n  extrn total;\n  total = total + n;\n}").unwrap();
+        std::fs::write(&dup, "add(n) return(n);\nother() missing();").unwrap();
+        let (main, lib, dup) = (main.to_str().unwrap(), lib.to_str().unwrap(), dup.to_str().unwrap());
+        assert_eq!(b(&["run", main, lib], ""), (7, "one: 7\n".to_string(), String::new()));
+        if have_cc() {
+            assert_eq!(b(&["run", "--target=x86_64", lib, main], ""), (7, "one: 7\n".to_string(), String::new()));
+        }
+        //check takes one file of a program alone, but build needs all of it
+        assert_eq!(b(&["check", main], "").0, 0);
+        let (status, _, stderr) = b(&["run", main], "");
+        assert_eq!(status, 1);
+        assert!(stderr.contains("error[E0210]: `total` is not defined in any file") && stderr.contains("main.b:2:9"), "{}", stderr);
+        let (status, _, stderr) = b(&["check", main, lib, dup], "");
+        assert_eq!(status, 1);
+        assert!(stderr.contains("error[E0211]: `add` is defined in more than one file") && stderr.contains("dup.b:1:1")
+            && stderr.contains("lib.b:3:1") && !stderr.contains("E0210"), "{}", stderr);
+        for name in [main, lib, dup] {
+            std::fs::remove_file(name).unwrap();
+        }
+    }
+
+    #[test]
+    fn imported_functions_and_data_are_values() {
+        //`f = inc` takes a function's address and `count` reads the word a global holds
+        let (main, lib) = (file("values.b"), file("values-lib.b"));
+        std::fs::write(&main, "main() { extrn twice, inc, count, v; auto f; f = inc; twice(f, 5); printf(\"%d %d*n\", count, v[1]); return(count); }").unwrap();
+        std::fs::write(&lib, "count 1; v[2] 10, 20; inc(n) { extrn count; count =+ n; } twice(g, n) { g(n); g(n); }").unwrap();
+        let (main, lib) = (main.to_str().unwrap(), lib.to_str().unwrap());
+        assert_eq!(b(&["run", main, lib], ""), (11, "11 20\n".to_string(), String::new()));
+        assert_eq!(b(&["run", "-O", lib, main], ""), (11, "11 20\n".to_string(), String::new()));
+        if have_cc() {
+            assert_eq!(b(&["run", "--target=x86_64", main, lib], ""), (11, "11 20\n".to_string(), String::new()));
+        }
+        std::fs::remove_file(main).unwrap();
+        std::fs::remove_file(lib).unwrap();
+    }
+
+    #[test]
+    fn bad_usage_exits_with_2() {
+        for args in [&["--emit=nothing"][..], &["--target=pdp7"], &["-o"], &["--bogus"], &["-", "-"], &["run", "--target=wasm"],
+                     &["--emit=asm", "--target=wasm"]] {
+            let (status, _, stderr) = b(args, "");
+            assert_eq!(status, 2, "{:?}", args);
+            assert!(stderr.starts_with("error: ") && stderr.contains("usage: b"), "{}", stderr);
+        }
+    }
+}
@@ -8,7 +8,7 @@
 mod lexer_tests {
     use b::lexer::scanner::Scanner;
     use b::lexer::token::*;
-    use b::lexer::LexError;
+    use b::lexer::{lex_all, LexError, LexWarning};
 
     /// Convenience: lex and collect tokens (unwrapping errors).
     fn lex_ok(src: &str) -> Vec<SpannedToken<'_>> {
@@ -91,16 +91,18 @@ mod lexer_tests {
 
     #[test]
     fn negative_number_token() {
+        //the sign is a separate token; the parser applies it
         let tokens = lex_ok("-42");
-        assert_eq!(tokens.len(), 1);
-        assert!(matches!(tokens[0].token, Token::Integer(-42)));
+        assert_eq!(tokens.len(), 2);
+        assert!(matches!(tokens[0].token, Token::Operator(Operator::Minus)));
+        assert!(matches!(tokens[1].token, Token::Integer(42)));
     }
 
     #[test]
     fn negative_zero() {
         let tokens = lex_ok("-0");
-        assert_eq!(tokens.len(), 1);
-        assert!(matches!(tokens[0].token, Token::Integer(0)));
+        assert_eq!(tokens.len(), 2);
+        assert!(matches!(tokens[1].token, Token::Integer(0)));
     }
 
     #[test]
@@ -198,6 +200,51 @@ mod lexer_tests {
         }
     }
 
+    #[test]
+    fn full_b_escape_table() {
+        let tokens = lex_ok(r#""*e*(*)*'*r*"""#);
+        match &tokens[0].token {
+            Token::StringLiteral(s) => assert_eq!(s, "\x04{}'\r\""),
+            other => panic!("expected StringLiteral, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn end_of_string_char_literal() {
+        // printer_test2.b compares characters against '*e'
+        let tokens = lex_ok("'*e'");
+        assert!(matches!(tokens[0].token, Token::CharLiteral(4)));
+        assert_eq!(STRING_TERMINATOR as i64, 4);
+    }
+
+    #[test]
+    fn string_bytes_appends_terminator() {
+        assert_eq!(string_bytes("hi"), vec![b'h', b'i', 4]);
+        assert_eq!(string_bytes(""), vec![4]);
+    }
+
+    #[test]
+    fn unknown_escape_warns_and_keeps_char() {
+        let lexed = lex_all(r#"x = "a*qb";"#);
+        assert!(lexed.errors.is_empty());
+        assert!(matches!(&lexed.tokens[2].token, Token::StringLiteral(s) if s == "aqb"));
+        assert_eq!(lexed.warnings.len(), 1);
+        match lexed.warnings[0] {
+            LexWarning::UnknownEscape(c, span) => {
+                assert_eq!(c, 'q');
+                assert_eq!((span.start, span.end), (6, 8));
+            }
+            _ => panic!("expected UnknownEscape"),
+        }
+    }
+
+    #[test]
+    fn known_escapes_do_not_warn() {
+        let lexed = lex_all(r#""*n*t*r*0*e*(*)***'*"" '*e'"#);
+        assert!(lexed.errors.is_empty());
+        assert!(lexed.warnings.is_empty());
+    }
+
     // ---- operators / delimiters ----
 
     #[test]
@@ -212,6 +259,53 @@ mod lexer_tests {
         assert!(matches!(ops[5], Token::Operator(Operator::AssignAmp)));
     }
 
+    #[test]
+    fn extended_compound_assignments() {
+        let tokens = lex_ok("=| =^ =<< =>> =< =<= => =>= === =!=");
+        let ops: Vec<_> = tokens.iter().map(|t| match t.token {
+            Token::Operator(op) => op,
+            _ => panic!("expected operator"),
+        }).collect();
+        assert_eq!(ops, vec![
+            Operator::AssignBar, Operator::AssignCaret, Operator::AssignLShift, Operator::AssignRShift,
+            Operator::AssignLess, Operator::AssignLessEq, Operator::AssignGreater, Operator::AssignGreaterEq,
+            Operator::AssignEqual, Operator::AssignNotEqual,
+        ]);
+        assert!(ops.iter().all(|op| op.is_assignment()));
+        assert_eq!(Operator::AssignNotEqual.compound_base(), Some(Operator::NotEqual));
+        assert_eq!(Operator::Assign.compound_base(), None);
+    }
+
+    #[test]
+    fn not_equal_and_assign_not() {
+        let tokens = lex_ok("a != b =!c");
+        let ops: Vec<_> = tokens.iter().map(|t| &t.token).collect();
+        assert!(matches!(ops[1], Token::Operator(Operator::NotEqual)));
+        assert!(matches!(ops[3], Token::Operator(Operator::Assign)));
+        assert!(matches!(ops[4], Token::Operator(Operator::Bang)));
+    }
+
+    #[test]
+    fn compound_minus_vs_assign_negative() {
+        let spaced = lex_all("a =- 1; b = -1;");
+        assert!(spaced.warnings.is_empty());
+        assert!(matches!(spaced.tokens[1].token, Token::Operator(Operator::AssignMinus)));
+        assert!(matches!(spaced.tokens[5].token, Token::Operator(Operator::Assign)));
+        assert!(matches!(spaced.tokens[6].token, Token::Operator(Operator::Minus)));
+
+        // glued to the operand: still `=-`, but warned about
+        let glued = lex_all("a =-1; b=*p; c =|1;");
+        assert!(matches!(glued.tokens[1].token, Token::Operator(Operator::AssignMinus)));
+        assert_eq!(glued.warnings.len(), 2);
+        match glued.warnings[0] {
+            LexWarning::AmbiguousCompoundAssign(op, span) => {
+                assert_eq!(op, Operator::AssignMinus);
+                assert_eq!((span.start, span.end), (2, 4));
+            }
+            _ => panic!("expected AmbiguousCompoundAssign"),
+        }
+    }
+
     #[test]
     fn shift_vs_comparison() {
         let tokens = lex_ok("<< >> <= >=");
@@ -229,7 +323,17 @@ mod lexer_tests {
         assert!(matches!(ops[0], Token::Operator(Operator::Inc)));
         assert!(matches!(ops[1], Token::Operator(Operator::Plus)));
         assert!(matches!(ops[2], Token::Operator(Operator::Dec)));
-        assert!(matches!(ops[3], Token::Integer(-1)));
+        assert!(matches!(ops[3], Token::Operator(Operator::Minus)));
+        assert!(matches!(ops[4], Token::Integer(1)));
+    }
+
+    #[test]
+    fn minus_digit_without_space_is_subtraction() {
+        let tokens = lex_ok("a-1");
+        assert_eq!(tokens.len(), 3);
+        assert!(matches!(tokens[0].token, Token::Identifier("a")));
+        assert!(matches!(tokens[1].token, Token::Operator(Operator::Minus)));
+        assert!(matches!(tokens[2].token, Token::Integer(1)));
     }
 
     #[test]
@@ -343,11 +447,89 @@ mod lexer_tests {
 
     #[test]
     fn span_tracking_across_newlines() {
-        let tokens = lex_ok("a\nb\nc");
+        let src = "a\nb\nc";
+        let mut scanner = Scanner::new(src);
+        let tokens = scanner.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        let lines = scanner.line_index();
         assert_eq!(tokens.len(), 3);
-        //assert_eq!(tokens[0].span.start.line, 1);
-        //assert_eq!(tokens[1].span.start.line, 2);
-        //assert_eq!(tokens[2].span.start.line, 3);
+        assert_eq!(lines.line_col(src, tokens[0].span.start).line, 1);
+        assert_eq!(lines.line_col(src, tokens[1].span.start).line, 2);
+        assert_eq!(lines.line_col(src, tokens[2].span.start).line, 3);
+    }
+
+    #[test]
+    fn spans_start_at_token_not_file_start() {
+        let tokens = lex_ok("ab  cd");
+        assert_eq!((tokens[0].span.start, tokens[0].span.end), (0, 2));
+        assert_eq!((tokens[1].span.start, tokens[1].span.end), (4, 6));
+    }
+
+    // ---- lex_all: full token stream with recovery ----
+
+    #[test]
+    fn lex_all_empty_input_is_just_eof() {
+        let lexed = b::lexer::lex_all("");
+        assert_eq!(lexed.tokens.len(), 1);
+        assert_eq!(lexed.tokens[0].token, Token::EOF);
+        assert!(lexed.errors.is_empty());
+    }
+
+    #[test]
+    fn lex_all_eof_spans_end_of_source() {
+        let src = "main() { }\n";
+        let lexed = b::lexer::lex_all(src);
+        let eof = lexed.tokens.last().unwrap();
+        assert_eq!(eof.token, Token::EOF);
+        assert_eq!((eof.span.start, eof.span.end), (src.len() as u32, src.len() as u32));
+    }
+
+    #[test]
+    fn lex_all_skips_bad_chars_and_keeps_going() {
+        let lexed = b::lexer::lex_all("a @ b $ c");
+        let names: Vec<_> = lexed.tokens.iter().map(|t| &t.token).collect();
+        assert_eq!(names, vec![&Token::Identifier("a"), &Token::Identifier("b"), &Token::Identifier("c"), &Token::EOF]);
+        assert_eq!(lexed.errors.len(), 2);
+        assert!(matches!(lexed.errors[0], LexError::UnexpectedChar('@', _)));
+        assert!(matches!(lexed.errors[1], LexError::UnexpectedChar('$', _)));
+    }
+
+    #[test]
+    fn string_and_char_literals_span_lines() {
+        let lexed = b::lexer::lex_all("x = \"ab\ncd\"; c = 'a\nb';\ny");
+        assert!(lexed.errors.is_empty(), "{:?}", lexed.errors);
+        let tokens: Vec<_> = lexed.tokens.iter().map(|t| &t.token).collect();
+        assert_eq!(tokens[2], &Token::StringLiteral("ab\ncd".to_string()));
+        assert_eq!(tokens[6], &Token::CharLiteral(('a' as i64) << 8 | '\n' as i64));
+        assert_eq!(tokens[8], &Token::Identifier("y"));
+        assert_eq!(lexed.lines.line_count(), 4);
+    }
+
+    #[test]
+    fn unterminated_literal_runs_to_the_end() {
+        let lexed = b::lexer::lex_all("x = \"abc;\ny = 1;");
+        assert_eq!(lexed.errors.len(), 1);
+        assert!(matches!(lexed.errors[0], LexError::UnterminatedString(_)));
+        assert!(!lexed.tokens.iter().any(|t| t.token == Token::Identifier("y")));
+        assert_eq!(lexed.tokens.last().unwrap().token, Token::EOF);
+        let lexed = b::lexer::lex_all("c = 'ab\nd = 2;");
+        assert_eq!(lexed.errors.len(), 1);
+        assert!(matches!(lexed.errors[0], LexError::UnterminatedChar(_)));
+    }
+
+    #[test]
+    fn lex_all_records_every_error() {
+        let lexed = b::lexer::lex_all("@\n99999999999999999999\n$\n\"open\n/* swallowed by the string");
+        assert_eq!(lexed.errors.len(), 4);
+        assert_eq!(lexed.tokens.len(), 1);
+        let lexed = b::lexer::lex_all("@\n/* open");
+        assert_eq!(lexed.errors.len(), 2);
+    }
+
+    #[test]
+    fn lex_all_line_index_is_complete() {
+        let src = "a\nb\n\nc";
+        let lexed = b::lexer::lex_all(src);
+        assert_eq!(lexed.lines.line_count(), 4);
     }
 }
 
@@ -355,20 +537,15 @@ mod lexer_tests {
 
 #[cfg(test)]
 mod parser_tests {
-    use b::lexer::scanner::Scanner;
+    use b::lexer::lex_all;
     use b::lexer::token::*;
     use b::parser::Parser;
     use b::ast::*;
 
     fn lex(src: &str) -> Vec<SpannedToken<'_>> {
-        let mut tokens: Vec<_> = Scanner::new(src)
-            .collect::<Result<Vec<_>, _>>()
-            .expect("lex error in test input");
-        tokens.push(SpannedToken {
-            token: Token::EOF,
-            span: b::common::span::Span::default(),
-        });
-        tokens
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        lexed.tokens
     }
 
     fn parse_succeeds(src: &str) -> bool {
@@ -470,6 +647,88 @@ mod parser_tests {
         }
     }
 
+    #[test]
+    fn not_equal_is_an_equality_operator() {
+        let tokens = lex("main() return(a != b == c < d);");
+        let mut parser = Parser::new(&tokens);
+        let prog = parser.parse_program().unwrap();
+        let printed = b::ast::pretty_printer::AstPrinter::new().print_program(&prog);
+        assert!(printed.contains("(== (!= a b) (< c d))"), "{}", printed);
+    }
+
+    #[test]
+    fn every_compound_assignment_parses() {
+        for op in ["=+", "=-", "=*", "=/", "=%", "=&", "=|", "=^", "=<<", "=>>",
+                   "=<", "=<=", "=>", "=>=", "===", "=!="] {
+            let src = format!("main() {{ x {} y; }}", op);
+            let tokens = lex(&src);
+            let mut parser = Parser::new(&tokens);
+            let prog = parser.parse_program().unwrap_or_else(|e| panic!("{}: {:?}", op, e));
+            let printed = b::ast::pretty_printer::AstPrinter::new().print_program(&prog);
+            assert!(printed.contains(&format!("({} x y)", op)), "{}: {}", op, printed);
+        }
+    }
+
+    #[test]
+    fn compound_assignment_is_right_associative() {
+        let tokens = lex("main() { a =<< b =| 1; }");
+        let mut parser = Parser::new(&tokens);
+        let prog = parser.parse_program().unwrap();
+        let printed = b::ast::pretty_printer::AstPrinter::new().print_program(&prog);
+        assert!(printed.contains("(=<< a (=| b 1))"));
+    }
+
+    #[test]
+    fn negative_global_initializers_fold() {
+        let tokens = lex("x -5; v[3] -1, 2, -'a';");
+        let mut parser = Parser::new(&tokens);
+        let prog = parser.parse_program().unwrap();
+        let values = |item: &Item| match item {
+            Item::Global(decls) => decls[0].initializer.as_ref().unwrap()
+                .iter().map(|e| e.const_value()).collect::<Vec<_>>(),
+            _ => panic!("expected global"),
+        };
+        assert_eq!(values(&prog.items[0]), vec![Some(-5)]);
+        assert_eq!(values(&prog.items[1]), vec![Some(-1), Some(2), Some(-97)]);
+    }
+
+    #[test]
+    fn non_constant_global_initializer_fails() {
+        assert!(parse_fails("x -y;"));
+        assert!(parse_fails("v[2] 1, -\"s\";"));
+    }
+
+    #[test]
+    fn negative_case_value() {
+        let tokens = lex("main() { switch x { case -1: return(1); case 2: ; } }");
+        let mut parser = Parser::new(&tokens);
+        let prog = parser.parse_program().unwrap();
+        let Item::Function(f) = &prog.items[0] else { panic!("expected function") };
+        let Stmt::Block { statements } = f.body.as_ref() else { panic!("expected block") };
+        let Stmt::Switch { cases, .. } = &statements[0] else { panic!("expected switch") };
+        let values: Vec<_> = cases.iter().map(|c| match c {
+            Stmt::Case { value, .. } => value.const_value(),
+            _ => panic!("expected case"),
+        }).collect();
+        assert_eq!(values, vec![Some(-1), Some(2)]);
+    }
+
+    #[test]
+    fn subtraction_without_spaces() {
+        let tokens = lex("main() { x = a-1; y = 3-1-1; }");
+        let mut parser = Parser::new(&tokens);
+        let prog = parser.parse_program().unwrap();
+        let Item::Function(f) = &prog.items[0] else { panic!("expected function") };
+        let Stmt::Block { statements } = f.body.as_ref() else { panic!("expected block") };
+        let Stmt::Expression { expression } = &statements[0] else { panic!("expected expression") };
+        let Expr::Assign { value, .. } = expression.as_ref() else { panic!("expected assign") };
+        assert!(matches!(value.as_ref(), Expr::Binary { .. }));
+        //left associative: (3 - 1) - 1
+        let Stmt::Expression { expression } = &statements[1] else { panic!("expected expression") };
+        let Expr::Assign { value, .. } = expression.as_ref() else { panic!("expected assign") };
+        assert_eq!(value.const_value(), Some(1));
+    }
+
     #[test]
     fn multiple_globals_comma_separated() {
         let tokens = lex("a, b, c;");
@@ -618,6 +877,21 @@ mod parser_tests {
         ));
     }
 
+    #[test]
+    fn switch_body_is_a_statement_list() {
+        // several statements per case fall through; case labels only the statement after it
+        let tokens = lex("main() { switch x { case 1: a; b; case 2: case 3: c; } }");
+        let mut parser = Parser::new(&tokens);
+        let prog = parser.parse_program().unwrap();
+        let Item::Function(f) = &prog.items[0] else { panic!("expected function") };
+        let Stmt::Block { statements } = f.body.as_ref() else { panic!("expected block") };
+        let Stmt::Switch { cases, .. } = &statements[0] else { panic!("expected switch") };
+        assert_eq!(cases.len(), 3);
+        assert!(matches!(&cases[1], Stmt::Expression { .. }));
+        let Stmt::Case { body, .. } = &cases[2] else { panic!("expected case") };
+        assert!(matches!(body.as_ref(), Stmt::Case { .. }));
+    }
+
     // ---- compound / edge-case assignments ----
 
     #[test]
@@ -747,18 +1021,13 @@ mod e2e_tests {
     //! End-to-end: lex → parse, testing that the pipeline handles edge-case
     //! source inputs without panics or excessive resource usage.
 
-    use b::lexer::scanner::Scanner;
-    use b::lexer::token::*;
+    use b::lexer::lex_all;
     use b::parser::Parser;
 
     fn lex_and_parse(src: &str) -> bool {
-        let tokens: Result<Vec<_>, _> = Scanner::new(src).collect();
-        let Ok(mut tokens) = tokens else { return false };
-        tokens.push(SpannedToken {
-            token: Token::EOF,
-            span: b::common::span::Span::default(),
-        });
-        let mut parser = Parser::new(&tokens);
+        let lexed = lex_all(src);
+        if !lexed.errors.is_empty() { return false };
+        let mut parser = Parser::new(&lexed.tokens);
         parser.parse_program().is_ok()
     }
 
@@ -832,7 +1101,7 @@ mod e2e_tests {
         // A program of just global variable declarations with no names should fail.
         let src = ";;;";
         // This should fail or produce an empty program. Either way, no panic.
-        let _ = lex_and_parse(&src);
+        let _ = lex_and_parse(src);
     }
 
     #[test]
@@ -0,0 +1,247 @@
+//! Running programs with the tree-walking interpreter.
+
+mod common;
+
+#[cfg(test)]
+mod interp_tests {
+    use b::common::span::Span;
+    use b::interp::{Interpreter, RuntimeError};
+    use b::ir::Word;
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+    use b::sema::analyze;
+
+    use crate::common::PRINTF;
+
+    // result of main() and everything it printed
+    fn run_with_input(src: &str, input: &str) -> (Result<Word, RuntimeError>, String) {
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        let mut parser = Parser::new(&lexed.tokens);
+        let program = parser.parse_program().expect("parse error in test input");
+        let sema = analyze(&program);
+        assert_eq!(sema.errors, vec![], "sema error in test input");
+        let mut interpreter = Interpreter::new(&program, &sema).unwrap().with_input(input);
+        let result = interpreter.run();
+        (result, String::from_utf8(interpreter.output().to_vec()).unwrap())
+    }
+
+    fn run(src: &str) -> (Result<Word, RuntimeError>, String) {
+        run_with_input(src, "")
+    }
+
+    fn span_of(src: &str, text: &str) -> Span {
+        let start = src.find(text).unwrap() as u32;
+        Span { start, end: start + text.len() as u32 }
+    }
+
+    const PRINTN: &str = "
+printn(n) {
+  auto a;
+  if (n < 0) { putchar('-'); n = -n; }
+  if (a = n / 10) printn(a);
+  putchar(n % 10 + '0');
+}
+";
+
+    #[test]
+    fn recursion_and_arithmetic() {
+        let src = "fact(n) return(n <= 1 ? 1 : n * fact(n - 1)); main() return(fact(10));";
+        assert_eq!(run(src).0, Ok(3628800));
+    }
+
+    #[test]
+    fn output_through_putchar() {
+        let src = format!("{}main() {{ printn(-1230); putchar('*n'); putchar('ok'); }}", PRINTN);
+        assert_eq!(run(&src), (Ok(0), "-1230\nok".to_string()));
+    }
+
+    #[test]
+    fn getchar_reads_input_then_end_of_file() {
+        let src = "main() { auto c; while ((c = getchar()) != '*e') putchar(c == 'a' ? 'A' : c); }";
+        assert_eq!(run_with_input(src, "banana").1, "bAnAnA");
+    }
+
+    #[test]
+    fn globals_vectors_and_strings() {
+        let src = "
+v[3] 10, 20;
+n 7;
+s \"hi\";
+main() {
+  extrn v, n, s;
+  v[3] = n;
+  return(v[0] + v[1] + v[2] + v[3] + (s[0] & 0377));
+}";
+        // the vector has 4 words: two initialized, the rest zero
+        assert_eq!(run(src).0, Ok(10 + 20 + 7 + 'h' as Word));
+    }
+
+    #[test]
+    fn pointers_reach_autos_and_vectors() {
+        let src = "
+swap(a, b) { auto t; t = *a; *a = *b; *b = t; }
+main() {
+  auto x, y, v[4], p;
+  x = 1; y = 2;
+  swap(&x, &y);
+  p = v;
+  *p++ = 5; *p++ = 6;
+  p[0] = 7;
+  return(x * 1000 + y * 100 + v[0] + v[1] + v[2] + (p - v));
+}";
+        assert_eq!(run(src).0, Ok(2000 + 100 + 5 + 6 + 7 + 2));
+    }
+
+    #[test]
+    fn compound_assignment_and_increments() {
+        let src = "main() { auto x, y; x = 5; x =+ 3; x =* 2; y = x++; y =- ++x; x =<< 1; return(x * 100 + y); }";
+        // x: 5, 8, 16, 17 (y = 16), 18 (y = -2), 36
+        assert_eq!(run(src).0, Ok(36 * 100 - 2));
+    }
+
+    #[test]
+    fn goto_forward_backward_and_computed() {
+        let src = "
+main() {
+  auto i, s, t;
+  i = s = 0;
+loop:
+  s =+ i;
+  if (++i < 5) goto loop;
+  t = s > 5 ? big : small;
+  goto t;
+small:
+  return(-1);
+big:
+  return(s);
+}";
+        assert_eq!(run(src).0, Ok(10));
+    }
+
+    #[test]
+    fn goto_into_a_loop_body_keeps_looping() {
+        let src = "
+main() {
+  auto i;
+  i = 0;
+  goto inside;
+  while (i < 10) {
+    i =+ 100;
+  inside:
+    i++;
+  }
+  return(i);
+}";
+        // enters with i = 0, increments to 1, then loops: 101 ... stops once >= 10
+        assert_eq!(run(src).0, Ok(102));
+    }
+
+    #[test]
+    fn switch_falls_through_and_defaults() {
+        let src = "
+f(x) {
+  auto r;
+  r = 0;
+  switch (x) {
+  case 1: r =+ 1;
+  case 2: r =+ 10;
+    goto out;
+  case 'a':
+    switch (x) { case 97: r =+ 1000; }
+    r =+ 100;
+  default: r =+ 10000;
+  }
+out:
+  return(r);
+}
+main() {
+  extrn printn;
+  printn(f(1)); putchar(' ');
+  printn(f(2)); putchar(' ');
+  printn(f(3)); putchar(' ');
+  printn(f('a'));
+}
+";
+        let src = format!("{}{}", PRINTN, src);
+        assert_eq!(run(&src).1, "11 10 10000 11100");
+    }
+
+    #[test]
+    fn switch_enters_the_middle_of_a_loop() {
+        // Duff's device: case 1 runs first, then the whole loop body once more
+        let src = "main() { auto r; r = 0; switch (1) { while (r < 10) { r =+ 10; case 1: r =+ 1; } } printn(r); }";
+        assert_eq!(run(&format!("{}{}", PRINTN, src)).1, "12");
+    }
+
+    #[test]
+    fn missing_arguments_are_zero() {
+        assert_eq!(run("f(a, b) return(b); main() return(f(1) + 1);").0, Ok(1));
+    }
+
+    #[test]
+    fn callees_walk_their_arguments_through_a_pointer() {
+        assert_eq!(run(PRINTF), (Ok(0), "1 -2 three fo 6 7 8 9!\n11 and 12, 100%%\n".to_string()));
+    }
+
+    #[test]
+    fn null_pointer_faults_at_the_access() {
+        let src = "main() { auto p; p = 0; return(*p + 1); }";
+        let (result, _) = run(src);
+        assert_eq!(result, Err(RuntimeError::OutOfBounds { address: 0, span: span_of(src, "*p") }));
+    }
+
+    #[test]
+    fn indexing_past_the_frame_faults() {
+        let src = "main() { auto v[2]; v[1 << 30] = 1; }";
+        let (result, _) = run(src);
+        assert!(matches!(result, Err(RuntimeError::OutOfBounds { span, .. }) if span == span_of(src, "v[1 << 30")));
+    }
+
+    #[test]
+    fn calling_a_number_is_an_error() {
+        let src = "main() { auto f; f = 42; f(1); }";
+        let (result, _) = run(src);
+        assert_eq!(result, Err(RuntimeError::NotAFunction { value: 42, span: span_of(src, "f(1") }));
+    }
+
+    #[test]
+    fn functions_are_values() {
+        let src = "twice(f, x) return(f(f(x))); inc(x) return(x + 1); main() { extrn inc; return(twice(inc, 5)); }";
+        assert_eq!(run(src).0, Ok(7));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let src = "main() { auto z; z = 0; return(7 % z); }";
+        assert_eq!(run(src).0, Err(RuntimeError::DivisionByZero { span: span_of(src, "7 % z") }));
+    }
+
+    #[test]
+    fn runaway_recursion_overflows() {
+        let src = "f(n) return(f(n + 1)); main() f(0);";
+        let (result, _) = run(src);
+        assert!(matches!(result, Err(RuntimeError::StackOverflow { span }) if span == span_of(src, "f(n + 1")));
+    }
+
+    #[test]
+    fn unknown_library_function() {
+        let src = "main() fprintf(\"x\");";
+        let (result, _) = run(src);
+        assert_eq!(result, Err(RuntimeError::UnresolvedImport { name: "fprintf".to_string(), span: span_of(src, "fprintf(\"x\"") }));
+    }
+
+    #[test]
+    fn runtime_errors_render_as_diagnostics() {
+        use b::common::source_map::{LineIndex, SourceMap};
+        use b::diagnostics::render::{ColorChoice, Emitter};
+        let src = "main() { auto p; p = 0; *p = 1; }";
+        let mut sources = SourceMap::new();
+        let file = sources.add_file("null.b", src, LineIndex::new(src));
+        let (result, _) = run(src);
+        let diagnostic = result.unwrap_err().to_diagnostic(file);
+        let rendered = Emitter::new(&sources, ColorChoice::Never).render(&diagnostic);
+        assert!(rendered.starts_with("error[E0301]: no memory at word address 0"), "{}", rendered);
+        assert!(rendered.contains("null.b:1:25"), "{}", rendered);
+    }
+}
@@ -0,0 +1,599 @@
+//! Lowering to the three-address IR.
+
+#[cfg(test)]
+mod lower_tests {
+    use b::ir::lower::lower;
+    use b::ir::*;
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+    use b::sema::analyze;
+
+    fn lower_src(src: &str) -> Module {
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        let mut parser = Parser::new(&lexed.tokens);
+        let program = parser.parse_program().expect("parse error in test input");
+        let sema = analyze(&program);
+        assert_eq!(sema.errors, vec![], "sema error in test input");
+        lower(&program, &sema)
+    }
+
+    fn instrs(f: &Function) -> impl Iterator<Item = &Instr> {
+        f.blocks.iter().flat_map(|b| &b.instrs)
+    }
+
+    #[test]
+    fn constants_fold_and_locals_live_in_the_frame() {
+        let module = lower_src("main() { auto x; x = 2 * 3 + 1; return(x); }");
+        let f = module.function("main").unwrap();
+        assert_eq!((f.params, f.frame_size), (0, 1));
+        let stored: Vec<Operand> = instrs(f).filter_map(|i| match i {
+            Instr::Store { value, .. } => Some(*value),
+            _ => None,
+        }).collect();
+        assert_eq!(stored, vec![Operand::Const(7)]);
+        assert!(instrs(f).any(|i| matches!(i, Instr::LocalAddr { offset: 0, .. })));
+        assert!(matches!(f.blocks[0].term, Terminator::Return(Operand::Reg(_))));
+    }
+
+    #[test]
+    fn params_take_the_first_frame_words() {
+        let module = lower_src("f(a, b) { auto c; c = b; }");
+        let f = module.function("f").unwrap();
+        assert_eq!((f.params, f.frame_size), (2, 3));
+        let offsets: Vec<u32> = instrs(f).filter_map(|i| match i {
+            Instr::LocalAddr { offset, .. } => Some(*offset),
+            _ => None,
+        }).collect();
+        // address of c, then of b
+        assert_eq!(offsets, vec![2, 1]);
+    }
+
+    #[test]
+    fn auto_vector_points_at_storage_after_the_slots() {
+        let module = lower_src("f() { auto n, v[3]; v[2] = 5; }");
+        let f = module.function("f").unwrap();
+        // n, v, then 4 words for v[0]..v[3]
+        assert_eq!(f.frame_size, 6);
+        assert!(matches!(&f.blocks[0].instrs[..3], [
+            Instr::LocalAddr { offset: 2, .. }, Instr::LocalAddr { offset: 1, .. }, Instr::Store { .. },
+        ]));
+    }
+
+    #[test]
+    fn while_loop_has_a_back_edge() {
+        let module = lower_src("f() { auto i; while (i < 10) i++; }");
+        let f = module.function("f").unwrap();
+        let Terminator::Jump(head) = f.blocks[0].term else { panic!("entry should fall into the loop head") };
+        let Terminator::Branch { then_block, else_block, .. } = &f.block(head).term else { panic!("expected branch") };
+        assert_eq!(f.block(*then_block).term, Terminator::Jump(head));
+        assert_eq!(f.block(*else_block).term, Terminator::Return(Operand::Const(0)));
+    }
+
+    #[test]
+    fn switch_cases_fall_through() {
+        let module = lower_src("f(c) { switch c { case 'a': c = 1; case -2: c = 2; default: c = 3; } return(c); }");
+        let f = module.function("f").unwrap();
+        let Terminator::Switch { cases, default, .. } = &f.blocks[0].term else { panic!("expected switch") };
+        assert_eq!(cases.iter().map(|&(v, _)| v).collect::<Vec<_>>(), vec![97, -2]);
+        // each arm jumps into the next one
+        assert_eq!(f.block(cases[0].1).term, Terminator::Jump(cases[1].1));
+        assert_eq!(f.block(cases[1].1).term, Terminator::Jump(*default));
+    }
+
+    #[test]
+    fn switch_without_default_goes_to_exit() {
+        let module = lower_src("f(c) { switch c { case 1: return(1); } return(0); }");
+        let f = module.function("f").unwrap();
+        let Terminator::Switch { default, .. } = &f.blocks[0].term else { panic!("expected switch") };
+        assert_eq!(f.block(*default).term, Terminator::Return(Operand::Const(0)));
+    }
+
+    #[test]
+    fn gotos_and_labels() {
+        let module = lower_src("f() { auto p; top: p = top; goto top; goto p; }");
+        let f = module.function("f").unwrap();
+        let top = f.blocks.iter().position(|b| b.label.as_deref() == Some("top")).unwrap() as u32;
+        assert_eq!(f.blocks[0].term, Terminator::Jump(BlockId(top)));
+        assert_eq!(f.blocks[top as usize].term, Terminator::Jump(BlockId(top)));
+        assert!(instrs(f).any(|i| matches!(i, Instr::LabelAddr { block, .. } if block.0 == top)));
+        assert!(f.blocks.iter().any(|b| matches!(&b.term, Terminator::IndirectJump { targets, .. } if *targets == vec![BlockId(top)])));
+    }
+
+    #[test]
+    fn compound_assignment_loads_operates_and_stores() {
+        let module = lower_src("f(x) { x =<< 2; }");
+        let f = module.function("f").unwrap();
+        assert!(matches!(&f.blocks[0].instrs[..], [
+            Instr::LocalAddr { .. }, Instr::Load { .. },
+            Instr::Binary { op: BinaryOp::Shl, rhs: Operand::Const(2), .. }, Instr::Store { .. },
+        ]));
+    }
+
+    #[test]
+    fn postfix_yields_the_old_value() {
+        let module = lower_src("f(x) return(x++);");
+        let f = module.function("f").unwrap();
+        let [_, Instr::Load { dest: old, .. }, Instr::Binary { op: BinaryOp::Add, .. }, Instr::Store { .. }] = &f.blocks[0].instrs[..] else {
+            panic!("unexpected instructions {:?}", f.blocks[0].instrs);
+        };
+        assert_eq!(f.blocks[0].term, Terminator::Return(Operand::Reg(*old)));
+    }
+
+    #[test]
+    fn calls_are_direct_for_functions_and_imports() {
+        let module = lower_src("g(); f(h) { putchar('x'); g(); h(1, 2); }");
+        let f = module.function("f").unwrap();
+        let callees: Vec<&Callee> = instrs(f).filter_map(|i| match i {
+            Instr::Call { callee, .. } => Some(callee),
+            _ => None,
+        }).collect();
+        assert!(matches!(callees[..], [Callee::Direct(a), Callee::Direct(b), Callee::Indirect(_)] if a == "putchar" && b == "g"));
+        assert_eq!(module.imports, vec!["putchar".to_string()]);
+    }
+
+    #[test]
+    fn globals_and_strings() {
+        let module = lower_src("x -1; v[2] 'a', \"hi\"; s \"hi\"; f() { extrn s; return(s); }");
+        assert_eq!(module.globals[0], Global { name: "x".to_string(), vector: None, init: vec![Init::Word(-1)] });
+        assert_eq!(module.globals[1], Global { name: "v".to_string(), vector: Some(3), init: vec![Init::Word(97), Init::String(0)] });
+        // identical literals share one string
+        assert_eq!(module.globals[2].init, vec![Init::String(0)]);
+        assert_eq!(module.strings, vec![b"hi\x04".to_vec()]);
+        assert_eq!(pack_string(&module.strings[0]), vec![0x04_69_68]);
+    }
+
+    #[test]
+    fn linking_shifts_string_indices_and_drops_resolved_imports() {
+        let main = lower_src("s \"a\"; main() { extrn s, t; f(s); g(\"b\"); return(t); }");
+        let lib = lower_src("t \"c\"; f(x) { putchar(x); return(\"c\"); }");
+        let module = link::link(vec![main, lib]);
+        assert_eq!(module.strings, vec![b"a\x04".to_vec(), b"b\x04".to_vec(), b"c\x04".to_vec()]);
+        assert_eq!(module.globals[1].init, vec![Init::String(2)]);
+        let f = module.function("f").unwrap();
+        assert!(instrs(f).any(|i| matches!(i, Instr::StringAddr { index: 2, .. })));
+        assert_eq!(module.imports, vec!["g".to_string(), "putchar".to_string()]);
+    }
+
+    #[test]
+    fn ternary_joins_into_one_register() {
+        let module = lower_src("f(c) return(c ? 1 : 2);");
+        let f = module.function("f").unwrap();
+        let copies: Vec<VReg> = instrs(f).filter_map(|i| match i {
+            Instr::Copy { dest, .. } => Some(*dest),
+            _ => None,
+        }).collect();
+        assert_eq!(copies.len(), 2);
+        assert_eq!(copies[0], copies[1]);
+    }
+
+    #[test]
+    fn example_program_lowers() {
+        let source = std::fs::read_to_string("src/examples/printer_test3.b").unwrap();
+        let module = lower_src(&source);
+        for f in &module.functions {
+            assert!(!f.blocks.is_empty());
+            // every branch target exists
+            for block in &f.blocks {
+                assert!(block.term.successors().iter().all(|b| (b.0 as usize) < f.blocks.len()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod text_tests {
+    use b::ir::lower::lower;
+    use b::ir::text::{parse, print, ParseIrError};
+    use b::ir::*;
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+    use b::sema::analyze;
+    use std::process::Command;
+
+    const HAND_WRITTEN: &str = "\
+import putchar
+string \"a\\22b\\04\"
+global x
+global n = -3
+global v[4] = 97, str 0
+
+func main params 2 frame 3 {
+bb0:
+  %0 = local 1
+  %1 = load %0
+  %2 = neg %1
+  %3 = label bb1
+  %4 = global putchar
+  %5 = call *%4(%2, 7)
+  %6 = call putchar()
+  store %0, %5
+  switch %1 [1: bb1, -1: bb2] default bb2
+bb1 top:
+  %7 = str 0
+  br %7, bb2, bb1
+bb2:
+  ijump %3 [bb1]
+}
+";
+
+    fn lower_src(src: &str) -> Module {
+        let lexed = lex_all(src);
+        let program = Parser::new(&lexed.tokens).parse_program().expect("parse error in test input");
+        let sema = analyze(&program);
+        assert_eq!(sema.errors, vec![]);
+        lower(&program, &sema)
+    }
+
+    #[test]
+    fn hand_written_text_round_trips() {
+        let module = parse(HAND_WRITTEN).unwrap();
+        assert_eq!(print(&module), HAND_WRITTEN);
+        assert_eq!(module.strings, vec![b"a\"b\x04".to_vec()]);
+        let main = module.function("main").unwrap();
+        assert_eq!((main.params, main.frame_size, main.reg_count), (2, 3, 8));
+        assert_eq!(main.blocks[1].label.as_deref(), Some("top"));
+    }
+
+    #[test]
+    fn lowered_program_round_trips() {
+        let module = lower_src("
+            s \"hi*n\";
+            t[2] 1, -1;
+            main() {
+                extrn s, t;
+                auto i, buf[4];
+                i = 0;
+                loop: if (i < 2) { buf[i] = t[i] ? s : 0; i =+ 1; goto loop; }
+                switch i { case 1: i--; case 2: default: return(f(i)); }
+            }
+            f(x) return(-x);
+        ");
+        let text = print(&module);
+        let parsed = parse(&text).unwrap();
+        assert_eq!(parsed, module);
+        assert_eq!(print(&parsed), text);
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_ignored() {
+        let module = parse("; a module\n\nfunc f params 0 frame 0 {  ; header\nbb0:\n  ret 1 ; done\n}\n").unwrap();
+        assert_eq!(print(&module), "func f params 0 frame 0 {\nbb0:\n  ret 1\n}\n");
+    }
+
+    #[test]
+    fn errors_report_their_line() {
+        let err = |text: &str| parse(text).unwrap_err();
+        assert_eq!(err("func f params 0 frame 0 {\nbb0:\n  %0 = frob 1\n  ret 0\n}"),
+            ParseIrError { line: 3, message: "unknown instruction `frob`".to_string() });
+        assert_eq!(err("func f params 0 frame 0 {\nbb1:\n  ret 0\n}").line, 2);
+        assert_eq!(err("func f params 0 frame 0 {\nbb0:\n  %0 = copy 1\n}").message, "block has no terminator");
+        assert_eq!(err("func f params 0 frame 0 {\nbb0:\n  ret 0\n").line, 1);
+        assert_eq!(err("global v[2] = 1,").line, 1);
+    }
+
+    #[test]
+    fn driver_emits_ir() {
+        let path = std::env::temp_dir().join(format!("b-ir-{}.b", std::process::id()));
+        std::fs::write(&path, "main() return(2 + 3);").unwrap();
+        let out = Command::new(env!("CARGO_BIN_EXE_b")).arg("--emit=ir").arg(&path).output().unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(out.status.code(), Some(0));
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), "func main params 0 frame 0 {\nbb0:\n  ret 5\n}\n");
+    }
+}
+
+#[cfg(test)]
+mod cfg_tests {
+    use b::ir::cfg::{natural_loops, remove_unreachable, to_dot, Cfg, Dominators};
+    use b::ir::text::{parse, print};
+    use b::ir::*;
+
+    fn function(text: &str) -> Function {
+        parse(text).unwrap().functions.remove(0)
+    }
+
+    fn ids(blocks: &[u32]) -> Vec<BlockId> {
+        blocks.iter().map(|&b| BlockId(b)).collect()
+    }
+
+    // bb0 -> bb1 | bb2 -> bb3, with bb4 unreachable
+    const DIAMOND: &str = "
+func f params 1 frame 1 {
+bb0:
+  br 1, bb1, bb2
+bb1:
+  jump bb3
+bb2:
+  jump bb3
+bb3:
+  ret 0
+bb4:
+  jump bb3
+}";
+
+    // bb1 is an outer loop header, bb2 an inner one with two back edges
+    const NESTED: &str = "
+func f params 0 frame 0 {
+bb0:
+  jump bb1
+bb1:
+  br 1, bb2, bb5
+bb2:
+  br 1, bb3, bb4
+bb3:
+  br 1, bb2, bb6
+bb4:
+  jump bb1
+bb5:
+  ret 0
+bb6:
+  jump bb2
+}";
+
+    #[test]
+    fn edges_and_reverse_postorder() {
+        let f = function(DIAMOND);
+        let cfg = Cfg::new(&f);
+        assert_eq!(cfg.successors(BlockId(0)), &ids(&[1, 2])[..]);
+        assert_eq!(cfg.predecessors(BlockId(3)), &ids(&[1, 2, 4])[..]);
+        assert_eq!(cfg.reverse_postorder(), ids(&[0, 2, 1, 3]));
+    }
+
+    #[test]
+    fn dominators_and_frontiers() {
+        let f = function(DIAMOND);
+        let cfg = Cfg::new(&f);
+        let doms = Dominators::new(&cfg);
+        assert_eq!(doms.idom(BlockId(0)), None);
+        assert_eq!(doms.idom(BlockId(3)), Some(BlockId(0)));
+        assert_eq!(doms.idom(BlockId(4)), None);
+        assert!(doms.dominates(BlockId(0), BlockId(3)));
+        assert!(!doms.dominates(BlockId(1), BlockId(3)));
+        assert_eq!(doms.children(BlockId(0)), &ids(&[2, 1, 3])[..]);
+
+        let frontiers = doms.frontiers(&cfg);
+        assert_eq!(frontiers[1], ids(&[3]));
+        assert_eq!(frontiers[2], ids(&[3]));
+        assert!(frontiers[0].is_empty() && frontiers[3].is_empty());
+    }
+
+    #[test]
+    fn loop_frontiers_include_the_header() {
+        let f = function(NESTED);
+        let cfg = Cfg::new(&f);
+        let frontiers = Dominators::new(&cfg).frontiers(&cfg);
+        assert_eq!(frontiers[4], ids(&[1]));
+        assert_eq!(frontiers[6], ids(&[2]));
+        assert_eq!(frontiers[2], ids(&[1, 2]));
+    }
+
+    #[test]
+    fn back_edge_to_the_entry() {
+        let f = function("func f params 0 frame 0 {\nbb0:\n  br 1, bb1, bb2\nbb1:\n  jump bb0\nbb2:\n  ret 0\n}");
+        let cfg = Cfg::new(&f);
+        let doms = Dominators::new(&cfg);
+        let frontiers = doms.frontiers(&cfg);
+        assert_eq!(frontiers[0], ids(&[0]));
+        assert_eq!(frontiers[1], ids(&[0]));
+        assert_eq!(natural_loops(&cfg, &doms)[0].blocks, ids(&[0, 1]));
+    }
+
+    #[test]
+    fn nested_natural_loops() {
+        let f = function(NESTED);
+        let cfg = Cfg::new(&f);
+        let doms = Dominators::new(&cfg);
+        let loops = natural_loops(&cfg, &doms);
+        assert_eq!(loops.len(), 2);
+        assert_eq!((loops[0].header, &loops[0].latches, &loops[0].blocks), (BlockId(1), &ids(&[4]), &ids(&[1, 2, 3, 4, 6])));
+        assert_eq!((loops[1].header, &loops[1].latches, &loops[1].blocks), (BlockId(2), &ids(&[3, 6]), &ids(&[2, 3, 6])));
+        assert!(loops[0].contains(BlockId(6)) && !loops[1].contains(BlockId(4)));
+    }
+
+    #[test]
+    fn unreachable_blocks_are_removed_and_renumbered() {
+        let mut f = function("
+func f params 0 frame 0 {
+bb0:
+  %0 = label bb3
+  jump bb2
+bb1:
+  ret 1
+bb2 done:
+  ret %0
+bb3 kept:
+  ret 3
+}");
+        assert_eq!(remove_unreachable(&mut f), 1);
+        let module = Module { functions: vec![f], ..Module::default() };
+        assert_eq!(print(&module), "\
+func f params 0 frame 0 {
+bb0:
+  %0 = label bb2
+  jump bb1
+bb1 done:
+  ret %0
+bb2 kept:
+  ret 3
+}
+");
+    }
+
+    #[test]
+    fn dot_labels_branch_edges() {
+        let f = function("
+func f params 1 frame 1 {
+bb0:
+  switch 1 [1: bb1, -2: bb2] default bb2
+bb1:
+  br 0, bb2, bb2
+bb2:
+  ret 0
+}");
+        let dot = to_dot(&f);
+        assert!(dot.starts_with("digraph \"f\" {\n"));
+        assert!(dot.contains("  bb0 -> bb1 [label=\"1\"];\n  bb0 -> bb2 [label=\"-2\"];\n  bb0 -> bb2 [label=\"default\"];\n"));
+        assert!(dot.contains("  bb1 -> bb2 [label=\"T\"];\n"));
+        assert!(dot.contains("  bb2 [label=\"bb2:\\l  ret 0\\l\"];\n"));
+    }
+}
+
+#[cfg(test)]
+mod ssa_tests {
+    use b::ir::lower::lower;
+    use b::ir::ssa::{from_ssa, to_ssa};
+    use b::ir::text::{parse, print, print_function};
+    use b::ir::*;
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+    use b::sema::analyze;
+    use rustc_hash::FxHashSet;
+
+    fn lower_fn(src: &str) -> Function {
+        let lexed = lex_all(src);
+        let mut parser = Parser::new(&lexed.tokens);
+        let program = parser.parse_program().expect("parse error in test input");
+        let sema = analyze(&program);
+        assert_eq!(sema.errors, vec![], "sema error in test input");
+        lower(&program, &sema).functions.remove(0)
+    }
+
+    fn instrs(f: &Function) -> impl Iterator<Item = &Instr> {
+        f.blocks.iter().flat_map(|b| &b.instrs)
+    }
+
+    fn phis(block: &Block) -> Vec<&Instr> {
+        block.instrs.iter().filter(|i| matches!(i, Instr::Phi { .. })).collect()
+    }
+
+    fn assert_single_assignment(f: &Function) {
+        let mut seen = FxHashSet::default();
+        for dest in instrs(f).filter_map(Instr::dest) {
+            assert!(seen.insert(dest), "{} written twice in\n{}", dest, print_function(f));
+        }
+    }
+
+    #[test]
+    fn loop_variables_become_phis() {
+        let mut f = lower_fn("f() { auto i, s; i = 0; s = 0; while (i < 10) { s =+ i; i++; } return(s); }");
+        assert_eq!(to_ssa(&mut f), vec![0, 1]);
+        assert_single_assignment(&f);
+        assert!(!instrs(&f).any(|i| matches!(i, Instr::LocalAddr { .. } | Instr::Load { .. } | Instr::Store { .. })));
+        // the loop header merges both variables from the entry and the latch
+        let header = f.blocks.iter().find(|b| !phis(b).is_empty()).unwrap();
+        assert_eq!(phis(header).len(), 2);
+        for phi in phis(header) {
+            let Instr::Phi { args, .. } = phi else { unreachable!() };
+            assert_eq!(args.len(), 2);
+            assert!(args.contains(&(BlockId(0), Operand::Const(0))));
+        }
+    }
+
+    #[test]
+    fn address_taken_autos_stay_in_memory() {
+        let mut f = lower_fn("f() { auto x, p; p = &x; *p = 4; return(x); }");
+        assert_eq!(to_ssa(&mut f), vec![1]);
+        assert!(instrs(&f).any(|i| matches!(i, Instr::LocalAddr { offset: 0, .. })));
+        assert!(!instrs(&f).any(|i| matches!(i, Instr::LocalAddr { offset: 1, .. })));
+    }
+
+    #[test]
+    fn params_and_vector_storage_are_not_promoted() {
+        // frame: a, v, then v's storage
+        let mut f = lower_fn("f(a) { auto v[2]; v[1] = a; return(v[1]); }");
+        assert_eq!(to_ssa(&mut f), vec![1]);
+        let offsets: Vec<u32> = instrs(&f).filter_map(|i| match i {
+            Instr::LocalAddr { offset, .. } => Some(*offset),
+            _ => None,
+        }).collect();
+        assert_eq!(offsets, vec![2, 0]);
+    }
+
+    #[test]
+    fn ternary_join_becomes_a_phi() {
+        let mut f = lower_fn("f(c) return(c ? 1 : 2);");
+        to_ssa(&mut f);
+        assert_single_assignment(&f);
+        let phis: Vec<&Instr> = f.blocks.iter().flat_map(phis).collect();
+        let [Instr::Phi { args, .. }] = &phis[..] else { panic!("expected one phi: {:?}", phis) };
+        let values: Vec<Operand> = args.iter().map(|&(_, value)| value).collect();
+        assert_eq!(values, vec![Operand::Const(1), Operand::Const(2)]);
+    }
+
+    #[test]
+    fn dead_phis_are_dropped() {
+        let mut f = lower_fn("f(c) { auto x; if (c) x = 1; else x = 2; return(0); }");
+        assert_eq!(to_ssa(&mut f), vec![1]);
+        assert!(f.blocks.iter().all(|b| phis(b).is_empty()));
+    }
+
+    #[test]
+    fn computed_gotos_are_left_alone() {
+        let mut f = lower_fn("f(c) { auto x, t; t = c ? a : b; x = 1; goto t; a: return(x); b: return(0); }");
+        let before = f.clone();
+        assert_eq!(to_ssa(&mut f), vec![]);
+        assert_eq!(f, before);
+    }
+
+    #[test]
+    fn phis_round_trip_through_text() {
+        let text = "\
+func f params 0 frame 0 {
+bb0:
+  jump bb1
+bb1:
+  %0 = phi [bb0: 1, bb1: %1]
+  %1 = phi [bb0: 2, bb1: %0]
+  br %0, bb1, bb2
+bb2:
+  ret %1
+}
+";
+        assert_eq!(print(&parse(text).unwrap()), text);
+    }
+
+    #[test]
+    fn swapping_phis_go_through_a_temporary() {
+        let mut f = parse("
+func f params 0 frame 0 {
+bb0:
+  jump bb1
+bb1:
+  %0 = phi [bb0: 1, bb1: %1]
+  %1 = phi [bb0: 2, bb1: %0]
+  br %0, bb1, bb2
+bb2:
+  ret %1
+}").unwrap().functions.remove(0);
+        from_ssa(&mut f);
+        // bb1 -> bb1 is a critical edge, so its copies get a block of their own
+        assert_eq!(print_function(&f), "\
+func f params 0 frame 0 {
+bb0:
+  %0 = copy 1
+  %1 = copy 2
+  jump bb1
+bb1:
+  br %0, bb3, bb2
+bb2:
+  ret %1
+bb3:
+  %2 = copy %0
+  %0 = copy %1
+  %1 = copy %2
+  jump bb1
+}
+");
+    }
+
+    #[test]
+    fn out_of_ssa_leaves_no_phis() {
+        let mut f = lower_fn("f(n) { auto i, s; i = s = 0; while (i < n) { s = s ? s * i : 1; i++; } return(s); }");
+        to_ssa(&mut f);
+        assert!(instrs(&f).any(|i| matches!(i, Instr::Phi { .. })));
+        from_ssa(&mut f);
+        assert!(!instrs(&f).any(|i| matches!(i, Instr::Phi { .. })));
+        assert!(f.blocks.iter().all(|b| !matches!(b.term, Terminator::Jump(t) if t.0 as usize >= f.blocks.len())));
+    }
+}
@@ -0,0 +1,155 @@
+//! The `--error-format=json` schema. These tests are the schema's documentation:
+//! keys may be added, but existing keys and their meaning must not change.
+
+#[cfg(test)]
+mod json_schema_tests {
+    use b::common::source_map::{FileId, LineIndex, SourceMap};
+    use b::common::span::Span;
+    use b::diagnostics::json::to_json;
+    use b::diagnostics::{Diagnostic, Label, Severity};
+    use b::lexer::scanner::Scanner;
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+
+    fn source_map(src: &str) -> (SourceMap<'_>, FileId) {
+        let mut map = SourceMap::new();
+        let file = map.add_file("main.b", src, LineIndex::new(src));
+        (map, file)
+    }
+
+    #[test]
+    fn lex_error_schema() {
+        let src = "main() {\n  x = 1 @ 2;\n}";
+        let (map, file) = source_map(src);
+        let err = Scanner::new(src).find_map(|r| r.err()).unwrap();
+        assert_eq!(
+            to_json(&err.to_diagnostic(file), &map),
+            concat!(
+                r#"{"severity":"error","code":"E0001","message":"unexpected character `@`","#,
+                r#""file":"main.b","span":{"start":17,"end":18,"line":2,"column":9,"end_line":2,"end_column":10},"#,
+                r#""labels":[{"file":"main.b","span":{"start":17,"end":18,"line":2,"column":9,"end_line":2,"end_column":10},"#,
+                r#""message":"not valid in any B token","primary":true}],"#,
+                r#""notes":[],"help":[],"suggestions":[]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn parse_error_schema_with_suggestion() {
+        let src = "main() { x += 1; }";
+        let (map, file) = source_map(src);
+        let tokens = lex_all(src).tokens;
+        let err = Parser::new(&tokens).parse_program().unwrap_err();
+        assert_eq!(
+            to_json(&err.to_diagnostic(file), &map),
+            concat!(
+                r#"{"severity":"error","code":"E0107","message":"`+=` is not a B operator","#,
+                r#""file":"main.b","span":{"start":12,"end":13,"line":1,"column":13,"end_line":1,"end_column":14},"#,
+                r#""labels":[{"file":"main.b","span":{"start":12,"end":13,"line":1,"column":13,"end_line":1,"end_column":14},"#,
+                r#""message":"expected an expression","primary":true},"#,
+                r#"{"file":"main.b","span":{"start":11,"end":12,"line":1,"column":12,"end_line":1,"end_column":13},"#,
+                r#""message":"parsed as a binary operator","primary":false}],"#,
+                r#""notes":[],"help":["B uses `=+`, not `+=`"],"#,
+                r#""suggestions":[{"file":"main.b","span":{"start":11,"end":13,"line":1,"column":12,"end_line":1,"end_column":14},"#,
+                r#""replacement":"=+","message":"use the B operator"}]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn diagnostic_without_labels_has_null_location() {
+        let (map, _) = source_map("");
+        let diag = Diagnostic::new(Severity::Warning, "no location").with_note("n");
+        assert_eq!(
+            to_json(&diag, &map),
+            r#"{"severity":"warning","code":null,"message":"no location","file":null,"span":null,"labels":[],"notes":["n"],"help":[],"suggestions":[]}"#
+        );
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        let (map, file) = source_map("x");
+        let diag = Diagnostic::error("quote \" backslash \\ newline \n tab \t bell \u{7}")
+            .with_label(Label::primary(file, Span { start: 0, end: 1 }, ""));
+        let json = to_json(&diag, &map);
+        assert!(json.contains(r#""message":"quote \" backslash \\ newline \n tab \t bell \u0007""#));
+    }
+}
+
+#[cfg(test)]
+mod driver_tests {
+    use std::process::Command;
+
+    fn write_source(name: &str, src: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("b-json-{}-{}.b", std::process::id(), name));
+        std::fs::write(&path, src).unwrap();
+        path
+    }
+
+    #[test]
+    fn json_mode_emits_one_line_per_diagnostic() {
+        let path = write_source("lex", "main() { x = @ + $; }");
+        let out = Command::new(env!("CARGO_BIN_EXE_b"))
+            .arg("--error-format=json")
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert_eq!(out.status.code(), Some(1));
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        let lines: Vec<&str> = stderr.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            assert!(line.starts_with(r#"{"severity":"error","code":"E0001""#));
+            assert!(line.contains(&format!(r#""file":"{}""#, path.display())));
+        }
+    }
+
+    #[test]
+    fn clean_file_exits_zero_with_no_output() {
+        let path = write_source("ok", "main() { auto x; x = 1; }");
+        let out = Command::new(env!("CARGO_BIN_EXE_b"))
+            .arg("--error-format=json")
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert_eq!(out.status.code(), Some(0));
+        assert!(out.stderr.is_empty());
+    }
+
+    #[test]
+    fn warnings_are_reported_but_do_not_fail() {
+        let path = write_source("warn", "main() { auto x; x = \"a*qb\"; }");
+        let out = Command::new(env!("CARGO_BIN_EXE_b"))
+            .arg("--error-format=json")
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert_eq!(out.status.code(), Some(0));
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert_eq!(stderr.lines().count(), 1);
+        assert!(stderr.starts_with(r#"{"severity":"warning","code":"W0001","message":"unknown escape `*q`""#));
+    }
+
+    #[test]
+    fn name_errors_are_reported_after_a_clean_parse() {
+        let path = write_source("sema", "main() { x = 1; }");
+        let out = Command::new(env!("CARGO_BIN_EXE_b"))
+            .arg("--error-format=json")
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert_eq!(out.status.code(), Some(1));
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert!(stderr.starts_with(r#"{"severity":"error","code":"E0201","message":"undefined name `x`""#));
+    }
+
+    #[test]
+    fn unknown_error_format_is_usage_error() {
+        let out = Command::new(env!("CARGO_BIN_EXE_b"))
+            .arg("--error-format=xml")
+            .arg("x.b")
+            .output()
+            .unwrap();
+        assert_eq!(out.status.code(), Some(2));
+    }
+}
@@ -0,0 +1,139 @@
+//! libb, the B library, under the interpreter, the vm and in linked executables.
+
+mod common;
+
+#[cfg(test)]
+mod libb_tests {
+    use std::process::Command;
+
+    use b::codegen::link::link;
+    use b::codegen::{c, x86_64};
+    use b::interp::Interpreter;
+    use b::ir::lower::lower;
+    use b::ir::{Module, Word};
+    use b::lexer::lex_all;
+    use b::libb;
+    use b::parser::Parser;
+    use b::sema::analyze;
+    use b::vm::compile::compile;
+    use b::vm::machine::Vm;
+
+    use crate::common::{self, check_assembly, check_c, execute, file, skip, status};
+
+    // main's result and output under the interpreter, checked against the vm, and the IR
+    fn run(src: &str, input: &str) -> (Word, String, Module) {
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        let mut parser = Parser::new(&lexed.tokens);
+        let program = parser.parse_program().expect("parse error in test input");
+        let sema = analyze(&program);
+        assert_eq!(sema.errors, vec![], "sema error in test input");
+        let mut interpreter = Interpreter::new(&program, &sema).unwrap().with_input(input);
+        let result = interpreter.run().expect("interpreter fault in test input");
+        let output = String::from_utf8(interpreter.output().to_vec()).unwrap();
+        let module = lower(&program, &sema);
+        let bytecode = compile(&module);
+        let mut vm = Vm::new(&bytecode).unwrap().with_input(input);
+        assert_eq!(vm.run(), Ok(result), "vm disagrees with the interpreter");
+        assert_eq!(String::from_utf8(vm.output().to_vec()).unwrap(), output, "vm disagrees with the interpreter");
+        (result, output, module)
+    }
+
+    // builds the program with the x86-64 and the C backend, checking what each generates, and where
+    // it can, runs both against the interpreter
+    fn check_executables(name: &str, src: &str, input: &str) {
+        let (result, output, module) = run(src, input);
+        let (asm, source) = (x86_64::emit(&module), c::emit(&module));
+        check_assembly(&asm);
+        check_c(&source);
+        if !(cfg!(all(target_arch = "x86_64", target_os = "linux")) && common::found("cc")) {
+            return skip(name, "x86-64 C toolchain");
+        }
+        let expected = (status(result), output);
+        let exe = file("libb", name);
+        link(&[&asm, &x86_64::runtime()], &exe).unwrap();
+        assert_eq!(execute(Command::new(&exe), input), expected, "x86-64");
+        let path = file("libb", &format!("{}.c", name));
+        std::fs::write(&path, source).unwrap();
+        let cc = Command::new("cc").args(["-std=c99", "-pedantic", "-Wall", "-Wextra", "-Werror", "-O2", "-o"]).arg(&exe).arg(&path).output().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(cc.status.success(), "cc failed:\n{}", String::from_utf8_lossy(&cc.stderr));
+        assert_eq!(execute(Command::new(&exe), input), expected, "C");
+        std::fs::remove_file(&exe).unwrap();
+    }
+
+    #[test]
+    fn printf_and_printn_format_like_classic_b() {
+        let src = "main() {
+  printf(\"%d %d %o %c%c %s|%x%%*n\", 42, -7, 8, 'h', 'i!', \"str\", 1);
+  printf(\"%d %d %d %d %d %d %d %d %d*n\", 1, 2, 3, 4, 5, 6, 7, 8, 9);
+  printn(255, 16); putchar(' '); printn(-255, 2); putchar(' '); printn(255, 99);
+  return(printn(5, 8));
+}";
+        let (result, output, _) = run(src, "");
+        assert_eq!(result, 5);
+        assert_eq!(output, "42 -7 10 hi! str|%x%%\n1 2 3 4 5 6 7 8 9\nff -11111111 2555");
+        check_executables("printf", src, "");
+    }
+
+    #[test]
+    fn strings_characters_and_vectors() {
+        let src = "main() {
+  auto s, v, w, i, c;
+  s = getvec(3);
+  i = 0;
+  while ((c = getchar()) != '*e') lchar(s, i++, c == ' ' ? '_' : c);
+  lchar(s, i, '*e');
+  printf(\"%s %d %c*n\", s, i, char(s, 1));
+  v = getvec(9);
+  v[9] = 99;
+  rlsevec(v, 9);
+  w = getvec(9);
+  return(w[9] == 0 & v != 0 & w != 0);
+}";
+        let (result, output, _) = run(src, "to be or");
+        assert_eq!((result, output.as_str()), (1, "to_be_or 8 o\n"));
+        check_executables("strings", src, "to be or");
+    }
+
+    #[test]
+    fn exit_ends_the_program_with_its_status() {
+        let src = "f(n) { while (n < 9) { if (n == 3) exit(n * 10); n++; } putchar('no'); } main() { putchar('a'); f(0); return(1); }";
+        let (result, output, _) = run(src, "");
+        assert_eq!((result, output.as_str()), (30, "a"));
+        check_executables("exit", src, "");
+    }
+
+    #[test]
+    fn files_are_written_and_read_back() {
+        let path = file("libb", "data");
+        let src = format!("main() {{
+  auto fd, buf[4], n;
+  fd = open(\"{0}\", 1);
+  if (fd < 3) return(-1);
+  n = write(fd, \"written*n\", 8);
+  if (close(fd) != 0 | close(fd) != -1) return(-2);
+  fd = open(\"{0}\", 0);
+  n = n * 100 + read(fd, buf, 20);
+  lchar(buf, 8, '*e');
+  close(fd);
+  write(1, buf, 7);
+  printf(\" %s*n\", buf);
+  return(open(\"{0}/nowhere\", 0) == -1 ? n : 0);
+}}", path.display());
+        let (result, output, _) = run(&src, "");
+        assert_eq!((result, output.as_str()), (808, "written written\n\n"));
+        check_executables("files", &src, "");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn definitions_in_the_program_replace_the_library() {
+        let src = "printn(n) putchar(n - 12 + 'n'); main() { extrn printn; printn(12); putchar(char(\"ab\", 1)); }";
+        let (_, output, module) = run(src, "");
+        assert_eq!(output, "nb");
+        assert_eq!(module.imports, vec!["putchar".to_string(), "char".to_string()]);
+        assert!(libb::provides("printn") && !libb::provides("fprintf"));
+        check_executables("override", src, "");
+    }
+}
@@ -0,0 +1,141 @@
+//! Panic-mode error recovery: one parse reports every error and still
+//! returns a partial `Program` with `Error` placeholders.
+
+#[cfg(test)]
+mod recovery_tests {
+    use b::ast::pretty_printer::AstPrinter;
+    use b::ast::*;
+    use b::lexer::lex_all;
+    use b::lexer::token::*;
+    use b::parser::{ParseError, Parser};
+
+    fn lex(src: &str) -> Vec<SpannedToken<'_>> {
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        lexed.tokens
+    }
+
+    /// Parse with recovery; return the error count and the S-expression of what parsed.
+    fn recover(src: &str) -> (usize, String) {
+        let tokens = lex(src);
+        let mut parser = Parser::new(&tokens);
+        let output = parser.parse();
+        (output.errors.len(), AstPrinter::new().print_program(&output.program))
+    }
+
+    #[test]
+    fn valid_program_has_no_errors() {
+        let (errors, _) = recover("main() { auto x; x = 1; while (x) x--; }");
+        assert_eq!(errors, 0);
+    }
+
+    #[test]
+    fn five_typos_five_errors() {
+        let src = "
+            main() {
+                a = ;
+                b = 1 2;
+                c = );
+                d = (1;
+                e = * ;
+                f = 1;
+            }";
+        let (errors, printed) = recover(src);
+        assert_eq!(errors, 5);
+        assert_eq!(printed.matches("(error)").count(), 5);
+        assert!(printed.contains("(expr (= f 1))"));
+    }
+
+    #[test]
+    fn errors_are_reported_in_source_order() {
+        let tokens = lex("main() { x = ; y = ; }");
+        let output = Parser::new(&tokens).parse();
+        let starts: Vec<u32> = output.errors.iter().map(|e| e.span().unwrap().start).collect();
+        assert_eq!(starts, vec![13, 19]);
+    }
+
+    #[test]
+    fn recovery_does_not_swallow_closing_brace() {
+        // the bad statement fails on `}`; the while body must still close there
+        let (errors, printed) = recover("main() { while (x) { y = } z = 1; }");
+        assert_eq!(errors, 1);
+        assert!(printed.contains("(expr (= z 1))"));
+    }
+
+    #[test]
+    fn broken_condition_becomes_error_expr() {
+        let (errors, printed) = recover("main() { if (x +) y = 1; else y = 2; }");
+        assert_eq!(errors, 1);
+        assert!(printed.contains("(if (error)"));
+        assert!(printed.contains("(expr (= y 2))"));
+    }
+
+    #[test]
+    fn later_functions_survive_a_broken_head() {
+        let (errors, printed) = recover("f(a b) { return (1); }\ng() { return (2); }\nh() ;");
+        assert_eq!(errors, 1);
+        assert!(!printed.contains("(fn f"));
+        assert!(printed.contains("(fn g ()"));
+        assert!(printed.contains("(fn h ()"));
+    }
+
+    #[test]
+    fn broken_global_skips_to_semicolon() {
+        let tokens = lex("v[3] 1, 2 3; main() { return; }");
+        let output = Parser::new(&tokens).parse();
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(output.program.items.len(), 1);
+        assert!(matches!(output.program.items[0], Item::Function(_)));
+    }
+
+    #[test]
+    fn misplaced_auto_is_reported_but_kept() {
+        let tokens = lex("main() { x = 1; auto y; y = 2; }");
+        let output = Parser::new(&tokens).parse();
+        assert_eq!(output.errors.len(), 1);
+        assert!(matches!(output.errors[0], ParseError::AutoRedecl(_)));
+        let Item::Function(f) = &output.program.items[0] else { panic!("expected function") };
+        let Stmt::Block { statements } = f.body.as_ref() else { panic!("expected block") };
+        assert!(matches!(statements[1], Stmt::Auto { .. }));
+    }
+
+    #[test]
+    fn bad_switch_arm_is_skipped() {
+        let (errors, printed) = recover("main() { switch x { case 1: a = ; case 2: b = 2; } }");
+        assert_eq!(errors, 1);
+        assert!(printed.contains("(case 2"));
+    }
+
+    #[test]
+    fn unclosed_block_at_eof_reports_once() {
+        let tokens = lex("main() { while (x) { if (y) {");
+        let output = Parser::new(&tokens).parse();
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(output.program.items.len(), 1);
+    }
+
+    #[test]
+    fn expression_cut_off_at_eof_reports_once() {
+        // an open paren, a dangling prefix operator, an unfinished call
+        for src in ["main() { (", "main() { x = -", "main() { f(", "main() { if (x) { y = a[f(1, !"] {
+            let tokens = lex(src);
+            let output = Parser::new(&tokens).parse();
+            assert!(matches!(output.errors[..], [ParseError::UnexpectedEOF]), "{}: {:?}", src, output.errors);
+            assert_eq!(output.program.items.len(), 1, "{}", src);
+        }
+    }
+
+    #[test]
+    fn garbage_terminates() {
+        // no sync points at all: must still stop at EOF
+        let (errors, _) = recover(") ) ( ] [ = = ?");
+        assert!(errors >= 1);
+    }
+
+    #[test]
+    fn parse_program_still_returns_first_error() {
+        let tokens = lex("main() { x = ; y = ; }");
+        let err = Parser::new(&tokens).parse_program().unwrap_err();
+        assert_eq!(err.span().unwrap().start, 13);
+    }
+}
@@ -0,0 +1,396 @@
+//! Semantic analysis: name resolution, symbol locations and the resolved side table.
+
+#[cfg(test)]
+mod resolve_tests {
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+    use b::sema::symbol_table::{Location, SymbolKind};
+    use b::sema::{analyze, SemaError, SemaOutput};
+
+    fn check(src: &str) -> SemaOutput {
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        let mut parser = Parser::new(&lexed.tokens);
+        let program = parser.parse_program().expect("parse error in test input");
+        analyze(&program)
+    }
+
+    /// The symbol recorded for the identifier starting at the nth occurrence of `name` in `src`.
+    fn symbol_at(out: &SemaOutput, src: &str, name: &str, nth: usize) -> b::sema::symbol_table::Symbol {
+        let start = src.match_indices(name).nth(nth).expect("no such occurrence").0 as u32;
+        *out.resolved.iter()
+            .find(|(span, _)| span.start == start)
+            .unwrap_or_else(|| panic!("occurrence {} of `{}` not resolved", nth, name))
+            .1
+    }
+
+    #[test]
+    fn valid_program_has_no_errors() {
+        let out = check("
+            count 0;
+            v[3] 1, 2, 3;
+            main() {
+                extrn count, v;
+                auto i;
+                i = 0;
+                while (i < 3) count =+ v[i++];
+                return(add(count, 1));
+            }
+            add(a, b) return(a + b);
+        ");
+        assert_eq!(out.errors, vec![]);
+    }
+
+    #[test]
+    fn params_then_autos_get_local_slots() {
+        let src = "f(a, b) { auto x, v[10]; x = a; v[0] = b; }";
+        let out = check(src);
+        assert!(out.errors.is_empty());
+        assert_eq!(symbol_at(&out, src, "a", 0).slot, Location::Local { slot: 0 });
+        assert_eq!(symbol_at(&out, src, "b", 0).slot, Location::Local { slot: 1 });
+        assert_eq!(symbol_at(&out, src, "x", 0).slot, Location::Local { slot: 2 });
+        let v = symbol_at(&out, src, "v", 0);
+        assert_eq!((v.slot, v.size, v.kind), (Location::Local { slot: 3 }, 10, SymbolKind::Auto));
+
+        let env = out.table.function(0);
+        assert_eq!(out.table.name(env.name), "f");
+        assert_eq!(env.params.len(), 2);
+        assert_eq!(env.slot_count, 4);
+    }
+
+    #[test]
+    fn params_whose_address_is_taken_are_recorded() {
+        let src = "f(fmt, x1, x2) { auto p, q; p = &x1; q = &p; q = &x1 + &fmt; } g(a) return(&a[1]);";
+        let out = check(src);
+        assert!(out.errors.is_empty());
+        let f = out.table.function(0);
+        let names: Vec<&str> = f.address_taken.iter().map(|&id| out.table.name(id)).collect();
+        assert_eq!(names, vec!["x1", "fmt"]);
+        assert!(f.params_in_frame());
+        // &a[1] is the address of a vector element, not of a
+        assert!(!out.table.function(1).params_in_frame());
+        // a parenthesized param is the same lvalue
+        let out = check("h(x1, x2) { auto adx; adx = &((x2)); return(*adx); }");
+        let h = out.table.function(0);
+        assert_eq!(h.address_taken.iter().map(|&id| out.table.name(id)).collect::<Vec<_>>(), vec!["x2"]);
+    }
+
+    #[test]
+    fn uses_resolve_to_their_declaration() {
+        let src = "g; f(p) { extrn g; auto x; x = p + g; }";
+        let out = check(src);
+        assert!(out.errors.is_empty());
+        // the uses in `x = p + g` are in the side table with the declared location
+        assert_eq!(symbol_at(&out, src, "x", 1).slot, Location::Local { slot: 1 });
+        assert_eq!(symbol_at(&out, src, "p", 1).kind, SymbolKind::Param);
+        let g = symbol_at(&out, src, "g", 2);
+        assert_eq!((g.kind, g.slot), (SymbolKind::Extrn, Location::Global { index: 0 }));
+    }
+
+    #[test]
+    fn calls_are_implicitly_extrn() {
+        let src = "main() { helper(); putchar('x'); putchar('y'); } helper() ;";
+        let out = check(src);
+        assert!(out.errors.is_empty());
+        assert_eq!(symbol_at(&out, src, "helper", 0).slot, Location::Function { func_index: 1 });
+        // undefined callees become a single shared import
+        assert_eq!(symbol_at(&out, src, "putchar", 0).slot, Location::Import { import_index: 0 });
+        assert_eq!(symbol_at(&out, src, "putchar", 1).slot, Location::Import { import_index: 0 });
+        assert_eq!(out.table.global.imports.len(), 1);
+    }
+
+    #[test]
+    fn extrn_of_unknown_name_is_an_import() {
+        let src = "main() { extrn errno; return(errno); }";
+        let out = check(src);
+        assert!(out.errors.is_empty());
+        assert_eq!(symbol_at(&out, src, "errno", 1).slot, Location::Import { import_index: 0 });
+    }
+
+    #[test]
+    fn undefined_name_is_reported() {
+        let out = check("main() { auto x; x = y; }");
+        assert!(matches!(&out.errors[..], [SemaError::UndefinedName { name, .. }] if name == "y"));
+    }
+
+    #[test]
+    fn global_without_extrn_is_reported() {
+        let out = check("total; main() { total = 1; }");
+        match &out.errors[..] {
+            [SemaError::NotDeclared { name, span, global }] => {
+                assert_eq!(name, "total");
+                assert_eq!((global.start, span.start), (0, 16));
+            }
+            other => panic!("unexpected errors {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_definitions_are_reported() {
+        let out = check("x; x; f(a, a) { auto b, b; extrn a; } f() ;");
+        let names: Vec<&str> = out.errors.iter().map(|e| match e {
+            SemaError::DuplicateDefinition { name, .. } => name.as_str(),
+            other => panic!("unexpected error {:?}", other),
+        }).collect();
+        assert_eq!(names, vec!["x", "f", "a", "b", "a"]);
+    }
+
+    #[test]
+    fn goto_label_is_not_an_undefined_name() {
+        let out = check("main() { auto x; loop: x++; goto loop; }");
+        assert!(out.errors.is_empty());
+    }
+
+    #[test]
+    fn locals_do_not_leak_between_functions() {
+        let out = check("f() { auto x; } g() { x = 1; }");
+        assert!(matches!(&out.errors[..], [SemaError::UndefinedName { .. }]));
+    }
+}
+
+#[cfg(test)]
+mod label_tests {
+    use b::common::span::Span;
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+    use b::sema::symbol_table::{Location, SymbolKind};
+    use b::sema::{analyze, GotoTarget, SemaError, SemaOutput, SemaWarning};
+
+    fn check(src: &str) -> SemaOutput {
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        let mut parser = Parser::new(&lexed.tokens);
+        let program = parser.parse_program().expect("parse error in test input");
+        analyze(&program)
+    }
+
+    /// Span of the nth occurrence of `text` in `src`.
+    fn span_of(src: &str, text: &str, nth: usize) -> Span {
+        let start = src.match_indices(text).nth(nth).expect("no such occurrence").0 as u32;
+        Span { start, end: start + text.len() as u32 }
+    }
+
+    #[test]
+    fn forward_and_backward_gotos_are_direct() {
+        let src = "main() { auto i; top: if (i++ > 3) goto done; goto top; done: return; }";
+        let out = check(src);
+        assert_eq!(out.errors, vec![]);
+        assert!(out.warnings.is_empty());
+        assert_eq!(out.gotos[&span_of(src, "done", 0)], GotoTarget::Label { index: 1 });
+        assert_eq!(out.gotos[&span_of(src, "top", 1)], GotoTarget::Label { index: 0 });
+
+        let env = out.table.function(0);
+        assert_eq!(env.labels.len(), 2);
+        let done = out.resolved[&span_of(src, "done", 1)];
+        assert_eq!((done.kind, done.slot), (SymbolKind::Label, Location::Label { index: 1 }));
+    }
+
+    #[test]
+    fn labels_inside_nested_statements_are_visible() {
+        let src = "main() { goto inner; while (1) { if (1) { inner: ; } } }";
+        let out = check(src);
+        assert_eq!(out.errors, vec![]);
+        assert_eq!(out.gotos[&span_of(src, "inner", 0)], GotoTarget::Label { index: 0 });
+    }
+
+    #[test]
+    fn duplicate_label_is_reported() {
+        let src = "main() { again: ; again: goto again; }";
+        let out = check(src);
+        match &out.errors[..] {
+            [SemaError::DuplicateLabel { name, span, previous }] => {
+                assert_eq!(name, "again");
+                assert_eq!(*span, span_of(src, "again", 1));
+                assert_eq!(*previous, span_of(src, "again", 0));
+            }
+            other => panic!("unexpected errors {:?}", other),
+        }
+    }
+
+    #[test]
+    fn goto_missing_label_is_reported() {
+        let out = check("main() { goto nowhere; }");
+        assert!(matches!(&out.errors[..], [SemaError::UndefinedLabel { name, .. }] if name == "nowhere"));
+    }
+
+    #[test]
+    fn labels_are_per_function() {
+        let out = check("f() { here: goto here; } g() { goto here; }");
+        assert!(matches!(&out.errors[..], [SemaError::UndefinedLabel { .. }]));
+    }
+
+    #[test]
+    fn unused_label_warns() {
+        let src = "main() { unused: return; }";
+        let out = check(src);
+        assert!(out.errors.is_empty());
+        assert_eq!(out.warnings, vec![SemaWarning::UnusedLabel { name: "unused".to_string(), span: span_of(src, "unused", 0) }]);
+    }
+
+    #[test]
+    fn computed_goto_is_flagged() {
+        // a label's value stored in a variable, and an arbitrary expression after goto
+        let src = "main() { auto p, t[2]; p = out; t[0] = out; goto p; goto t[0]; out: ; }";
+        let out = check(src);
+        assert_eq!(out.errors, vec![]);
+        // taking the label's value counts as a use
+        assert!(out.warnings.is_empty());
+        assert_eq!(out.gotos[&span_of(src, "p", 2)], GotoTarget::Computed);
+        let index_expr = Span { start: span_of(src, "t[0]", 1).start, end: span_of(src, "t[0]", 1).start + 3 };
+        assert_eq!(out.gotos[&index_expr], GotoTarget::Computed);
+    }
+}
+
+#[cfg(test)]
+mod switch_tests {
+    use b::common::span::Span;
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+    use b::sema::{analyze, SemaError, SemaOutput, SemaWarning};
+
+    fn check(src: &str) -> SemaOutput {
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        let mut parser = Parser::new(&lexed.tokens);
+        let program = parser.parse_program().expect("parse error in test input");
+        analyze(&program)
+    }
+
+    fn span_of(src: &str, text: &str, nth: usize) -> Span {
+        let start = src.match_indices(text).nth(nth).expect("no such occurrence").0 as u32;
+        Span { start, end: start + text.len() as u32 }
+    }
+
+    #[test]
+    fn fall_through_switch_is_clean() {
+        let out = check("f(c) { auto n; switch c { case 'a': case 'b': n++; n++; case -1: default: return(n); } }");
+        assert_eq!(out.errors, vec![]);
+        assert!(out.warnings.is_empty());
+    }
+
+    #[test]
+    fn string_case_is_not_a_constant() {
+        let src = "f(c) switch c { case \"no\": ; }";
+        let out = check(src);
+        assert_eq!(out.errors, vec![SemaError::NonConstantCase { span: span_of(src, "\"no\"", 0) }]);
+    }
+
+    #[test]
+    fn case_values_are_constant_expressions() {
+        let src = "f(c, n) switch c { case n: ; case f(1): ; case 'a' + 1: ; case (2 << 3) - 1: ; case 98: ; }";
+        let out = check(src);
+        assert_eq!(out.errors, vec![
+            SemaError::NonConstantCase { span: span_of(src, "n", 1) },
+            SemaError::NonConstantCase { span: span_of(src, "f(1", 0) },
+            SemaError::DuplicateCase { value: 98, span: span_of(src, "98", 0), previous: span_of(src, "'a' + 1", 0), packed: false },
+        ]);
+    }
+
+    #[test]
+    fn duplicate_case_after_char_packing() {
+        let src = "f(c) switch c { case 97: ; case 1: ; case 'a': ; }";
+        let out = check(src);
+        assert_eq!(out.errors, vec![SemaError::DuplicateCase {
+            value: 97, span: span_of(src, "'a'", 0), previous: span_of(src, "97", 0), packed: true,
+        }]);
+    }
+
+    #[test]
+    fn nested_switches_have_their_own_cases() {
+        let out = check("f(a, b) switch a { case 1: switch b { case 1: ; default: ; } default: ; }");
+        assert_eq!(out.errors, vec![]);
+    }
+
+    #[test]
+    fn second_default_is_reported() {
+        let src = "f(c) switch c { default: ; case 1: { default: ; } }";
+        let out = check(src);
+        assert_eq!(out.errors, vec![SemaError::MultipleDefault {
+            span: span_of(src, "default", 1), previous: span_of(src, "default", 0),
+        }]);
+    }
+
+    #[test]
+    fn case_outside_switch_is_reported() {
+        let out = check("f(c) { case 1: ; default: ; }");
+        let keywords: Vec<&str> = out.errors.iter().map(|e| match e {
+            SemaError::LabelOutsideSwitch { keyword, .. } => *keyword,
+            other => panic!("unexpected error {:?}", other),
+        }).collect();
+        assert_eq!(keywords, vec!["case", "default"]);
+    }
+
+    #[test]
+    fn statements_before_first_case_warn() {
+        let src = "f(c) { auto n, k; switch c { ; n = 1; k = 2; case 1: n = 3; } }";
+        let out = check(src);
+        assert_eq!(out.errors, vec![]);
+        let span = Span { start: span_of(src, "n = 1", 0).start, end: span_of(src, "k = 2", 0).end };
+        assert_eq!(out.warnings, vec![SemaWarning::UnreachableBeforeCase { span }]);
+    }
+
+    #[test]
+    fn labels_nested_in_the_body_are_reachable() {
+        // Duff's device: the loop is entered at the case inside it
+        let duff = "f(n) { auto r; r = 0; switch (n) { while (r < 10) { r =+ 10; case 1: r =+ 1; } } return(r); }";
+        assert_eq!(check(duff).warnings, vec![]);
+        let src = "f(c) { switch c { if (c) { again: c--; } goto again; } }";
+        assert_eq!(check(src).warnings, vec![]);
+        // a nested switch's cases are its own, so the outer body is still entered nowhere before them
+        let src = "f(c) { switch c { c = 1; switch c { case 1: ; } case 2: ; } }";
+        let out = check(src);
+        let span = Span { start: span_of(src, "c = 1", 0).start, end: span_of(src, "case 1", 0).end };
+        assert_eq!(out.warnings, vec![SemaWarning::UnreachableBeforeCase { span }]);
+    }
+}
+
+#[cfg(test)]
+mod link_tests {
+    use b::common::source_map::FileId;
+    use b::common::span::Span;
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+    use b::sema::analyze;
+    use b::sema::link::{link, LinkError, Unit};
+
+    fn unit(file: u32, src: &str) -> Unit {
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        let mut parser = Parser::new(&lexed.tokens);
+        let program = parser.parse_program().expect("parse error in test input");
+        let sema = analyze(&program);
+        assert_eq!(sema.errors, vec![], "sema error in test input");
+        Unit::new(FileId(file), &sema)
+    }
+
+    fn span_of(src: &str, text: &str, nth: usize) -> Span {
+        let start = src.match_indices(text).nth(nth).expect("no such occurrence").0 as u32;
+        Span { start, end: start + text.len() as u32 }
+    }
+
+    #[test]
+    fn imports_resolve_to_other_files_and_libb() {
+        let main = unit(0, "main() { extrn total, names; add(1); printf(\"%d*n\", total + names[0]); }");
+        let lib = unit(1, "total; names[1] 1; add(n) { extrn total; total =+ n; }");
+        assert_eq!(link(&[main, lib]), vec![]);
+    }
+
+    #[test]
+    fn unresolved_import_is_reported_where_it_is_used() {
+        let src = "main() { extrn count; return(count + helper()); }";
+        let errors = link(&[unit(0, src), unit(1, "helper() return(1);")]);
+        assert_eq!(errors, vec![LinkError::Unresolved { name: "count".to_string(), file: FileId(0), span: span_of(src, "count", 0) }]);
+    }
+
+    #[test]
+    fn second_definition_points_at_the_first() {
+        let (a, b) = ("f() return(1); x 1;", "g() return(2);\nf() return(3); x;");
+        let errors = link(&[unit(0, a), unit(1, b)]);
+        assert_eq!(errors, vec![
+            LinkError::MultiplyDefined { name: "f".to_string(), file: FileId(1), span: span_of(b, "f", 0), previous_file: FileId(0), previous: span_of(a, "f", 0) },
+            LinkError::MultiplyDefined { name: "x".to_string(), file: FileId(1), span: span_of(b, "x", 0), previous_file: FileId(0), previous: span_of(a, "x", 0) },
+        ]);
+        let diag = errors[0].to_diagnostic();
+        assert_eq!((diag.code, diag.labels.len()), (Some("E0211"), 2));
+    }
+}
@@ -0,0 +1,80 @@
+//! Line/column resolution through `LineIndex` and `SourceMap`.
+
+#[cfg(test)]
+mod source_map_tests {
+    use b::common::source_map::{LineCol, LineIndex, SourceMap};
+    use b::common::span::Span;
+    use b::lexer::scanner::Scanner;
+
+    /// Lex to completion and return the scanner's line table.
+    fn lexed_index(src: &str) -> LineIndex {
+        let mut scanner = Scanner::new(src);
+        scanner.by_ref().for_each(drop);
+        scanner.line_index()
+    }
+
+    #[test]
+    fn scanner_table_matches_direct_scan() {
+        let src = "main() {\n  auto x;\n\n  /* a\n comment */ x = 1;\n}\n";
+        assert_eq!(lexed_index(src), LineIndex::new(src));
+    }
+
+    #[test]
+    fn first_offset_is_line_one_col_one() {
+        let src = "x;";
+        assert_eq!(lexed_index(src).line_col(src, 0), LineCol { line: 1, col: 1 });
+    }
+
+    #[test]
+    fn offsets_on_later_lines() {
+        let src = "a;\nbb;\n  ccc;";
+        let lines = lexed_index(src);
+        assert_eq!(lines.line_col(src, 3), LineCol { line: 2, col: 1 });
+        assert_eq!(lines.line_col(src, 9), LineCol { line: 3, col: 3 });
+        assert_eq!(lines.line_text(src, 2), "  ccc;");
+    }
+
+    #[test]
+    fn newline_belongs_to_the_line_it_ends() {
+        let src = "ab\ncd";
+        let lines = LineIndex::new(src);
+        assert_eq!(lines.line_col(src, 2), LineCol { line: 1, col: 3 });
+        assert_eq!(lines.line_col(src, 3), LineCol { line: 2, col: 1 });
+    }
+
+    #[test]
+    fn columns_count_chars_not_bytes() {
+        let src = "\"é\" x";
+        let lines = LineIndex::new(src);
+        // 'x' is at byte 5 but char 4
+        assert_eq!(lines.line_col(src, 5), LineCol { line: 1, col: 5 });
+    }
+
+    #[test]
+    fn line_text_strips_crlf() {
+        let src = "a;\r\nb;\r\n";
+        let lines = LineIndex::new(src);
+        assert_eq!(lines.line_text(src, 0), "a;");
+        assert_eq!(lines.line_text(src, 1), "b;");
+    }
+
+    #[test]
+    fn source_map_keeps_files_apart() {
+        let first = "a;\nb;";
+        let second = "\n\n  c;";
+        let mut map = SourceMap::new();
+        let f1 = map.add_file("first.b", first, LineIndex::new(first));
+        let f2 = map.add_file("second.b", second, LineIndex::new(second));
+        assert_ne!(f1, f2);
+        assert_eq!(map.name(f2), "second.b");
+
+        let loc = map.lookup(f1, Span { start: 3, end: 4 });
+        assert_eq!((loc.start.line, loc.start.col), (2, 1));
+        assert_eq!(loc.line_text, "b;");
+
+        let loc = map.lookup(f2, Span { start: 4, end: 5 });
+        assert_eq!((loc.start.line, loc.start.col), (3, 3));
+        assert_eq!(loc.end, LineCol { line: 3, col: 4 });
+        assert_eq!(loc.line_text, "  c;");
+    }
+}
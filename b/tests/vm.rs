@@ -0,0 +1,206 @@
+//! Compiling to bytecode, the module file format, and running on the vm.
+
+mod common;
+
+#[cfg(test)]
+mod vm_tests {
+    use b::interp::Interpreter;
+    use b::ir::lower::lower;
+    use b::ir::ssa::to_ssa;
+    use b::ir::{self, Word};
+    use b::lexer::lex_all;
+    use b::parser::Parser;
+    use b::sema::analyze;
+    use b::vm::compile::compile;
+    use b::vm::format::{self, FormatError};
+    use b::vm::machine::{Vm, VmError};
+    use b::vm::{Module, Op};
+
+    use crate::common::PRINTF;
+
+    // the IR, and what the interpreter makes of the program
+    fn lower_and_interpret(src: &str, input: &str) -> (ir::Module, Option<Word>, Vec<u8>) {
+        let lexed = lex_all(src);
+        assert!(lexed.errors.is_empty(), "lex error in test input");
+        let mut parser = Parser::new(&lexed.tokens);
+        let program = parser.parse_program().expect("parse error in test input");
+        let sema = analyze(&program);
+        assert_eq!(sema.errors, vec![], "sema error in test input");
+        let mut interpreter = Interpreter::new(&program, &sema).unwrap().with_input(input);
+        let result = interpreter.run().ok();
+        (lower(&program, &sema), result, interpreter.output().to_vec())
+    }
+
+    fn execute(module: &Module, input: &str) -> (Result<Word, VmError>, String) {
+        let mut vm = Vm::new(module).unwrap().with_input(input);
+        let result = vm.run();
+        (result, String::from_utf8(vm.output().to_vec()).unwrap())
+    }
+
+    // runs the program from plain and from SSA-form IR, checking both agree with the interpreter
+    fn run_with_input(src: &str, input: &str) -> (Result<Word, VmError>, String) {
+        let (mut ir, expected, output) = lower_and_interpret(src, input);
+        let plain = execute(&compile(&ir), input);
+        ir.functions.iter_mut().for_each(|f| { to_ssa(f); });
+        let ssa = execute(&compile(&ir), input);
+        assert_eq!(plain, ssa, "SSA form changed the result");
+        if let Some(expected) = expected {
+            assert_eq!((plain.0.clone(), plain.1.as_bytes()), (Ok(expected), &output[..]), "vm disagrees with the interpreter");
+        }
+        plain
+    }
+
+    fn run(src: &str) -> (Result<Word, VmError>, String) {
+        run_with_input(src, "")
+    }
+
+    fn module_of(src: &str) -> Module {
+        compile(&lower_and_interpret(src, "").0)
+    }
+
+    const PRINTN: &str = "
+printn(n) {
+  auto a;
+  if (n < 0) { putchar('-'); n = -n; }
+  if (a = n / 10) printn(a);
+  putchar(n % 10 + '0');
+}
+";
+
+    #[test]
+    fn runs_like_the_interpreter() {
+        assert_eq!(run("fact(n) return(n <= 1 ? 1 : n * fact(n - 1)); main() return(fact(10));").0, Ok(3628800));
+        let src = format!("{}main() {{ printn(-1230); putchar('*n'); putchar('ok'); }}", PRINTN);
+        assert_eq!(run(&src), (Ok(0), "-1230\nok".to_string()));
+        let src = "main() { auto c; while ((c = getchar()) != '*e') putchar(c == 'a' ? 'A' : c); }";
+        assert_eq!(run_with_input(src, "banana").1, "bAnAnA");
+    }
+
+    #[test]
+    fn library_functions_are_values() {
+        let src = "main() { extrn putchar; auto f; f = putchar; f('o'); (f == putchar ? putchar : f)('k'); return(f != 0); }";
+        assert_eq!(run(src), (Ok(1), "ok".to_string()));
+    }
+
+    #[test]
+    fn globals_strings_and_pointers() {
+        let src = "
+v[3] 10, 20;
+n 7;
+s \"hi\";
+swap(a, b) { auto t; t = *a; *a = *b; *b = t; }
+main() {
+  extrn v, n, s;
+  auto x, y, w[4], p;
+  x = 1; y = 2;
+  swap(&x, &y);
+  p = w;
+  *p++ = 5; *p++ = 6;
+  v[3] = n;
+  return(x * 1000 + y * 100 + w[0] + w[1] + (p - w) + v[0] + v[1] + v[3] + (s[0] & 0377));
+}";
+        assert_eq!(run(src).0, Ok(2000 + 100 + 5 + 6 + 2 + 10 + 20 + 7 + 'h' as Word));
+    }
+
+    #[test]
+    fn switch_goto_and_function_values() {
+        let src = "
+f(x) {
+  auto r;
+  r = 0;
+  switch (x) {
+  case 1: r =+ 1;
+  case 2: r =+ 10;
+    goto out;
+  case -5: r =+ 100;
+  default: r =+ 1000;
+  }
+out:
+  return(r);
+}
+twice(g, x) return(g(g(x)));
+main() {
+  extrn f, twice;
+  auto i, t;
+  i = 0;
+loop:
+  if (++i < 5) goto loop;
+  t = i == 5 ? yes : no;
+  goto t;
+no:
+  return(-1);
+yes:
+  return(f(1) + f(2) + f(-5) + f(3) + twice(f, 1) * 100000);
+}";
+        assert_eq!(run(src).0, Ok(11 + 10 + 1100 + 1000 + 1000 * 100000));
+    }
+
+    #[test]
+    fn callees_walk_their_arguments_through_a_pointer() {
+        assert_eq!(run(PRINTF).0, Ok(0));
+    }
+
+    #[test]
+    fn deep_recursion_does_not_use_the_host_stack() {
+        let src = "depth(n) return(n ? 1 + depth(n - 1) : 0); main() return(depth(50000));";
+        let module = module_of(src);
+        assert_eq!(execute(&module, "").0, Ok(50000));
+    }
+
+    #[test]
+    fn layout_skips_jumps_to_the_next_block() {
+        let module = module_of("main() { auto i; i = 0; while (i < 10) i++; return(i); }");
+        let code = &module.functions[0].code;
+        let jumps = code.iter().filter(|op| matches!(op, Op::Jump { .. })).count();
+        assert_eq!(jumps, 1, "{:?}", code);
+        assert!(matches!(code.last(), Some(Op::Return { .. })));
+    }
+
+    #[test]
+    fn faults_name_the_function() {
+        let module = module_of("f(p) return(*p); main() return(f(0));");
+        assert_eq!(execute(&module, "").0, Err(VmError::OutOfBounds { address: 0, function: "f".to_string() }));
+        let module = module_of("main() { auto f; f = 42; f(1); }");
+        assert_eq!(execute(&module, "").0, Err(VmError::NotAFunction { value: 42, function: "main".to_string() }));
+        let module = module_of("main() { auto z; z = 0; return(1 / z); }");
+        assert_eq!(execute(&module, "").0, Err(VmError::DivisionByZero { function: "main".to_string() }));
+        let module = module_of("main() fprintf(\"x\");");
+        assert_eq!(execute(&module, "").0, Err(VmError::UnresolvedImport { name: "fprintf".to_string(), function: "main".to_string() }));
+        let module = module_of("f(n) return(f(n + 1)); main() f(0);");
+        assert_eq!(execute(&module, "").0, Err(VmError::StackOverflow { function: "f".to_string() }));
+    }
+
+    #[test]
+    fn a_fault_leaves_the_vm_usable() {
+        let module = module_of("bad() { auto p; p = 0; return(*p); } good(x) { auto v[2]; v[1] = x; return(v[1] * 2); }");
+        let mut vm = Vm::new(&module).unwrap();
+        assert!(vm.call("bad", &[]).is_err());
+        assert_eq!(vm.call("good", &[21]), Ok(42));
+        assert_eq!(vm.call("main", &[]), Err(VmError::NoFunction { name: "main".to_string() }));
+    }
+
+    #[test]
+    fn modules_round_trip_through_the_file_format() {
+        let src = format!("{}s \"neg*n\"; main() {{ extrn s; auto i, c; i = 0; while ((c = s[i / 8] >> (i % 8 * 8) & 0377) != '*e') {{ putchar(c); i++; }} printn(-1234567890123); return(-9223372036854775807 - 1); }}", PRINTN);
+        let module = module_of(&src);
+        let bytes = format::write(&module);
+        assert!(bytes.starts_with(&format::MAGIC));
+        let read = format::read(&bytes).unwrap();
+        assert_eq!(read, module);
+        assert_eq!(execute(&read, ""), (Ok(Word::MIN), "neg\n-1234567890123".to_string()));
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let bytes = format::write(&module_of("main() { auto i; i = 3; while (i) i--; return(i); }"));
+        assert_eq!(format::read(b"ELF!").unwrap_err().message, "not a module file");
+        assert_eq!(format::read(b"BVM\x09").unwrap_err(), FormatError { offset: 3, message: "unsupported version 9".to_string() });
+        for end in 4..bytes.len() {
+            assert!(format::read(&bytes[..end]).is_err(), "accepted a file cut at {}", end);
+        }
+        let mut module = format::read(&bytes).unwrap();
+        module.functions[0].code.push(Op::Jump { target: 1000 });
+        let error = format::read(&format::write(&module)).unwrap_err();
+        assert_eq!(error.message, "in function `main`: jump to 1000 past the end of the code");
+    }
+}
@@ -0,0 +1,364 @@
+//! WebAssembly code generation: a structural check of every module produced, and running them
+//! under node where it is installed.
+
+mod common;
+
+#[cfg(test)]
+mod wasm_tests {
+    use std::process::Command;
+
+    use b::codegen::wasm::binary::{encode, HEADER};
+    use b::codegen::wasm::compile::compile;
+    use b::codegen::wasm::text::print;
+    use b::ir::Word;
+
+    use crate::common::{self, file, found, lower_and_interpret, skip, CALLS, ECHO, FACT, GLOBALS, PRINTF, PRINTN};
+
+    // what the checker learns about a module
+    #[derive(Debug, Default)]
+    struct Summary {
+        sections: Vec<u8>,
+        imports: Vec<(String, String)>,
+        functions: usize, // defined, not imported
+        exports: Vec<String>,
+        table: usize,
+        data: usize,
+    }
+
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl Reader<'_> {
+        fn byte(&mut self) -> u8 {
+            let b = *self.bytes.get(self.pos).unwrap_or_else(|| panic!("module ends early at byte {}", self.pos));
+            self.pos += 1;
+            b
+        }
+
+        fn u(&mut self) -> u64 {
+            let (mut value, mut shift) = (0u64, 0);
+            loop {
+                let b = self.byte();
+                value |= ((b & 0x7f) as u64) << shift;
+                if b & 0x80 == 0 {
+                    return value;
+                }
+                shift += 7;
+                assert!(shift < 35, "unsigned LEB128 longer than 5 bytes at {}", self.pos);
+            }
+        }
+
+        fn s(&mut self) {
+            let mut shift = 0;
+            while self.byte() & 0x80 != 0 {
+                shift += 7;
+                assert!(shift < 70, "signed LEB128 longer than 10 bytes at {}", self.pos);
+            }
+        }
+
+        fn name(&mut self) -> String {
+            let len = self.u() as usize;
+            let name = String::from_utf8(self.bytes[self.pos..self.pos + len].to_vec()).expect("name is not UTF-8");
+            self.pos += len;
+            name
+        }
+
+        fn val_type(&mut self) {
+            let t = self.byte();
+            assert!(t == 0x7f || t == 0x7e, "value type {:#x} at {}", t, self.pos - 1);
+        }
+
+        // a constant expression: one i32.const and end
+        fn init(&mut self) {
+            assert_eq!(self.byte(), 0x41, "initializer is not i32.const");
+            self.s();
+            assert_eq!(self.byte(), 0x0b, "initializer does not end");
+        }
+    }
+
+    // walks the whole binary, checking section order and sizes, that every index refers to
+    // something that exists, and that each body's blocks nest and its branches stay inside them
+    fn check(bytes: &[u8]) -> Summary {
+        assert_eq!(bytes[..8], HEADER, "bad header");
+        let mut r = Reader { bytes, pos: 8 };
+        let mut summary = Summary::default();
+        let (mut types, mut globals, mut memories): (Vec<(usize, usize)>, usize, usize) = (Vec::new(), 0, 0);
+        let mut function_types: Vec<u64> = Vec::new(); // imports first
+        let mut bodies = 0;
+        while r.pos < bytes.len() {
+            let id = r.byte();
+            assert!(summary.sections.last().is_none_or(|&last| id > last), "section {} out of order", id);
+            summary.sections.push(id);
+            let size = r.u() as usize;
+            let end = r.pos + size;
+            assert!(end <= bytes.len(), "section {} runs past the end", id);
+            let count = r.u();
+            for _ in 0..count {
+                match id {
+                    1 => {
+                        assert_eq!(r.byte(), 0x60, "type is not a function type");
+                        let params = r.u() as usize;
+                        (0..params).for_each(|_| r.val_type());
+                        let results = r.u() as usize;
+                        (0..results).for_each(|_| r.val_type());
+                        types.push((params, results));
+                    }
+                    2 => {
+                        let (module, name) = (r.name(), r.name());
+                        assert_eq!(r.byte(), 0x00, "only functions are imported");
+                        let ty = r.u();
+                        assert!((ty as usize) < types.len(), "import type {} out of range", ty);
+                        function_types.push(ty);
+                        summary.imports.push((module, name));
+                    }
+                    3 => {
+                        let ty = r.u();
+                        assert!((ty as usize) < types.len(), "function type {} out of range", ty);
+                        function_types.push(ty);
+                        summary.functions += 1;
+                    }
+                    4 => {
+                        assert_eq!(r.byte(), 0x70, "table is not funcref");
+                        assert_eq!(r.byte(), 0x00, "table has a maximum");
+                        summary.table = r.u() as usize;
+                    }
+                    5 => {
+                        assert_eq!(r.byte(), 0x00, "memory has a maximum");
+                        assert!(r.u() > 0, "memory is empty");
+                        memories += 1;
+                    }
+                    6 => {
+                        r.val_type();
+                        assert!(r.byte() <= 1, "bad mutability");
+                        r.init();
+                        globals += 1;
+                    }
+                    7 => {
+                        let name = r.name();
+                        let (kind, index) = (r.byte(), r.u() as usize);
+                        match kind {
+                            0x00 => assert!(index < function_types.len(), "export {} of function {} out of range", name, index),
+                            0x02 => assert!(index < memories, "export {} of memory {} out of range", name, index),
+                            _ => panic!("export {} of kind {}", name, kind),
+                        }
+                        summary.exports.push(name);
+                    }
+                    9 => {
+                        assert_eq!(r.byte(), 0x00, "element segment is not active funcref");
+                        r.init();
+                        let len = r.u();
+                        for _ in 0..len {
+                            assert!((r.u() as usize) < function_types.len(), "table entry out of range");
+                        }
+                        assert!((len as usize) < summary.table, "elements overflow the table");
+                    }
+                    10 => {
+                        let size = r.u() as usize;
+                        let body_end = r.pos + size;
+                        let (params, _) = types[function_types[summary.imports.len() + bodies] as usize];
+                        let mut locals = params as u64;
+                        for _ in 0..r.u() {
+                            locals += r.u();
+                            r.val_type();
+                        }
+                        check_body(&mut r, locals, function_types.len() as u64, types.len() as u64, globals as u64);
+                        assert_eq!(r.pos, body_end, "function body {} size is wrong", bodies);
+                        bodies += 1;
+                    }
+                    11 => {
+                        assert_eq!(r.byte(), 0x00, "data segment is not active");
+                        r.init();
+                        let len = r.u() as usize;
+                        r.pos += len;
+                        summary.data += len;
+                    }
+                    _ => panic!("unexpected section {}", id),
+                }
+            }
+            assert_eq!(r.pos, end, "section {} size is wrong", id);
+        }
+        assert_eq!(bodies, summary.functions, "function and code sections disagree");
+        summary
+    }
+
+    fn check_body(r: &mut Reader, locals: u64, functions: u64, types: u64, globals: u64) {
+        let mut depth = 1u64; // the function body is a block
+        while depth > 0 {
+            let at = r.pos;
+            match r.byte() {
+                0x00 | 0x0f | 0x1b => {}
+                0x02..=0x04 => {
+                    assert_eq!(r.byte(), 0x40, "block type at {} is not empty", at);
+                    depth += 1;
+                }
+                0x05 => assert!(depth > 1, "else outside an if at {}", at),
+                0x0b => depth -= 1,
+                0x0c => assert!(r.u() < depth, "branch at {} leaves the function", at),
+                0x0e => {
+                    for _ in 0..r.u() + 1 {
+                        assert!(r.u() < depth, "br_table at {} leaves the function", at);
+                    }
+                }
+                0x10 => assert!(r.u() < functions, "call at {} of a missing function", at),
+                0x11 => {
+                    assert!(r.u() < types, "call_indirect at {} of a missing type", at);
+                    assert_eq!(r.byte(), 0x00, "call_indirect at {} not through table 0", at);
+                }
+                0x20..=0x22 => assert!(r.u() < locals, "local at {} out of range", at),
+                0x23 | 0x24 => assert!(r.u() < globals, "global at {} out of range", at),
+                0x29 | 0x37 => {
+                    assert!(r.u() <= 3, "alignment at {} wider than the access", at);
+                    r.u();
+                }
+                0x41 | 0x42 => r.s(),
+                0x45..=0x5a | 0x6a..=0x8a | 0xa7 | 0xac | 0xad => {}
+                op => panic!("unexpected opcode {:#x} at {}", op, at),
+            }
+        }
+    }
+
+    // a host that feeds `input` to getchar and prints what putchar writes, then main's result
+    const HOST: &str = "
+const fs = require('fs');
+const input = Buffer.from(process.argv[3]);
+let read = 0;
+const out = [];
+const host = { b: { putchar: c => { out.push(c); }, getchar: () => read < input.length ? input[read++] : -1 } };
+WebAssembly.instantiate(fs.readFileSync(process.argv[2]), host).then(({ instance }) => {
+  const result = instance.exports.main();
+  process.stdout.write(Buffer.from(out));
+  process.stdout.write('\\n' + result);
+});
+";
+
+    // main's result and output when node runs the module
+    fn execute(bytes: &[u8], name: &str, input: &str) -> (Word, String) {
+        let (module, host) = (file("wasm", &format!("{}.wasm", name)), file("wasm", &format!("{}.js", name)));
+        std::fs::write(&module, bytes).unwrap();
+        std::fs::write(&host, HOST).unwrap();
+        let out = Command::new("node").arg(&host).arg(&module).arg(input).output().unwrap();
+        std::fs::remove_file(&module).unwrap();
+        std::fs::remove_file(&host).unwrap();
+        assert!(out.status.success(), "node failed:\n{}", String::from_utf8_lossy(&out.stderr));
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        let (output, result) = stdout.rsplit_once('\n').unwrap();
+        (result.parse().unwrap(), output.to_string())
+    }
+
+    // checks the module from plain and from SSA-form IR, and where node is installed, runs both
+    // against the interpreter
+    fn check_against_interpreter(name: &str, src: &str, input: &str) {
+        common::check_against_interpreter(src, input, |module, (result, output), form| {
+            let bytes = encode(&compile(module));
+            check(&bytes);
+            if !found("node") {
+                return skip(name, "node");
+            }
+            assert_eq!(execute(&bytes, name, input), (result, output.to_string()), "{}", form);
+        });
+    }
+
+    #[test]
+    fn modules_import_the_host_and_export_main() {
+        let (module, _, _) = lower_and_interpret("s[1] \"hi\"; main() { extrn s; putchar(getchar()); return(s); }", "");
+        let summary = check(&encode(&compile(&module)));
+        assert_eq!(summary.sections, vec![1, 2, 3, 4, 5, 6, 7, 9, 10, 11]);
+        assert_eq!(summary.imports, vec![("b".to_string(), "putchar".to_string()), ("b".to_string(), "getchar".to_string())]);
+        assert_eq!(summary.exports, vec!["memory".to_string(), "main".to_string()]);
+        // the two wrappers, main, and the export
+        assert_eq!(summary.functions, 4);
+        assert_eq!(summary.table, 4);
+        assert!(summary.data >= 3 * 8);
+
+        let (module, _, _) = lower_and_interpret("f(x) return(x);", "");
+        let summary = check(&encode(&compile(&module)));
+        assert!(summary.imports.is_empty());
+        assert_eq!(summary.exports, vec!["memory".to_string()]);
+    }
+
+    #[test]
+    fn libb_is_compiled_into_the_module() {
+        let src = "printn(n) putchar(n + 'a'); main() {
+  auto s[2];
+  printf(\"%d %d %o %c%c %s|%x%%*n\", 42, -7, 8, 'h', 'i!', \"str\", 1);
+  printf(\"%d %d %d %d %d %d %d %d %d*n\", 1, 2, 3, 4, 5, 6, 7, 8, 9);
+  printf(\"%d %o*n\", 0100000000000000000000, 0100000000000000000000);
+  lchar(s, 0, 'o'); lchar(s, 1, 'k'); lchar(s, 2, 'a'); lchar(s, 2, '*e');
+  printf(\"%s %c*n\", s, char(\"xyz\", 2));
+  printn(1);
+  return(lchar(s, 9, 'q'));
+}";
+        check_against_interpreter("libb", src, "");
+        let (module, _, _) = lower_and_interpret(src, "");
+        let summary = check(&encode(&compile(&module)));
+        assert_eq!(summary.imports, vec![("b".to_string(), "putchar".to_string())]);
+        let src = "main() {
+  printn(255, 16); putchar(' '); printn(-255, 2); putchar(' '); printn(255, 99); putchar(' ');
+  printn(0100000000000000000000, 2); putchar(' '); printn(0100000000000000000000, 36);
+  return(printn(5, 8));
+}";
+        check_against_interpreter("printn", src, "");
+
+        let (module, _, _) = lower_and_interpret("main() { auto v; v = getvec(3); printn(v, 2); exit(0); }", "");
+        let text = print(&compile(&module));
+        for expected in ["(import \"b\" \"getvec\" (func $b.getvec (type 0)))", "(import \"b\" \"exit\" (func $b.exit (type 0)))", "(func $printn "] {
+            assert!(text.contains(expected), "missing `{}` in\n{}", expected, text);
+        }
+    }
+
+    #[test]
+    fn text_names_every_function() {
+        let (module, _, _) = lower_and_interpret("v 5; f(x) return(x + 1); main() { extrn f, v, other; return(other(f(v))); }", "");
+        let text = print(&compile(&module));
+        for expected in ["(import \"b\" \"other\" (func $b.other (type 0)))", "(func $f ", "(func $main ", "call $f",
+                         "call $b.other", "(export \"main\" (func $export.main))", "(global $sp (mut i32)", "(elem (i32.const 1) func $b.other $f $main)"] {
+            assert!(text.contains(expected), "missing `{}` in\n{}", expected, text);
+        }
+        let opens = text.matches('(').count();
+        assert_eq!(opens, text.matches(')').count(), "unbalanced parentheses");
+        assert_eq!(text.lines().filter(|l| l.trim() == "end").count(), text.lines().filter(|l| matches!(l.trim(), "block" | "loop" | "if")).count());
+    }
+
+    #[test]
+    fn programs_run_like_the_interpreter() {
+        check_against_interpreter("fact", FACT, "");
+        check_against_interpreter("echo", ECHO, "banana split");
+    }
+
+    #[test]
+    fn arithmetic_wraps_like_the_ir() {
+        let src = format!("{}
+main() {{
+  auto big, min;
+  big = 0777777777777777777777;
+  min = -big - 1;
+  printn(big + 1 == min); putchar(' ');
+  printn(big * 3); putchar(' ');
+  printn(min / -1 == min); putchar(' ');
+  printn(min % -1); putchar(' ');
+  printn(1 << 65); putchar(' ');
+  printn(-16 >> 2); putchar(' ');
+  printn(-7 / 2); putchar(' ');
+  printn(-7 % 2);
+  return(!0 + ~0);
+}}", PRINTN);
+        check_against_interpreter("wrap", &src, "");
+    }
+
+    #[test]
+    fn frames_globals_and_pointers() {
+        check_against_interpreter("frames", GLOBALS, "");
+    }
+
+    #[test]
+    fn calls_switches_and_computed_gotos() {
+        check_against_interpreter("calls", CALLS, "");
+    }
+
+    #[test]
+    fn callees_walk_their_arguments_through_a_pointer() {
+        check_against_interpreter("printf", PRINTF, "");
+    }
+}
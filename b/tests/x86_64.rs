@@ -0,0 +1,100 @@
+//! x86-64 code generation, and running the linked executables where a C toolchain is available.
+
+mod common;
+
+#[cfg(test)]
+mod x86_64_tests {
+    use std::process::Command;
+
+    use b::codegen::link::{link, LinkError};
+    use b::codegen::x86_64::{emit, runtime};
+
+    use crate::common::{self, check_assembly, execute, file, lower_and_interpret, skip, status, CALLS, ECHO, FACT, GLOBALS, PRINTF, PRINTN};
+
+    fn have_cc() -> bool {
+        cfg!(all(target_arch = "x86_64", target_os = "linux")) && common::found("cc")
+    }
+
+    // checks the assembly from plain and from SSA-form IR, and where it can, links and runs both
+    // against the interpreter
+    fn check_against_interpreter(name: &str, src: &str, input: &str) {
+        common::check_against_interpreter(src, input, |module, (result, output), form| {
+            let asm = emit(module);
+            check_assembly(&asm);
+            if !have_cc() {
+                return skip(name, "x86-64 C toolchain");
+            }
+            let exe = file("x86_64", name);
+            link(&[&asm, &runtime()], &exe).unwrap();
+            let run = execute(Command::new(&exe), input);
+            std::fs::remove_file(&exe).unwrap();
+            assert_eq!(run, (status(result), output.to_string()), "{}", form);
+        });
+    }
+
+    #[test]
+    fn output_is_gnu_assembly_for_system_v() {
+        let (module, _, _) = lower_and_interpret("v[2] 1, 2; f(x) return(x); main() { extrn v; return(f(v[1])); }", "");
+        let asm = emit(&module);
+        for expected in [".globl b_f", ".globl b_main", ".globl main", "call b_main", "call b_f",
+                         ".globl b_v", ".section .init_array", "movq (,%rax,8), %rax", "movq %rdi, -8(%rbp)"] {
+            assert!(asm.contains(expected), "missing `{}` in\n{}", expected, asm);
+        }
+        let (module, _, _) = lower_and_interpret("f() return(1);", "");
+        let asm = emit(&module);
+        assert!(!asm.contains(".globl main") && !asm.contains(".init_array"), "{}", asm);
+    }
+
+    #[test]
+    fn programs_run_like_the_interpreter() {
+        check_against_interpreter("fact", FACT, "");
+        check_against_interpreter("echo", ECHO, "banana split");
+    }
+
+    #[test]
+    fn arithmetic_wraps_like_the_ir() {
+        let src = format!("{}
+main() {{
+  auto big, min;
+  big = 0777777777777777777777;
+  min = -big - 1;
+  printn(big + 1 == min); putchar(' ');
+  printn(big * 3); putchar(' ');
+  printn(min / -1 == min); putchar(' ');
+  printn(min % -1); putchar(' ');
+  printn(1 << 65); putchar(' ');
+  printn(-16 >> 2); putchar(' ');
+  printn(-7 / 2); putchar(' ');
+  printn(-7 % 2);
+  return(!0 + ~0);
+}}", PRINTN);
+        check_against_interpreter("wrap", &src, "");
+    }
+
+    #[test]
+    fn globals_strings_pointers_and_vectors() {
+        check_against_interpreter("globals", GLOBALS, "");
+    }
+
+    #[test]
+    fn calls_switches_and_computed_gotos() {
+        check_against_interpreter("calls", CALLS, "");
+    }
+
+    #[test]
+    fn unresolved_imports_fail_to_link() {
+        if !have_cc() {
+            return skip("unresolved", "x86-64 C toolchain");
+        }
+        let (module, _, _) = lower_and_interpret("main() nowhere(1);", "");
+        match link(&[&emit(&module), &runtime()], &file("x86_64", "unresolved")) {
+            Err(LinkError::Failed { stderr, .. }) => assert!(stderr.contains("b_nowhere"), "{}", stderr),
+            other => panic!("expected a link failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn callees_walk_their_arguments_through_a_pointer() {
+        check_against_interpreter("printf", PRINTF, "");
+    }
+}